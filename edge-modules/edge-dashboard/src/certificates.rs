@@ -0,0 +1,122 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_core::{RuntimeSettings, UrlExt};
+use edgelet_http::UrlConnector;
+use futures::future::{Either, IntoFuture};
+use futures::Future;
+use hyper::Client;
+use openssl::hash::MessageDigest;
+use openssl::x509::{X509NameRef, X509};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use workload::apis::client::APIClient as WorkloadClient;
+use workload::apis::configuration::Configuration;
+
+use crate::error::{internal_error, ApiError};
+use crate::{AuthRequest, Context};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Certificate {
+    subject: String,
+    issuer: String,
+    not_before: String,
+    not_after: String,
+    fingerprint: String,
+}
+
+impl Certificate {
+    fn from_x509(cert: &X509) -> Result<Self, ActixError> {
+        let fingerprint = cert
+            .digest(MessageDigest::sha256())
+            .map_err(internal_error)?
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(":");
+
+        Ok(Certificate {
+            subject: name_to_string(cert.subject_name()),
+            issuer: name_to_string(cert.issuer_name()),
+            not_before: cert.not_before().to_string(),
+            not_after: cert.not_after().to_string(),
+            fingerprint,
+        })
+    }
+}
+
+// The management API does not expose the trust bundle, so it is read from the
+// workload API which serves it to every module.
+pub fn get_certificates(
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let api_ver = info.api_version.clone();
+    let response = context
+        .edge_config
+        .as_ref()
+        .map(|config| {
+            Either::A(
+                workload_client(config.connect().workload_uri())
+                    .map(move |client| {
+                        client
+                            .workload_api()
+                            .trust_bundle(&api_ver)
+                            .map_err(internal_error)
+                            .and_then(|trust_bundle| {
+                                parse_certificates(trust_bundle.certificate())
+                                    .map(|certs| HttpResponse::Ok().json(certs))
+                            })
+                    })
+                    .into_future()
+                    .flatten(),
+            )
+        })
+        .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()));
+
+    Box::new(response)
+}
+
+fn workload_client(url: &Url) -> Result<WorkloadClient, ActixError> {
+    let connector = UrlConnector::new(url).map_err(internal_error)?;
+    let base_path = url.to_base_path().map_err(internal_error)?;
+
+    let mut configuration = Configuration::new(Client::builder().build(connector));
+    configuration.base_path = base_path
+        .to_str()
+        .ok_or_else(|| internal_error("Invalid workload URI"))?
+        .to_string();
+
+    let scheme = url.scheme().to_string();
+    configuration.uri_composer = Box::new(move |base_path, path| {
+        Ok(UrlConnector::build_hyper_uri(&scheme, base_path, path)?)
+    });
+
+    Ok(WorkloadClient::new(configuration))
+}
+
+fn parse_certificates(pem: &str) -> Result<Vec<Certificate>, ActixError> {
+    X509::stack_from_pem(pem.as_bytes())
+        .map_err(internal_error)?
+        .iter()
+        .map(Certificate::from_x509)
+        .collect()
+}
+
+fn name_to_string(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use actix_web::Error as ActixError;
+use actix_web::*;
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::twin::fetch_module_twin;
+use crate::{AuthRequest, Context};
+
+const EDGE_AGENT: &str = "$edgeAgent";
+
+const DEFAULT_CHANGELOG_LIMIT: usize = 10;
+
+// changes kept in memory, oldest first out
+const CHANGELOG_CAPACITY: usize = 100;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ChangelogQuery {
+    limit: Option<usize>,
+}
+
+/// A change to the deployment manifest, i.e. to the desired properties of the
+/// `$edgeAgent` twin. `timestamp` is when IoT Hub last updated them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChangelogEntry {
+    timestamp: Option<String>,
+    changed_by: Option<String>,
+    added_modules: Vec<String>,
+    removed_modules: Vec<String>,
+    updated_modules: Vec<String>,
+}
+
+/// The deployment manifest changes seen by the dashboard. IoT Hub keeps no
+/// history of twins, nor who changed them, so each time the `$edgeAgent` twin
+/// is read it's compared with the previous one. Several changes between two
+/// reads show up as one.
+#[derive(Default)]
+pub struct Changelog {
+    state: Mutex<ChangelogState>,
+}
+
+#[derive(Default)]
+struct ChangelogState {
+    last: Option<Manifest>,
+    entries: VecDeque<ChangelogEntry>,
+}
+
+struct Manifest {
+    version: Option<i64>,
+    modules: Map<String, JsonValue>,
+}
+
+impl Manifest {
+    fn new(twin: &JsonValue) -> Self {
+        let desired = &twin["properties"]["desired"];
+        let mut modules = Map::new();
+        for key in &["systemModules", "modules"] {
+            if let Some(map) = desired[key].as_object() {
+                modules.extend(
+                    map.iter()
+                        .map(|(name, module)| (name.clone(), module.clone())),
+                );
+            }
+        }
+
+        Manifest {
+            version: desired["$version"].as_i64(),
+            modules,
+        }
+    }
+}
+
+impl Changelog {
+    pub fn observe(&self, twin: &JsonValue) {
+        let manifest = Manifest::new(twin);
+        let mut state = self.state.lock().expect("Unexpected lock error");
+        let state = &mut *state;
+
+        if let Some(ref last) = state.last {
+            if last.version == manifest.version {
+                return;
+            }
+
+            let entry = ChangelogEntry {
+                timestamp: twin["properties"]["desired"]["$metadata"]["$lastUpdated"]
+                    .as_str()
+                    .map(ToOwned::to_owned),
+                changed_by: None,
+                added_modules: manifest
+                    .modules
+                    .keys()
+                    .filter(|name| !last.modules.contains_key(*name))
+                    .cloned()
+                    .collect(),
+                removed_modules: last
+                    .modules
+                    .keys()
+                    .filter(|name| !manifest.modules.contains_key(*name))
+                    .cloned()
+                    .collect(),
+                updated_modules: manifest
+                    .modules
+                    .iter()
+                    .filter(|(name, module)| {
+                        last.modules
+                            .get(*name)
+                            .map_or(false, |last| last != *module)
+                    })
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+            };
+
+            if state.entries.len() == CHANGELOG_CAPACITY {
+                state.entries.pop_front();
+            }
+            state.entries.push_back(entry);
+        }
+
+        state.last = Some(manifest);
+    }
+
+    /// The latest changes, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<ChangelogEntry> {
+        let state = self.state.lock().expect("Unexpected lock error");
+        state.entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+pub fn get_changelog(
+    context: web::Data<Arc<Context>>,
+    query: web::Query<ChangelogQuery>,
+    _info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let limit = query.limit.unwrap_or(DEFAULT_CHANGELOG_LIMIT);
+    let changelog = context.changelog.clone();
+
+    let response = fetch_module_twin(&context, EDGE_AGENT.to_string()).map(move |twin| {
+        changelog.observe(&twin);
+        HttpResponse::Ok().json(changelog.recent(limit))
+    });
+
+    Box::new(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as JsonValue};
+
+    use super::Changelog;
+
+    fn agent_twin(version: i64, modules: JsonValue) -> JsonValue {
+        json!({
+            "properties": {
+                "desired": {
+                    "systemModules": {
+                        "edgeAgent": { "settings": { "image": "mcr.microsoft.com/azureiotedge-agent:1.0" } },
+                    },
+                    "modules": modules,
+                    "$metadata": { "$lastUpdated": format!("2019-09-0{}T10:00:00Z", version) },
+                    "$version": version,
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn changes_between_manifests_are_recorded() {
+        let changelog = Changelog::default();
+        changelog.observe(&agent_twin(
+            1,
+            json!({
+                "tempSensor": { "settings": { "image": "sensor:1.0" } },
+                "filter": { "settings": { "image": "filter:1.0" } },
+            }),
+        ));
+        assert!(changelog.recent(10).is_empty());
+
+        let twin = agent_twin(
+            2,
+            json!({
+                "tempSensor": { "settings": { "image": "sensor:1.1" } },
+                "analytics": { "settings": { "image": "analytics:1.0" } },
+            }),
+        );
+        changelog.observe(&twin);
+        // an unchanged twin isn't a change
+        changelog.observe(&twin);
+
+        let entries = changelog.recent(10);
+        assert_eq!(1, entries.len());
+        assert_eq!(
+            json!({
+                "timestamp": "2019-09-02T10:00:00Z",
+                "changed_by": null,
+                "added_modules": ["analytics"],
+                "removed_modules": ["filter"],
+                "updated_modules": ["tempSensor"],
+            }),
+            serde_json::to_value(&entries[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn recent_changes_are_newest_first() {
+        let changelog = Changelog::default();
+        for version in 1..=4 {
+            changelog.observe(&agent_twin(version, json!({})));
+        }
+
+        let timestamps: Vec<_> = changelog
+            .recent(2)
+            .into_iter()
+            .map(|entry| entry.timestamp.unwrap())
+            .collect();
+        assert_eq!(
+            vec!["2019-09-04T10:00:00Z", "2019-09-03T10:00:00Z"],
+            timestamps
+        );
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_core::RuntimeSettings;
+use futures::future::{Either, IntoFuture};
+use futures::Future;
+use kube_client::get_config;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{internal_error, ApiError};
+use crate::middleware::{module_client, CorrelationId};
+use crate::provisioning::provisioning_status;
+use crate::{AuthRequest, Context};
+
+/// The parts of the device's configuration that are safe to show remotely.
+/// Connection strings, keys and certificates are deliberately left out.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeviceConfig {
+    hostname: String,
+    provisioning_source: String,
+    hub_name: Option<String>,
+    device_id: Option<String>,
+    iotedged_version: String,
+    management_uri_scheme: String,
+    namespace: Option<String>,
+}
+
+pub fn get_device_config(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    // The namespace only applies when the dashboard runs in a cluster.
+    let namespace = get_config()
+        .ok()
+        .map(|_| context.settings.namespace.clone());
+
+    let response = context
+        .edge_config
+        .as_ref()
+        .map(|config| {
+            let mgmt_uri = config.connect().management_uri();
+            let provisioning = provisioning_status(config);
+            let hostname = config.hostname().to_string();
+            let management_uri_scheme = mgmt_uri.scheme().to_string();
+
+            Either::A(
+                Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                    .map_err(internal_error)
+                    .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                    .map(|mod_client| {
+                        mod_client.get_system_info().map_err(internal_error).map(
+                            move |system_info| {
+                                HttpResponse::Ok().json(DeviceConfig {
+                                    hostname,
+                                    provisioning_source: provisioning.source,
+                                    hub_name: provisioning.hub_name,
+                                    device_id: provisioning.device_id,
+                                    iotedged_version: system_info.version().clone(),
+                                    management_uri_scheme,
+                                    namespace,
+                                })
+                            },
+                        )
+                    })
+                    .into_future()
+                    .flatten(),
+            )
+        })
+        .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()));
+
+    Box::new(response)
+}
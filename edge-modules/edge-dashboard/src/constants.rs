@@ -0,0 +1,4 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// label iotedged puts on every pod and deployment it creates for a module
+pub const EDGE_MODULE_LABEL: &str = "net.azure-devices.edge.module";
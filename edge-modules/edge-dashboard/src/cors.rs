@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use actix_cors::Cors;
+use actix_web::http::Method;
+use log::warn;
+
+use crate::settings::Settings;
+
+// Which browser origins may call the API, and how.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorsConfig {
+    allowed_origins: Vec<String>,
+    allowed_methods: Vec<String>,
+    max_age_seconds: u32,
+}
+
+impl CorsConfig {
+    pub fn new(
+        allowed_origins: Vec<String>,
+        allowed_methods: Vec<String>,
+        max_age_seconds: u32,
+    ) -> Self {
+        CorsConfig {
+            allowed_origins,
+            allowed_methods,
+            max_age_seconds,
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        let allowed_origins = settings
+            .cors_allowed_origins
+            .as_ref()
+            .map(|origins| split_list(origins))
+            .unwrap_or_default();
+        let allowed_methods = split_list(&settings.cors_allowed_methods)
+            .into_iter()
+            .map(|method| method.to_uppercase())
+            .filter(|method| {
+                let valid = Method::from_bytes(method.as_bytes()).is_ok();
+                if !valid {
+                    warn!("Ignoring invalid CORS method {}", method);
+                }
+                valid
+            })
+            .collect();
+
+        CorsConfig::new(
+            allowed_origins,
+            allowed_methods,
+            settings.cors_max_age_seconds,
+        )
+    }
+
+    pub fn allowed_origins(&self) -> &[String] {
+        &self.allowed_origins
+    }
+
+    pub fn allowed_methods(&self) -> &[String] {
+        &self.allowed_methods
+    }
+
+    pub fn max_age_seconds(&self) -> u32 {
+        self.max_age_seconds
+    }
+
+    /// The CORS middleware. With no allowed origins any origin is allowed,
+    /// which is meant for development.
+    pub fn cors(&self) -> Cors {
+        let mut cors = Cors::new()
+            .allowed_methods(self.allowed_methods.iter().map(String::as_str))
+            .max_age(self.max_age_seconds as usize);
+
+        if self.allowed_origins.is_empty() {
+            cors = cors.send_wildcard();
+        } else {
+            for origin in &self.allowed_origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
+        cors
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{
+        ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+        ORIGIN,
+    };
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::{test, web, App, HttpResponse};
+    use structopt::StructOpt;
+
+    use super::CorsConfig;
+    use crate::settings::Settings;
+
+    fn preflight(config: &CorsConfig, origin: &str) -> actix_web::dev::ServiceResponse {
+        let mut app = test::init_service(App::new().wrap(config.cors()).route(
+            "/api/modules",
+            web::get().to(|| HttpResponse::Ok().finish()),
+        ));
+
+        test::call_service(
+            &mut app,
+            test::TestRequest::with_uri("/api/modules")
+                .method(Method::OPTIONS)
+                .header(ORIGIN, origin)
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .to_request(),
+        )
+    }
+
+    #[test]
+    fn preflight_allows_configured_origin() {
+        let config = CorsConfig::new(
+            vec!["https://dashboard.contoso.com".to_string()],
+            vec!["GET".to_string(), "POST".to_string()],
+            600,
+        );
+
+        let response = preflight(&config, "https://dashboard.contoso.com");
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            "https://dashboard.contoso.com",
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+        let methods = response.headers()[ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap();
+        assert!(methods.contains("GET"));
+        assert!(methods.contains("POST"));
+    }
+
+    #[test]
+    fn preflight_allows_any_origin_without_configured_origins() {
+        let config = CorsConfig::new(Vec::new(), vec!["GET".to_string()], 600);
+
+        let response = preflight(&config, "http://localhost:3000");
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("*", response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]);
+        assert!(response
+            .headers()
+            .contains_key(ACCESS_CONTROL_ALLOW_METHODS));
+    }
+
+    #[test]
+    fn config_is_read_from_settings() {
+        let settings = Settings::from_iter(vec![
+            "edge-dashboard",
+            "-h",
+            "localhost",
+            "-p",
+            "0",
+            "--cors-allowed-origins",
+            "https://a.contoso.com, https://b.contoso.com",
+            "--cors-allowed-methods",
+            "get,delete,not a method",
+        ]);
+
+        let config = CorsConfig::from_settings(&settings);
+
+        assert_eq!(
+            &["https://a.contoso.com", "https://b.contoso.com"],
+            config.allowed_origins()
+        );
+        assert_eq!(&["GET", "DELETE"], config.allowed_methods());
+        assert_eq!(3600, config.max_age_seconds());
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::Error as ActixError;
+use actix_web::*;
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::error::ApiError;
+use crate::twin::fetch_module_twin;
+use crate::{AuthRequest, Context};
+
+const EDGE_HUB: &str = "$edgeHub";
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyType {
+    /// The module receives messages from the other one.
+    Upstream,
+    /// The module sends messages to the other one.
+    Downstream,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Dependency {
+    module_id: String,
+    dependency_type: DependencyType,
+    route_name: String,
+}
+
+// Dependencies are only implied by edgeHub's routes, which come from the
+// desired properties of its twin.
+pub fn get_dependencies(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    _info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let module_id = match req.match_info().get("id") {
+        Some(module_id) => module_id.to_string(),
+        None => return Box::new(ApiError::bad_request("Invalid module ID").into_future()),
+    };
+
+    let response = fetch_module_twin(&context, EDGE_HUB.to_string()).map(move |twin| {
+        let routes = &twin["properties"]["desired"]["routes"];
+        HttpResponse::Ok().json(route_dependencies(routes, &module_id))
+    });
+
+    Box::new(response)
+}
+
+/// The modules which routes connect directly to `module_id`. Routes from
+/// every module (`FROM /messages/*`) or to IoT Hub (`INTO $upstream`) name no
+/// other module and are left out.
+pub fn route_dependencies(routes: &JsonValue, module_id: &str) -> Vec<Dependency> {
+    let routes = match routes.as_object() {
+        Some(routes) => routes,
+        None => return Vec::new(),
+    };
+
+    let mut dependencies = Vec::new();
+    for (route_name, route) in routes {
+        // routes are either the statement itself or, since schema 1.1, an
+        // object with the statement and its priority and time to live
+        let statement = match route {
+            JsonValue::String(statement) => statement.as_str(),
+            JsonValue::Object(route) => match route.get("route").and_then(JsonValue::as_str) {
+                Some(statement) => statement,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let (source, sink) = match parse_route(statement) {
+            Some(route) => route,
+            None => continue,
+        };
+
+        if let (Some(source), Some(sink)) = (source, sink) {
+            if source == module_id && sink != module_id {
+                dependencies.push(Dependency {
+                    module_id: sink.to_string(),
+                    dependency_type: DependencyType::Downstream,
+                    route_name: route_name.clone(),
+                });
+            } else if sink == module_id && source != module_id {
+                dependencies.push(Dependency {
+                    module_id: source.to_string(),
+                    dependency_type: DependencyType::Upstream,
+                    route_name: route_name.clone(),
+                });
+            }
+        }
+    }
+
+    dependencies
+}
+
+// Reads the modules a `FROM <source> [WHERE <condition>] INTO <sink>` route
+// statement sends messages from and to, when it names them.
+fn parse_route(statement: &str) -> Option<(Option<&str>, Option<&str>)> {
+    // keywords are case insensitive, and upper-casing ASCII keeps offsets
+    let upper = statement.to_ascii_uppercase();
+    let from = upper.find("FROM ")? + "FROM ".len();
+    let into = upper.rfind(" INTO ")?;
+    let source_end = upper[from..into]
+        .find(" WHERE ")
+        .map_or(into, |offset| from + offset);
+
+    let source = statement[from..source_end].trim();
+    let sink = statement[into + " INTO ".len()..].trim();
+
+    Some((
+        module_in_path(source, "/messages/modules/"),
+        module_in_path(sink, "/modules/"),
+    ))
+}
+
+fn module_in_path<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = path.find(prefix)? + prefix.len();
+    let module = path[start..]
+        .split(|c| c == '/' || c == '"')
+        .next()
+        .filter(|module| !module.is_empty() && *module != "*")?;
+    Some(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_route, route_dependencies};
+
+    #[test]
+    fn routes_are_parsed() {
+        assert_eq!(
+            Some((Some("tempSensor"), Some("filter"))),
+            parse_route(
+                r#"FROM /messages/modules/tempSensor/outputs/temperatureOutput INTO BrokeredEndpoint("/modules/filter/inputs/input1")"#
+            )
+        );
+        assert_eq!(
+            Some((Some("filter"), None)),
+            parse_route("from /messages/modules/filter/* where temperature > 25 into $upstream")
+        );
+        assert_eq!(
+            Some((None, Some("filter"))),
+            parse_route(r#"FROM /messages/* INTO BrokeredEndpoint("/modules/filter/inputs/all")"#)
+        );
+        assert_eq!(None, parse_route("garbage"));
+    }
+
+    #[test]
+    fn dependencies_are_found_in_both_directions() {
+        let routes = json!({
+            "sensorToFilter": r#"FROM /messages/modules/tempSensor/outputs/temperatureOutput INTO BrokeredEndpoint("/modules/filter/inputs/input1")"#,
+            "filterToAlerts": {
+                "route": r#"FROM /messages/modules/filter/outputs/alerts INTO BrokeredEndpoint("/modules/alerts/inputs/input1")"#,
+                "priority": 0,
+                "timeToLiveSecs": 3600
+            },
+            "filterToIoTHub": "FROM /messages/modules/filter/* INTO $upstream",
+            "sensorToIoTHub": "FROM /messages/modules/tempSensor/* INTO $upstream",
+        });
+
+        assert_eq!(
+            json!([
+                { "module_id": "alerts", "dependency_type": "downstream", "route_name": "filterToAlerts" },
+                { "module_id": "tempSensor", "dependency_type": "upstream", "route_name": "sensorToFilter" },
+            ]),
+            serde_json::to_value(route_dependencies(&routes, "filter")).unwrap()
+        );
+        assert!(route_dependencies(&json!(null), "filter").is_empty());
+    }
+}
@@ -1,9 +1,14 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::fmt::{self, Display};
 use std::io::Error as IoError;
 
+use actix_web::http::StatusCode;
+use actix_web::{Error as ActixError, HttpResponse, ResponseError};
 use edgelet_docker::LoadSettingsError;
 use failure::Fail;
+use futures::future::{err, FutureResult};
+use serde::Serialize;
 
 #[derive(Fail, Debug)]
 pub enum Error {
@@ -25,3 +30,142 @@ impl From<IoError> for Error {
         Error::Io(err)
     }
 }
+
+/// The JSON body of every error response of the dashboard API.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    code: u16,
+    message: String,
+    details: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            code: status.as_u16(),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn with_details(mut self, details: impl Display) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_ref().map(String::as_str)
+    }
+
+    /// The error as a failed future, for handlers which return futures.
+    pub fn into_future<T>(self) -> FutureResult<T, ActixError> {
+        err(self.into())
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(ref details) = self.details {
+            write!(f, ": {}", details)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn error_response(&self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        HttpResponse::build(status).json(self)
+    }
+}
+
+// iotedged's configuration couldn't be loaded, so nothing which needs it can
+// be served.
+impl<'a> From<&'a Error> for ApiError {
+    fn from(error: &'a Error) -> Self {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The iotedged configuration could not be loaded",
+        )
+        .with_details(format!("{:?}", error))
+    }
+}
+
+/// Wraps an unexpected failure, such as a failed request to iotedged, in a
+/// 500 `ApiError`. Usable wherever `ErrorInternalServerError` would be.
+pub fn internal_error<E: Display>(error: E) -> ActixError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+        .with_details(error)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::Body;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::http::StatusCode;
+    use actix_web::{HttpResponse, ResponseError};
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{internal_error, ApiError};
+
+    fn json_body(response: &HttpResponse) -> JsonValue {
+        match response.body().as_ref() {
+            Some(Body::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("expected a JSON body"),
+        }
+    }
+
+    #[test]
+    fn api_error_is_serialized_as_json() {
+        let response = ApiError::not_found("Module not found")
+            .with_details("no container named tempSensor")
+            .error_response();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(
+            "application/json",
+            response.headers().get(CONTENT_TYPE).unwrap()
+        );
+        assert_eq!(
+            json!({
+                "code": 404,
+                "message": "Module not found",
+                "details": "no container named tempSensor"
+            }),
+            json_body(&response)
+        );
+    }
+
+    #[test]
+    fn internal_error_keeps_cause_as_details() {
+        let response = HttpResponse::from(internal_error("connection refused"));
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            json!({
+                "code": 500,
+                "message": "Internal server error",
+                "details": "connection refused"
+            }),
+            json_body(&response)
+        );
+    }
+}
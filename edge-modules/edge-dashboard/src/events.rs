@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_utils::sanitize_dns_label;
+use futures::future::{join_all, Either};
+use futures::Future;
+use k8s_openapi::api::core::v1 as api_core;
+use kube_client::{get_config, Client as KubeClient};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::error::{internal_error, ApiError};
+use crate::{AuthRequest, Context};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Event {
+    timestamp: Option<String>,
+    reason: Option<String>,
+    message: Option<String>,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+}
+
+impl From<api_core::Event> for Event {
+    fn from(event: api_core::Event) -> Self {
+        Event {
+            timestamp: event
+                .last_timestamp
+                .or(event.first_timestamp)
+                .map(|time| time.0.to_rfc3339()),
+            reason: event.reason,
+            message: event.message,
+            type_: event.type_,
+        }
+    }
+}
+
+// Events are only recorded for Kubernetes deployments, so these are read
+// straight from the API server rather than through the management API.
+pub fn get_events(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    _info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            let namespace = context.settings.namespace.clone();
+            let label_selector = format!("{}={}", EDGE_MODULE_LABEL, sanitize_dns_label(module_id));
+
+            match get_config() {
+                Ok(config) => {
+                    let mut client = KubeClient::new(config);
+                    let fut = client
+                        .list_pods(&namespace, Some(&label_selector))
+                        .and_then(move |pods| {
+                            let requests: Vec<_> = pods
+                                .items
+                                .iter()
+                                .filter_map(|pod| {
+                                    pod.metadata.as_ref().and_then(|meta| meta.name.as_ref())
+                                })
+                                .map(|pod_name| {
+                                    let field_selector =
+                                        format!("involvedObject.name={}", pod_name);
+                                    client.list_events(&namespace, Some(&field_selector))
+                                })
+                                .collect();
+                            join_all(requests)
+                        })
+                        .map(|lists| {
+                            let mut events: Vec<Event> = lists
+                                .into_iter()
+                                .flat_map(|list| list.items)
+                                .map(Event::from)
+                                .collect();
+                            events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+                            HttpResponse::Ok().json(events)
+                        })
+                        .map_err(internal_error);
+
+                    Either::A(fut)
+                }
+                Err(err) => Either::B(
+                    ApiError::new(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "The Kubernetes configuration could not be loaded",
+                    )
+                    .with_details(err)
+                    .into_future(),
+                ),
+            }
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
@@ -0,0 +1,265 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::{self, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use actix_web::error::BlockingError;
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_utils::sanitize_dns_label;
+use futures::future::Either;
+use futures::Future;
+use kube_client::{get_config, Client as KubeClient};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::error::{internal_error, ApiError};
+use crate::{AuthRequest, Context};
+
+// output beyond this is read and discarded so the command can't block on a full pipe
+const MAX_OUTPUT_BYTES: u64 = 64 * 1024;
+
+// each exec holds a kubectl process and a blocking thread until it finishes
+const MAX_TIMEOUT_SECONDS: u32 = 300;
+
+#[derive(Debug, Deserialize)]
+pub struct ExecRequest {
+    command: Vec<String>,
+    timeout_seconds: u32,
+}
+
+impl ExecRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        if self.command.is_empty() {
+            return Err(ApiError::bad_request("Command must not be empty"));
+        }
+
+        if self.timeout_seconds == 0 || self.timeout_seconds > MAX_TIMEOUT_SECONDS {
+            return Err(ApiError::bad_request(format!(
+                "Timeout must be between 1 and {} seconds",
+                MAX_TIMEOUT_SECONDS
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExecResult {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+#[derive(Debug)]
+enum ExecError {
+    Io(io::Error),
+    TimedOut,
+}
+
+impl From<io::Error> for ExecError {
+    fn from(err: io::Error) -> Self {
+        ExecError::Io(err)
+    }
+}
+
+// Commands go through `kubectl exec`, which uses the pod's exec subresource,
+// as neither the management API nor the kube client can stream exec sessions.
+// That only exists on Kubernetes and runs arbitrary commands in a module, so
+// it has to be turned on with --enable-kubectl-exec.
+pub fn exec_module(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    _info: web::Query<AuthRequest>,
+    body: web::Json<ExecRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    if !context.settings.enable_kubectl_exec {
+        return Box::new(
+            ApiError::not_found("Exec is only available when --enable-kubectl-exec is set")
+                .into_future(),
+        );
+    }
+
+    let body = body.into_inner();
+    if let Err(err) = body.validate() {
+        return Box::new(err.into_future());
+    }
+
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            let namespace = context.settings.namespace.clone();
+            let container_name = sanitize_dns_label(module_id);
+            let label_selector = format!("{}={}", EDGE_MODULE_LABEL, container_name);
+
+            match get_config() {
+                Ok(config) => {
+                    let fut = KubeClient::new(config)
+                        .list_pods(&namespace, Some(&label_selector))
+                        .map_err(internal_error)
+                        .and_then(move |pods| {
+                            let pod_name = pods
+                                .items
+                                .into_iter()
+                                .filter_map(|pod| pod.metadata.and_then(|meta| meta.name))
+                                .next();
+
+                            match pod_name {
+                                Some(pod_name) => Either::A(
+                                    web::block(move || {
+                                        run_exec(&namespace, &pod_name, &container_name, &body)
+                                    })
+                                    .then(|result| {
+                                        match result {
+                                            Ok(result) => Ok(HttpResponse::Ok().json(result)),
+                                            Err(BlockingError::Error(ExecError::TimedOut)) => {
+                                                Err(ApiError::new(
+                                                    StatusCode::GATEWAY_TIMEOUT,
+                                                    "Command timed out",
+                                                )
+                                                .into())
+                                            }
+                                            Err(err) => Err(internal_error(format!("{:?}", err))),
+                                        }
+                                    }),
+                                ),
+                                None => {
+                                    Either::B(ApiError::not_found("Module not found").into_future())
+                                }
+                            }
+                        });
+
+                    Either::A(fut)
+                }
+                Err(err) => Either::B(
+                    ApiError::new(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "The Kubernetes configuration could not be loaded",
+                    )
+                    .with_details(err)
+                    .into_future(),
+                ),
+            }
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+fn run_exec(
+    namespace: &str,
+    pod_name: &str,
+    container_name: &str,
+    request: &ExecRequest,
+) -> Result<ExecResult, ExecError> {
+    let mut child = Command::new("kubectl")
+        .args(&[
+            "exec",
+            "--namespace",
+            namespace,
+            pod_name,
+            "-c",
+            container_name,
+            "--",
+        ])
+        .args(&request.command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().map(read_limited);
+    let stderr = child.stderr.take().map(read_limited);
+
+    let deadline = Instant::now() + Duration::from_secs(u64::from(request.timeout_seconds));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            kill(&mut child);
+            return Err(ExecError::TimedOut);
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    Ok(ExecResult {
+        stdout: join_output(stdout)?,
+        stderr: join_output(stderr)?,
+        exit_code: status.code().unwrap_or(-1),
+    })
+}
+
+fn read_limited(mut pipe: impl Read + Send + 'static) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        pipe.by_ref()
+            .take(MAX_OUTPUT_BYTES)
+            .read_to_end(&mut output)?;
+        io::copy(&mut pipe, &mut io::sink())?;
+        Ok(output)
+    })
+}
+
+fn join_output(reader: Option<JoinHandle<io::Result<Vec<u8>>>>) -> Result<String, ExecError> {
+    let output = match reader {
+        Some(reader) => reader
+            .join()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "output reader panicked"))??,
+        None => Vec::new(),
+    };
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+fn kill(child: &mut Child) {
+    if let Err(err) = child.kill() {
+        warn!("Could not stop timed out command: {:?}", err);
+    }
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::ExecRequest;
+
+    fn request(command: &[&str], timeout_seconds: u32) -> ExecRequest {
+        serde_json::from_value(json!({
+            "command": command,
+            "timeout_seconds": timeout_seconds,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn validate_accepts_timeouts_in_range() {
+        assert!(request(&["ls"], 1).validate().is_ok());
+        assert!(request(&["ls"], 300).validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_timeouts_out_of_range() {
+        assert_eq!(request(&["ls"], 0).validate().unwrap_err().code(), 400);
+        assert_eq!(request(&["ls"], 301).validate().unwrap_err().code(), 400);
+        assert_eq!(
+            request(&["ls"], u32::max_value())
+                .validate()
+                .unwrap_err()
+                .code(),
+            400
+        );
+    }
+
+    #[test]
+    fn validate_rejects_empty_command() {
+        assert_eq!(request(&[], 10).validate().unwrap_err().code(), 400);
+    }
+}
@@ -1,10 +1,29 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-#[derive(Debug)]
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
 pub enum Health {
     Healthy,
     Degraded,
     Poor,
+    // iotedged could not be asked for the modules
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthStatus {
+    health: Health,
+    error_message: Option<String>,
+}
+
+impl HealthStatus {
+    pub fn unknown(error_message: String) -> Self {
+        HealthStatus {
+            health: Health::Unknown,
+            error_message: Some(error_message),
+        }
+    }
 }
 
 #[derive(Debug)]
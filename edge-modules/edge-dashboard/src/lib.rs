@@ -1,18 +1,37 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+mod certificates;
+mod changelog;
+mod config;
+mod constants;
+mod cors;
+mod dependencies;
 mod error;
+mod events;
+mod exec;
 mod health;
+mod middleware;
 mod modules;
+mod namespaces;
+mod network;
+mod preferences;
+mod provisioning;
+mod rate_limit;
 mod settings;
+mod simulation;
 mod state;
+mod stats;
 mod status;
+mod system;
+mod twin;
+mod volumes;
 
-#[cfg(windows)]
 use std::env;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
-use actix_cors::Cors;
 use actix_web::*;
 use edgelet_core::Provisioning;
 use edgelet_core::RuntimeSettings;
@@ -20,23 +39,47 @@ use edgelet_docker::Settings as DockerSettings;
 use serde_derive::Deserialize;
 use structopt::StructOpt;
 
+use changelog::Changelog;
+use cors::CorsConfig;
 pub use error::Error;
+use middleware::ClientTimeouts;
+use rate_limit::RateLimiter;
 use settings::Settings;
+use simulation::{SimulatedFailures, DEV_MODE_ENV};
 
 pub struct Context {
     pub edge_config: Result<DockerSettings, Error>,
     pub settings: Settings,
+    pub system_info_limit: RateLimiter,
+    pub api_token: Option<String>,
+    pub client_timeouts: ClientTimeouts,
+    pub dev_mode: bool,
+    pub simulated_failures: Arc<SimulatedFailures>,
+    pub changelog: Arc<Changelog>,
 }
 
 impl Context {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, Error> {
         let settings = Settings::from_args();
         let edge_config = get_config(settings.config_path.as_ref().map(String::as_str));
+        let system_info_limit =
+            RateLimiter::new(settings.system_info_rate_limit, Duration::from_secs(60));
+        let api_token = get_api_token(&settings)?;
+        let client_timeouts = ClientTimeouts::new(
+            Duration::from_secs(settings.mgmt_connect_timeout),
+            Duration::from_secs(settings.mgmt_request_timeout),
+        );
 
-        Context {
+        Ok(Context {
             edge_config,
             settings,
-        }
+            system_info_limit,
+            api_token,
+            client_timeouts,
+            dev_mode: env::var(DEV_MODE_ENV).map_or(false, |value| value == "1"),
+            simulated_failures: Arc::new(SimulatedFailures::default()),
+            changelog: Arc::new(Changelog::default()),
+        })
     }
 }
 
@@ -62,20 +105,92 @@ impl Main {
         let context = web::Data::new(self.context.clone());
         let device = web::Data::new(set_up(context.clone()));
 
+        let api_token = self.context.api_token.clone();
+        let cors = CorsConfig::from_settings(&self.context.settings);
+
         HttpServer::new(move || {
             App::new()
-                .wrap(Cors::new().send_wildcard())
+                .wrap(
+                    middleware::BearerAuth::new(api_token.clone())
+                        .with_public_path("/api/system/info"),
+                )
+                .wrap(middleware::Correlation)
+                .wrap(cors.cors())
                 .register_data(context.clone())
                 .register_data(device.clone())
                 .service(
                     web::resource("/api/modules/{id}/restart").to_async(modules::restart_module),
                 )
-                .service(web::resource("/api/modules/{id}/logs").to_async(modules::get_logs))
+                .service(
+                    web::resource("/api/modules/{id}/start")
+                        .route(web::post().to_async(modules::start_module)),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/stop")
+                        .route(web::post().to_async(modules::stop_module)),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/copy")
+                        .route(web::post().to_async(modules::copy_module)),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/env")
+                        .route(web::patch().to_async(modules::update_env)),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/dependencies")
+                        .to_async(dependencies::get_dependencies),
+                )
+                .service(web::resource("/api/modules/{id}/events").to_async(events::get_events))
+                .service(
+                    web::resource("/api/modules/{id}/exec")
+                        .route(web::post().to_async(exec::exec_module)),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/logs")
+                        .route(web::get().to_async(modules::get_logs))
+                        .route(web::delete().to_async(modules::clear_logs)),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/logs/lines").to_async(modules::get_log_lines),
+                )
+                .service(
+                    web::resource("/api/modules/{id}/logs/stream").to_async(modules::stream_logs),
+                )
+                .service(web::resource("/api/modules/{id}/network").to_async(network::get_network))
+                .service(web::resource("/api/modules/{id}/stats").to_async(stats::get_stats))
+                .service(web::resource("/api/modules/{id}/twin").to_async(twin::get_twin))
+                .service(web::resource("/api/modules/{id}/volumes").to_async(volumes::get_volumes))
+                .service(
+                    web::resource("/api/modules/events").to_async(modules::stream_module_events),
+                )
+                .service(web::resource("/api/modules/summary").to_async(modules::get_summary))
                 .service(web::resource("/api/modules").to_async(modules::get_modules))
+                .service(
+                    web::resource("/api/modules/{id}")
+                        .route(web::get().to_async(modules::get_module_by_id)),
+                )
+                .service(web::resource("/api/changelog").to_async(changelog::get_changelog))
+                .service(web::resource("/api/namespaces").to_async(namespaces::get_namespaces))
                 .service(web::resource("/api/health").to_async(modules::get_health))
+                .service(
+                    web::resource("/api/preferences")
+                        .route(web::get().to(preferences::get_preferences))
+                        .route(web::put().to(preferences::set_preferences)),
+                )
                 .service(web::resource("/api/provisioning-state").to(status::get_state))
+                .service(web::resource("/api/provisioning/status").to(provisioning::get_status))
                 .service(web::resource("/api/connectivity").to(status::get_connectivity))
                 .service(web::resource("/api/diagnostics").to(status::get_diagnostics))
+                .service(web::resource("/api/system/info").to_async(system::get_system_info))
+                .service(web::resource("/api/config").to_async(config::get_device_config))
+                .service(
+                    web::resource("/api/certificates").to_async(certificates::get_certificates),
+                )
+                .service(
+                    web::resource("/api/simulate/module_failure")
+                        .route(web::post().to(simulation::simulate_module_failure)),
+                )
         })
         .bind(address)?
         .run()?;
@@ -145,6 +260,18 @@ fn get_default_config_path() -> PathBuf {
     }
 }
 
+// A token given on the command line or in the environment wins over the file.
+fn get_api_token(settings: &Settings) -> Result<Option<String>, Error> {
+    if let Some(ref token) = settings.api_token {
+        return Ok(Some(token.clone()));
+    }
+
+    match settings.api_token_file {
+        Some(ref path) => Ok(Some(fs::read_to_string(path)?.trim().to_string())),
+        None => Ok(None),
+    }
+}
+
 fn get_config(config_path: Option<&str>) -> Result<DockerSettings, Error> {
     let config_path = config_path
         .map(|p| Path::new(p).to_owned())
@@ -1,9 +1,24 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::env;
+
 use edge_dashboard::{Context, Error, Main};
+use env_logger::Builder;
+use log::LevelFilter;
 
 fn main() -> Result<(), Error> {
-    let context = Context::new();
+    init_logging();
+
+    let context = Context::new()?;
     let app = Main::new(context);
     app.run()
 }
+
+fn init_logging() {
+    let mut builder = Builder::new();
+    builder.filter(None, LevelFilter::Info);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+    builder.init();
+}
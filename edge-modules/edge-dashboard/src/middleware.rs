@@ -0,0 +1,434 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::{Duration, Instant};
+
+use actix_web::body::{Body, ResponseBody};
+use actix_web::dev::{ResponseHead, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE,
+};
+use actix_web::http::StatusCode;
+use actix_web::{Error as ActixError, HttpMessage, HttpRequest, ResponseError};
+use bytes::Bytes;
+use edgelet_http_mgmt::{
+    ModuleClient, RetryConfig, RetryingModuleClient, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_REQUEST_TIMEOUT,
+};
+use futures::future::{ok, Either, FutureResult};
+use futures::{Future, Poll};
+use log::info;
+use openssl::memcmp;
+use serde_json::{json, Value as JsonValue};
+use url::Url;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// Identifies a dashboard request, and the management API requests made for
+/// it, in the logs of both the dashboard and iotedged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorrelationId(String);
+
+impl CorrelationId {
+    fn new() -> Self {
+        CorrelationId(Uuid::new_v4().to_string())
+    }
+
+    /// The ID the `Correlation` middleware gave the request.
+    pub fn of(req: &HttpRequest) -> Option<Self> {
+        req.extensions().get::<CorrelationId>().cloned()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// How long management API clients wait to connect to iotedged, and for
+/// iotedged to answer a request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientTimeouts {
+    connect: Duration,
+    request: Duration,
+}
+
+impl ClientTimeouts {
+    pub fn new(connect: Duration, request: Duration) -> Self {
+        ClientTimeouts { connect, request }
+    }
+
+    pub fn connect(&self) -> Duration {
+        self.connect
+    }
+
+    pub fn request(&self) -> Duration {
+        self.request
+    }
+}
+
+impl Default for ClientTimeouts {
+    fn default() -> Self {
+        ClientTimeouts::new(DEFAULT_CONNECT_TIMEOUT, DEFAULT_REQUEST_TIMEOUT)
+    }
+}
+
+/// A management API client which passes the correlation ID of the dashboard
+/// request on to iotedged, and retries requests while iotedged is restarting.
+pub fn module_client(
+    url: &Url,
+    timeouts: ClientTimeouts,
+    correlation_id: Option<&CorrelationId>,
+) -> Result<RetryingModuleClient, ActixError> {
+    let mut headers = HeaderMap::new();
+    if let Some(correlation_id) = correlation_id {
+        let value =
+            HeaderValue::from_str(correlation_id.as_str()).map_err(ErrorInternalServerError)?;
+        headers.insert(HeaderName::from_static(CORRELATION_ID_HEADER), value);
+    }
+    ModuleClient::with_headers_timeout_and_retry(
+        url,
+        headers,
+        timeouts.connect,
+        timeouts.request,
+        RetryConfig::default(),
+    )
+    .map_err(ErrorInternalServerError)
+}
+
+/// Gives every request a correlation ID, which is returned in the
+/// `X-Correlation-Id` header and added to error bodies, and logs each request
+/// once it has been handled.
+pub struct Correlation;
+
+impl<S> Transform<S> for Correlation
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = ActixError>,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = CorrelationMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CorrelationMiddleware { service })
+    }
+}
+
+pub struct CorrelationMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service for CorrelationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = ActixError>,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = ActixError;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let correlation_id = CorrelationId::new();
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        req.extensions_mut().insert(correlation_id.clone());
+
+        Box::new(self.service.call(req).map(move |res| {
+            let mut res = res.map_body(|head, body| error_body(head, body, &correlation_id));
+            if let Ok(value) = HeaderValue::from_str(correlation_id.as_str()) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(CORRELATION_ID_HEADER), value);
+            }
+
+            let elapsed = started.elapsed();
+            info!(
+                "method={} path={} status={} latency_ms={} correlation_id={}",
+                method,
+                path,
+                res.status().as_u16(),
+                elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+                correlation_id.as_str()
+            );
+            res
+        }))
+    }
+}
+
+// Error bodies become JSON carrying the correlation ID. A JSON object body gets
+// the ID added, anything else is kept as the message. Streamed bodies are left
+// alone.
+fn error_body(
+    head: &mut ResponseHead,
+    body: ResponseBody<Body>,
+    correlation_id: &CorrelationId,
+) -> ResponseBody<Body> {
+    if !head.status.is_client_error() && !head.status.is_server_error() {
+        return body;
+    }
+
+    let bytes = match body {
+        ResponseBody::Body(Body::Bytes(ref bytes))
+        | ResponseBody::Other(Body::Bytes(ref bytes)) => bytes.clone(),
+        ResponseBody::Body(Body::Empty)
+        | ResponseBody::Body(Body::None)
+        | ResponseBody::Other(Body::Empty)
+        | ResponseBody::Other(Body::None) => Bytes::new(),
+        _ => return body,
+    };
+
+    let mut error = match serde_json::from_slice(&bytes) {
+        Ok(JsonValue::Object(error)) => JsonValue::Object(error),
+        _ => json!({ "message": String::from_utf8_lossy(&bytes) }),
+    };
+    error["correlationId"] = json!(correlation_id.as_str());
+
+    head.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    ResponseBody::Body(Body::from(error.to_string()))
+}
+
+/// Rejects requests which don't carry the dashboard's API token in an
+/// `Authorization: Bearer` header, with 401 when there is no token and 403 when
+/// it doesn't match. Requests to public paths, and all requests when no token
+/// is configured, are let through.
+pub struct BearerAuth {
+    token: Option<String>,
+    public_paths: Vec<String>,
+}
+
+impl BearerAuth {
+    pub fn new(token: Option<String>) -> Self {
+        BearerAuth {
+            token,
+            public_paths: Vec::new(),
+        }
+    }
+
+    pub fn with_public_path(mut self, path: &str) -> Self {
+        self.public_paths.push(path.to_string());
+        self
+    }
+}
+
+impl<S> Transform<S> for BearerAuth
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = ActixError>,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = BearerAuthMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(BearerAuthMiddleware {
+            service,
+            token: self.token.clone(),
+            public_paths: self.public_paths.clone(),
+        })
+    }
+}
+
+pub struct BearerAuthMiddleware<S> {
+    service: S,
+    token: Option<String>,
+    public_paths: Vec<String>,
+}
+
+impl<S> Service for BearerAuthMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = ActixError>,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = ActixError;
+    type Future = Either<S::Future, FutureResult<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let public = self.public_paths.iter().any(|path| path == req.path());
+        let result = match self.token {
+            Some(ref token) if !public => authorize(&req, token),
+            _ => Ok(()),
+        };
+
+        match result {
+            Ok(()) => Either::A(self.service.call(req)),
+            Err(error) => {
+                let mut response = error.error_response();
+                if response.status() == StatusCode::UNAUTHORIZED {
+                    response
+                        .headers_mut()
+                        .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                }
+                Either::B(ok(req.into_response(response)))
+            }
+        }
+    }
+}
+
+fn authorize(req: &ServiceRequest, token: &str) -> Result<(), ApiError> {
+    let bearer = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token)
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "A bearer token is required"))?;
+
+    // compared in constant time, so the token can't be guessed from timings
+    if bearer.len() == token.len() && memcmp::eq(bearer.as_bytes(), token.as_bytes()) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "The bearer token is not valid",
+        ))
+    }
+}
+
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let mut parts = authorization.splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("Bearer") => Some(token.trim()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::{Body, ResponseBody};
+    use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+    use serde_json::Value as JsonValue;
+
+    use super::{BearerAuth, Correlation, CORRELATION_ID_HEADER};
+
+    fn body_json(response: &actix_web::dev::ServiceResponse<Body>) -> JsonValue {
+        match response.response().body() {
+            ResponseBody::Body(Body::Bytes(bytes)) | ResponseBody::Other(Body::Bytes(bytes)) => {
+                serde_json::from_slice(bytes).unwrap()
+            }
+            _ => panic!("expected a body"),
+        }
+    }
+
+    #[test]
+    fn error_responses_carry_correlation_id() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(Correlation)
+                .route("/ok", web::get().to(|| HttpResponse::Ok().body("fine")))
+                .route(
+                    "/text",
+                    web::get().to(|| HttpResponse::BadRequest().body("Invalid module ID")),
+                )
+                .route(
+                    "/json",
+                    web::get().to(|| {
+                        HttpResponse::ServiceUnavailable()
+                            .json(serde_json::json!({ "health": "Unknown" }))
+                    }),
+                ),
+        );
+
+        let response =
+            test::call_service(&mut app, test::TestRequest::with_uri("/ok").to_request());
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(response.headers().contains_key(CORRELATION_ID_HEADER));
+
+        let response =
+            test::call_service(&mut app, test::TestRequest::with_uri("/text").to_request());
+        let id = response.headers()[CORRELATION_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let error = body_json(&response);
+        assert_eq!("Invalid module ID", error["message"]);
+        assert_eq!(id, error["correlationId"]);
+
+        let response =
+            test::call_service(&mut app, test::TestRequest::with_uri("/json").to_request());
+        let id = response.headers()[CORRELATION_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let error = body_json(&response);
+        assert_eq!("Unknown", error["health"]);
+        assert_eq!(id, error["correlationId"]);
+    }
+
+    #[test]
+    fn protected_routes_require_bearer_token() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(BearerAuth::new(Some("secret".to_string())).with_public_path("/public"))
+                .route(
+                    "/protected",
+                    web::get().to(|| HttpResponse::Ok().body("fine")),
+                )
+                .route("/public", web::get().to(|| HttpResponse::Ok().body("fine"))),
+        );
+
+        let response = test::call_service(
+            &mut app,
+            test::TestRequest::with_uri("/protected").to_request(),
+        );
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!("Bearer", response.headers()[WWW_AUTHENTICATE]);
+        assert_eq!(401, body_json(&response)["code"]);
+
+        let response = test::call_service(
+            &mut app,
+            test::TestRequest::with_uri("/protected")
+                .header(AUTHORIZATION, "Bearer wrong")
+                .to_request(),
+        );
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        assert_eq!(403, body_json(&response)["code"]);
+
+        let response = test::call_service(
+            &mut app,
+            test::TestRequest::with_uri("/protected")
+                .header(AUTHORIZATION, "Bearer secret")
+                .to_request(),
+        );
+        assert_eq!(StatusCode::OK, response.status());
+
+        let response = test::call_service(
+            &mut app,
+            test::TestRequest::with_uri("/public").to_request(),
+        );
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn no_token_is_required_when_none_is_configured() {
+        let mut app = test::init_service(App::new().wrap(BearerAuth::new(None)).route(
+            "/protected",
+            web::get().to(|| HttpResponse::Ok().body("fine")),
+        ));
+
+        let response = test::call_service(
+            &mut app,
+            test::TestRequest::with_uri("/protected").to_request(),
+        );
+        assert_eq!(StatusCode::OK, response.status());
+    }
+}
@@ -1,31 +1,88 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use actix_web::error::ErrorInternalServerError;
 use actix_web::Error as ActixError;
 use actix_web::*;
-use edgelet_core::{LogOptions, Module as EdgeModule, ModuleRuntime, RuntimeSettings};
-use edgelet_http_mgmt::*;
-use futures::future::{ok, Either, IntoFuture};
+use bytes::Bytes;
+use chrono::DateTime;
+use edgelet_core::{LogOptions, LogTail, Module as EdgeModule, ModuleRuntime, RuntimeSettings};
+use futures::future::{join_all, Either, IntoFuture};
 use futures::stream::Stream;
 use futures::{Async, Future};
+use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::timer::Interval;
 use url::Url;
 
-use crate::health::Status;
+use crate::error::{internal_error, ApiError};
+use crate::health::{HealthStatus, Status};
+use crate::middleware::{module_client, CorrelationId};
+use crate::simulation::SimulatedFailures;
+use crate::stats::ModuleStats;
 use crate::AuthRequest;
 use crate::Context;
 
+// lines of a module's log returned when the request doesn't say how much it wants
+const DEFAULT_LOG_TAIL: u64 = 200;
+
+// seconds a module is given to stop before it is killed, unless the request says otherwise
+const DEFAULT_STOP_TIMEOUT_SECS: u32 = 10;
+
+// modules in a page of `get_modules`, unless the request says otherwise
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+// environment variables set by the edge runtime that must not be overwritten
+const RESERVED_ENV_KEYS: &[&str] = &[
+    "EdgeHubConnectionString",
+    "EdgeModuleCACertificateFile",
+    "IOTEDGE_APIVERSION",
+    "IOTEDGE_AUTHSCHEME",
+    "IOTEDGE_DEVICEID",
+    "IOTEDGE_GATEWAYHOSTNAME",
+    "IOTEDGE_IOTHUBHOSTNAME",
+    "IOTEDGE_MODULEGENERATIONID",
+    "IOTEDGE_MODULEID",
+    "IOTEDGE_WORKLOADURI",
+];
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Module {
     name: String,
     status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu: Option<f64>,
+    #[serde(rename = "memoryInMb", skip_serializing_if = "Option::is_none")]
+    memory_in_mb: Option<u64>,
 }
 
 impl Module {
     pub fn new(name: String, status: String) -> Self {
-        Module { name, status }
+        Module {
+            name,
+            status,
+            cpu: None,
+            memory_in_mb: None,
+        }
+    }
+
+    pub fn with_stats(mut self, stats: Option<&ModuleStats>) -> Self {
+        self.cpu = stats.map(ModuleStats::cpu_percent);
+        self.memory_in_mb = stats.map(|stats| stats.memory_mb().round() as u64);
+        self
+    }
+
+    /// Reports the module as failed while a failure is simulated for it.
+    pub fn with_simulated_failure(mut self, failures: &SimulatedFailures) -> Self {
+        if failures.is_failed(&self.name) {
+            self.status = "failed".to_string();
+        }
+        self
     }
 
     pub fn name(&self) -> &String {
@@ -35,6 +92,16 @@ impl Module {
     pub fn status(&self) -> &String {
         &self.status
     }
+
+    /// CPU usage in percent, when the container runtime reported it.
+    pub fn cpu(&self) -> Option<f64> {
+        self.cpu
+    }
+
+    /// Memory usage, when the container runtime reported it.
+    pub fn memory_in_mb(&self) -> Option<u64> {
+        self.memory_in_mb
+    }
 }
 
 pub fn restart_module(
@@ -42,6 +109,8 @@ pub fn restart_module(
     context: web::Data<Arc<Context>>,
     info: web::Query<AuthRequest>,
 ) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
     let api_ver = &info.api_version;
     let response = req
         .match_info()
@@ -54,39 +123,270 @@ pub fn restart_module(
                     let mgmt_uri = config.connect().management_uri();
                     Either::A(
                         Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
-                            .map_err(ErrorInternalServerError)
-                            .and_then(|url| {
-                                ModuleClient::new(&url).map_err(ErrorInternalServerError)
-                            })
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
                             .map(|mod_client| {
                                 mod_client
                                     .restart(module_id)
-                                    .map_err(ErrorInternalServerError)
-                                    .map(|_| {
-                                        HttpResponse::Ok().body(format!("Module has restarted"))
-                                    })
+                                    .map_err(internal_error)
+                                    .map(|_| HttpResponse::Ok().body("Module has restarted"))
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+pub fn start_module(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                mod_client
+                                    .start(module_id)
+                                    .map_err(internal_error)
+                                    .map(|_| HttpResponse::Ok().body("Module has started"))
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StopQuery {
+    timeout: Option<u32>,
+}
+
+pub fn stop_module(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+    query: web::Query<StopQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let timeout = Duration::from_secs(query.timeout.unwrap_or(DEFAULT_STOP_TIMEOUT_SECS).into());
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                mod_client
+                                    .stop(module_id, Some(timeout))
+                                    .map_err(internal_error)
+                                    .map(|_| HttpResponse::Ok().body("Module has stopped"))
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+pub fn update_env(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+    env: web::Json<HashMap<String, String>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let env = env.into_inner();
+
+    if let Some(key) = env
+        .keys()
+        .find(|key| RESERVED_ENV_KEYS.contains(&key.as_str()))
+    {
+        return Box::new(
+            ApiError::bad_request(format!(
+                "Environment variable {} is reserved and cannot be updated",
+                key
+            ))
+            .into_future(),
+        );
+    }
+
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                mod_client
+                                    .update_env(module_id, env)
+                                    .map_err(internal_error)
+                                    .map(|_| HttpResponse::Accepted().finish())
                             })
                             .into_future()
                             .flatten(),
                     )
                 })
-                .unwrap_or_else(|err| {
-                    Either::B(ok(HttpResponse::ServiceUnavailable()
-                        .content_type("text/plain")
-                        .body(format!("{:?}", err))))
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyRequest {
+    new_id: String,
+    #[serde(default)]
+    env_overrides: BTreeMap<String, String>,
+}
+
+pub fn copy_module(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+    body: web::Json<CopyRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let body = body.into_inner();
+
+    if body.new_id.trim().is_empty() {
+        return Box::new(ApiError::bad_request("New module ID must not be empty").into_future());
+    }
+    if let Some(key) = body
+        .env_overrides
+        .keys()
+        .find(|key| RESERVED_ENV_KEYS.contains(&key.as_str()))
+    {
+        return Box::new(
+            ApiError::bad_request(format!(
+                "Environment variable {} is reserved and cannot be updated",
+                key
+            ))
+            .into_future(),
+        );
+    }
+
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                mod_client
+                                    .copy(module_id, &body.new_id, body.env_overrides)
+                                    .map_err(internal_error)
+                                    .map(|details| HttpResponse::Created().json(details))
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
                 })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
         })
-        .unwrap_or_else(|| Either::B(ok(HttpResponse::BadRequest().body("Invalid module ID"))));
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
 
     Box::new(response)
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct LogQuery {
+    tail: Option<u64>,
+    since: Option<String>,
+}
+
+// Without either parameter only the last DEFAULT_LOG_TAIL lines are returned,
+// since busy modules have logs far too large to send whole.
+fn log_options(query: &LogQuery) -> Result<LogOptions, String> {
+    let since = query
+        .since
+        .as_ref()
+        .map(|since| {
+            DateTime::parse_from_rfc3339(since)
+                .map(|since| since.timestamp() as i32)
+                .map_err(|_| format!("Invalid since timestamp {:?}, expected RFC 3339", since))
+        })
+        .transpose()?;
+
+    let tail = match (query.tail, since) {
+        (Some(tail), _) => LogTail::Num(tail),
+        (None, Some(_)) => LogTail::All,
+        (None, None) => LogTail::Num(DEFAULT_LOG_TAIL),
+    };
+
+    Ok(LogOptions::new()
+        .with_tail(tail)
+        .with_since(since.unwrap_or_default()))
+}
+
 pub fn get_logs(
     req: HttpRequest,
     context: web::Data<Arc<Context>>,
     info: web::Query<AuthRequest>,
+    query: web::Query<LogQuery>,
 ) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
     let api_ver = &info.api_version;
+    let options = match log_options(&query) {
+        Ok(options) => options,
+        Err(message) => return Box::new(ApiError::bad_request(message).into_future()),
+    };
 
     let response = req
         .match_info()
@@ -99,16 +399,14 @@ pub fn get_logs(
                     let mgmt_uri = config.connect().management_uri();
                     Either::A(
                         Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
-                            .map_err(ErrorInternalServerError)
-                            .and_then(|url| {
-                                ModuleClient::new(&url).map_err(ErrorInternalServerError)
-                            }) // can't connect to the endpoint
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref())) // can't connect to the endpoint
                             .map(move |mod_client| {
                                 mod_client
-                                    .logs(module_id, &LogOptions::new())
-                                    .map_err(ErrorInternalServerError)
+                                    .logs(module_id, &options)
+                                    .map_err(internal_error)
                                     .and_then(|data| {
-                                        data.map_err(ErrorInternalServerError)
+                                        data.map_err(internal_error)
                                             .fold(Vec::new(), |mut acc, chunk| {
                                                 let stream = chunk.as_ref();
                                                 if stream.len() >= 8 {
@@ -123,8 +421,11 @@ pub fn get_logs(
                                                 if let Ok(content) = String::from_utf8(clone) {
                                                     HttpResponse::Ok().body(content)
                                                 } else {
-                                                    HttpResponse::ServiceUnavailable()
-                                                        .body("Logs unable to be displayed")
+                                                    ApiError::new(
+                                                        StatusCode::SERVICE_UNAVAILABLE,
+                                                        "Logs unable to be displayed",
+                                                    )
+                                                    .error_response()
                                                 }
                                             })
                                     })
@@ -133,35 +434,579 @@ pub fn get_logs(
                             .flatten(),
                     )
                 })
-                .unwrap_or_else(|err| {
-                    Either::B(ok(HttpResponse::ServiceUnavailable()
-                        .content_type("text/plain")
-                        .body(format!("{:?}", err))))
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LogLine {
+    timestamp: Option<String>,
+    stream: &'static str,
+    line: String,
+}
+
+// Splits a Docker log stream into its lines. Each frame has an 8 byte header
+// holding the stream type in its first byte and the big-endian length of the
+// payload in its last four. A leading RFC 3339 timestamp is split out of a
+// line; a truncated frame at the end is dropped.
+fn parse_log_lines(mut body: &[u8]) -> Vec<LogLine> {
+    let mut lines = Vec::new();
+    while body.len() >= 8 {
+        let (header, rest) = body.split_at(8);
+        let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if rest.len() < len {
+            break;
+        }
+        let (payload, rest) = rest.split_at(len);
+        body = rest;
+
+        let stream = if header[0] == 2 { "stderr" } else { "stdout" };
+        for line in String::from_utf8_lossy(payload).lines() {
+            let (timestamp, line) = match line.find(' ') {
+                Some(index) if DateTime::parse_from_rfc3339(&line[..index]).is_ok() => {
+                    (Some(line[..index].to_string()), &line[index + 1..])
+                }
+                _ => (None, line),
+            };
+            lines.push(LogLine {
+                timestamp,
+                stream,
+                line: line.to_string(),
+            });
+        }
+    }
+    lines
+}
+
+pub fn get_log_lines(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+    query: web::Query<LogQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let options = match log_options(&query) {
+        Ok(options) => options,
+        Err(message) => return Box::new(ApiError::bad_request(message).into_future()),
+    };
+
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(move |config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(move |mod_client| {
+                                mod_client
+                                    .logs(module_id, &options)
+                                    .map_err(internal_error)
+                                    .and_then(|data| {
+                                        data.map_err(internal_error)
+                                            .fold(Vec::new(), |mut acc, chunk| {
+                                                acc.extend_from_slice(chunk.as_ref());
+                                                Ok::<_, ActixError>(acc)
+                                            })
+                                            .map(|body| {
+                                                HttpResponse::Ok().json(parse_log_lines(&body))
+                                            })
+                                    })
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ClearLogsQuery {
+    confirm: Option<String>,
+}
+
+// Clearing a module's logs throws away whatever they could tell about a
+// problem, so the request has to confirm it by repeating the module ID in
+// `confirm`. Only logs the container runtime keeps in a file can be cleared.
+pub fn clear_logs(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+    query: web::Query<ClearLogsQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            if query.confirm.as_ref().map(String::as_str) != Some(module_id) {
+                return Either::B(
+                    ApiError::bad_request(
+                        "Confirm clearing the logs by passing the module ID as the confirm parameter",
+                    )
+                    .into_future(),
+                );
+            }
+
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                mod_client.clear_logs(module_id).then(|result| match result {
+                                    Ok(()) => Ok(HttpResponse::NoContent().finish()),
+                                    Err(ref err) if err.is_not_found() => {
+                                        Err(ApiError::not_found("Module not found").into())
+                                    }
+                                    Err(ref err) if err.is_conflict() => Err(ApiError::new(
+                                        StatusCode::CONFLICT,
+                                        "Module's log driver keeps no log file",
+                                    )
+                                    .into()),
+                                    Err(err) => Err(internal_error(err)),
+                                })
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
                 })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
         })
-        .unwrap_or_else(|| Either::B(ok(HttpResponse::BadRequest().body("Invalid module ID"))));
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
 
     Box::new(response)
 }
 
+// Each chunk of a followed log is one frame of the Docker log stream, so its
+// 8 byte header is dropped and every line in it is sent as its own event.
+pub fn stream_logs(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(move |config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(move |mod_client| {
+                                mod_client
+                                    .logs(module_id, &LogOptions::new().with_follow(true))
+                                    .map_err(internal_error)
+                                    .map(|data| {
+                                        let events = data
+                                            .map(|chunk| sse_events(chunk.as_ref()))
+                                            .map_err(internal_error);
+                                        HttpResponse::Ok()
+                                            .content_type("text/event-stream")
+                                            .header("Cache-Control", "no-cache")
+                                            .streaming(events)
+                                    })
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+fn sse_events(frame: &[u8]) -> Bytes {
+    let mut events = String::new();
+    if frame.len() >= 8 {
+        let (_, log) = frame.split_at(8);
+        for line in String::from_utf8_lossy(log).lines() {
+            events.push_str(&format!("data: {}\n\n", line));
+        }
+    }
+    Bytes::from(events)
+}
+
+// Status reported in module_update events for modules which have gone away
+const REMOVED_MODULE_STATUS: &str = "removed";
+
+// Lists the modules every `module_events_interval` seconds and sends a
+// module_update event for each module added, removed, or whose status changed
+// since the last check. An event's ID identifies the state of all modules
+// after it, so a client reconnecting with the ID of the last event it saw
+// isn't sent every module again unless something has changed.
+pub fn stream_module_events(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let interval = Duration::from_secs(context.settings.module_events_interval);
+    let failures = context.simulated_failures.clone();
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok())
+        .map(ToOwned::to_owned);
+
+    let response = context
+        .edge_config
+        .as_ref()
+        .map(move |config| {
+            let mgmt_uri = config.connect().management_uri();
+            Either::A(
+                Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                    .map_err(internal_error)
+                    .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                    .map(move |mod_client| {
+                        let mut previous: Option<BTreeMap<String, String>> = None;
+                        let events = Interval::new(Instant::now(), interval)
+                            .map_err(internal_error)
+                            .and_then(move |_| mod_client.list().map_err(internal_error))
+                            .map(move |data| {
+                                let current: BTreeMap<String, String> = data
+                                    .iter()
+                                    .map(to_module)
+                                    .map(|module| module.with_simulated_failure(&failures))
+                                    .map(|module| (module.name, module.status))
+                                    .collect();
+                                let id = snapshot_id(&current);
+                                let updates = match previous.take() {
+                                    Some(previous) => module_updates(&previous, &current),
+                                    None if last_event_id.as_ref() == Some(&id) => Vec::new(),
+                                    None => module_updates(&BTreeMap::new(), &current),
+                                };
+                                previous = Some(current);
+                                module_events(&updates, &id)
+                            })
+                            .filter(|events| !events.is_empty());
+                        HttpResponse::Ok()
+                            .content_type("text/event-stream")
+                            .header("Cache-Control", "no-cache")
+                            .streaming(events)
+                    })
+                    .into_future(),
+            )
+        })
+        .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()));
+
+    Box::new(response)
+}
+
+fn module_updates(
+    previous: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> Vec<Module> {
+    let changed = current
+        .iter()
+        .filter(|(name, status)| previous.get(*name) != Some(status))
+        .map(|(name, status)| Module::new(name.clone(), status.clone()));
+    let removed = previous
+        .keys()
+        .filter(|name| !current.contains_key(*name))
+        .map(|name| Module::new(name.clone(), REMOVED_MODULE_STATUS.to_string()));
+    changed.chain(removed).collect()
+}
+
+fn snapshot_id(modules: &BTreeMap<String, String>) -> String {
+    let mut hasher = DefaultHasher::new();
+    modules.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+// Only the last event of a batch carries the ID, since the ID describes the
+// state once all of them have been applied.
+fn module_events(updates: &[Module], id: &str) -> Bytes {
+    let mut events = String::new();
+    for (i, module) in updates.iter().enumerate() {
+        if i == updates.len() - 1 {
+            events.push_str(&format!("id: {}\n", id));
+        }
+        events.push_str("event: module_update\n");
+        events.push_str(&format!(
+            "data: {}\n\n",
+            serde_json::to_string(module).unwrap_or_default()
+        ));
+    }
+    Bytes::from(events)
+}
+
+fn to_module<M: EdgeModule>(module: &M) -> Module {
+    let status = if let Ok(Async::Ready(t)) = module.runtime_state().poll() {
+        (*(t.status().clone()).to_string()).to_string()
+    } else {
+        "".to_string()
+    };
+    Module::new(module.name().to_string(), status)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    page: Option<usize>,
+    page_size: Option<usize>,
+}
+
+/// One page of a list. `page` is 1-based and `total` counts the items on all
+/// pages.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PagedResponse<T> {
+    items: Vec<T>,
+    total: usize,
+    page: usize,
+    page_size: usize,
+}
+
+impl<T> PagedResponse<T> {
+    pub fn new(all: Vec<T>, page: usize, page_size: usize) -> Self {
+        let total = all.len();
+        let items = all
+            .into_iter()
+            .skip((page - 1).saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+
+        PagedResponse {
+            items,
+            total,
+            page,
+            page_size,
+        }
+    }
+}
+
+// iotedged always lists every module, so the page is cut out here. CPU and
+// memory usage are only read for the modules on the page, as the container
+// runtime takes a moment to sample each of them.
 pub fn get_modules(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+    query: web::Query<PageQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let page = query.page.unwrap_or(1);
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page == 0 || page_size == 0 {
+        return Box::new(
+            ApiError::bad_request("page and page_size must be at least 1").into_future(),
+        );
+    }
+
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let failures = context.simulated_failures.clone();
+    let api_ver = &info.api_version;
+    let response = context
+        .edge_config
+        .as_ref()
+        .map(move |config| {
+            let mgmt_uri = config.connect().management_uri();
+            Either::A(
+                Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                    .map_err(internal_error)
+                    .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                    .map(move |mod_client| {
+                        mod_client
+                            .list()
+                            .map_err(internal_error)
+                            .and_then(move |data| {
+                                let mods = data
+                                    .iter()
+                                    .map(to_module)
+                                    .map(|module| module.with_simulated_failure(&failures))
+                                    .collect();
+                                with_stats(&mod_client, PagedResponse::new(mods, page, page_size))
+                            })
+                            .map(module_response)
+                    })
+                    .into_future()
+                    .flatten()
+                    .or_else(|err| Ok(error_response(err))),
+            )
+        })
+        .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()));
+
+    Box::new(response)
+}
+
+pub fn get_module_by_id(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let failures = context.simulated_failures.clone();
+    let api_ver = &info.api_version;
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                let stats = mod_client.stats(module_id).then(|result| {
+                                    Ok::<_, ActixError>(
+                                        result.ok().map(|stats| ModuleStats::from(&stats)),
+                                    )
+                                });
+                                mod_client
+                                    .get(module_id)
+                                    .map_err(internal_error)
+                                    .join(stats)
+                                    .and_then(move |(module, stats)| match module {
+                                        Some(module) => Ok(HttpResponse::Ok().json(
+                                            to_module(&module)
+                                                .with_stats(stats.as_ref())
+                                                .with_simulated_failure(&failures),
+                                        )),
+                                        None => Err(ApiError::not_found(format!(
+                                            "Module {} not found",
+                                            module_id
+                                        ))
+                                        .into()),
+                                    })
+                            })
+                            .into_future()
+                            .flatten()
+                            .or_else(|err| Ok(error_response(err))),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
+
+// Modules whose usage can't be read, because they aren't running or the
+// runtime doesn't report it, are still listed, just without figures.
+fn with_stats<M>(
+    client: &M,
+    paged: PagedResponse<Module>,
+) -> impl Future<Item = PagedResponse<Module>, Error = ActixError>
+where
+    M: ModuleRuntime,
+{
+    let PagedResponse {
+        items,
+        total,
+        page,
+        page_size,
+    } = paged;
+    let items = items
+        .into_iter()
+        .map(|module| {
+            client.stats(module.name()).then(move |result| {
+                let stats = result.ok().map(|stats| ModuleStats::from(&stats));
+                Ok::<_, ActixError>(module.with_stats(stats.as_ref()))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    join_all(items).map(move |items| PagedResponse {
+        items,
+        total,
+        page,
+        page_size,
+    })
+}
+
+fn module_response(paged: PagedResponse<Module>) -> HttpResponse {
+    HttpResponse::Ok().json(paged)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ModuleSummary {
+    total: usize,
+    running: usize,
+    stopped: usize,
+    failed: usize,
+    other: usize,
+}
+
+pub fn get_summary(
+    req: HttpRequest,
     context: web::Data<Arc<Context>>,
     info: web::Query<AuthRequest>,
 ) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
-    return_modules(context, &info.api_version, module_response)
+    return_modules(
+        context,
+        &info.api_version,
+        CorrelationId::of(&req),
+        summary_response,
+        error_response,
+    )
 }
 
-fn module_response(mods: Vec<Module>) -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type("text/json")
-        .body(format!("{:?}", mods))
+fn summary_response(mods: Vec<Module>) -> HttpResponse {
+    let summary = mods
+        .iter()
+        .fold(ModuleSummary::default(), |mut summary, module| {
+            summary.total += 1;
+            match module.status().as_str() {
+                "running" => summary.running += 1,
+                "stopped" => summary.stopped += 1,
+                "failed" => summary.failed += 1,
+                _ => summary.other += 1,
+            }
+            summary
+        });
+
+    HttpResponse::Ok().json(summary)
 }
 
 pub fn get_health(
+    req: HttpRequest,
     context: web::Data<Arc<Context>>,
     info: web::Query<AuthRequest>,
 ) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
-    return_modules(context, &info.api_version, health_response)
+    return_modules(
+        context,
+        &info.api_version,
+        CorrelationId::of(&req),
+        health_response,
+        unknown_health_response,
+    )
 }
 
 fn health_response(mods: Vec<Module>) -> HttpResponse {
@@ -188,11 +1033,25 @@ fn health_response(mods: Vec<Module>) -> HttpResponse {
     ))
 }
 
+// Unlike an unhealthy device, a device whose modules couldn't be listed has
+// unknown health, so callers can tell the two apart.
+fn unknown_health_response(err: ActixError) -> HttpResponse {
+    HttpResponse::ServiceUnavailable().json(HealthStatus::unknown(err.to_string()))
+}
+
+fn error_response(err: ActixError) -> HttpResponse {
+    err.into()
+}
+
 fn return_modules(
     context: web::Data<Arc<Context>>,
     api_ver: &str,
+    correlation_id: Option<CorrelationId>,
     f: fn(Vec<Module>) -> HttpResponse,
+    on_error: fn(ActixError) -> HttpResponse,
 ) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let timeouts = context.client_timeouts;
+    let failures = context.simulated_failures.clone();
     let response = context
         .edge_config
         .as_ref()
@@ -200,37 +1059,596 @@ fn return_modules(
             let mgmt_uri = config.connect().management_uri();
             Either::A(
                 Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
-                    .map_err(ErrorInternalServerError)
-                    .and_then(|url| ModuleClient::new(&url).map_err(ErrorInternalServerError))
+                    .map_err(internal_error)
+                    .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
                     .map(|mod_client| {
                         mod_client
                             .list()
                             .map(move |data| {
                                 let mods: Vec<Module> = data
                                     .iter()
-                                    .map(move |c| {
-                                        let status =
-                                            if let Ok(Async::Ready(t)) = c.runtime_state().poll() {
-                                                (*(t.status().clone()).to_string()).to_string()
-                                            } else {
-                                                "".to_string()
-                                            };
-                                        Module::new(c.name().to_string(), status)
-                                    })
+                                    .map(to_module)
+                                    .map(|module| module.with_simulated_failure(&failures))
                                     .collect();
                                 f(mods) // changes depending on API call
                             })
-                            .map_err(ErrorInternalServerError)
+                            .map_err(internal_error)
                     })
                     .into_future()
-                    .flatten(),
+                    .flatten()
+                    .or_else(move |err| Ok(on_error(err))),
             )
         })
-        .unwrap_or_else(|err| {
-            Either::B(ok(HttpResponse::ServiceUnavailable()
-                .content_type("text/plain")
-                .body(format!("{:?}", err))))
-        });
+        .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()));
 
     Box::new(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use actix_web::body::Body as ActixBody;
+    use actix_web::error::ErrorInternalServerError;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use actix_web::{web, HttpResponse};
+    use edgelet_core::ModuleRuntime;
+    use edgelet_http_mgmt::ModuleClient;
+    use edgelet_test_utils::{get_unused_tcp_port, run_tcp_server};
+    use futures::{future, Future};
+    use hyper::{Body, Method, Request, Response};
+    use structopt::StructOpt;
+    use tokio::runtime::Runtime;
+    use url::Url;
+
+    use super::{
+        clear_logs, get_module_by_id, get_modules, log_options, module_events, module_response,
+        module_updates, parse_log_lines, snapshot_id, start_module, stop_module,
+        unknown_health_response, ClearLogsQuery, LogLine, LogQuery, Module, PageQuery,
+        PagedResponse, StopQuery,
+    };
+    use crate::middleware::ClientTimeouts;
+    use crate::rate_limit::RateLimiter;
+    use crate::settings::Settings;
+    use crate::simulation::SimulatedFailures;
+    use crate::{get_config, AuthRequest, Context};
+
+    // A context whose iotedged management endpoint is on the given port.
+    fn test_context(port: u16) -> web::Data<Arc<Context>> {
+        let sample = fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../edgelet/edgelet-docker/test/linux/sample_settings.yaml"
+        ))
+        .unwrap();
+        let config_path = env::temp_dir().join(format!("edge-dashboard-{}.yaml", port));
+        fs::write(
+            &config_path,
+            sample.replace(
+                "http://localhost:8080",
+                &format!("http://localhost:{}", port),
+            ),
+        )
+        .unwrap();
+
+        let context = Context {
+            edge_config: get_config(config_path.to_str()),
+            settings: Settings::from_iter(vec!["edge-dashboard", "-h", "localhost", "-p", "0"]),
+            system_info_limit: RateLimiter::new(60, Duration::from_secs(60)),
+            api_token: None,
+            client_timeouts: ClientTimeouts::default(),
+            dev_mode: false,
+            simulated_failures: Arc::new(SimulatedFailures::default()),
+            changelog: Arc::default(),
+        };
+        web::Data::new(Arc::new(context))
+    }
+
+    fn auth_query() -> web::Query<AuthRequest> {
+        web::Query(AuthRequest {
+            api_version: "2018-06-28".to_string(),
+        })
+    }
+
+    fn logs_query(query: &LogQuery) -> String {
+        let port = get_unused_tcp_port();
+        let requested = Arc::new(Mutex::new(None));
+        let requested_copy = requested.clone();
+
+        let server = run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            *requested_copy.lock().unwrap() = req.uri().query().map(ToOwned::to_owned);
+            future::ok(Response::new(Body::empty()))
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let url = Url::parse(&format!(
+            "http://localhost:{}/modules/?api-version=2018-06-28",
+            port
+        ))
+        .unwrap();
+        let client = ModuleClient::new(&url).unwrap();
+        let options = log_options(query).unwrap();
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        runtime
+            .block_on(client.logs("tempSensor", &options).map(|_| ()))
+            .unwrap();
+
+        let query = requested.lock().unwrap().clone();
+        query.unwrap()
+    }
+
+    #[test]
+    fn logs_default_to_last_lines() {
+        let query = logs_query(&LogQuery::default());
+        assert!(query.contains("tail=200"));
+        assert!(query.contains("since=0"));
+    }
+
+    #[test]
+    fn logs_use_tail_and_since() {
+        let query = logs_query(&LogQuery {
+            tail: Some(50),
+            since: Some("2019-07-01T12:00:00Z".to_string()),
+        });
+        assert!(query.contains("tail=50"));
+        assert!(query.contains("since=1561982400"));
+    }
+
+    #[test]
+    fn logs_since_without_tail_returns_all() {
+        let query = logs_query(&LogQuery {
+            tail: None,
+            since: Some("2019-07-01T12:00:00+00:00".to_string()),
+        });
+        assert!(query.contains("tail=all"));
+        assert!(query.contains("since=1561982400"));
+    }
+
+    #[test]
+    fn logs_reject_invalid_since() {
+        let query = LogQuery {
+            tail: None,
+            since: Some("yesterday".to_string()),
+        };
+        assert!(log_options(&query).is_err());
+    }
+
+    #[test]
+    fn list_failure_reports_unknown_health() {
+        let response = unknown_health_response(ErrorInternalServerError("connection refused"));
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+
+        let body = match response.body().as_ref() {
+            Some(ActixBody::Bytes(bytes)) => bytes.clone(),
+            _ => panic!("expected a JSON body"),
+        };
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Unknown", json["health"]);
+        assert_eq!("connection refused", json["error_message"]);
+    }
+
+    #[test]
+    fn start_module_starts_module() {
+        let port = get_unused_tcp_port();
+        let requested = Arc::new(Mutex::new(None));
+        let requested_copy = requested.clone();
+
+        let server = run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            *requested_copy.lock().unwrap() =
+                Some((req.method().clone(), req.uri().path().to_string()));
+            future::ok(Response::new(Body::empty()))
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let req = TestRequest::default()
+            .param("id", "tempSensor")
+            .to_http_request();
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let response = runtime
+            .block_on(start_module(req, test_context(port), auth_query()))
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            Some((Method::POST, "/modules/tempSensor/start".to_string())),
+            *requested.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn start_module_requires_module_id() {
+        let req = TestRequest::default().to_http_request();
+
+        let response = start_module(req, test_context(get_unused_tcp_port()), auth_query())
+            .wait()
+            .unwrap_or_else(HttpResponse::from);
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn stop_module_stops_module() {
+        let port = get_unused_tcp_port();
+        let requested = Arc::new(Mutex::new(None));
+        let requested_copy = requested.clone();
+
+        let server = run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            *requested_copy.lock().unwrap() =
+                Some((req.method().clone(), req.uri().path().to_string()));
+            future::ok(Response::new(Body::empty()))
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let req = TestRequest::default()
+            .param("id", "tempSensor")
+            .to_http_request();
+        let query = web::Query(StopQuery { timeout: Some(30) });
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let response = runtime
+            .block_on(stop_module(req, test_context(port), auth_query(), query))
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            Some((Method::POST, "/modules/tempSensor/stop".to_string())),
+            *requested.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn clear_logs_requires_confirmation() {
+        for confirm in &[None, Some("edgeHub".to_string())] {
+            let req = TestRequest::default()
+                .param("id", "tempSensor")
+                .to_http_request();
+            let query = web::Query(ClearLogsQuery {
+                confirm: confirm.clone(),
+            });
+
+            let response = clear_logs(
+                req,
+                test_context(get_unused_tcp_port()),
+                auth_query(),
+                query,
+            )
+            .wait()
+            .unwrap_or_else(HttpResponse::from);
+
+            assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        }
+    }
+
+    #[test]
+    fn clear_logs_clears_module_logs() {
+        let port = get_unused_tcp_port();
+        let requested = Arc::new(Mutex::new(None));
+        let requested_copy = requested.clone();
+
+        let server = run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            *requested_copy.lock().unwrap() =
+                Some((req.method().clone(), req.uri().path().to_string()));
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NO_CONTENT;
+            future::ok(response)
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let req = TestRequest::default()
+            .param("id", "tempSensor")
+            .to_http_request();
+        let query = web::Query(ClearLogsQuery {
+            confirm: Some("tempSensor".to_string()),
+        });
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let response = runtime
+            .block_on(clear_logs(req, test_context(port), auth_query(), query))
+            .unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(
+            Some((Method::DELETE, "/modules/tempSensor/logs".to_string())),
+            *requested.lock().unwrap()
+        );
+    }
+
+    fn frame(stream: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![stream, 0, 0, 0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn log_lines_are_split_by_stream() {
+        let mut body = frame(1, b"2019-07-01T12:00:00.123456789Z started\nready\n");
+        body.extend(frame(2, b"failed: \xff\n"));
+        // a truncated frame is dropped
+        body.extend(&frame(1, b"partial")[..10]);
+
+        assert_eq!(
+            vec![
+                LogLine {
+                    timestamp: Some("2019-07-01T12:00:00.123456789Z".to_string()),
+                    stream: "stdout",
+                    line: "started".to_string(),
+                },
+                LogLine {
+                    timestamp: None,
+                    stream: "stdout",
+                    line: "ready".to_string(),
+                },
+                LogLine {
+                    timestamp: None,
+                    stream: "stderr",
+                    line: "failed: \u{fffd}".to_string(),
+                },
+            ],
+            parse_log_lines(&body)
+        );
+    }
+
+    fn snapshot(modules: &[(&str, &str)]) -> BTreeMap<String, String> {
+        modules
+            .iter()
+            .map(|(name, status)| (name.to_string(), status.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn module_updates_include_changes_and_removals() {
+        let previous = snapshot(&[
+            ("edgeAgent", "running"),
+            ("edgeHub", "running"),
+            ("tempSensor", "running"),
+        ]);
+        let current = snapshot(&[
+            ("edgeAgent", "running"),
+            ("edgeHub", "failed"),
+            ("filter", "running"),
+        ]);
+
+        let updates: Vec<(String, String)> = module_updates(&previous, &current)
+            .into_iter()
+            .map(|module| (module.name().clone(), module.status().clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("edgeHub".to_string(), "failed".to_string()),
+                ("filter".to_string(), "running".to_string()),
+                ("tempSensor".to_string(), "removed".to_string()),
+            ],
+            updates
+        );
+        assert!(module_updates(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn module_events_are_sse_frames() {
+        let current = snapshot(&[("edgeAgent", "running"), ("edgeHub", "running")]);
+        let id = snapshot_id(&current);
+        assert_ne!(id, snapshot_id(&snapshot(&[("edgeAgent", "running")])));
+
+        let updates = vec![
+            Module::new("edgeAgent".to_string(), "running".to_string()),
+            Module::new("edgeHub".to_string(), "running".to_string()),
+        ];
+        let events = module_events(&updates, &id);
+        assert_eq!(
+            format!(
+                "event: module_update\n\
+                 data: {{\"name\":\"edgeAgent\",\"status\":\"running\"}}\n\n\
+                 id: {}\n\
+                 event: module_update\n\
+                 data: {{\"name\":\"edgeHub\",\"status\":\"running\"}}\n\n",
+                id
+            ),
+            String::from_utf8(events.to_vec()).unwrap()
+        );
+        assert!(module_events(&[], &id).is_empty());
+    }
+
+    fn modules(count: usize) -> Vec<Module> {
+        (0..count)
+            .map(|i| Module::new(format!("module{}", i), "running".to_string()))
+            .collect()
+    }
+
+    fn paged_modules(response: &actix_web::HttpResponse) -> PagedResponse<Module> {
+        match response.body().as_ref() {
+            Some(ActixBody::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("expected a JSON body"),
+        }
+    }
+
+    #[test]
+    fn last_page_has_remaining_modules() {
+        let response = module_response(PagedResponse::new(modules(45), 3, 20));
+        assert_eq!(StatusCode::OK, response.status());
+
+        let paged = paged_modules(&response);
+        assert_eq!(45, paged.total);
+        assert_eq!(3, paged.page);
+        assert_eq!(20, paged.page_size);
+        let names: Vec<&str> = paged
+            .items
+            .iter()
+            .map(|module| module.name().as_str())
+            .collect();
+        assert_eq!(
+            vec!["module40", "module41", "module42", "module43", "module44"],
+            names
+        );
+    }
+
+    #[test]
+    fn page_beyond_total_is_empty() {
+        let paged = paged_modules(&module_response(PagedResponse::new(modules(5), 2, 20)));
+
+        assert!(paged.items.is_empty());
+        assert_eq!(5, paged.total);
+        assert_eq!(2, paged.page);
+    }
+
+    #[test]
+    fn zero_page_size_is_rejected() {
+        let query = web::Query(PageQuery {
+            page: Some(1),
+            page_size: Some(0),
+        });
+
+        let response = get_modules(
+            TestRequest::default().to_http_request(),
+            test_context(get_unused_tcp_port()),
+            auth_query(),
+            query,
+        )
+        .wait()
+        .unwrap_or_else(HttpResponse::from);
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn get_modules_reports_usage_where_available() {
+        let port = get_unused_tcp_port();
+        let server = run_tcp_server("127.0.0.1", port, |req: Request<Body>| {
+            let module = |name: &str| {
+                serde_json::json!({
+                    "id": name,
+                    "name": name,
+                    "type": "docker",
+                    "config": { "settings": { "image": "tempsensor:1.0" } },
+                    "status": { "runtimeStatus": { "status": "running" } }
+                })
+            };
+            let mut response = match req.uri().path() {
+                "/modules" => Response::new(Body::from(
+                    serde_json::json!({ "modules": [module("sampled"), module("unsampled")] })
+                        .to_string(),
+                )),
+                "/modules/sampled/stats" => Response::new(Body::from(
+                    serde_json::json!({ "memory_stats": { "usage": 8 * 1024 * 1024 } }).to_string(),
+                )),
+                _ => Response::new(Body::from(r#"{"message":"no stats"}"#)),
+            };
+            if req.uri().path() == "/modules/unsampled/stats" {
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            future::ok(response)
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let query = web::Query(PageQuery {
+            page: None,
+            page_size: None,
+        });
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let response = runtime
+            .block_on(get_modules(
+                TestRequest::default().to_http_request(),
+                test_context(port),
+                auth_query(),
+                query,
+            ))
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let paged = paged_modules(&response);
+        assert_eq!(Some(8), paged.items[0].memory_in_mb());
+        assert_eq!(None, paged.items[1].memory_in_mb());
+    }
+
+    fn get_module_response(port: u16) -> actix_web::HttpResponse {
+        let req = TestRequest::default()
+            .param("id", "tempSensor")
+            .to_http_request();
+        Runtime::new()
+            .unwrap()
+            .block_on(get_module_by_id(req, test_context(port), auth_query()))
+            .unwrap()
+    }
+
+    #[test]
+    fn get_module_by_id_returns_module() {
+        let port = get_unused_tcp_port();
+        let requested = Arc::new(Mutex::new(None));
+        let requested_copy = requested.clone();
+
+        let server = run_tcp_server("127.0.0.1", port, move |req: Request<Body>| {
+            let body = if req.uri().path().ends_with("/stats") {
+                serde_json::json!({ "memory_stats": { "usage": 8 * 1024 * 1024 } })
+            } else {
+                *requested_copy.lock().unwrap() = Some(req.uri().path().to_string());
+                serde_json::json!({
+                    "id": "tempSensor",
+                    "name": "tempSensor",
+                    "type": "docker",
+                    "config": { "settings": { "image": "tempsensor:1.0" } },
+                    "status": { "runtimeStatus": { "status": "running" } }
+                })
+            };
+            future::ok(Response::new(Body::from(body.to_string())))
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let response = get_module_response(port);
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            Some("/modules/tempSensor".to_string()),
+            *requested.lock().unwrap()
+        );
+        let module: Module = match response.body().as_ref() {
+            Some(ActixBody::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("expected a JSON body"),
+        };
+        assert_eq!("tempSensor", module.name().as_str());
+        assert_eq!("running", module.status().as_str());
+        assert_eq!(Some(8), module.memory_in_mb());
+    }
+
+    #[test]
+    fn get_module_by_id_reports_missing_module() {
+        let port = get_unused_tcp_port();
+        let server = run_tcp_server("127.0.0.1", port, |_| {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            future::ok(response)
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let response = get_module_response(port);
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let error: serde_json::Value = match response.body().as_ref() {
+            Some(ActixBody::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("expected a JSON body"),
+        };
+        assert_eq!(404, error["code"]);
+        assert_eq!("Module tempSensor not found", error["message"]);
+    }
+
+    #[test]
+    fn get_module_by_id_fails_when_iotedged_is_unreachable() {
+        let response = get_module_response(get_unused_tcp_port());
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use futures::future::{join_all, Either};
+use futures::Future;
+use k8s_openapi::api::core::v1 as api_core;
+use kube_client::{get_config, Client as KubeClient};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::error::{internal_error, ApiError};
+use crate::AuthRequest;
+
+// labels marking a namespace as holding the modules of an IoT Edge device
+const EDGE_DEVICE_ID_LABEL: &str = "net.azure-devices.edge.device-id";
+const EDGE_HUBNAME_LABEL: &str = "net.azure-devices.edge.hub";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NamespaceSummary {
+    namespace: String,
+    device_id: String,
+    hub_hostname: Option<String>,
+    module_count: usize,
+}
+
+impl NamespaceSummary {
+    fn new(namespace: &api_core::Namespace, module_count: usize) -> Option<Self> {
+        let metadata = namespace.metadata.as_ref()?;
+        let labels = metadata.labels.as_ref()?;
+
+        Some(NamespaceSummary {
+            namespace: metadata.name.clone()?,
+            device_id: labels.get(EDGE_DEVICE_ID_LABEL)?.clone(),
+            hub_hostname: labels.get(EDGE_HUBNAME_LABEL).cloned(),
+            module_count,
+        })
+    }
+}
+
+// Lists every IoT Edge deployment in the cluster, not only the one this
+// dashboard is connected to, so like events these come straight from the
+// API server.
+pub fn get_namespaces(
+    _info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let response = match get_config() {
+        Ok(config) => {
+            let mut client = KubeClient::new(config);
+            let fut = client
+                .list_namespaces(Some(EDGE_DEVICE_ID_LABEL))
+                .and_then(move |namespaces| {
+                    let requests: Vec<_> = namespaces
+                        .items
+                        .into_iter()
+                        .filter_map(|namespace| {
+                            let name = namespace.metadata.as_ref()?.name.clone()?;
+                            let modules = client
+                                .list_deployments(&name, None, Some(EDGE_MODULE_LABEL))
+                                .map(move |deployments| {
+                                    NamespaceSummary::new(&namespace, deployments.items.len())
+                                });
+                            Some(modules)
+                        })
+                        .collect();
+                    join_all(requests)
+                })
+                .map(|summaries| {
+                    let mut summaries: Vec<_> = summaries.into_iter().flatten().collect();
+                    summaries.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+                    HttpResponse::Ok().json(summaries)
+                })
+                .map_err(internal_error);
+
+            Either::A(fut)
+        }
+        Err(err) => Either::B(
+            ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The Kubernetes configuration could not be loaded",
+            )
+            .with_details(err)
+            .into_future(),
+        ),
+    };
+
+    Box::new(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1 as api_core;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+
+    use super::{NamespaceSummary, EDGE_DEVICE_ID_LABEL, EDGE_HUBNAME_LABEL};
+
+    fn namespace(labels: &[(&str, &str)]) -> api_core::Namespace {
+        let labels: BTreeMap<_, _> = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        api_core::Namespace {
+            metadata: Some(api_meta::ObjectMeta {
+                name: Some("device1".to_string()),
+                labels: Some(labels),
+                ..api_meta::ObjectMeta::default()
+            }),
+            ..api_core::Namespace::default()
+        }
+    }
+
+    #[test]
+    fn summary_is_read_from_namespace_labels() {
+        let summary = NamespaceSummary::new(
+            &namespace(&[
+                (EDGE_DEVICE_ID_LABEL, "device1"),
+                (EDGE_HUBNAME_LABEL, "myhub.azure-devices.net"),
+            ]),
+            3,
+        )
+        .unwrap();
+
+        assert_eq!("device1", summary.namespace);
+        assert_eq!("device1", summary.device_id);
+        assert_eq!(
+            Some("myhub.azure-devices.net".to_string()),
+            summary.hub_hostname
+        );
+        assert_eq!(3, summary.module_count);
+
+        assert!(NamespaceSummary::new(&namespace(&[]), 0).is_none());
+    }
+}
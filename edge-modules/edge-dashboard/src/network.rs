@@ -0,0 +1,121 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_core::{Module as EdgeModule, ModuleRuntime, RuntimeSettings};
+use edgelet_docker::DockerConfig;
+use edgelet_http_mgmt::*;
+use futures::future::{Either, IntoFuture};
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{internal_error, ApiError};
+use crate::middleware::{module_client, CorrelationId};
+use crate::{AuthRequest, Context};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Network {
+    network_name: String,
+    ip_address: Option<String>,
+    mac_address: Option<String>,
+    gateway: Option<String>,
+}
+
+// Networks come from the endpoints in the module's create options. A module
+// without endpoints is only attached to the network named by its network mode.
+fn module_networks(config: &DockerConfig) -> Vec<Network> {
+    let create_options = config.create_options();
+
+    let mut networks: Vec<Network> = create_options
+        .networking_config()
+        .and_then(|networking| networking.endpoints_config())
+        .into_iter()
+        .flatten()
+        .map(|(name, endpoint)| Network {
+            network_name: name.clone(),
+            ip_address: endpoint
+                .ip_address()
+                .or_else(|| endpoint.ipam_config().and_then(|ipam| ipam.i_pv4_address()))
+                .map(ToString::to_string),
+            mac_address: endpoint.mac_address().map(ToString::to_string),
+            gateway: endpoint.gateway().map(ToString::to_string),
+        })
+        .collect();
+
+    if networks.is_empty() {
+        if let Some(network_mode) = create_options
+            .host_config()
+            .and_then(|host_config| host_config.network_mode())
+        {
+            networks.push(Network {
+                network_name: network_mode.to_string(),
+                ip_address: None,
+                mac_address: None,
+                gateway: None,
+            });
+        }
+    }
+
+    networks.sort_by(|a, b| a.network_name.cmp(&b.network_name));
+    networks
+}
+
+fn network_response(
+    modules: Vec<<ModuleClient as ModuleRuntime>::Module>,
+    module_id: &str,
+) -> Result<HttpResponse, ActixError> {
+    let settings = modules
+        .into_iter()
+        .find(|module| module.name() == module_id)
+        .map(|module| module.config().settings().clone());
+
+    match settings {
+        Some(settings) => serde_json::from_value::<DockerConfig>(settings)
+            .map(|config| HttpResponse::Ok().json(module_networks(&config)))
+            .map_err(internal_error),
+        None => Err(ApiError::not_found("Module not found").into()),
+    }
+}
+
+pub fn get_network(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            let module_id = module_id.to_string();
+            context
+                .edge_config
+                .as_ref()
+                .map(move |config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(move |mod_client| {
+                                mod_client
+                                    .list()
+                                    .map_err(internal_error)
+                                    .and_then(move |modules| network_response(modules, &module_id))
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
@@ -0,0 +1,140 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use actix_web::http::Cookie;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded;
+use url::percent_encoding::percent_decode;
+
+use crate::error::ApiError;
+
+const PREFERENCES_COOKIE: &str = "dashboard-preferences";
+
+const DEFAULT_REFRESH_INTERVAL_SECS: u32 = 30;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Preferences {
+    theme: Theme,
+    refresh_interval_seconds: u32,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            theme: Theme::Light,
+            refresh_interval_seconds: DEFAULT_REFRESH_INTERVAL_SECS,
+        }
+    }
+}
+
+// Preferences are kept in the browser rather than on the device, so that every
+// user of the dashboard has their own. The cookie isn't HttpOnly, as the UI
+// reads it on load; its value is the percent-encoded JSON of the preferences.
+pub fn get_preferences(req: HttpRequest) -> HttpResponse {
+    let preferences: Preferences = req
+        .cookie(PREFERENCES_COOKIE)
+        .and_then(|cookie| {
+            let json = percent_decode(cookie.value().as_bytes())
+                .decode_utf8()
+                .ok()?
+                .into_owned();
+            serde_json::from_str(&json).ok()
+        })
+        .unwrap_or_default();
+
+    HttpResponse::Ok().json(preferences)
+}
+
+pub fn set_preferences(body: web::Json<Preferences>) -> Result<HttpResponse, ActixError> {
+    let preferences = body.into_inner();
+    if preferences.refresh_interval_seconds == 0 {
+        return Err(ApiError::bad_request("refresh_interval_seconds must be at least 1").into());
+    }
+
+    let json = serde_json::to_string(&preferences)?;
+    let value: String = form_urlencoded::byte_serialize(json.as_bytes()).collect();
+    let cookie = Cookie::build(PREFERENCES_COOKIE, value)
+        .path("/")
+        .http_only(false)
+        .permanent()
+        .finish();
+
+    Ok(HttpResponse::Ok().cookie(cookie).json(preferences))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::Body;
+    use actix_web::http::{Cookie, StatusCode};
+    use actix_web::test::TestRequest;
+    use actix_web::{web, HttpResponse};
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{get_preferences, set_preferences, Preferences, Theme, PREFERENCES_COOKIE};
+
+    fn body_json(response: &HttpResponse) -> JsonValue {
+        match response.body().as_ref() {
+            Some(Body::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("expected a JSON body"),
+        }
+    }
+
+    #[test]
+    fn preferences_survive_in_cookie() {
+        let preferences = Preferences {
+            theme: Theme::Dark,
+            refresh_interval_seconds: 10,
+        };
+
+        let response = set_preferences(web::Json(preferences)).unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        let cookie = response
+            .cookies()
+            .find(|cookie| cookie.name() == PREFERENCES_COOKIE)
+            .unwrap();
+        assert_eq!(Some(false), cookie.http_only());
+
+        let req = TestRequest::default()
+            .cookie(Cookie::new(PREFERENCES_COOKIE, cookie.value().to_string()))
+            .to_http_request();
+        let response = get_preferences(req);
+        assert_eq!(
+            json!({ "theme": "dark", "refresh_interval_seconds": 10 }),
+            body_json(&response)
+        );
+    }
+
+    #[test]
+    fn defaults_are_returned_without_cookie() {
+        let response = get_preferences(TestRequest::default().to_http_request());
+        assert_eq!(
+            json!({ "theme": "light", "refresh_interval_seconds": 30 }),
+            body_json(&response)
+        );
+
+        let req = TestRequest::default()
+            .cookie(Cookie::new(PREFERENCES_COOKIE, "not json"))
+            .to_http_request();
+        let response = get_preferences(req);
+        assert_eq!("light", body_json(&response)["theme"]);
+    }
+
+    #[test]
+    fn zero_refresh_interval_is_rejected() {
+        let preferences = Preferences {
+            theme: Theme::Light,
+            refresh_interval_seconds: 0,
+        };
+
+        let response = set_preferences(web::Json(preferences)).unwrap_or_else(HttpResponse::from);
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
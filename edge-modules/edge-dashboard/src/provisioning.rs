@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use actix_web::*;
+use chrono::{DateTime, Utc};
+use edgelet_core::{Provisioning, RuntimeSettings};
+use edgelet_docker::Settings as DockerSettings;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ApiError;
+use crate::state::parse_query;
+use crate::{AuthRequest, Context};
+
+// where iotedged caches the result of DPS and external provisioning
+const PROVISIONING_BACKUP_PATH: &str = "cache/provisioning_backup.json";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProvisioningStatus {
+    pub(crate) source: String,
+    pub(crate) hub_name: Option<String>,
+    pub(crate) device_id: Option<String>,
+    status: String,
+    last_reproved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct ProvisioningBackup {
+    device_id: String,
+    hub_name: String,
+}
+
+pub fn get_status(
+    context: web::Data<Arc<Context>>,
+    _info: web::Query<AuthRequest>,
+) -> HttpResponse {
+    match context.edge_config.as_ref() {
+        Ok(config) => HttpResponse::Ok().json(provisioning_status(config)),
+        Err(err) => ApiError::from(err).error_response(),
+    }
+}
+
+pub(crate) fn provisioning_status(config: &DockerSettings) -> ProvisioningStatus {
+    match config.provisioning() {
+        Provisioning::Manual(manual) => {
+            let conn_str = manual.device_connection_string();
+            let map = parse_query(conn_str, ';', '=');
+            let provisioned = conn_str != edgelet_core::DEFAULT_CONNECTION_STRING;
+            ProvisioningStatus {
+                source: "manual".to_string(),
+                hub_name: map.get("HostName").cloned(),
+                device_id: map.get("DeviceId").cloned(),
+                status: provisioning_state(provisioned),
+                last_reproved_at: None,
+            }
+        }
+        Provisioning::Dps(_) => from_backup("dps", config.homedir()),
+        Provisioning::External(_) => from_backup("external", config.homedir()),
+    }
+}
+
+// The backup is rewritten every time the device is provisioned, so its
+// modification time is when the device was last (re)provisioned.
+fn from_backup(source: &str, homedir: &Path) -> ProvisioningStatus {
+    let path = homedir.join(PROVISIONING_BACKUP_PATH);
+    let backup = fs::read(&path)
+        .ok()
+        .and_then(|buf| serde_json::from_slice::<ProvisioningBackup>(&buf).ok());
+    let last_reproved_at = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(DateTime::<Utc>::from);
+
+    match backup {
+        Some(backup) => ProvisioningStatus {
+            source: source.to_string(),
+            hub_name: Some(backup.hub_name),
+            device_id: Some(backup.device_id),
+            status: provisioning_state(true),
+            last_reproved_at,
+        },
+        None => ProvisioningStatus {
+            source: source.to_string(),
+            hub_name: None,
+            device_id: None,
+            status: provisioning_state(false),
+            last_reproved_at: None,
+        },
+    }
+}
+
+fn provisioning_state(provisioned: bool) -> String {
+    if provisioned {
+        "provisioned".to_string()
+    } else {
+        "not provisioned".to_string()
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Allows at most `max_requests` in each fixed window of `window` length.
+pub struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    state: Mutex<(Instant, u32)>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        RateLimiter {
+            max_requests,
+            window,
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().expect("Unexpected lock error");
+        let now = Instant::now();
+        if now.duration_since(state.0) >= self.window {
+            *state = (now, 0);
+        }
+
+        if state.1 < self.max_requests {
+            state.1 += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
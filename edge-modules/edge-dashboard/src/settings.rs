@@ -12,4 +12,62 @@ pub struct Settings {
 
     #[structopt(short = "c", long = "config-path")]
     pub config_path: Option<String>,
+
+    #[structopt(short = "n", long = "namespace", default_value = "default")]
+    pub namespace: String,
+
+    /// Maximum number of requests per minute to the system info endpoint
+    #[structopt(long = "system-info-rate-limit", default_value = "60")]
+    pub system_info_rate_limit: u32,
+
+    /// Seconds between checks for module status changes sent to the module events stream
+    #[structopt(long = "module-events-interval", default_value = "5")]
+    pub module_events_interval: u64,
+
+    /// Connection string of an IoT Hub shared access policy allowed to read module twins
+    #[structopt(long = "iothub-connection-string")]
+    pub iothub_connection_string: Option<String>,
+
+    /// Token API clients have to pass in an `Authorization: Bearer` header. No
+    /// token is required when neither this nor --api-token-file is set
+    #[structopt(long = "api-token", env = "DASHBOARD_API_TOKEN")]
+    pub api_token: Option<String>,
+
+    /// File to read the API token from
+    #[structopt(long = "api-token-file")]
+    pub api_token_file: Option<String>,
+
+    /// Comma-separated origins allowed to make cross-origin requests. Any
+    /// origin is allowed when none are given
+    #[structopt(long = "cors-allowed-origins", env = "DASHBOARD_CORS_ALLOWED_ORIGINS")]
+    pub cors_allowed_origins: Option<String>,
+
+    /// Comma-separated methods allowed in cross-origin requests
+    #[structopt(
+        long = "cors-allowed-methods",
+        env = "DASHBOARD_CORS_ALLOWED_METHODS",
+        default_value = "GET,POST,PUT,PATCH,DELETE"
+    )]
+    pub cors_allowed_methods: String,
+
+    /// Seconds browsers may cache the result of a preflight request
+    #[structopt(
+        long = "cors-max-age",
+        env = "DASHBOARD_CORS_MAX_AGE",
+        default_value = "3600"
+    )]
+    pub cors_max_age_seconds: u32,
+
+    /// Seconds to wait for a connection to the iotedged management API
+    #[structopt(long = "mgmt-connect-timeout", default_value = "5")]
+    pub mgmt_connect_timeout: u64,
+
+    /// Seconds to wait for the iotedged management API to answer a request
+    #[structopt(long = "mgmt-request-timeout", default_value = "30")]
+    pub mgmt_request_timeout: u64,
+
+    /// Allow running commands in modules through `kubectl exec`. Only works
+    /// when iotedged runs on Kubernetes and kubectl is on the PATH
+    #[structopt(long = "enable-kubectl-exec")]
+    pub enable_kubectl_exec: bool,
 }
@@ -0,0 +1,117 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::*;
+use serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::Context;
+
+// Failure simulation is only available when this is set to 1.
+pub const DEV_MODE_ENV: &str = "IOTEDGE_DASHBOARD_DEV_MODE";
+
+// A day is plenty to try out the dashboard.
+const MAX_FAILURE_DURATION_SECS: u64 = 24 * 60 * 60;
+
+// Modules reported as failed until the instant their simulated failure ends.
+// Expired failures are dropped the next time they're looked up.
+#[derive(Default)]
+pub struct SimulatedFailures {
+    failures: Mutex<HashMap<String, Instant>>,
+}
+
+impl SimulatedFailures {
+    // Returns when the failure ends, or None when that instant can't be
+    // represented, in which case no failure is simulated.
+    pub fn start(&self, module_id: String, duration: Duration) -> Option<Instant> {
+        let until = Instant::now().checked_add(duration)?;
+        let mut failures = self.failures.lock().expect("Unexpected lock error");
+        failures.insert(module_id, until);
+        Some(until)
+    }
+
+    pub fn is_failed(&self, module_id: &str) -> bool {
+        let mut failures = self.failures.lock().expect("Unexpected lock error");
+        match failures.get(module_id) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                failures.remove(module_id);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModuleFailureQuery {
+    module_id: String,
+    duration_seconds: u64,
+}
+
+// Reports a module as failed for `duration_seconds`, so that the health
+// dashboard can be tried out without breaking a module. The module itself is
+// left alone.
+pub fn simulate_module_failure(
+    context: web::Data<Arc<Context>>,
+    query: web::Query<ModuleFailureQuery>,
+) -> HttpResponse {
+    if !context.dev_mode {
+        return ApiError::not_found(format!(
+            "Failure simulation is only available when {}=1",
+            DEV_MODE_ENV
+        ))
+        .error_response();
+    }
+    if query.module_id.is_empty() || query.duration_seconds == 0 {
+        return ApiError::bad_request("module_id and duration_seconds must be set")
+            .error_response();
+    }
+    if query.duration_seconds > MAX_FAILURE_DURATION_SECS {
+        return ApiError::bad_request(format!(
+            "duration_seconds must be at most {}",
+            MAX_FAILURE_DURATION_SECS
+        ))
+        .error_response();
+    }
+
+    match context.simulated_failures.start(
+        query.module_id.clone(),
+        Duration::from_secs(query.duration_seconds),
+    ) {
+        Some(_) => HttpResponse::Accepted().finish(),
+        None => ApiError::bad_request("duration_seconds is too large").error_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::SimulatedFailures;
+
+    #[test]
+    fn simulated_failure_ends_after_its_duration() {
+        let failures = SimulatedFailures::default();
+        failures.start("tempSensor".to_string(), Duration::from_millis(50));
+
+        assert!(failures.is_failed("tempSensor"));
+        assert!(!failures.is_failed("edgeHub"));
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(!failures.is_failed("tempSensor"));
+    }
+
+    #[test]
+    fn unrepresentable_failure_is_not_started() {
+        let failures = SimulatedFailures::default();
+        assert!(failures
+            .start("tempSensor".to_string(), Duration::from_secs(u64::MAX))
+            .is_none());
+        assert!(!failures.is_failed("tempSensor"));
+    }
+}
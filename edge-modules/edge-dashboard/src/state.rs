@@ -4,9 +4,12 @@ use std::collections::HashMap;
 use std::fs;
 use std::io::Result;
 
+use actix_web::http::StatusCode;
 use actix_web::*;
 use serde::{Deserialize, Serialize};
 
+use crate::error::ApiError;
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum State {
     Manual,
@@ -104,6 +107,6 @@ pub fn return_response(new_device: &Device) -> HttpResponse {
     // if the new_device is able to be created (fields are able to be parsed into JSON strings)
     match serde_json::to_string(new_device) {
         Ok(json_file) => HttpResponse::Ok().body(json_file),
-        Err(_)        => HttpResponse::UnprocessableEntity().body("Unable to process device connection string. Are you sure the string is correctly set up?"),
+        Err(_)        => ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "Unable to process device connection string. Are you sure the string is correctly set up?").error_response(),
     }
 }
@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_core::ModuleRuntime;
+use futures::future::{Either, IntoFuture};
+use futures::Future;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use url::Url;
+
+use crate::error::{internal_error, ApiError};
+use crate::middleware::{module_client, CorrelationId};
+use crate::{AuthRequest, Context};
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ModuleStats {
+    cpu_percent: f64,
+    memory_mb: f64,
+    network_rx_bytes: u64,
+    network_tx_bytes: u64,
+    block_read_bytes: u64,
+    block_write_bytes: u64,
+}
+
+// Converts a single sample from the Docker stats API the same way `docker stats`
+// does: CPU usage is the container's share of the host's CPU time since the
+// previous sample, and page cache is not counted as used memory.
+impl From<&JsonValue> for ModuleStats {
+    fn from(stats: &JsonValue) -> Self {
+        let cpu_delta = as_f64(&stats["cpu_stats"]["cpu_usage"]["total_usage"])
+            - as_f64(&stats["precpu_stats"]["cpu_usage"]["total_usage"]);
+        let system_delta = as_f64(&stats["cpu_stats"]["system_cpu_usage"])
+            - as_f64(&stats["precpu_stats"]["system_cpu_usage"]);
+        let online_cpus = stats["cpu_stats"]["online_cpus"]
+            .as_f64()
+            .or_else(|| {
+                stats["cpu_stats"]["cpu_usage"]["percpu_usage"]
+                    .as_array()
+                    .map(|cpus| cpus.len() as f64)
+            })
+            .unwrap_or(1.0);
+        let cpu_percent = if cpu_delta > 0.0 && system_delta > 0.0 {
+            cpu_delta / system_delta * online_cpus * 100.0
+        } else {
+            0.0
+        };
+
+        let memory_bytes = as_f64(&stats["memory_stats"]["usage"])
+            - as_f64(&stats["memory_stats"]["stats"]["cache"]);
+
+        let networks = stats["networks"].as_object();
+        let network_bytes = |key: &str| {
+            networks.map_or(0, |networks| {
+                networks
+                    .values()
+                    .map(|network| network[key].as_u64().unwrap_or(0))
+                    .sum()
+            })
+        };
+
+        let block_bytes = |op: &str| {
+            stats["blkio_stats"]["io_service_bytes_recursive"]
+                .as_array()
+                .map_or(0, |entries| {
+                    entries
+                        .iter()
+                        .filter(|entry| {
+                            entry["op"]
+                                .as_str()
+                                .map_or(false, |entry_op| entry_op.eq_ignore_ascii_case(op))
+                        })
+                        .map(|entry| entry["value"].as_u64().unwrap_or(0))
+                        .sum()
+                })
+        };
+
+        ModuleStats {
+            cpu_percent,
+            memory_mb: memory_bytes.max(0.0) / BYTES_PER_MB,
+            network_rx_bytes: network_bytes("rx_bytes"),
+            network_tx_bytes: network_bytes("tx_bytes"),
+            block_read_bytes: block_bytes("read"),
+            block_write_bytes: block_bytes("write"),
+        }
+    }
+}
+
+impl ModuleStats {
+    pub fn cpu_percent(&self) -> f64 {
+        self.cpu_percent
+    }
+
+    pub fn memory_mb(&self) -> f64 {
+        self.memory_mb
+    }
+}
+
+fn as_f64(value: &JsonValue) -> f64 {
+    value.as_f64().unwrap_or(0.0)
+}
+
+// The management API passes the container runtime's stats through as is, so a
+// sample is converted here. Runtimes which can't sample usage, like the
+// Kubernetes one, leave it unavailable rather than failing the dashboard.
+pub fn get_stats(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    let api_ver = &info.api_version;
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            context
+                .edge_config
+                .as_ref()
+                .map(|config| {
+                    let mgmt_uri = config.connect().management_uri();
+                    Either::A(
+                        Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                            .map_err(internal_error)
+                            .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                            .map(|mod_client| {
+                                mod_client.stats(module_id).then(|result| match result {
+                                    Ok(stats) => {
+                                        Ok(HttpResponse::Ok().json(ModuleStats::from(&stats)))
+                                    }
+                                    Err(ref err) if err.is_not_found() => {
+                                        Err(ApiError::not_found("Module not found").into())
+                                    }
+                                    Err(err) => Err(ApiError::new(
+                                        StatusCode::SERVICE_UNAVAILABLE,
+                                        "Module usage is not available",
+                                    )
+                                    .with_details(err)
+                                    .into()),
+                                })
+                            })
+                            .into_future()
+                            .flatten(),
+                    )
+                })
+                .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()))
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
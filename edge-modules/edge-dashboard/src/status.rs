@@ -3,17 +3,22 @@
 use std::net::TcpStream;
 use std::process::Command;
 
-use actix_web::error::ErrorInternalServerError;
+use actix_web::http::StatusCode;
 use actix_web::Error as ActixError;
 use actix_web::*;
 
+use crate::error::{internal_error, ApiError};
 use crate::state::{return_response, Device};
 
 pub fn get_state(device: web::Data<Option<Device>>) -> HttpResponse {
     if let Some(dev) = device.get_ref() {
         return_response(&dev)
     } else {
-        HttpResponse::UnprocessableEntity().body("Device connection string unable to be processed.")
+        ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Device connection string unable to be processed.",
+        )
+        .error_response()
     }
 }
 
@@ -25,14 +30,25 @@ pub fn get_connectivity(_req: HttpRequest, device: web::Data<Option<Device>>) ->
             let r = resolve_and_tls_handshake(&(&**iothub_hostname, 443), iothub_hostname);
             match r {
                 Ok(_) => HttpResponse::Ok().body("Succesfully connected to IoT Hub."),
-                Err(_) => HttpResponse::UnprocessableEntity()
-                    .body("Failed to establish connection with IoT Hub."),
+                Err(_) => ApiError::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Failed to establish connection with IoT Hub.",
+                )
+                .error_response(),
             }
         } else {
-            HttpResponse::UnprocessableEntity().body("IoT Hub name could not be processed")
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "IoT Hub name could not be processed",
+            )
+            .error_response()
         }
     } else {
-        HttpResponse::UnprocessableEntity().body("IoT Hub name could not be processed")
+        ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "IoT Hub name could not be processed",
+        )
+        .error_response()
     }
 }
 
@@ -43,19 +59,19 @@ fn resolve_and_tls_handshake(
 ) -> Result<(), ActixError> {
     let host_addr = to_socket_addrs
         .to_socket_addrs()
-        .map_err(ErrorInternalServerError)?
+        .map_err(internal_error)?
         .next()
         .ok_or_else(|| "")
-        .map_err(ErrorInternalServerError)?;
+        .map_err(internal_error)?;
 
     let stream = TcpStream::connect_timeout(&host_addr, std::time::Duration::from_secs(10))
-        .map_err(ErrorInternalServerError)?;
+        .map_err(internal_error)?;
 
-    let tls_connector = native_tls::TlsConnector::new().map_err(ErrorInternalServerError)?;
+    let tls_connector = native_tls::TlsConnector::new().map_err(internal_error)?;
 
     let _ = tls_connector
         .connect(tls_hostname, stream)
-        .map_err(ErrorInternalServerError)?;
+        .map_err(internal_error)?;
 
     Ok(())
 }
@@ -65,5 +81,9 @@ pub fn get_diagnostics() -> HttpResponse {
         .args(&["check", "--output", "json"])
         .output()
         .map(|out| HttpResponse::Ok().body(out.stdout))
-        .unwrap_or_else(|_| HttpResponse::ServiceUnavailable().body("Failed to execute command"))
+        .unwrap_or_else(|err| {
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Failed to execute command")
+                .with_details(err)
+                .error_response()
+        })
 }
@@ -0,0 +1,86 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::process::Command;
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use futures::future::{Either, IntoFuture};
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::{internal_error, ApiError};
+use crate::middleware::{module_client, CorrelationId};
+use crate::{AuthRequest, Context};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SystemInfo {
+    os_type: String,
+    architecture: String,
+    server_version: Option<String>,
+    kernel_version: Option<String>,
+    iotedged_version: String,
+}
+
+// Returns no secrets, so unlike the module endpoints this is served without
+// authentication. It is rate limited on its own instead.
+pub fn get_system_info(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let correlation_id = CorrelationId::of(&req);
+    let timeouts = context.client_timeouts;
+    if !context.system_info_limit.try_acquire() {
+        return Box::new(
+            ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_future(),
+        );
+    }
+
+    let api_ver = &info.api_version;
+    let response = context
+        .edge_config
+        .as_ref()
+        .map(|config| {
+            let mgmt_uri = config.connect().management_uri();
+            Either::A(
+                Url::parse(&format!("{}/modules/?api-version={}", mgmt_uri, api_ver))
+                    .map_err(internal_error)
+                    .and_then(|url| module_client(&url, timeouts, correlation_id.as_ref()))
+                    .map(|mod_client| {
+                        mod_client
+                            .get_system_info()
+                            .map_err(internal_error)
+                            .map(|system_info| {
+                                HttpResponse::Ok().json(SystemInfo {
+                                    os_type: system_info.os_type().clone(),
+                                    architecture: system_info.architecture().clone(),
+                                    server_version: command_output(
+                                        "docker",
+                                        &["version", "--format", "{{.Server.Version}}"],
+                                    ),
+                                    kernel_version: command_output("uname", &["-r"]),
+                                    iotedged_version: system_info.version().clone(),
+                                })
+                            })
+                    })
+                    .into_future()
+                    .flatten(),
+            )
+        })
+        .unwrap_or_else(|err| Either::B(ApiError::from(err).into_future()));
+
+    Box::new(response)
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|out| out.trim().to_string())
+}
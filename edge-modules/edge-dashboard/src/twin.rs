@@ -0,0 +1,325 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use futures::future::{err, Either};
+use futures::Future;
+use openssl::base64;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::r#async::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use url::form_urlencoded;
+
+use crate::error::{internal_error, ApiError};
+use crate::provisioning::provisioning_status;
+use crate::state::parse_query;
+use crate::{AuthRequest, Context};
+
+const TWIN_API_VERSION: &str = "2018-06-30";
+
+// lifetime of the SAS token signed for each twin request
+const SAS_TOKEN_TTL_SECS: u64 = 5 * 60;
+
+#[derive(Debug, Serialize)]
+pub struct TwinDiff {
+    desired: JsonValue,
+    reported: JsonValue,
+    pending_changes: Vec<PatchOperation>,
+}
+
+/// A JSON Patch (RFC 6902) operation.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct PatchOperation {
+    op: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<JsonValue>,
+}
+
+impl PatchOperation {
+    fn new(op: &str, path: String, value: Option<JsonValue>) -> Self {
+        PatchOperation {
+            op: op.to_string(),
+            path,
+            value,
+        }
+    }
+}
+
+// Module twins can only be read with IoT Hub service credentials, which the
+// device's own connection string doesn't carry, so they come from the
+// dashboard's settings.
+pub fn get_twin(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    _info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let module_id = match req.match_info().get("id") {
+        Some(module_id) => module_id.to_string(),
+        None => return Box::new(ApiError::bad_request("Invalid module ID").into_future()),
+    };
+
+    let response = fetch_module_twin(&context, module_id)
+        .map(|twin| HttpResponse::Ok().json(twin_diff(&twin)));
+
+    Box::new(response)
+}
+
+/// Reads the twin of a module on this device from IoT Hub.
+pub fn fetch_module_twin(
+    context: &Context,
+    module_id: String,
+) -> Box<dyn Future<Item = JsonValue, Error = ActixError>> {
+    let device_id = match context.edge_config.as_ref() {
+        Ok(config) => provisioning_status(config).device_id,
+        Err(err) => return Box::new(ApiError::from(err).into_future()),
+    };
+    let device_id = match device_id {
+        Some(device_id) => device_id,
+        None => {
+            return Box::new(
+                ApiError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Device ID could not be determined",
+                )
+                .into_future(),
+            )
+        }
+    };
+    let connection_string = match context.settings.iothub_connection_string {
+        Some(ref connection_string) => connection_string,
+        None => {
+            return Box::new(
+                ApiError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "No IoT Hub connection string is configured",
+                )
+                .into_future(),
+            )
+        }
+    };
+
+    let twin = parse_query(connection_string, ';', '=')
+        .get("HostName")
+        .cloned()
+        .ok_or_else(|| internal_error("Invalid IoT Hub connection string"))
+        .and_then(|hostname| {
+            let token = sas_token(
+                connection_string,
+                &hostname,
+                now_secs() + SAS_TOKEN_TTL_SECS,
+            )?;
+            Ok((hostname, token))
+        })
+        .map(|(hostname, token)| {
+            let url = format!(
+                "https://{}/twins/{}/modules/{}?api-version={}",
+                hostname, device_id, module_id, TWIN_API_VERSION
+            );
+            let fut = Client::new()
+                .get(&url)
+                .header("Authorization", token)
+                .send()
+                .map_err(internal_error)
+                .and_then(move |mut res| {
+                    if res.status() == reqwest::StatusCode::NOT_FOUND {
+                        return Either::A(
+                            ApiError::not_found(format!("Twin of module {} not found", module_id))
+                                .into_future(),
+                        );
+                    }
+
+                    Either::B(res.json::<JsonValue>().map_err(internal_error))
+                });
+            Either::A(fut)
+        })
+        .unwrap_or_else(|error| Either::B(err(error)));
+
+    Box::new(twin)
+}
+
+fn twin_diff(twin: &JsonValue) -> TwinDiff {
+    let desired = twin["properties"]["desired"].clone();
+    let reported = twin["properties"]["reported"].clone();
+    let pending_changes = pending_changes(&desired, &reported);
+
+    TwinDiff {
+        desired,
+        reported,
+        pending_changes,
+    }
+}
+
+/// The patch which would bring the reported properties in line with the
+/// desired ones. Only desired properties are compared: properties a module
+/// reports without them being desired aren't pending changes. `$version` and
+/// `$metadata` are ignored.
+pub fn pending_changes(desired: &JsonValue, reported: &JsonValue) -> Vec<PatchOperation> {
+    let mut ops = Vec::new();
+    if let (Some(desired), Some(reported)) = (desired.as_object(), reported.as_object()) {
+        diff_objects("", desired, reported, &mut ops);
+    }
+    ops
+}
+
+fn diff_objects(
+    path: &str,
+    desired: &Map<String, JsonValue>,
+    reported: &Map<String, JsonValue>,
+    ops: &mut Vec<PatchOperation>,
+) {
+    for (key, desired_value) in desired.iter().filter(|(key, _)| !key.starts_with('$')) {
+        let path = format!("{}/{}", path, escape_pointer(key));
+        match (desired_value, reported.get(key)) {
+            // a desired property set to null is being deleted
+            (JsonValue::Null, Some(_)) => ops.push(PatchOperation::new("remove", path, None)),
+            (JsonValue::Null, None) => (),
+            (_, None) => ops.push(PatchOperation::new(
+                "add",
+                path,
+                Some(without_metadata(desired_value)),
+            )),
+            (JsonValue::Object(desired), Some(JsonValue::Object(reported))) => {
+                diff_objects(&path, desired, reported, ops)
+            }
+            (_, Some(reported_value)) => {
+                if desired_value != reported_value {
+                    ops.push(PatchOperation::new(
+                        "replace",
+                        path,
+                        Some(without_metadata(desired_value)),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn without_metadata(value: &JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Object(map) => JsonValue::Object(
+            map.iter()
+                .filter(|(key, _)| !key.starts_with('$'))
+                .map(|(key, value)| (key.clone(), without_metadata(value)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+// JSON Pointer (RFC 6901) escaping of a single path segment.
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0)
+}
+
+// IoT Hub shared access signature for a shared access policy in the
+// connection string, valid until `expiry` (seconds since the Unix epoch).
+fn sas_token(connection_string: &str, hostname: &str, expiry: u64) -> Result<String, ActixError> {
+    let parts = parse_query(connection_string, ';', '=');
+    let (key_name, key) = match (
+        parts.get("SharedAccessKeyName"),
+        parts.get("SharedAccessKey"),
+    ) {
+        (Some(key_name), Some(key)) => (key_name, key),
+        _ => {
+            return Err(internal_error(
+                "IoT Hub connection string has no shared access policy",
+            ))
+        }
+    };
+
+    let resource: String = form_urlencoded::byte_serialize(hostname.as_bytes()).collect();
+    let key = base64::decode_block(key).map_err(internal_error)?;
+    let key = PKey::hmac(&key).map_err(internal_error)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(internal_error)?;
+    signer
+        .update(format!("{}\n{}", resource, expiry).as_bytes())
+        .map_err(internal_error)?;
+    let signature = base64::encode_block(&signer.sign_to_vec().map_err(internal_error)?);
+
+    let signature: String = form_urlencoded::byte_serialize(signature.as_bytes()).collect();
+    Ok(format!(
+        "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+        resource, signature, expiry, key_name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{pending_changes, sas_token, twin_diff, PatchOperation};
+
+    #[test]
+    fn pending_changes_cover_unapplied_desired_properties() {
+        let desired = json!({
+            "interval": 10,
+            "thresholds": { "low": 5, "high": 50 },
+            "mode": "fast",
+            "legacy": null,
+            "a/b": true,
+            "$version": 7,
+            "$metadata": { "$lastUpdated": "2019-07-01T12:00:00Z" }
+        });
+        let reported = json!({
+            "interval": 10,
+            "thresholds": { "low": 5, "high": 40 },
+            "legacy": "on",
+            "uptime": 1234,
+            "$version": 3
+        });
+
+        let mut ops = pending_changes(&desired, &reported);
+        ops.sort_by(|a, b| a.path.cmp(&b.path));
+
+        assert_eq!(
+            vec![
+                PatchOperation::new("add", "/a~1b".to_string(), Some(json!(true))),
+                PatchOperation::new("remove", "/legacy".to_string(), None),
+                PatchOperation::new("add", "/mode".to_string(), Some(json!("fast"))),
+                PatchOperation::new("replace", "/thresholds/high".to_string(), Some(json!(50))),
+            ],
+            ops
+        );
+    }
+
+    #[test]
+    fn applied_twin_has_no_pending_changes() {
+        let twin = json!({
+            "properties": {
+                "desired": { "interval": 10, "$version": 2 },
+                "reported": { "interval": 10, "$version": 5 }
+            }
+        });
+
+        let diff = twin_diff(&twin);
+        assert!(diff.pending_changes.is_empty());
+        assert_eq!(json!({ "interval": 10, "$version": 2 }), diff.desired);
+    }
+
+    #[test]
+    fn sas_token_is_signed_for_hub() {
+        let connection_string =
+            "HostName=hub.azure-devices.net;SharedAccessKeyName=registryRead;SharedAccessKey=a2V5";
+
+        let token = sas_token(connection_string, "hub.azure-devices.net", 1_562_000_000).unwrap();
+
+        assert!(token.starts_with("SharedAccessSignature sr=hub.azure-devices.net&sig="));
+        assert!(token.ends_with("&se=1562000000&skn=registryRead"));
+        assert!(sas_token("HostName=hub.azure-devices.net", "hub.azure-devices.net", 0).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use actix_web::Error as ActixError;
+use actix_web::*;
+use edgelet_utils::sanitize_dns_label;
+use futures::future::Either;
+use futures::Future;
+use k8s_openapi::api::core::v1 as api_core;
+use kube_client::{get_config, Client as KubeClient};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::error::{internal_error, ApiError};
+use crate::{AuthRequest, Context};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Volume {
+    name: String,
+    mount_path: String,
+    read_only: bool,
+    source_type: String,
+    source_name: Option<String>,
+}
+
+impl Volume {
+    fn new(mount: &api_core::VolumeMount, volume: Option<&api_core::Volume>) -> Self {
+        let (source_type, source_name) = volume.map_or(("unknown", None), volume_source);
+
+        Volume {
+            name: mount.name.clone(),
+            mount_path: mount.mount_path.clone(),
+            read_only: mount.read_only.unwrap_or(false),
+            source_type: source_type.to_string(),
+            source_name,
+        }
+    }
+}
+
+fn volume_source(volume: &api_core::Volume) -> (&'static str, Option<String>) {
+    if let Some(claim) = volume.persistent_volume_claim.as_ref() {
+        ("persistentVolumeClaim", Some(claim.claim_name.clone()))
+    } else if let Some(config_map) = volume.config_map.as_ref() {
+        ("configMap", config_map.name.clone())
+    } else if let Some(secret) = volume.secret.as_ref() {
+        ("secret", secret.secret_name.clone())
+    } else if volume.empty_dir.is_some() {
+        ("emptyDir", None)
+    } else if let Some(host_path) = volume.host_path.as_ref() {
+        ("hostPath", Some(host_path.path.clone()))
+    } else {
+        ("other", None)
+    }
+}
+
+// The module container shares its pod with the iotedged proxy, so only the
+// container named after the module is reported.
+fn module_volumes(pod: &api_core::Pod, container_name: &str) -> Vec<Volume> {
+    let spec = match pod.spec.as_ref() {
+        Some(spec) => spec,
+        None => return Vec::new(),
+    };
+    let volumes = spec.volumes.as_ref().map_or(&[][..], Vec::as_slice);
+
+    spec.containers
+        .iter()
+        .filter(|container| container.name == container_name)
+        .flat_map(|container| container.volume_mounts.iter().flatten())
+        .map(|mount| {
+            let volume = volumes.iter().find(|volume| volume.name == mount.name);
+            Volume::new(mount, volume)
+        })
+        .collect()
+}
+
+// Mounts are read from the module's pod spec on the API server, where the
+// volume each mount refers to can be resolved to its source.
+pub fn get_volumes(
+    req: HttpRequest,
+    context: web::Data<Arc<Context>>,
+    _info: web::Query<AuthRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = ActixError>> {
+    let response = req
+        .match_info()
+        .get("id")
+        .map(|module_id| {
+            let namespace = context.settings.namespace.clone();
+            let container_name = sanitize_dns_label(module_id);
+            let label_selector = format!("{}={}", EDGE_MODULE_LABEL, container_name);
+
+            match get_config() {
+                Ok(config) => {
+                    let fut = KubeClient::new(config)
+                        .list_pods(&namespace, Some(&label_selector))
+                        .map(move |pods| match pods.items.first() {
+                            Some(pod) => {
+                                HttpResponse::Ok().json(module_volumes(pod, &container_name))
+                            }
+                            None => ApiError::not_found("Module not found").error_response(),
+                        })
+                        .map_err(internal_error);
+
+                    Either::A(fut)
+                }
+                Err(err) => Either::B(
+                    ApiError::new(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "The Kubernetes configuration could not be loaded",
+                    )
+                    .with_details(err)
+                    .into_future(),
+                ),
+            }
+        })
+        .unwrap_or_else(|| Either::B(ApiError::bad_request("Invalid module ID").into_future()));
+
+    Box::new(response)
+}
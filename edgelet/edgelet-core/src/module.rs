@@ -57,6 +57,7 @@ pub struct ModuleRuntimeState {
     finished_at: Option<DateTime<Utc>>,
     image_id: Option<String>,
     pid: Option<i32>,
+    pod_ip: Option<String>,
 }
 
 impl Default for ModuleRuntimeState {
@@ -69,6 +70,7 @@ impl Default for ModuleRuntimeState {
             finished_at: None,
             image_id: None,
             pid: None,
+            pod_ip: None,
         }
     }
 }
@@ -136,6 +138,17 @@ impl ModuleRuntimeState {
         self.pid = pid;
         self
     }
+
+    /// The IP address of the pod the module runs in, for runtimes which run
+    /// modules in pods.
+    pub fn pod_ip(&self) -> Option<&str> {
+        self.pod_ip.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_pod_ip(mut self, pod_ip: Option<String>) -> Self {
+        self.pod_ip = pod_ip;
+        self
+    }
 }
 
 #[derive(serde_derive::Deserialize, Debug, serde_derive::Serialize)]
@@ -282,7 +295,7 @@ impl ToString for LogTail {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct LogOptions {
     follow: bool,
     tail: LogTail,
@@ -433,10 +446,8 @@ pub trait ModuleRuntime: Sized {
     type CreateFuture: Future<Item = (), Error = Self::Error> + Send;
     type GetFuture: Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send;
     type ListFuture: Future<Item = Vec<Self::Module>, Error = Self::Error> + Send;
-    type ListWithDetailsStream: Stream<
-            Item = (Self::Module, ModuleRuntimeState),
-            Error = Self::Error,
-        > + Send;
+    type ListWithDetailsStream: Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error>
+        + Send;
     type LogsFuture: Future<Item = Self::Logs, Error = Self::Error> + Send;
     type RemoveFuture: Future<Item = (), Error = Self::Error> + Send;
     type RestartFuture: Future<Item = (), Error = Self::Error> + Send;
@@ -444,19 +455,30 @@ pub trait ModuleRuntime: Sized {
     type StopFuture: Future<Item = (), Error = Self::Error> + Send;
     type SystemInfoFuture: Future<Item = SystemInfo, Error = Self::Error> + Send;
     type RemoveAllFuture: Future<Item = (), Error = Self::Error> + Send;
+    type StatsFuture: Future<Item = serde_json::Value, Error = Self::Error> + Send;
+    type ClearLogsFuture: Future<Item = (), Error = Self::Error> + Send;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture;
     fn get(&self, id: &str) -> Self::GetFuture;
     fn start(&self, id: &str) -> Self::StartFuture;
     fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture;
     fn restart(&self, id: &str) -> Self::RestartFuture;
-    fn remove(&self, id: &str) -> Self::RemoveFuture;
+    /// Removes a module. Runtimes which require removing some modules to be
+    /// confirmed, because it deletes their persistent state, only remove
+    /// those if `force` is set.
+    fn remove(&self, id: &str, force: bool) -> Self::RemoveFuture;
     fn system_info(&self) -> Self::SystemInfoFuture;
     fn list(&self) -> Self::ListFuture;
     fn list_with_details(&self) -> Self::ListWithDetailsStream;
     fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture;
     fn registry(&self) -> &Self::ModuleRegistry;
     fn remove_all(&self) -> Self::RemoveAllFuture;
+    /// A sample of the module's resource usage, in the format of the
+    /// container runtime's stats API.
+    fn stats(&self, id: &str) -> Self::StatsFuture;
+    /// Empties the module's log, where the container runtime keeps it in a
+    /// file.
+    fn clear_logs(&self, id: &str) -> Self::ClearLogsFuture;
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -498,9 +520,11 @@ impl fmt::Display for RegistryOperation {
 // Useful for error contexts
 #[derive(Clone, Debug, PartialEq)]
 pub enum RuntimeOperation {
+    ClearModuleLogs(String),
     CreateModule(String),
     GetModule(String),
     GetModuleLogs(String),
+    GetModuleStats(String),
     Init,
     ListModules,
     RemoveModule(String),
@@ -514,11 +538,17 @@ pub enum RuntimeOperation {
 impl fmt::Display for RuntimeOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            RuntimeOperation::ClearModuleLogs(name) => {
+                write!(f, "Could not clear logs for module {}", name)
+            }
             RuntimeOperation::CreateModule(name) => write!(f, "Could not create module {}", name),
             RuntimeOperation::GetModule(name) => write!(f, "Could not get module {}", name),
             RuntimeOperation::GetModuleLogs(name) => {
                 write!(f, "Could not get logs for module {}", name)
             }
+            RuntimeOperation::GetModuleStats(name) => {
+                write!(f, "Could not get stats for module {}", name)
+            }
             RuntimeOperation::Init => write!(f, "Could not initialize module runtime"),
             RuntimeOperation::ListModules => write!(f, "Could not list modules"),
             RuntimeOperation::RemoveModule(name) => write!(f, "Could not remove module {}", name),
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 use std::convert::From;
+use std::fs::OpenOptions;
 use std::ops::Deref;
 use std::time::Duration;
 
@@ -302,6 +303,8 @@ impl ModuleRuntime for DockerModuleRuntime {
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type SystemInfoFuture = Box<dyn Future<Item = CoreSystemInfo, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = serde_json::Value, Error = Self::Error> + Send>;
+    type ClearLogsFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         info!("Creating module {}...", module.name());
@@ -518,7 +521,7 @@ impl ModuleRuntime for DockerModuleRuntime {
         )
     }
 
-    fn remove(&self, id: &str) -> Self::RemoveFuture {
+    fn remove(&self, id: &str, _force: bool) -> Self::RemoveFuture {
         info!("Removing module {}...", id);
 
         let id = id.to_string();
@@ -699,11 +702,66 @@ impl ModuleRuntime for DockerModuleRuntime {
         let self_for_remove = self.clone();
         Box::new(self.list().and_then(move |list| {
             let n = list.into_iter().map(move |c| {
-                <DockerModuleRuntime as ModuleRuntime>::remove(&self_for_remove, c.name())
+                <DockerModuleRuntime as ModuleRuntime>::remove(&self_for_remove, c.name(), true)
             });
             future::join_all(n).map(|_| ())
         }))
     }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        let id = id.to_string();
+
+        let result = self
+            .client
+            .container_api()
+            .container_stats(&id, false)
+            .map_err(|err| {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id)),
+                );
+                log_failure(Level::Warn, &err);
+                err
+            });
+        Box::new(result)
+    }
+
+    // Only file based log drivers, like the default json-file, report a log
+    // path. The file is truncated in place, as the container keeps it open.
+    fn clear_logs(&self, id: &str) -> Self::ClearLogsFuture {
+        let id = id.to_string();
+
+        let result = self
+            .client
+            .container_api()
+            .container_inspect(&id, false)
+            .then(|result| match result {
+                Ok(container) => match container.log_path() {
+                    Some(path) if !path.is_empty() => OpenOptions::new()
+                        .write(true)
+                        .open(path)
+                        .and_then(|file| file.set_len(0))
+                        .with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ClearModuleLogs(
+                                id.clone(),
+                            ))
+                        })
+                        .map_err(Error::from),
+                    _ => Err(Error::from(ErrorKind::Conflict.context(
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ClearModuleLogs(id)),
+                    ))),
+                },
+                Err(err) => Err(Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ClearModuleLogs(id)),
+                )),
+            })
+            .map_err(|err| {
+                log_failure(Level::Warn, &err);
+                err
+            });
+        Box::new(result)
+    }
 }
 
 impl Authenticator for DockerModuleRuntime {
@@ -1341,6 +1399,8 @@ mod tests {
         type StopFuture = FutureResult<(), Self::Error>;
         type SystemInfoFuture = FutureResult<CoreSystemInfo, Self::Error>;
         type RemoveAllFuture = FutureResult<(), Self::Error>;
+        type StatsFuture = FutureResult<serde_json::Value, Self::Error>;
+        type ClearLogsFuture = FutureResult<(), Self::Error>;
 
         fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
             unimplemented!()
@@ -1362,7 +1422,7 @@ mod tests {
             unimplemented!()
         }
 
-        fn remove(&self, _id: &str) -> Self::RemoveFuture {
+        fn remove(&self, _id: &str, _force: bool) -> Self::RemoveFuture {
             unimplemented!()
         }
 
@@ -1389,6 +1449,14 @@ mod tests {
         fn remove_all(&self) -> Self::RemoveAllFuture {
             unimplemented!()
         }
+
+        fn stats(&self, _id: &str) -> Self::StatsFuture {
+            unimplemented!()
+        }
+
+        fn clear_logs(&self, _id: &str) -> Self::ClearLogsFuture {
+            unimplemented!()
+        }
     }
 
     impl Authenticator for TestModuleList {
@@ -1060,7 +1060,7 @@ fn container_remove_succeeds() {
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRuntime::remove(&runtime, "m1"));
+        .and_then(|runtime| ModuleRuntime::remove(&runtime, "m1", false));
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
@@ -1568,7 +1568,7 @@ fn remove_fails_for_empty_id() {
     let name = "";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRuntime::remove(&runtime, name))
+        .and_then(|runtime| ModuleRuntime::remove(&runtime, name, false))
         .then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
@@ -1600,7 +1600,7 @@ fn remove_fails_for_white_space_id() {
     let name = "      ";
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRuntime::remove(&runtime, name))
+        .and_then(|runtime| ModuleRuntime::remove(&runtime, name, false))
         .then(|result| match result {
             Ok(_) => panic!("Expected test to fail but it didn't!"),
             Err(err) => match err.kind() {
@@ -1,5 +1,8 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 mod module;
+mod retry;
+mod timeout;
 
-pub use self::module::ModuleClient;
+pub use self::module::{ModuleClient, DEFAULT_CONNECT_TIMEOUT, DEFAULT_REQUEST_TIMEOUT};
+pub use self::retry::RetryingModuleClient;
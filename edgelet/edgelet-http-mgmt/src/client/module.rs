@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -9,11 +10,17 @@ use failure::{Fail, ResultExt};
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
 use futures::stream;
-use hyper::{Body, Chunk as HyperChunk, Client};
+use hyper::header::HeaderMap;
+use hyper::{Body, Chunk as HyperChunk, Client, StatusCode};
 use management::apis::client::APIClient;
 use management::apis::configuration::Configuration;
-use management::models::{Config, ModuleDetails as HttpModuleDetails};
+use management::apis::Error as MgmtError;
+use management::models::{
+    Config, EnvVar, ModuleDetails as HttpModuleDetails, ModuleSpec as HttpModuleSpec,
+    SystemInfo as HttpSystemInfo,
+};
 use serde_json;
+use tokio::timer::Timeout;
 use url::Url;
 
 use edgelet_core::*;
@@ -21,21 +28,61 @@ use edgelet_core::{ModuleOperation, RuntimeOperation, SystemInfo as CoreSystemIn
 use edgelet_docker::{self, DockerConfig};
 use edgelet_http::{UrlConnector, API_VERSION};
 
+use super::retry::RetryingModuleClient;
+use super::timeout::TimeoutConnector;
 use crate::error::{Error, ErrorKind};
+use crate::util::RetryConfig;
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct ModuleClient {
     client: Arc<APIClient>,
+    request_timeout: Duration,
 }
 
 impl ModuleClient {
     pub fn new(url: &Url) -> Result<Self, Error> {
-        let client = Client::builder()
-            .build(UrlConnector::new(url).context(ErrorKind::InitializeModuleClient)?);
+        Self::with_timeout(url, DEFAULT_CONNECT_TIMEOUT, DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    /// Creates a client which adds `headers` to every request it sends.
+    pub fn with_headers(url: &Url, headers: HeaderMap) -> Result<Self, Error> {
+        Self::with_headers_and_timeout(
+            url,
+            headers,
+            DEFAULT_CONNECT_TIMEOUT,
+            DEFAULT_REQUEST_TIMEOUT,
+        )
+    }
+
+    /// Creates a client whose requests fail with `ErrorKind::RequestTimeout`
+    /// when iotedged hasn't responded within `request_timeout`, so that an
+    /// unresponsive iotedged doesn't leave callers waiting forever. For logs,
+    /// the timeout covers the response headers but not the log stream.
+    pub fn with_timeout(
+        url: &Url,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+    ) -> Result<Self, Error> {
+        Self::with_headers_and_timeout(url, HeaderMap::new(), connect_timeout, request_timeout)
+    }
+
+    pub fn with_headers_and_timeout(
+        url: &Url,
+        headers: HeaderMap,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+    ) -> Result<Self, Error> {
+        let connector = UrlConnector::new(url).context(ErrorKind::InitializeModuleClient)?;
+        let client = Client::builder().build(TimeoutConnector::new(connector, connect_timeout));
 
         let base_path = url
             .to_base_path()
             .context(ErrorKind::InitializeModuleClient)?;
         let mut configuration = Configuration::new(client);
+        configuration.headers = headers;
         configuration.base_path = base_path
             .to_str()
             .ok_or(ErrorKind::InitializeModuleClient)?
@@ -48,15 +95,173 @@ impl ModuleClient {
 
         let module_client = ModuleClient {
             client: Arc::new(APIClient::new(configuration)),
+            request_timeout,
         };
         Ok(module_client)
     }
+
+    /// Creates a client which retries requests that fail because iotedged is
+    /// unavailable, such as while it restarts. See `Error::is_transient`.
+    pub fn with_retry(url: &Url, config: RetryConfig) -> Result<RetryingModuleClient, Error> {
+        Self::new(url).map(|client| RetryingModuleClient::new(client, config))
+    }
+
+    /// Like `with_retry`, for a client built as by `with_headers_and_timeout`.
+    /// Each attempt is timed out on its own.
+    pub fn with_headers_timeout_and_retry(
+        url: &Url,
+        headers: HeaderMap,
+        connect_timeout: Duration,
+        request_timeout: Duration,
+        config: RetryConfig,
+    ) -> Result<RetryingModuleClient, Error> {
+        Self::with_headers_and_timeout(url, headers, connect_timeout, request_timeout)
+            .map(|client| RetryingModuleClient::new(client, config))
+    }
+
+    fn timeout<F>(&self, future: F) -> impl Future<Item = F::Item, Error = Error> + Send
+    where
+        F: Future<Error = Error> + Send,
+    {
+        Timeout::new(future, self.request_timeout).map_err(|err| {
+            if err.is_elapsed() {
+                Error::from(ErrorKind::RequestTimeout)
+            } else if err.is_inner() {
+                err.into_inner().expect("error is an inner error")
+            } else {
+                let err = err.into_timer().expect("error is a timer error");
+                Error::from(err.context(ErrorKind::RequestTimeout))
+            }
+        })
+    }
+
+    pub fn update_env(
+        &self,
+        id: &str,
+        env: HashMap<String, String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        let id = id.to_string();
+        let client = self.client.clone();
+
+        let update = self
+            .client
+            .module_api()
+            .get_module(&API_VERSION.to_string(), &id)
+            .map_err({
+                let id = id.clone();
+                move |err| Error::from_mgmt_error(err, ErrorKind::UpdateModule(id))
+            })
+            .and_then(move |details| {
+                let config = with_env_overrides(details.config(), env);
+                let spec =
+                    HttpModuleSpec::new(details.name().clone(), details.type_().clone(), config);
+
+                client
+                    .module_api()
+                    .update_module(&API_VERSION.to_string(), &id, spec)
+                    .map(|_| ())
+                    .map_err(move |err| Error::from_mgmt_error(err, ErrorKind::UpdateModule(id)))
+            });
+        self.timeout(update)
+    }
+
+    /// Creates a module named `new_id` with the same spec as module `id`, with
+    /// `env` merged into its environment.
+    pub fn copy(
+        &self,
+        id: &str,
+        new_id: &str,
+        env: BTreeMap<String, String>,
+    ) -> impl Future<Item = HttpModuleDetails, Error = Error> {
+        let id = id.to_string();
+        let new_id = new_id.to_string();
+        let client = self.client.clone();
+
+        let copy = self
+            .client
+            .module_api()
+            .get_module(&API_VERSION.to_string(), &id)
+            .map_err(move |err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
+                )
+            })
+            .and_then(move |details| {
+                let config = with_env_overrides(details.config(), env);
+                let spec = HttpModuleSpec::new(new_id.clone(), details.type_().clone(), config);
+
+                client
+                    .module_api()
+                    .create_module(&API_VERSION.to_string(), spec)
+                    .map_err(move |err| {
+                        Error::from_mgmt_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(new_id)),
+                        )
+                    })
+            });
+        self.timeout(copy)
+    }
+
+    /// The module `id`, or `None` if iotedged has no module by that name.
+    pub fn get(&self, id: &str) -> impl Future<Item = Option<ModuleDetails>, Error = Error> {
+        let id = id.to_string();
+
+        let module = self
+            .client
+            .module_api()
+            .get_module(&API_VERSION.to_string(), &id)
+            .then(move |result| match result {
+                Ok(m) => {
+                    let type_ = m.type_().clone();
+                    let config = m.config().clone();
+                    Ok(Some(ModuleDetails(m, ModuleConfig(type_, config))))
+                }
+                Err(MgmtError::Api(ref e)) if e.code == StatusCode::NOT_FOUND => Ok(None),
+                Err(err) => Err(Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
+                )),
+            });
+        self.timeout(module)
+    }
+
+    // Unlike ModuleRuntime::system_info this includes the version of iotedged
+    // itself rather than the version of the calling process.
+    pub fn get_system_info(&self) -> impl Future<Item = HttpSystemInfo, Error = Error> {
+        let system_info = self
+            .client
+            .system_information_api()
+            .get_system_info(&API_VERSION.to_string())
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                )
+            });
+        self.timeout(system_info)
+    }
+}
+
+fn with_env_overrides(config: &Config, env: impl IntoIterator<Item = (String, String)>) -> Config {
+    let mut env_vars = config.env().map(<[EnvVar]>::to_vec).unwrap_or_default();
+    for (key, value) in env {
+        if let Some(existing) = env_vars.iter_mut().find(|var| *var.key() == key) {
+            existing.set_value(value);
+        } else {
+            env_vars.push(EnvVar::new(key, value));
+        }
+    }
+
+    config.clone().with_env(env_vars)
 }
 
 impl Clone for ModuleClient {
     fn clone(&self) -> Self {
         ModuleClient {
             client: self.client.clone(),
+            request_timeout: self.request_timeout,
         }
     }
 }
@@ -67,6 +272,14 @@ pub struct ModuleDetails(HttpModuleDetails, ModuleConfig);
 #[derive(Clone, Debug)]
 pub struct ModuleConfig(String, Config);
 
+impl ModuleConfig {
+    /// The runtime specific settings of the module, such as the image and
+    /// create options of a Docker module.
+    pub fn settings(&self) -> &serde_json::Value {
+        self.1.settings()
+    }
+}
+
 impl fmt::Display for ModuleConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let edgelet_docker::MODULE_TYPE = self.0.as_ref() {
@@ -163,6 +376,8 @@ impl ModuleRuntime for ModuleClient {
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type SystemInfoFuture = Box<dyn Future<Item = CoreSystemInfo, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = serde_json::Value, Error = Self::Error> + Send>;
+    type ClearLogsFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         unimplemented!()
@@ -192,7 +407,7 @@ impl ModuleRuntime for ModuleClient {
                 },
                 other => other,
             });
-        Box::new(start)
+        Box::new(self.timeout(start))
     }
 
     fn stop(&self, id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
@@ -215,7 +430,7 @@ impl ModuleRuntime for ModuleClient {
                 },
                 other => other,
             });
-        Box::new(stop)
+        Box::new(self.timeout(stop))
     }
 
     fn restart(&self, id: &str) -> Self::RestartFuture {
@@ -238,10 +453,10 @@ impl ModuleRuntime for ModuleClient {
                 },
                 other => other,
             });
-        Box::new(restart)
+        Box::new(self.timeout(restart))
     }
 
-    fn remove(&self, _id: &str) -> Self::RemoveFuture {
+    fn remove(&self, _id: &str, _force: bool) -> Self::RemoveFuture {
         unimplemented!()
     }
 
@@ -271,11 +486,11 @@ impl ModuleRuntime for ModuleClient {
                     ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
                 )
             });
-        Box::new(modules)
+        Box::new(self.timeout(modules))
     }
 
     fn list_with_details(&self) -> Self::ListWithDetailsStream {
-        let modules = self
+        let list = self
             .client
             .module_api()
             .list_modules(&API_VERSION.to_string())
@@ -284,7 +499,9 @@ impl ModuleRuntime for ModuleClient {
                     err,
                     ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
                 )
-            })
+            });
+        let modules = self
+            .timeout(list)
             .map(|list| {
                 let iter = list.modules().to_owned().into_iter().map(|m| {
                     let type_ = m.type_().clone();
@@ -321,7 +538,7 @@ impl ModuleRuntime for ModuleClient {
                     ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id)),
                 )),
             });
-        Box::new(result)
+        Box::new(self.timeout(result))
     }
 
     fn registry(&self) -> &Self::ModuleRegistry {
@@ -337,6 +554,38 @@ impl ModuleRuntime for ModuleClient {
             future::join_all(n).map(|_| ())
         }))
     }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        let id = id.to_string();
+
+        let stats = self
+            .client
+            .module_api()
+            .module_stats(&API_VERSION.to_string(), &id)
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleStats(id)),
+                )
+            });
+        Box::new(self.timeout(stats))
+    }
+
+    fn clear_logs(&self, id: &str) -> Self::ClearLogsFuture {
+        let id = id.to_string();
+
+        let clear = self
+            .client
+            .module_api()
+            .clear_module_logs(&API_VERSION.to_string(), &id)
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ClearModuleLogs(id)),
+                )
+            });
+        Box::new(self.timeout(clear))
+    }
 }
 
 pub struct Logs(String, Body);
@@ -363,3 +612,37 @@ impl AsRef<[u8]> for Chunk {
         self.0.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use edgelet_core::ModuleRuntime;
+    use tokio::runtime::Runtime;
+    use url::Url;
+
+    use super::ModuleClient;
+    use crate::ErrorKind;
+
+    #[test]
+    fn unanswered_request_times_out() {
+        // connections are accepted by the OS but never answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+        let client =
+            ModuleClient::with_timeout(&url, Duration::from_secs(5), Duration::from_millis(100))
+                .unwrap();
+
+        let err = Runtime::new().unwrap().block_on(client.list()).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::RequestTimeout => (),
+            kind => panic!("expected a request timeout, got {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use futures::prelude::*;
+use futures::stream;
+use management::models::{ModuleDetails as HttpModuleDetails, SystemInfo as HttpSystemInfo};
+
+use edgelet_core::{LogOptions, ModuleRuntime, ModuleRuntimeState, ModuleSpec, SystemInfo};
+
+use super::module::{Chunk, Logs, ModuleClient, ModuleConfig, ModuleDetails};
+use crate::error::Error;
+use crate::util::{retry_future, RetryConfig};
+
+/// A `ModuleClient` which retries requests that fail because iotedged is
+/// unavailable, as while it restarts, with exponential backoff.
+#[derive(Clone)]
+pub struct RetryingModuleClient {
+    client: ModuleClient,
+    config: RetryConfig,
+}
+
+impl RetryingModuleClient {
+    pub fn new(client: ModuleClient, config: RetryConfig) -> Self {
+        RetryingModuleClient { client, config }
+    }
+
+    pub fn client(&self) -> &ModuleClient {
+        &self.client
+    }
+
+    pub fn config(&self) -> &RetryConfig {
+        &self.config
+    }
+
+    pub fn update_env(
+        &self,
+        id: &str,
+        env: HashMap<String, String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        let id = id.to_string();
+        self.retry(move |client| client.update_env(&id, env.clone()))
+    }
+
+    /// Not retried, as the copy may have been created by a request which
+    /// failed after iotedged received it.
+    pub fn copy(
+        &self,
+        id: &str,
+        new_id: &str,
+        env: BTreeMap<String, String>,
+    ) -> impl Future<Item = HttpModuleDetails, Error = Error> {
+        self.client.copy(id, new_id, env)
+    }
+
+    pub fn get(&self, id: &str) -> impl Future<Item = Option<ModuleDetails>, Error = Error> {
+        let id = id.to_string();
+        self.retry(move |client| client.get(&id))
+    }
+
+    pub fn get_system_info(&self) -> impl Future<Item = HttpSystemInfo, Error = Error> {
+        self.retry(|client| client.get_system_info())
+    }
+
+    fn retry<F, R>(&self, mut operation: F) -> impl Future<Item = R::Item, Error = Error> + Send
+    where
+        F: FnMut(&ModuleClient) -> R + Send,
+        R: IntoFuture<Error = Error>,
+        R::Future: Send,
+        R::Item: Send,
+    {
+        let client = self.client.clone();
+        retry_future(self.config, move || operation(&client), Error::is_transient)
+    }
+}
+
+impl ModuleRuntime for RetryingModuleClient {
+    type Error = Error;
+    type Config = ModuleConfig;
+    type Module = ModuleDetails;
+    type ModuleRegistry = ModuleClient;
+    type Chunk = Chunk;
+    type Logs = Logs;
+
+    type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type GetFuture =
+        Box<dyn Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type ListFuture = Box<dyn Future<Item = Vec<Self::Module>, Error = Self::Error> + Send>;
+    type ListWithDetailsStream =
+        Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type SystemInfoFuture = Box<dyn Future<Item = SystemInfo, Error = Self::Error> + Send>;
+    type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = serde_json::Value, Error = Self::Error> + Send>;
+    type ClearLogsFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        ModuleRuntime::create(&self.client, module)
+    }
+
+    fn get(&self, id: &str) -> Self::GetFuture {
+        ModuleRuntime::get(&self.client, id)
+    }
+
+    fn start(&self, id: &str) -> Self::StartFuture {
+        let id = id.to_string();
+        Box::new(self.retry(move |client| client.start(&id)))
+    }
+
+    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        let id = id.to_string();
+        Box::new(self.retry(move |client| client.stop(&id, wait_before_kill)))
+    }
+
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        let id = id.to_string();
+        Box::new(self.retry(move |client| client.restart(&id)))
+    }
+
+    fn remove(&self, id: &str, force: bool) -> Self::RemoveFuture {
+        ModuleRuntime::remove(&self.client, id, force)
+    }
+
+    fn system_info(&self) -> Self::SystemInfoFuture {
+        ModuleRuntime::system_info(&self.client)
+    }
+
+    fn list(&self) -> Self::ListFuture {
+        Box::new(self.retry(|client| client.list()))
+    }
+
+    fn list_with_details(&self) -> Self::ListWithDetailsStream {
+        let modules = self
+            .retry(|client| client.list_with_details().collect())
+            .map(stream::iter_ok)
+            .flatten_stream();
+        Box::new(modules)
+    }
+
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        let id = id.to_string();
+        let options = options.clone();
+        Box::new(self.retry(move |client| client.logs(&id, &options)))
+    }
+
+    fn registry(&self) -> &Self::ModuleRegistry {
+        &self.client
+    }
+
+    fn remove_all(&self) -> Self::RemoveAllFuture {
+        ModuleRuntime::remove_all(&self.client)
+    }
+
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        let id = id.to_string();
+        Box::new(self.retry(move |client| client.stats(&id)))
+    }
+
+    fn clear_logs(&self, id: &str) -> Self::ClearLogsFuture {
+        let id = id.to_string();
+        Box::new(self.retry(move |client| client.clear_logs(&id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use edgelet_core::ModuleRuntime;
+    use edgelet_test_utils::{get_unused_tcp_port, run_tcp_server};
+    use futures::{future, Future};
+    use hyper::{Body, Request, Response, StatusCode};
+    use tokio::runtime::Runtime;
+    use url::Url;
+
+    use crate::util::RetryConfig;
+    use crate::ModuleClient;
+
+    #[test]
+    fn list_is_retried_while_iotedged_is_unavailable() {
+        let port = get_unused_tcp_port();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let requests_copy = requests.clone();
+        let server = run_tcp_server("127.0.0.1", port, move |_req: Request<Body>| {
+            let response = if requests_copy.fetch_add(1, Ordering::SeqCst) < 2 {
+                Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body(r#"{"message":"starting"}"#.into())
+                    .unwrap()
+            } else {
+                Response::new(r#"{"modules":[]}"#.into())
+            };
+            future::ok(response)
+        })
+        .map_err(|err| eprintln!("{}", err));
+
+        let url = Url::parse(&format!("http://localhost:{}", port)).unwrap();
+        let config = RetryConfig::new(Duration::from_millis(1), Duration::from_millis(10), 5);
+        let client = ModuleClient::with_retry(&url, config).unwrap();
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(server);
+        let modules = runtime.block_on(client.list()).unwrap();
+
+        assert!(modules.is_empty());
+        assert_eq!(3, requests.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn refused_connections_are_transient() {
+        let port = get_unused_tcp_port();
+        let url = Url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        let config = RetryConfig::new(Duration::from_millis(1), Duration::from_millis(10), 2);
+        let client = ModuleClient::with_retry(&url, config).unwrap();
+
+        let err = Runtime::new().unwrap().block_on(client.list()).unwrap_err();
+
+        assert!(err.is_transient());
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io;
+use std::time::Duration;
+
+use futures::Future;
+use hyper::client::connect::{Connect, Connected, Destination};
+use tokio::timer::Timeout;
+
+/// Wraps a connector so that connections which aren't established within
+/// `timeout` fail with `io::ErrorKind::TimedOut`.
+pub struct TimeoutConnector<C> {
+    connector: C,
+    timeout: Duration,
+}
+
+impl<C> TimeoutConnector<C> {
+    pub fn new(connector: C, timeout: Duration) -> Self {
+        TimeoutConnector { connector, timeout }
+    }
+}
+
+impl<C> Connect for TimeoutConnector<C>
+where
+    C: Connect<Error = io::Error>,
+    C::Future: 'static,
+{
+    type Transport = C::Transport;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = (Self::Transport, Connected), Error = Self::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let timeout = self.timeout;
+        let connect = Timeout::new(self.connector.connect(dst), timeout).map_err(move |err| {
+            if err.is_elapsed() {
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("Connection timed out after {:?}", timeout),
+                )
+            } else if err.is_inner() {
+                err.into_inner().expect("error is an inner error")
+            } else {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    err.into_timer().expect("error is a timer error"),
+                )
+            }
+        });
+
+        Box::new(connect)
+    }
+}
@@ -7,7 +7,7 @@ use edgelet_docker::ErrorKind as DockerErrorKind;
 use edgelet_iothub::Error as IoTHubError;
 use failure::{Backtrace, Context, Fail};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
-use hyper::{Body, Response, StatusCode};
+use hyper::{Body, Error as HyperError, Response, StatusCode};
 use log::error;
 use serde_json;
 
@@ -54,6 +54,9 @@ pub enum ErrorKind {
     #[fail(display = "State not modified")]
     NotModified,
 
+    #[fail(display = "The management API did not respond in time")]
+    RequestTimeout,
+
     #[fail(display = "Could not prepare update for module {:?}", _0)]
     PrepareUpdateModule(String),
 
@@ -98,6 +101,47 @@ impl Error {
             MgmtError::Api(_) => Error::from(ErrorKind::Client(error).context(context)),
         }
     }
+
+    /// Whether the request failed because iotedged couldn't serve it at the
+    /// time, as while it restarts: connecting to it failed or it responded
+    /// with 503 Service Unavailable.
+    pub fn is_transient(&self) -> bool {
+        Fail::iter_chain(self).any(|cause| {
+            if let Some(ErrorKind::Client(MgmtError::Api(ref e))) =
+                cause.downcast_ref::<ErrorKind>()
+            {
+                e.code == StatusCode::SERVICE_UNAVAILABLE
+            } else if let Some(e) = cause.downcast_ref::<HyperError>() {
+                e.is_connect()
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Whether iotedged responded with 404 Not Found, as for a module it
+    /// doesn't have.
+    pub fn is_not_found(&self) -> bool {
+        self.has_status(StatusCode::NOT_FOUND)
+    }
+
+    /// Whether iotedged responded with 409 Conflict, as when asked to clear
+    /// logs the container runtime doesn't keep in a file.
+    pub fn is_conflict(&self) -> bool {
+        self.has_status(StatusCode::CONFLICT)
+    }
+
+    fn has_status(&self, status: StatusCode) -> bool {
+        Fail::iter_chain(self).any(|cause| {
+            if let Some(ErrorKind::Client(MgmtError::Api(ref e))) =
+                cause.downcast_ref::<ErrorKind>()
+            {
+                e.code == status
+            } else {
+                false
+            }
+        })
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -9,11 +9,15 @@ use hyper::{Body, Response};
 mod client;
 mod error;
 mod server;
+pub mod util;
 
-pub use client::ModuleClient;
+pub use client::{
+    ModuleClient, RetryingModuleClient, DEFAULT_CONNECT_TIMEOUT, DEFAULT_REQUEST_TIMEOUT,
+};
 pub use error::{Error, ErrorKind};
 pub use server::ListModules;
 pub use server::ManagementService;
+pub use util::RetryConfig;
 
 pub trait IntoResponse {
     fn into_response(self) -> Response<Body>;
@@ -57,6 +57,8 @@ impl ManagementService {
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stop"      => StopModule::new(runtime.clone()),
             post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/restart"   => RestartModule::new(runtime.clone()),
             get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone()),
+            delete  Version2019_01_30 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ClearModuleLogs::new(runtime.clone()),
+            get     Version2019_01_30 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stats"     => GetModuleStats::new(runtime.clone()),
 
             get     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => ListIdentities::new(identity.clone()),
             post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => CreateIdentity::new(identity.clone()),
@@ -3,6 +3,7 @@
 use failure::{Fail, ResultExt};
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
+use url::form_urlencoded::parse as parse_query;
 
 use edgelet_core::{ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
@@ -27,21 +28,34 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let response = params
             .name("name")
             .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
-            .map(|name| {
-                let name = name.to_string();
-
-                self.runtime.remove(&name).then(|result| match result {
-                    Ok(_) => Ok(name),
-                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
-                        RuntimeOperation::RemoveModule(name),
-                    )))),
-                })
+            .and_then(|name| {
+                let force = req
+                    .uri()
+                    .query()
+                    .and_then(|query| {
+                        parse_query(query.as_bytes())
+                            .find(|&(ref key, _)| key == "force")
+                            .map(|(_, val)| val.parse::<bool>())
+                    })
+                    .unwrap_or(Ok(false))
+                    .context(ErrorKind::MalformedRequestParameter("force"))?;
+                Ok((name.to_string(), force))
+            })
+            .map(|(name, force)| {
+                self.runtime
+                    .remove(&name, force)
+                    .then(|result| match result {
+                        Ok(_) => Ok(name),
+                        Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::RemoveModule(name),
+                        )))),
+                    })
             })
             .into_future()
             .flatten()
@@ -138,4 +152,35 @@ mod tests {
         // assert
         assert_eq!(StatusCode::BAD_REQUEST, response.status());
     }
+
+    #[test]
+    fn delete_bad_force() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = DeleteModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::delete("http://localhost/modules/test?force=yes")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
 }
@@ -87,13 +87,18 @@ where
         }
     }
 
-    Ok(ModuleDetails::new(
+    let mut details = ModuleDetails::new(
         "id".to_string(),
         module.name().to_string(),
         module.type_().to_string(),
         config,
         status,
-    ))
+    );
+    if let Some(pod_ip) = state.pod_ip() {
+        details.set_pod_ip(pod_ip.to_string());
+    }
+
+    Ok(details)
 }
 
 #[cfg(test)]
@@ -118,7 +123,8 @@ mod tests {
             .with_status_description(Some("description".to_string()))
             .with_started_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(14, 20, 0, 1)))
             .with_finished_at(Some(Utc.ymd(2018, 4, 13).and_hms_milli(15, 20, 0, 1)))
-            .with_image_id(Some("image-id".to_string()));
+            .with_image_id(Some("image-id".to_string()))
+            .with_pod_ip(Some("10.244.0.12".to_string()));
         let config = TestConfig::new("microsoft/test-image".to_string());
         let module: TestModule<Error, _> =
             TestModule::new("test-module".to_string(), config, Ok(state));
@@ -169,6 +175,7 @@ mod tests {
                     "description",
                     module.status().runtime_status().description().unwrap()
                 );
+                assert_eq!(Some("10.244.0.12"), module.pod_ip());
                 Ok(())
             })
             .wait()
@@ -14,6 +14,7 @@ use management::models::*;
 
 use crate::error::{Error, ErrorKind};
 
+mod clear_logs;
 mod create;
 mod delete;
 mod get;
@@ -22,9 +23,11 @@ mod logs;
 mod prepare_update;
 mod restart;
 mod start;
+mod stats;
 mod stop;
 mod update;
 
+pub use self::clear_logs::ClearModuleLogs;
 pub use self::create::CreateModule;
 pub use self::delete::DeleteModule;
 pub use self::get::GetModule;
@@ -33,6 +36,7 @@ pub use self::logs::ModuleLogs;
 pub use self::prepare_update::PrepareUpdateModule;
 pub use self::restart::RestartModule;
 pub use self::start::StartModule;
+pub use self::stats::GetModuleStats;
 pub use self::stop::StopModule;
 pub use self::update::UpdateModule;
 
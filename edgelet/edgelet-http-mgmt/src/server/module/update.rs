@@ -71,7 +71,7 @@ where
                     info!("Updating module {}", name);
                 }
 
-                runtime.remove(&name).then(|result| {
+                runtime.remove(&name, false).then(|result| {
                     result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
                     Ok((core_spec, spec, name, runtime))
                 })
@@ -0,0 +1,199 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either, Loop};
+use futures::{Future, IntoFuture};
+use rand::Rng;
+use tokio::timer::Delay;
+
+pub const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+pub const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(10);
+
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 5;
+
+/// How often, and how far apart, a failed operation is retried. The delay
+/// before each retry doubles from `base_delay` up to `max_delay`, and is then
+/// cut by a random amount of up to half so that clients which failed together
+/// don't all retry together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryConfig {
+    base_delay: Duration,
+    max_delay: Duration,
+    max_attempts: u32,
+}
+
+impl RetryConfig {
+    pub fn new(base_delay: Duration, max_delay: Duration, max_attempts: u32) -> Self {
+        RetryConfig {
+            base_delay,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    pub fn base_delay(&self) -> Duration {
+        self.base_delay
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    /// The number of times the operation is tried, including the first.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = 2_u32
+            .checked_pow(retry)
+            .and_then(|factor| self.base_delay.checked_mul(factor))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+
+        let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_millis());
+        let jitter = rand::thread_rng().gen_range(0, millis / 2 + 1);
+        Duration::from_millis(millis - jitter)
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig::new(
+            DEFAULT_RETRY_BASE_DELAY,
+            DEFAULT_RETRY_MAX_DELAY,
+            DEFAULT_RETRY_MAX_ATTEMPTS,
+        )
+    }
+}
+
+/// Runs the future `operation` returns, and runs a new one after a delay for
+/// as long as it fails with an error `should_retry` accepts and attempts are
+/// left. The error of the last attempt is returned when all of them fail.
+pub fn retry_future<F, R, P>(
+    config: RetryConfig,
+    mut operation: F,
+    should_retry: P,
+) -> impl Future<Item = R::Item, Error = R::Error> + Send
+where
+    F: FnMut() -> R + Send,
+    R: IntoFuture,
+    R::Future: Send,
+    R::Item: Send,
+    R::Error: Send,
+    P: Fn(&R::Error) -> bool + Send + Sync + 'static,
+{
+    let should_retry = Arc::new(should_retry);
+
+    future::loop_fn(1, move |attempt| {
+        let should_retry = should_retry.clone();
+
+        operation().into_future().then(move |result| match result {
+            Ok(item) => Either::A(future::ok(Loop::Break(item))),
+            Err(err) => {
+                if attempt < config.max_attempts && should_retry(&err) {
+                    // A failed timer only means the retry isn't delayed.
+                    let delay = Delay::new(Instant::now() + config.delay(attempt - 1))
+                        .then(move |_| Ok(Loop::Continue(attempt + 1)));
+                    Either::B(delay)
+                } else {
+                    Either::A(future::err(err))
+                }
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::future;
+    use tokio::runtime::Runtime;
+
+    use super::{retry_future, RetryConfig};
+
+    fn config() -> RetryConfig {
+        RetryConfig::new(Duration::from_millis(1), Duration::from_millis(4), 5)
+    }
+
+    #[test]
+    fn operation_is_retried_until_it_succeeds() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let attempts_copy = attempts.clone();
+
+        let task = retry_future(
+            config(),
+            move || {
+                if attempts_copy.fetch_add(1, Ordering::SeqCst) < 2 {
+                    future::err("unavailable")
+                } else {
+                    future::ok(42)
+                }
+            },
+            |_| true,
+        );
+
+        assert_eq!(42, Runtime::new().unwrap().block_on(task).unwrap());
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn operation_is_tried_at_most_max_attempts_times() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let attempts_copy = attempts.clone();
+
+        let task = retry_future(
+            config(),
+            move || {
+                attempts_copy.fetch_add(1, Ordering::SeqCst);
+                future::err::<(), _>("unavailable")
+            },
+            |_| true,
+        );
+
+        assert_eq!(
+            "unavailable",
+            Runtime::new().unwrap().block_on(task).unwrap_err()
+        );
+        assert_eq!(5, attempts.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn errors_which_are_not_retried_fail_at_once() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let attempts_copy = attempts.clone();
+
+        let task = retry_future(
+            config(),
+            move || {
+                attempts_copy.fetch_add(1, Ordering::SeqCst);
+                future::err::<(), _>("not found")
+            },
+            |err| *err == "unavailable",
+        );
+
+        assert!(Runtime::new().unwrap().block_on(task).is_err());
+        assert_eq!(1, attempts.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max_delay() {
+        let config = RetryConfig::new(Duration::from_millis(100), Duration::from_secs(10), 5);
+
+        for _ in 0..20 {
+            let first = config.delay(0);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+
+            let third = config.delay(2);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+
+            let late = config.delay(40);
+            assert!(late >= Duration::from_secs(5) && late <= Duration::from_secs(10));
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+
+use futures::{Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use crate::KubeModuleRuntime;
+
+/// API groups registered by the Azure Arc agents when a cluster is connected.
+const AZURE_ARC_API_GROUPS: &[&str] = &["arc.azure.com", "clusterconfig.azure.com"];
+
+/// Selects the control plane nodes of an AKS Engine cluster. AKS labels its
+/// agent nodes with `kubernetes.azure.com/role` too, but it never exposes its
+/// masters, so only AKS Engine has nodes matching this selector.
+const AKS_ENGINE_MASTER_SELECTOR: &str = "kubernetes.azure.com/role=master";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClusterType {
+    AzureArc,
+    AksEngine,
+    Standard,
+    Unknown,
+}
+
+impl Default for ClusterType {
+    fn default() -> Self {
+        ClusterType::Unknown
+    }
+}
+
+impl fmt::Display for ClusterType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ClusterType::AzureArc => "Azure Arc",
+            ClusterType::AksEngine => "AKS Engine",
+            ClusterType::Standard => "Standard",
+            ClusterType::Unknown => "Unknown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Probes the API server to find out what kind of cluster the runtime is
+/// running in. The API groups come from the client's cached API discovery.
+/// Detection never fails: a cluster whose APIs cannot be discovered is
+/// reported as `ClusterType::Unknown`, and a cluster whose nodes cannot be
+/// listed is assumed not to be AKS Engine. The result is only reported in the
+/// runtime's startup log; nothing in the runtime behaves differently on it.
+pub fn detect_cluster_type<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+) -> impl Future<Item = ClusterType, Error = ()>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = runtime.client().clone();
+
+    runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .discover_apis()
+        .then(move |discovery| {
+            let arc = discovery.map(|discovery| {
+                discovery
+                    .groups()
+                    .iter()
+                    .any(|group| AZURE_ARC_API_GROUPS.contains(&group.name()))
+            });
+
+            let nodes = client_copy
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_nodes(Some(AKS_ENGINE_MASTER_SELECTOR));
+
+            nodes.then(move |nodes| {
+                let cluster_type = match arc {
+                    Err(_) => ClusterType::Unknown,
+                    Ok(true) => ClusterType::AzureArc,
+                    Ok(false) => match nodes {
+                        Ok(ref nodes) if !nodes.items.is_empty() => ClusterType::AksEngine,
+                        _ => ClusterType::Standard,
+                    },
+                };
+
+                Ok(cluster_type)
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::cluster::{detect_cluster_type, ClusterType};
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_detects_azure_arc() {
+        let dispatch_table = routes!(
+            GET "/api" => core_versions(),
+            GET "/apis" => api_groups(&["apps", "clusterconfig.azure.com"]),
+            GET "/api/v1/nodes" => nodes(false),
+        );
+
+        assert_eq!(ClusterType::AzureArc, detect(dispatch_table));
+    }
+
+    #[test]
+    fn it_detects_aks_engine() {
+        let dispatch_table = routes!(
+            GET "/api" => core_versions(),
+            GET "/apis" => api_groups(&["apps"]),
+            GET "/api/v1/nodes" => nodes(true),
+        );
+
+        assert_eq!(ClusterType::AksEngine, detect(dispatch_table));
+    }
+
+    #[test]
+    fn it_detects_standard_cluster() {
+        let dispatch_table = routes!(
+            GET "/api" => core_versions(),
+            GET "/apis" => api_groups(&["apps"]),
+            GET "/api/v1/nodes" => nodes(false),
+        );
+
+        assert_eq!(ClusterType::Standard, detect(dispatch_table));
+    }
+
+    #[test]
+    fn it_detects_standard_cluster_when_nodes_unavailable() {
+        let dispatch_table = routes!(
+            GET "/api" => core_versions(),
+            GET "/apis" => api_groups(&["apps"]),
+        );
+
+        assert_eq!(ClusterType::Standard, detect(dispatch_table));
+    }
+
+    #[test]
+    fn it_reports_unknown_when_discovery_fails() {
+        let dispatch_table = routes!(
+            GET "/api/v1/nodes" => nodes(true),
+        );
+
+        assert_eq!(ClusterType::Unknown, detect(dispatch_table));
+    }
+
+    fn core_versions() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({ "kind": "APIVersions", "versions": ["v1"] }).to_string()
+            })
+        }
+    }
+
+    fn detect(dispatch_table: BTreeMap<(HttpMethod, RequestPath), RequestHandler>) -> ClusterType {
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = detect_cluster_type(&runtime);
+
+        Runtime::new().unwrap().block_on(task).unwrap()
+    }
+
+    fn api_groups(names: &[&str]) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let groups: Vec<_> = names
+            .iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "versions": [{ "groupVersion": format!("{}/v1", name), "version": "v1" }]
+                })
+            })
+            .collect();
+
+        let body = json!({
+            "kind": "APIGroupList",
+            "apiVersion": "v1",
+            "groups": groups
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+
+    fn nodes(aks_engine: bool) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, move || {
+                let items = if aks_engine {
+                    json!([{
+                        "metadata": {
+                            "name": "k8s-master-12345678-0",
+                            "labels": { "kubernetes.azure.com/role": "master" }
+                        }
+                    }])
+                } else {
+                    json!([])
+                };
+
+                json!({
+                    "kind": "NodeList",
+                    "apiVersion": "v1",
+                    "metadata": {},
+                    "items": items
+                })
+                .to_string()
+            })
+        }
+    }
+}
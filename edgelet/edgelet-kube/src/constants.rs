@@ -1,7 +1,11 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use kube_client::CustomResource;
+
 pub const EDGE_EDGE_AGENT_NAME: &str = "edgeagent";
 
+pub const EDGE_EDGE_HUB_NAME: &str = "edgehub";
+
 pub const EDGE_MODULE_LABEL: &str = "net.azure-devices.edge.module";
 
 pub const EDGE_ORIGINAL_MODULEID: &str = "net.azure-devices.edge.original-moduleid";
@@ -10,6 +14,46 @@ pub const EDGE_DEVICE_LABEL: &str = "net.azure-devices.edge.deviceid";
 
 pub const EDGE_HUBNAME_LABEL: &str = "net.azure-devices.edge.hub";
 
+pub const CONFIG_MAP_MIRROR_RETRY_SECS: u64 = 10;
+
+pub const ACTIVE_DEADLINE_ENFORCE_INTERVAL_SECS: u64 = 10;
+
+pub const PVC_LOCK_TIMEOUT_SECS: u64 = 60;
+
+pub const PVC_LOCK_RETRY_INTERVAL_MILLIS: u64 = 500;
+
+pub const EDGE_LOG_MAX_SIZE_ANNOTATION: &str = "net.azure-devices.edge.log-max-size";
+
+pub const EDGE_LOG_MAX_FILES_ANNOTATION: &str = "net.azure-devices.edge.log-max-files";
+
+pub const EDGE_ACTIVE_DEADLINE_ANNOTATION: &str = "net.azure-devices.edge.active-deadline-seconds";
+
+pub const EDGE_READINESS_GATES_ANNOTATION: &str = "net.azure-devices.edge.readiness-gates";
+
+pub const SAFE_TO_EVICT_ANNOTATION: &str = "cluster-autoscaler.kubernetes.io/safe-to-evict";
+
+// module env vars with this prefix are set as pod annotations instead, with
+// the rest of the variable name as the annotation key
+pub const ANNOTATION_ENV_PREFIX: &str = "k8s.annotation.";
+
+// a module env var naming, comma separated, more image pull secrets for the
+// module's pod
+pub const IMAGE_PULL_SECRET_ENV: &str = "k8s.imagePullSecret";
+
+pub const SECCOMP_POD_ANNOTATION: &str = "seccomp.security.alpha.kubernetes.io/pod";
+
+pub const GRPC_HEALTH_PROBE_PATH: &str = "/bin/grpc_health_probe";
+
+pub const APPARMOR_ANNOTATION_PREFIX: &str = "container.apparmor.security.beta.kubernetes.io";
+
+pub const DEFAULT_CLUSTER_DOMAIN: &str = "cluster.local";
+
+pub const DEFAULT_EDGE_HUB_DRAIN_GRACE_PERIOD_SECS: u64 = 60;
+
+pub const HEADLESS_SERVICE_SUFFIX: &str = "-headless";
+
+pub const NODE_OS_LABEL: &str = "beta.kubernetes.io/os";
+
 pub const PROXY_CONTAINER_NAME: &str = "proxy";
 
 pub const PROXY_CONFIG_VOLUME_NAME: &str = "config-volume";
@@ -18,6 +62,91 @@ pub const PROXY_TRUST_BUNDLE_VOLUME_NAME: &str = "trust-bundle-volume";
 
 pub const PROXY_TRUST_BUNDLE_FILENAME: &str = "trust_bundle.pem";
 
+pub const TRUST_BUNDLE_INIT_CONTAINER_NAME: &str = "trust-bundle-init";
+
+pub const TRUST_BUNDLE_INIT_IMAGE: &str = "busybox:1.31";
+
+pub const OS_TRUST_STORE_VOLUME_NAME: &str = "os-trust-store-volume";
+
+// A directory of its own under the source directory of update-ca-certificates,
+// so that the certificates the image ships with aren't hidden by the mount.
+pub const OS_TRUST_STORE_PATH: &str = "/usr/local/share/ca-certificates/iotedge";
+
+// update-ca-certificates only picks up files with a .crt extension
+pub const OS_TRUST_STORE_FILENAME: &str = "trust_bundle.crt";
+
+pub const PROXY_ENV_KEYS: &[&str] = &["HTTPS_PROXY", "NO_PROXY"];
+
 pub const USE_PERSISTENT_VOLUME_CLAIMS: &str = "USE_PERSISTENT_VOLUMES";
 
 pub const PULL_SECRET_DATA_NAME: &str = ".dockerconfigjson";
+
+// the API server rejects config maps whose data is larger than this many bytes
+pub const CONFIG_MAP_MAX_DATA_SIZE: usize = 1024 * 1024;
+
+pub const EDGE_METRICS_PORT_LABEL: &str = "net.azure-devices.edge.metrics-port";
+
+pub const EDGE_METRICS_PATH_LABEL: &str = "net.azure-devices.edge.metrics-path";
+
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+pub const POD_MONITOR: CustomResource =
+    CustomResource::new("monitoring.coreos.com", "v1", "podmonitors");
+
+pub const CERTIFICATE: CustomResource =
+    CustomResource::new("cert-manager.io", "v1", "certificates");
+
+pub const TLS_CERTIFICATE_VOLUME_NAME: &str = "tls-certificate-volume";
+
+pub const TLS_CERTIFICATE_MOUNT_PATH: &str = "/etc/iotedge/tls";
+
+pub const TLS_CERT_PATH: &str = "TLS_CERT_PATH";
+
+pub const TLS_KEY_PATH: &str = "TLS_KEY_PATH";
+
+pub const SERVICE_EXPORT: CustomResource =
+    CustomResource::new("multicluster.x-k8s.io", "v1alpha1", "serviceexports");
+
+pub const VERTICAL_POD_AUTOSCALER: CustomResource =
+    CustomResource::new("autoscaling.k8s.io", "v1", "verticalpodautoscalers");
+
+/// Static CSI volumes ignore the requested size, but Kubernetes requires
+/// Persistent Volumes and their claims to declare a capacity.
+pub const CSI_VOLUME_CAPACITY: &str = "1Gi";
+
+pub const VALIDATING_ADMISSION_POLICY: CustomResource = CustomResource::cluster_scoped(
+    "admissionregistration.k8s.io",
+    "v1",
+    "validatingadmissionpolicies",
+);
+
+pub const VALIDATING_ADMISSION_POLICY_BINDING: CustomResource = CustomResource::cluster_scoped(
+    "admissionregistration.k8s.io",
+    "v1",
+    "validatingadmissionpolicybindings",
+);
+
+pub const AZURE_INSTANCE_METADATA_TOKEN_ENDPOINT: &str =
+    "http://169.254.169.254/metadata/identity/oauth2/token";
+
+pub const AZURE_AUTHORITY_HOST: &str = "https://login.microsoftonline.com/";
+
+pub const AZURE_AD_TOKEN_EXCHANGE_AUDIENCE: &str = "api://AzureADTokenExchange";
+
+pub const WORKLOAD_IDENTITY_TOKEN_EXPIRATION_SECS: u64 = 3600;
+
+pub const KEY_VAULT_RESOURCE: &str = "https://vault.azure.net";
+
+pub const KEY_VAULT_API_VERSION: &str = "7.0";
+
+pub const KEY_VAULT_SECRET_DATA_NAME: &str = "value";
+
+pub const DEFAULT_REKOR_URL: &str = "https://rekor.sigstore.dev";
+
+pub const EVENT_SOURCE_COMPONENT: &str = "iotedged";
+
+pub const PROVISIONED_REASON: &str = "Provisioned";
+
+pub const REPROVISIONED_REASON: &str = "Reprovisioned";
+
+pub const PROVISIONING_FAILED_REASON: &str = "ProvisioningFailed";
@@ -6,9 +6,14 @@ use edgelet_utils::sanitize_dns_label;
 mod to_docker;
 mod to_k8s;
 
-pub use self::to_docker::pod_to_module;
+pub use self::to_docker::{deployment_to_status, pod_deadline_exceeded, pod_to_module};
 pub use self::to_k8s::{
-    auth_to_image_pull_secret, spec_to_deployment, spec_to_role_binding, spec_to_service_account,
+    apply_node_labels, apply_vpa_recommendation, auth_to_image_pull_secret, check_config_map_size,
+    key_vault_secret_to_secret, module_to_headless_service, settings_to_admission_policy,
+    settings_to_cluster_role, settings_to_cluster_role_binding, settings_to_event,
+    settings_to_role, settings_to_role_binding, spec_to_certificate,
+    spec_to_csi_volume_claim_patches, spec_to_csi_volumes, spec_to_deployment, spec_to_pod_monitor,
+    spec_to_role_binding, spec_to_service, spec_to_service_account, spec_to_service_export,
     trust_bundle_to_config_map,
 };
 
@@ -1,12 +1,17 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::time::Duration;
+
+use chrono::{Duration as ChronoDuration, Utc};
 use docker::models::ContainerCreateBody;
 use edgelet_docker::DockerConfig;
+use k8s_openapi::api::apps::v1 as api_apps;
 use k8s_openapi::api::core::v1 as api_core;
 use log::debug;
 
 use crate::constants::*;
 use crate::error::{Error, ErrorKind, Result};
+use crate::module::{DeploymentCondition, DeploymentStatus};
 use crate::KubeModule;
 
 fn get_container_by_name<'a>(
@@ -58,15 +63,72 @@ pub fn pod_to_module(pod: &api_core::Pod) -> Option<Result<KubeModule>> {
                 )
                 .map_err(Error::from)
                 .and_then(|config| KubeModule::new(module_id.to_string(), config))
+                .map(|module| {
+                    let pod_ip = pod.status.as_ref().and_then(|status| status.pod_ip.clone());
+                    module
+                        .with_pod_ip(pod_ip)
+                        .with_deadline_exceeded(pod_deadline_exceeded(pod))
+                })
         })
 }
 
+/// Whether a pod has run for longer than its module's active deadline, which
+/// the runtime sets as an annotation on the module's pods.
+pub fn pod_deadline_exceeded(pod: &api_core::Pod) -> bool {
+    let deadline = pod
+        .metadata
+        .as_ref()
+        .and_then(|meta| meta.annotations.as_ref())
+        .and_then(|annotations| annotations.get(EDGE_ACTIVE_DEADLINE_ANNOTATION))
+        .and_then(|seconds| seconds.parse().ok())
+        .and_then(|seconds| ChronoDuration::from_std(Duration::from_secs(seconds)).ok());
+    let start_time = pod
+        .status
+        .as_ref()
+        .and_then(|status| status.start_time.as_ref());
+
+    match (deadline, start_time) {
+        (Some(deadline), Some(start_time)) => start_time
+            .0
+            .checked_add_signed(deadline)
+            .map_or(false, |end| end <= Utc::now()),
+        _ => false,
+    }
+}
+
+/// Reads the rollout progress of a module's Deployment. Returns `None` before
+/// the Deployment controller has reported a status.
+pub fn deployment_to_status(deployment: &api_apps::Deployment) -> Option<DeploymentStatus> {
+    deployment.status.as_ref().map(|status| DeploymentStatus {
+        ready_replicas: status.ready_replicas.unwrap_or(0),
+        updated_replicas: status.updated_replicas.unwrap_or(0),
+        unavailable_replicas: status.unavailable_replicas.unwrap_or(0),
+        conditions: status
+            .conditions
+            .iter()
+            .flatten()
+            .map(|condition| DeploymentCondition {
+                type_: condition.type_.clone(),
+                status: condition.status.clone(),
+                reason: condition.reason.clone(),
+                message: condition.message.clone(),
+                last_update_time: condition
+                    .last_update_time
+                    .as_ref()
+                    .map(|time| time.0.to_rfc3339()),
+            })
+            .collect(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use edgelet_core::Module;
+    use edgelet_core::{Module, ModuleStatus};
+    use futures::Future;
     use k8s_openapi::api::core::v1 as api_core;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
     use serde_json;
 
     const POD_SUCCESS: &str = r###"
@@ -94,6 +156,10 @@ mod tests {
                     "name": "edgehub"
                 }
             ]
+        },
+        "status" :
+        {
+            "podIP" : "10.244.0.12"
         }
     }
     "###;
@@ -105,6 +171,40 @@ mod tests {
         let module = pod_to_module(&pod_1).unwrap().unwrap();
         assert_eq!(module.name(), "$edgeHub");
         assert_eq!(module.config().image(), "correct_image");
+        assert_eq!(module.pod_ip(), Some("10.244.0.12"));
+        let state = module.runtime_state().wait().unwrap();
+        assert_eq!(state.pod_ip(), Some("10.244.0.12"));
+    }
+
+    #[test]
+    fn pod_past_active_deadline() {
+        let mut pod: api_core::Pod = serde_json::from_str(POD_SUCCESS).unwrap();
+        pod.metadata
+            .as_mut()
+            .and_then(|meta| meta.annotations.as_mut())
+            .unwrap()
+            .insert(
+                EDGE_ACTIVE_DEADLINE_ANNOTATION.to_string(),
+                "3600".to_string(),
+            );
+        pod.status = Some(api_core::PodStatus {
+            start_time: Some(Time(Utc::now() - ChronoDuration::hours(2))),
+            ..api_core::PodStatus::default()
+        });
+
+        let module = pod_to_module(&pod).unwrap().unwrap();
+        assert!(module.deadline_exceeded());
+        let state = module.runtime_state().wait().unwrap();
+        assert_eq!(*state.status(), ModuleStatus::Failed);
+
+        pod.status = Some(api_core::PodStatus {
+            start_time: Some(Time(Utc::now() - ChronoDuration::minutes(30))),
+            ..api_core::PodStatus::default()
+        });
+        assert!(!pod_to_module(&pod).unwrap().unwrap().deadline_exceeded());
+
+        let pod: api_core::Pod = serde_json::from_str(POD_SUCCESS).unwrap();
+        assert!(!pod_to_module(&pod).unwrap().unwrap().deadline_exceeded());
     }
 
     const POD_NO_ANNOTATION: &str = r###"
@@ -239,4 +339,45 @@ mod tests {
         assert!(result.unwrap().is_err());
     }
 
+    #[test]
+    fn deployment_status_from_rollout() {
+        let deployment: api_apps::Deployment = serde_json::from_str(
+            r###"{
+                "kind": "Deployment",
+                "status": {
+                    "readyReplicas": 1,
+                    "updatedReplicas": 1,
+                    "unavailableReplicas": 1,
+                    "conditions": [
+                        {
+                            "type": "Progressing",
+                            "status": "True",
+                            "reason": "ReplicaSetUpdated",
+                            "message": "ReplicaSet \"edgehub-5d8f\" is progressing.",
+                            "lastUpdateTime": "2019-08-01T00:00:00Z"
+                        }
+                    ]
+                }
+            }"###,
+        )
+        .unwrap();
+
+        let status = deployment_to_status(&deployment).unwrap();
+        assert_eq!(status.ready_replicas, 1);
+        assert_eq!(status.updated_replicas, 1);
+        assert_eq!(status.unavailable_replicas, 1);
+        assert_eq!(status.conditions[0].type_, "Progressing");
+        assert_eq!(
+            status.conditions[0].last_update_time,
+            Some("2019-08-01T00:00:00+00:00".to_string())
+        );
+        assert_eq!(
+            status.to_string(),
+            "1 ready, 1 updated, 1 unavailable replicas: ReplicaSet \"edgehub-5d8f\" is progressing."
+        );
+
+        let deployment: api_apps::Deployment =
+            serde_json::from_str(r#"{"kind": "Deployment"}"#).unwrap();
+        assert!(deployment_to_status(&deployment).is_none());
+    }
 }
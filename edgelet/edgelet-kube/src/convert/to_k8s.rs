@@ -1,9 +1,11 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::str;
 
 use base64;
+use chrono::{DateTime, Utc};
 use docker::models::{AuthConfig, HostConfig};
 use edgelet_core::{Certificate, ModuleSpec};
 use edgelet_docker::DockerConfig;
@@ -11,15 +13,20 @@ use failure::ResultExt;
 use k8s_openapi::api::apps::v1 as api_apps;
 use k8s_openapi::api::core::v1 as api_core;
 use k8s_openapi::api::rbac::v1 as api_rbac;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use k8s_openapi::ByteString;
 use log::warn;
-use serde_json;
+use serde_json::{self, json, Value as JsonValue};
 
 use crate::constants::*;
 use crate::convert::sanitize_dns_value;
 use crate::error::{ErrorKind, Result};
-use crate::settings::Settings;
+use crate::settings::{
+    GrpcProbe, KubeOS, KubeServiceIPType, ModuleSettings, Probe, ProbeAction, ProbeConfig,
+    ResourceRequirements, SeccompProfile, Settings, Toleration,
+};
 
 // Use username and server from Docker AuthConfig to construct an image pull secret name.
 fn auth_to_pull_secret_name(auth: &AuthConfig) -> Option<String> {
@@ -111,6 +118,48 @@ pub fn auth_to_image_pull_secret(
     ))
 }
 
+/// Converts a secret read from Azure Key Vault into a Kubernetes Secret with
+/// the same (sanitized) name.
+pub fn key_vault_secret_to_secret(
+    settings: &Settings,
+    name: &str,
+    value: &str,
+) -> Result<(String, api_core::Secret)> {
+    let secret_name = sanitize_dns_value(name)?;
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let mut secret_data = BTreeMap::new();
+    secret_data.insert(
+        KEY_VAULT_SECRET_DATA_NAME.to_string(),
+        ByteString(value.as_bytes().to_vec()),
+    );
+
+    Ok((
+        secret_name.clone(),
+        api_core::Secret {
+            data: Some(secret_data),
+            metadata: Some(api_meta::ObjectMeta {
+                name: Some(secret_name),
+                namespace: Some(settings.namespace().to_string()),
+                labels: Some(labels),
+                ..api_meta::ObjectMeta::default()
+            }),
+            ..api_core::Secret::default()
+        },
+    ))
+}
+
 /// Converts Docker `ModuleSpec` to K8s `PodSpec`
 fn spec_to_podspec(
     settings: &Settings,
@@ -119,33 +168,63 @@ fn spec_to_podspec(
     module_image: String,
 ) -> Result<api_core::PodSpec> {
     // privileged container
-    let security = spec
+    let privileged = spec
         .config()
         .create_options()
         .host_config()
         .and_then(HostConfig::privileged)
-        .and_then(|privileged| {
-            if *privileged {
-                let context = api_core::SecurityContext {
-                    privileged: Some(*privileged),
-                    ..api_core::SecurityContext::default()
-                };
-                Some(context)
+        .map_or(false, |privileged| *privileged);
+
+    // capabilities added to or dropped from the module container
+    let capabilities = settings
+        .module(&module_label_value)
+        .and_then(|module_settings| {
+            let to_option = |capabilities: &[String]| {
+                if capabilities.is_empty() {
+                    None
+                } else {
+                    Some(capabilities.to_vec())
+                }
+            };
+            let add = to_option(module_settings.capabilities_add());
+            let drop = to_option(module_settings.capabilities_drop());
+
+            if add.is_some() || drop.is_some() {
+                Some(api_core::Capabilities { add, drop })
             } else {
                 None
             }
         });
 
+    // Windows containers support neither privileged mode nor Linux capabilities.
+    let os = settings.module_os(&module_label_value);
+    let security = if os == Some(KubeOS::Windows) {
+        None
+    } else if privileged || capabilities.is_some() {
+        Some(api_core::SecurityContext {
+            privileged: if privileged { Some(true) } else { None },
+            capabilities,
+            ..api_core::SecurityContext::default()
+        })
+    } else {
+        None
+    };
+
     // Environment Variables - use env from ModuleSpec
     let mut env_vars: Vec<api_core::EnvVar> = spec
         .env()
         .iter()
+        .filter(|(key, _)| {
+            !key.starts_with(ANNOTATION_ENV_PREFIX) && key.as_str() != IMAGE_PULL_SECRET_ENV
+        })
         .map(|(key, val)| api_core::EnvVar {
             name: key.clone(),
             value: Some(val.clone()),
             ..api_core::EnvVar::default()
         })
         .collect();
+    // the module's env is a HashMap, so sort it to keep the pod spec stable
+    env_vars.sort_by(|a, b| a.name.cmp(&b.name));
     // Pass along "USE_PERSISTENT_VOLUMES" to EdgeAgent
     if settings.use_pvc() && EDGE_EDGE_AGENT_NAME == module_label_value {
         let env_var = api_core::EnvVar {
@@ -326,21 +405,254 @@ fn spec_to_podspec(
             }
         }
     };
-    //pull secrets
-    let image_pull_secrets = spec.config().auth().and_then(|auth| {
-        Some(vec![api_core::LocalObjectReference {
-            name: auth_to_pull_secret_name(auth),
-        }])
+    // proxy settings iotedged was started with take precedence over the module's own
+    let mut module_env_vars = env_vars.clone();
+    for (key, value) in settings.proxy_env() {
+        module_env_vars.retain(|env_var| !env_var.name.eq_ignore_ascii_case(key));
+        module_env_vars.push(api_core::EnvVar {
+            name: key.clone(),
+            value: Some(value.clone()),
+            ..api_core::EnvVar::default()
+        });
+    }
+
+    // TLS certificate issued by cert-manager for modules which serve HTTPS
+    if settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::tls_certificate)
+        .is_some()
+    {
+        volumes.push(api_core::Volume {
+            name: TLS_CERTIFICATE_VOLUME_NAME.to_string(),
+            secret: Some(api_core::SecretVolumeSource {
+                secret_name: Some(tls_secret_name(&module_label_value)),
+                ..api_core::SecretVolumeSource::default()
+            }),
+            ..api_core::Volume::default()
+        });
+        volume_mounts.push(api_core::VolumeMount {
+            mount_path: TLS_CERTIFICATE_MOUNT_PATH.to_string(),
+            name: TLS_CERTIFICATE_VOLUME_NAME.to_string(),
+            read_only: Some(true),
+            ..api_core::VolumeMount::default()
+        });
+        module_env_vars.push(api_core::EnvVar {
+            name: TLS_CERT_PATH.to_string(),
+            value: Some(format!("{}/tls.crt", TLS_CERTIFICATE_MOUNT_PATH)),
+            ..api_core::EnvVar::default()
+        });
+        module_env_vars.push(api_core::EnvVar {
+            name: TLS_KEY_PATH.to_string(),
+            value: Some(format!("{}/tls.key", TLS_CERTIFICATE_MOUNT_PATH)),
+            ..api_core::EnvVar::default()
+        });
+    }
+
+    // The trust bundle is copied out of its ConfigMap volume, which is mounted
+    // read-only, into a volume shared with the module before it starts.
+    let init_containers = if settings.inject_trust_bundle_to_os_store() {
+        volumes.push(api_core::Volume {
+            name: OS_TRUST_STORE_VOLUME_NAME.to_string(),
+            empty_dir: Some(api_core::EmptyDirVolumeSource::default()),
+            ..api_core::Volume::default()
+        });
+        volume_mounts.push(api_core::VolumeMount {
+            mount_path: OS_TRUST_STORE_PATH.to_string(),
+            name: OS_TRUST_STORE_VOLUME_NAME.to_string(),
+            read_only: Some(true),
+            ..api_core::VolumeMount::default()
+        });
+
+        Some(vec![trust_bundle_init_container(settings)])
+    } else {
+        None
+    };
+
+    // CSI volumes are mounted through the claims created by
+    // spec_to_csi_volumes, and emptyDir volumes live as long as the pod
+    if let Some(module_settings) = settings.module(&module_label_value) {
+        for csi_volume in module_settings.csi_volumes() {
+            let volume_name = sanitize_dns_value(csi_volume.name())?;
+            volumes.push(api_core::Volume {
+                name: volume_name.clone(),
+                persistent_volume_claim: Some(api_core::PersistentVolumeClaimVolumeSource {
+                    claim_name: csi_volume_claim_name(&module_label_value, &volume_name),
+                    read_only: Some(csi_volume.read_only()),
+                }),
+                ..api_core::Volume::default()
+            });
+            volume_mounts.push(api_core::VolumeMount {
+                mount_path: csi_volume.mount_path().to_string(),
+                name: volume_name,
+                read_only: Some(csi_volume.read_only()),
+                ..api_core::VolumeMount::default()
+            });
+        }
+
+        for empty_dir_volume in module_settings.empty_dir_volumes() {
+            let volume_name = sanitize_dns_value(empty_dir_volume.name())?;
+            volumes.push(api_core::Volume {
+                name: volume_name.clone(),
+                empty_dir: Some(api_core::EmptyDirVolumeSource {
+                    medium: empty_dir_volume.medium().map(ToOwned::to_owned),
+                    ..api_core::EmptyDirVolumeSource::default()
+                }),
+                ..api_core::Volume::default()
+            });
+            volume_mounts.push(api_core::VolumeMount {
+                mount_path: empty_dir_volume.mount_path().to_string(),
+                name: volume_name,
+                ..api_core::VolumeMount::default()
+            });
+        }
+    }
+
+    let resources = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::resources)
+        .map(|resources| resources_to_requirements(&module_label_value, resources))
+        .transpose()?;
+
+    // env from config maps and secrets, on top of the module's own env
+    let env_from = settings.module(&module_label_value).and_then(|module| {
+        let config_maps = module
+            .env_from_config_map()
+            .iter()
+            .map(|name| api_core::EnvFromSource {
+                config_map_ref: Some(api_core::ConfigMapEnvSource {
+                    name: Some(name.clone()),
+                    ..api_core::ConfigMapEnvSource::default()
+                }),
+                ..api_core::EnvFromSource::default()
+            });
+        let secrets = module
+            .env_from_secret()
+            .iter()
+            .map(|name| api_core::EnvFromSource {
+                secret_ref: Some(api_core::SecretEnvSource {
+                    name: Some(name.clone()),
+                    ..api_core::SecretEnvSource::default()
+                }),
+                ..api_core::EnvFromSource::default()
+            });
+
+        Some(config_maps.chain(secrets).collect::<Vec<_>>()).filter(|env_from| !env_from.is_empty())
     });
 
+    // health checks, where a configured liveness probe replaces the gRPC one
+    let probes = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::probes);
+    let liveness_probe = probes
+        .and_then(ProbeConfig::liveness)
+        .map(probe_to_k8s)
+        .or_else(|| {
+            settings
+                .module(&module_label_value)
+                .and_then(ModuleSettings::grpc_probe)
+                .map(grpc_probe_to_probe)
+        });
+    let readiness_probe = probes.and_then(ProbeConfig::readiness).map(probe_to_k8s);
+
+    let share_process_namespace = settings
+        .module(&module_label_value)
+        .map_or(false, ModuleSettings::share_process_namespace);
+    let automount_service_account_token = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::automount_service_account_token);
+
+    let hostname = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::hostname)
+        .map(ToOwned::to_owned);
+    let subdomain = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::subdomain)
+        .map(ToOwned::to_owned);
+
+    // The v1.10 API has no pod OS field, so besides the configured node
+    // selector, pods are kept to nodes of the matching OS with the label the
+    // kubelet sets on every node.
+    let mut node_selector = settings.node_selector().clone();
+    if let Some(os) = os {
+        node_selector.insert(NODE_OS_LABEL.to_string(), os.as_str().to_string());
+    }
+    let node_selector = Some(node_selector).filter(|node_selector| !node_selector.is_empty());
+
+    let tolerations = Some(
+        settings
+            .tolerations()
+            .iter()
+            .map(toleration_to_k8s)
+            .collect::<Vec<_>>(),
+    )
+    .filter(|tolerations| !tolerations.is_empty());
+
+    // edgeHub is given time to flush the messages it holds when it's deleted
+    let termination_grace_period_seconds = if module_label_value == EDGE_EDGE_HUB_NAME {
+        Some(
+            i64::try_from(settings.edge_hub_drain_grace_period_seconds())
+                .unwrap_or_else(|_| i64::max_value()),
+        )
+    } else {
+        None
+    };
+
+    let host_aliases = Some(
+        settings
+            .host_aliases()
+            .iter()
+            .map(|alias| api_core::HostAlias {
+                hostnames: Some(alias.hostnames().to_vec()),
+                ip: Some(alias.ip().to_string()),
+            })
+            .collect::<Vec<_>>(),
+    )
+    .filter(|host_aliases| !host_aliases.is_empty());
+
+    // pull secrets
+    // the one made from the module's registry credentials, then the device's
+    // and the module's own
+    let mut pull_secret_names: Vec<String> = spec
+        .config()
+        .auth()
+        .and_then(auth_to_pull_secret_name)
+        .into_iter()
+        .chain(settings.image_pull_secrets().iter().cloned())
+        .collect();
+    if let Some(names) = spec.env().get(IMAGE_PULL_SECRET_ENV) {
+        pull_secret_names.extend(
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(ToOwned::to_owned),
+        );
+    }
+    let mut image_pull_secrets: Vec<api_core::LocalObjectReference> = Vec::new();
+    for name in pull_secret_names {
+        if image_pull_secrets
+            .iter()
+            .all(|secret| secret.name.as_ref() != Some(&name))
+        {
+            image_pull_secrets.push(api_core::LocalObjectReference { name: Some(name) });
+        }
+    }
+    let image_pull_secrets = Some(image_pull_secrets).filter(|secrets| !secrets.is_empty());
+
     Ok(api_core::PodSpec {
+        automount_service_account_token,
         containers: vec![
             // module
             api_core::Container {
                 name: module_label_value.clone(),
-                env: Some(env_vars.clone()),
+                env: Some(module_env_vars),
+                env_from,
                 image: Some(module_image),
-                image_pull_policy: Some(settings.image_pull_policy().to_string()),
+                image_pull_policy: Some(settings.image_pull_policy().as_str().to_string()),
+                liveness_probe,
+                readiness_probe,
+                resources,
                 security_context: security,
                 volume_mounts: Some(volume_mounts),
                 ..api_core::Container::default()
@@ -350,18 +662,189 @@ fn spec_to_podspec(
                 name: PROXY_CONTAINER_NAME.to_string(),
                 env: Some(env_vars),
                 image: Some(settings.proxy_image().to_string()),
-                image_pull_policy: Some(settings.image_pull_policy().to_string()),
+                image_pull_policy: Some(settings.image_pull_policy().as_str().to_string()),
                 volume_mounts: Some(proxy_volume_mounts),
                 ..api_core::Container::default()
             },
         ],
+        host_aliases,
+        hostname,
         image_pull_secrets,
+        init_containers,
+        node_selector,
         service_account_name: Some(module_label_value),
+        share_process_namespace: if share_process_namespace {
+            Some(true)
+        } else {
+            None
+        },
+        subdomain,
+        termination_grace_period_seconds,
+        tolerations,
         volumes: Some(volumes),
         ..api_core::PodSpec::default()
     })
 }
 
+fn toleration_to_k8s(toleration: &Toleration) -> api_core::Toleration {
+    api_core::Toleration {
+        effect: toleration.effect().map(ToOwned::to_owned),
+        key: toleration.key().map(ToOwned::to_owned),
+        operator: toleration.operator().map(ToOwned::to_owned),
+        toleration_seconds: toleration.toleration_seconds(),
+        value: toleration.value().map(ToOwned::to_owned),
+    }
+}
+
+fn trust_bundle_init_container(settings: &Settings) -> api_core::Container {
+    let source = format!(
+        "{}/{}",
+        settings.proxy_trust_bundle_path().trim_end_matches('/'),
+        PROXY_TRUST_BUNDLE_FILENAME
+    );
+    let target = format!("{}/{}", OS_TRUST_STORE_PATH, OS_TRUST_STORE_FILENAME);
+
+    api_core::Container {
+        name: TRUST_BUNDLE_INIT_CONTAINER_NAME.to_string(),
+        command: Some(vec!["cp".to_string(), source, target]),
+        image: Some(TRUST_BUNDLE_INIT_IMAGE.to_string()),
+        image_pull_policy: Some(settings.image_pull_policy().as_str().to_string()),
+        volume_mounts: Some(vec![
+            api_core::VolumeMount {
+                mount_path: settings.proxy_trust_bundle_path().to_string(),
+                name: PROXY_TRUST_BUNDLE_VOLUME_NAME.to_string(),
+                read_only: Some(true),
+                ..api_core::VolumeMount::default()
+            },
+            api_core::VolumeMount {
+                mount_path: OS_TRUST_STORE_PATH.to_string(),
+                name: OS_TRUST_STORE_VOLUME_NAME.to_string(),
+                ..api_core::VolumeMount::default()
+            },
+        ]),
+        ..api_core::Container::default()
+    }
+}
+
+fn resources_to_requirements(
+    module_label_value: &str,
+    resources: &ResourceRequirements,
+) -> Result<api_core::ResourceRequirements> {
+    let to_quantities = |kind: &str, quantities: &BTreeMap<String, String>| {
+        if quantities.is_empty() {
+            return Ok(None);
+        }
+
+        quantities
+            .iter()
+            .map(|(resource, quantity)| {
+                if is_valid_quantity(quantity) {
+                    Ok((resource.clone(), Quantity(quantity.clone())))
+                } else {
+                    Err(ErrorKind::InvalidConfiguration(format!(
+                        "{} {:?} of resource {} for module {} is not a valid quantity",
+                        kind, quantity, resource, module_label_value
+                    ))
+                    .into())
+                }
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+            .map(Some)
+    };
+
+    Ok(api_core::ResourceRequirements {
+        requests: to_quantities("Request", resources.requests())?,
+        limits: to_quantities("Limit", resources.limits())?,
+    })
+}
+
+// A decimal number with an optional sign, followed by a binary (Ki, Mi, ...) or
+// decimal (m, k, M, ...) suffix or an exponent (e3), such as 250m or 1.5Gi.
+fn is_valid_quantity(quantity: &str) -> bool {
+    let unsigned = if quantity.starts_with('+') || quantity.starts_with('-') {
+        &quantity[1..]
+    } else {
+        quantity
+    };
+    let number_len = unsigned
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or_else(|| unsigned.len());
+    let (number, suffix) = unsigned.split_at(number_len);
+
+    let valid_suffix = match suffix {
+        "" | "n" | "u" | "m" | "k" | "M" | "G" | "T" | "P" | "E" | "Ki" | "Mi" | "Gi" | "Ti"
+        | "Pi" | "Ei" => true,
+        exponent => {
+            (exponent.starts_with('e') || exponent.starts_with('E'))
+                && exponent[1..].parse::<i32>().is_ok()
+        }
+    };
+
+    valid_suffix && number.parse::<f64>().is_ok()
+}
+
+// The v1.10 API has no gRPC probe, so the check runs grpc_health_probe in the
+// module container, which the module image is expected to ship.
+fn grpc_probe_to_probe(grpc_probe: &GrpcProbe) -> api_core::Probe {
+    let mut command = vec![
+        GRPC_HEALTH_PROBE_PATH.to_string(),
+        format!("-addr=localhost:{}", grpc_probe.port()),
+    ];
+    if let Some(service) = grpc_probe.service() {
+        command.push(format!("-service={}", service));
+    }
+
+    api_core::Probe {
+        exec: Some(api_core::ExecAction {
+            command: Some(command),
+        }),
+        ..api_core::Probe::default()
+    }
+}
+
+fn probe_to_k8s(probe: &Probe) -> api_core::Probe {
+    let mut k8s_probe = api_core::Probe {
+        initial_delay_seconds: probe.initial_delay_seconds(),
+        period_seconds: probe.period_seconds(),
+        timeout_seconds: probe.timeout_seconds(),
+        success_threshold: probe.success_threshold(),
+        failure_threshold: probe.failure_threshold(),
+        ..api_core::Probe::default()
+    };
+
+    match probe.action() {
+        ProbeAction::HttpGet { path, port, scheme } => {
+            k8s_probe.http_get = Some(api_core::HTTPGetAction {
+                path: Some(path.clone()),
+                port: IntOrString::Int(i32::from(*port)),
+                scheme: scheme.clone(),
+                ..api_core::HTTPGetAction::default()
+            });
+        }
+        ProbeAction::TcpSocket { port } => {
+            k8s_probe.tcp_socket = Some(api_core::TCPSocketAction {
+                port: IntOrString::Int(i32::from(*port)),
+                ..api_core::TCPSocketAction::default()
+            });
+        }
+        ProbeAction::Exec { command } => {
+            k8s_probe.exec = Some(api_core::ExecAction {
+                command: Some(command.clone()),
+            });
+        }
+    }
+
+    k8s_probe
+}
+
+fn seccomp_profile_to_annotation(profile: &SeccompProfile) -> String {
+    match profile {
+        SeccompProfile::RuntimeDefault => "runtime/default".to_string(),
+        SeccompProfile::Unconfined => "unconfined".to_string(),
+        SeccompProfile::Localhost { path } => format!("localhost/{}", path),
+    }
+}
+
 /// Converts Docker Module Spec into a K8S Deployment.
 pub fn spec_to_deployment(
     settings: &Settings,
@@ -394,10 +877,98 @@ pub fn spec_to_deployment(
         }
     }
 
-    // annotations
-    let mut annotations = BTreeMap::new();
+    // annotations, starting with those passed in the module's env so that
+    // they can't replace the ones the runtime sets itself
+    let mut annotations = env_to_annotations(spec, &module_label_value)?;
     annotations.insert(EDGE_ORIGINAL_MODULEID.to_string(), spec.name().to_string());
 
+    // Kubernetes leaves log rotation to the container runtime, so log limits
+    // are published as annotations for the node's log tooling to pick up.
+    if let Some(log_max_size) = settings.log_max_size() {
+        annotations.insert(
+            EDGE_LOG_MAX_SIZE_ANNOTATION.to_string(),
+            log_max_size.to_string(),
+        );
+    }
+    if let Some(log_max_files) = settings.log_max_files() {
+        annotations.insert(
+            EDGE_LOG_MAX_FILES_ANNOTATION.to_string(),
+            log_max_files.to_string(),
+        );
+    }
+
+    // The v1.10 API has no readinessGates field in the pod spec, so the
+    // condition types are listed in an annotation instead.
+    if let Some(module_settings) = settings.module(&module_label_value) {
+        if !module_settings.readiness_gates().is_empty() {
+            annotations.insert(
+                EDGE_READINESS_GATES_ANNOTATION.to_string(),
+                module_settings.readiness_gates().join(","),
+            );
+        }
+
+        if !module_settings.safe_to_evict() {
+            annotations.insert(SAFE_TO_EVICT_ANNOTATION.to_string(), "false".to_string());
+        }
+
+        // Deployments don't allow activeDeadlineSeconds in their pod template,
+        // so the deadline is published as an annotation the runtime enforces.
+        if let Some(active_deadline_seconds) = module_settings.active_deadline_seconds() {
+            if active_deadline_seconds == 0 {
+                return Err(ErrorKind::InvalidModuleConfig(format!(
+                    "Active deadline of module {} must be at least 1 second",
+                    module_label_value
+                ))
+                .into());
+            }
+            annotations.insert(
+                EDGE_ACTIVE_DEADLINE_ANNOTATION.to_string(),
+                active_deadline_seconds.to_string(),
+            );
+        }
+    }
+
+    // Seccomp and AppArmor only apply to Linux nodes.
+    let linux = settings.module_os(&module_label_value) != Some(KubeOS::Windows);
+
+    // The v1.10 API has no seccompProfile field in the pod security context, so
+    // the profile is set with the annotation the kubelet reads instead.
+    let seccomp_profile = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::seccomp_profile)
+        .cloned()
+        .or_else(|| {
+            if settings.secure_defaults() {
+                Some(SeccompProfile::RuntimeDefault)
+            } else {
+                None
+            }
+        });
+    if let Some(seccomp_profile) = seccomp_profile.filter(|_| linux) {
+        annotations.insert(
+            SECCOMP_POD_ANNOTATION.to_string(),
+            seccomp_profile_to_annotation(&seccomp_profile),
+        );
+    }
+
+    if let Some(apparmor_profile) = settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::apparmor_profile)
+        .filter(|_| linux)
+    {
+        if apparmor_profile != "runtime/default" && !apparmor_profile.starts_with("localhost/") {
+            return Err(ErrorKind::InvalidConfiguration(format!(
+                "AppArmor profile {:?} for module {} is not runtime/default or localhost/*",
+                apparmor_profile, module_label_value
+            ))
+            .into());
+        }
+        annotations.insert(
+            format!("{}/{}", APPARMOR_ANNOTATION_PREFIX, module_label_value),
+            apparmor_profile.to_string(),
+        );
+    }
+
     // Assemble everything
     let deployment = api_apps::Deployment {
         metadata: Some(api_meta::ObjectMeta {
@@ -432,6 +1003,126 @@ pub fn spec_to_deployment(
     Ok((deployment_name, deployment))
 }
 
+fn env_to_annotations(
+    spec: &ModuleSpec<DockerConfig>,
+    module_label_value: &str,
+) -> Result<BTreeMap<String, String>> {
+    spec.env()
+        .iter()
+        .filter(|(key, _)| key.starts_with(ANNOTATION_ENV_PREFIX))
+        .map(|(key, value)| {
+            let annotation = &key[ANNOTATION_ENV_PREFIX.len()..];
+            if is_valid_annotation_key(annotation) {
+                Ok((annotation.to_string(), value.clone()))
+            } else {
+                Err(ErrorKind::InvalidModuleConfig(format!(
+                    "Env var {} of module {} does not name a valid annotation",
+                    key, module_label_value
+                ))
+                .into())
+            }
+        })
+        .collect()
+}
+
+// Annotation keys are a name of at most 63 alphanumeric characters, '-', '_'
+// or '.', beginning and ending with an alphanumeric character, optionally
+// prefixed by a DNS subdomain and '/'.
+fn is_valid_annotation_key(key: &str) -> bool {
+    let (prefix, name) = match key.rfind('/') {
+        Some(index) => (Some(&key[..index]), &key[index + 1..]),
+        None => (None, key),
+    };
+
+    let valid_prefix = prefix.map_or(true, |prefix| {
+        prefix.len() <= 253
+            && prefix.split('.').all(|label| {
+                !label.is_empty()
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+            })
+    });
+    let valid_name = !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric());
+
+    valid_prefix && valid_name
+}
+
+/// Uses the target of a Vertical Pod Autoscaler's recommendation for the named
+/// container as its resource requests. Deployments are left unchanged when the
+/// autoscaler has not produced a recommendation yet.
+pub fn apply_vpa_recommendation(
+    deployment: &mut api_apps::Deployment,
+    container_name: &str,
+    vpa: &JsonValue,
+) {
+    let target = vpa["status"]["recommendation"]["containerRecommendations"]
+        .as_array()
+        .and_then(|recommendations| {
+            recommendations
+                .iter()
+                .find(|recommendation| recommendation["containerName"] == container_name)
+        })
+        .and_then(|recommendation| recommendation["target"].as_object());
+
+    let container = deployment
+        .spec
+        .as_mut()
+        .and_then(|spec| spec.template.spec.as_mut())
+        .and_then(|pod_spec| {
+            pod_spec
+                .containers
+                .iter_mut()
+                .find(|container| container.name == container_name)
+        });
+
+    if let (Some(target), Some(container)) = (target, container) {
+        let resources = container
+            .resources
+            .get_or_insert_with(api_core::ResourceRequirements::default);
+        let requests = resources.requests.get_or_insert_with(BTreeMap::new);
+        for (resource, quantity) in target {
+            if let Some(quantity) = quantity.as_str() {
+                requests.insert(resource.clone(), Quantity(quantity.to_string()));
+            }
+        }
+    }
+}
+
+/// Annotates the pods of a deployment with those of the node's labels whose
+/// keys are listed. Labels the node doesn't have are skipped.
+pub fn apply_node_labels(
+    deployment: &mut api_apps::Deployment,
+    node: &api_core::Node,
+    label_keys: &[String],
+) {
+    let node_labels = match node.metadata.as_ref().and_then(|meta| meta.labels.as_ref()) {
+        Some(node_labels) => node_labels,
+        None => return,
+    };
+
+    if let Some(pod_meta) = deployment
+        .spec
+        .as_mut()
+        .and_then(|spec| spec.template.metadata.as_mut())
+    {
+        let annotations = pod_meta.annotations.get_or_insert_with(BTreeMap::new);
+        for key in label_keys {
+            if let Some(value) = node_labels.get(key) {
+                annotations.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
 /// Converts Docker Module Spec into Service Account.
 pub fn spec_to_service_account(
     settings: &Settings,
@@ -522,11 +1213,27 @@ pub fn spec_to_role_binding(
     Ok((role_binding_name, role_binding))
 }
 
-/// Creates Config Map with Edge Trust Bundle.
-pub fn trust_bundle_to_config_map(
+fn csi_volume_claim_name(module_label_value: &str, volume_name: &str) -> String {
+    format!("{}-{}", module_label_value, volume_name)
+}
+
+/// Converts the CSI volumes configured for a module into Persistent Volumes
+/// and the claims bound to them. Kubernetes 1.10 has no inline CSI pod
+/// volumes, so each volume is statically provisioned and the pod mounts its
+/// claim instead. Persistent Volumes are cluster scoped, so creating them needs
+/// the Cluster Role from `settings_to_cluster_role`.
+pub fn spec_to_csi_volumes(
     settings: &Settings,
-    cert: &impl Certificate,
-) -> Result<(String, api_core::ConfigMap)> {
+    spec: &ModuleSpec<DockerConfig>,
+) -> Result<Vec<(api_core::PersistentVolume, api_core::PersistentVolumeClaim)>> {
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    let csi_volumes = match settings.module(&module_label_value) {
+        Some(module_settings) if !module_settings.csi_volumes().is_empty() => {
+            module_settings.csi_volumes()
+        }
+        _ => return Ok(Vec::new()),
+    };
+
     let device_label_value =
         sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
     let hubname_label = sanitize_dns_value(
@@ -537,285 +1244,2389 @@ pub fn trust_bundle_to_config_map(
 
     // labels
     let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value.clone());
     labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
     labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
 
-    let cert = cert.pem().context(ErrorKind::IdentityCertificate)?;
-    let cert = str::from_utf8(cert.as_ref()).context(ErrorKind::IdentityCertificate)?;
-
-    let mut data = BTreeMap::new();
-    data.insert(PROXY_TRUST_BUNDLE_FILENAME.to_string(), cert.to_string());
-    let config_map_name = settings.proxy_trust_bundle_config_map_name().to_string();
+    // annotations
+    let mut annotations = BTreeMap::new();
+    annotations.insert(EDGE_ORIGINAL_MODULEID.to_string(), spec.name().to_string());
 
-    let config_map = api_core::ConfigMap {
-        metadata: Some(api_meta::ObjectMeta {
-            name: Some(config_map_name.clone()),
-            namespace: Some(settings.namespace().to_string()),
-            labels: Some(labels),
-            ..api_meta::ObjectMeta::default()
-        }),
-        data: Some(data),
-        ..api_core::ConfigMap::default()
-    };
-    Ok((config_map_name, config_map))
-}
+    csi_volumes
+        .iter()
+        .map(|csi_volume| {
+            let volume_name = sanitize_dns_value(csi_volume.name())?;
+            let claim_name = csi_volume_claim_name(&module_label_value, &volume_name);
+            // Persistent Volumes are not namespaced.
+            let persistent_volume_name = format!("{}-{}", settings.namespace(), claim_name);
+
+            let access_modes = vec![if csi_volume.read_only() {
+                "ReadOnlyMany".to_string()
+            } else {
+                "ReadWriteMany".to_string()
+            }];
+            let mut capacity = BTreeMap::new();
+            capacity.insert(
+                "storage".to_string(),
+                Quantity(CSI_VOLUME_CAPACITY.to_string()),
+            );
 
-#[cfg(test)]
-mod tests {
-    use std::collections::{BTreeMap, HashMap};
-    use std::str;
+            let persistent_volume = api_core::PersistentVolume {
+                metadata: Some(api_meta::ObjectMeta {
+                    name: Some(persistent_volume_name.clone()),
+                    labels: Some(labels.clone()),
+                    annotations: Some(annotations.clone()),
+                    ..api_meta::ObjectMeta::default()
+                }),
+                spec: Some(api_core::PersistentVolumeSpec {
+                    access_modes: Some(access_modes.clone()),
+                    capacity: Some(capacity.clone()),
+                    csi: Some(api_core::CSIPersistentVolumeSource {
+                        driver: csi_volume.driver().to_string(),
+                        read_only: Some(csi_volume.read_only()),
+                        volume_attributes: Some(csi_volume.volume_attributes().clone()),
+                        volume_handle: persistent_volume_name.clone(),
+                        ..api_core::CSIPersistentVolumeSource::default()
+                    }),
+                    // keep the data when the module is removed
+                    persistent_volume_reclaim_policy: Some("Retain".to_string()),
+                    storage_class_name: Some(String::new()),
+                    ..api_core::PersistentVolumeSpec::default()
+                }),
+                ..api_core::PersistentVolume::default()
+            };
 
-    use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+            let persistent_volume_claim = api_core::PersistentVolumeClaim {
+                metadata: Some(api_meta::ObjectMeta {
+                    name: Some(claim_name),
+                    namespace: Some(settings.namespace().to_string()),
+                    labels: Some(labels.clone()),
+                    annotations: Some(annotations.clone()),
+                    ..api_meta::ObjectMeta::default()
+                }),
+                spec: Some(api_core::PersistentVolumeClaimSpec {
+                    access_modes: Some(access_modes),
+                    resources: Some(api_core::ResourceRequirements {
+                        requests: Some(capacity),
+                        ..api_core::ResourceRequirements::default()
+                    }),
+                    // an empty storage class disables dynamic provisioning
+                    storage_class_name: Some(String::new()),
+                    volume_name: Some(persistent_volume_name),
+                    ..api_core::PersistentVolumeClaimSpec::default()
+                }),
+                ..api_core::PersistentVolumeClaim::default()
+            };
 
-    use docker::models::AuthConfig;
-    use docker::models::ContainerCreateBody;
-    use docker::models::HostConfig;
-    use docker::models::Mount;
-    use edgelet_core::{ImagePullPolicy, ModuleSpec};
-    use edgelet_docker::DockerConfig;
-    use edgelet_test_utils::cert::TestCert;
+            Ok((persistent_volume, persistent_volume_claim))
+        })
+        .collect()
+}
 
-    use crate::constants::*;
-    use crate::convert::to_k8s::{Auth, AuthEntry};
-    use crate::convert::{
-        auth_to_image_pull_secret, spec_to_deployment, spec_to_role_binding,
-        spec_to_service_account, trust_bundle_to_config_map,
+/// Merge patches setting the VolumeAttributesClass of the claims from
+/// `spec_to_csi_volumes`, keyed by claim name. The v1.10 API has no
+/// volumeAttributesClassName in the claim spec, so it is patched in once the
+/// claim exists. Unlike the rest of the spec it can be changed, so patching an
+/// existing claim moves its volume to the new class.
+pub fn spec_to_csi_volume_claim_patches(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+) -> Result<Vec<(String, serde_json::Value)>> {
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    let csi_volumes = match settings.module(&module_label_value) {
+        Some(module_settings) => module_settings.csi_volumes(),
+        None => return Ok(Vec::new()),
     };
-    use crate::tests::make_settings;
-    use crate::ErrorKind;
 
-    fn create_module_spec() -> ModuleSpec<DockerConfig> {
-        let create_body = ContainerCreateBody::new()
-            .with_host_config(
-                HostConfig::new()
-                    .with_binds(vec![String::from("/a:/b:ro"), String::from("/c:/d")])
-                    .with_privileged(true)
-                    .with_mounts(vec![
-                        Mount::new()
-                            .with__type(String::from("bind"))
-                            .with_read_only(true)
-                            .with_source(String::from("/e"))
-                            .with_target(String::from("/f")),
-                        Mount::new()
-                            .with__type(String::from("bind"))
-                            .with_source(String::from("/g"))
-                            .with_target(String::from("/h")),
-                        Mount::new()
-                            .with__type(String::from("volume"))
-                            .with_read_only(true)
-                            .with_source(String::from("i"))
-                            .with_target(String::from("/j")),
-                        Mount::new()
-                            .with__type(String::from("volume"))
-                            .with_source(String::from("k"))
-                            .with_target(String::from("/l")),
-                    ]),
-            )
-            .with_labels({
-                let mut labels = HashMap::<String, String>::new();
-                labels.insert(String::from("label1"), String::from("value1"));
-                labels.insert(String::from("label2"), String::from("value2"));
-                labels
-            });
-        let auth_config = AuthConfig::new()
-            .with_password(String::from("a password"))
-            .with_username(String::from("USERNAME"))
-            .with_serveraddress(String::from("REGISTRY"));
-        ModuleSpec::new(
-            "$edgeAgent".to_string(),
-            "docker".to_string(),
-            DockerConfig::new("my-image:v1.0".to_string(), create_body, Some(auth_config)).unwrap(),
-            {
-                let mut env = HashMap::new();
-                env.insert(String::from("a"), String::from("b"));
-                env.insert(String::from("C"), String::from("D"));
-                env
+    csi_volumes
+        .iter()
+        .filter_map(|csi_volume| {
+            csi_volume
+                .volume_attributes_class_name()
+                .map(|class_name| (csi_volume, class_name))
+        })
+        .map(|(csi_volume, class_name)| {
+            let volume_name = sanitize_dns_value(csi_volume.name())?;
+            Ok((
+                csi_volume_claim_name(&module_label_value, &volume_name),
+                json!({ "spec": { "volumeAttributesClassName": class_name } }),
+            ))
+        })
+        .collect()
+}
+
+fn tls_secret_name(module_label_value: &str) -> String {
+    format!("{}-tls", module_label_value)
+}
+
+/// Creates a cert-manager Certificate for modules which have a TLS certificate
+/// configured. cert-manager stores the issued certificate in a Secret which is
+/// mounted into the module's pod. Returns `None` for other modules.
+pub fn spec_to_certificate(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+) -> Result<Option<(String, JsonValue)>> {
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    let tls_certificate = match settings
+        .module(&module_label_value)
+        .and_then(ModuleSettings::tls_certificate)
+    {
+        Some(tls_certificate) => tls_certificate,
+        None => return Ok(None),
+    };
+
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let certificate_name = module_label_value.clone();
+
+    // other modules reach the module through its Service's fully-qualified name
+    let mut dns_names = tls_certificate.dns_names().to_vec();
+    let service_dns_name = settings.service_dns_name(&module_label_value);
+    if !dns_names.contains(&service_dns_name) {
+        dns_names.push(service_dns_name);
+    }
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value.clone());
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let issuer_ref = tls_certificate.issuer_ref();
+    let mut issuer = json!({
+        "name": issuer_ref.name(),
+        "kind": issuer_ref.kind(),
+    });
+    if let Some(group) = issuer_ref.group() {
+        issuer["group"] = json!(group);
+    }
+
+    let certificate = json!({
+        "apiVersion": CERTIFICATE.api_version(),
+        "kind": "Certificate",
+        "metadata": {
+            "name": certificate_name,
+            "namespace": settings.namespace(),
+            "labels": labels,
+            "annotations": {
+                EDGE_ORIGINAL_MODULEID: spec.name(),
             },
-            ImagePullPolicy::default(),
+        },
+        "spec": {
+            "secretName": tls_secret_name(&module_label_value),
+            "dnsNames": dns_names,
+            "issuerRef": issuer,
+        },
+    });
+
+    Ok(Some((certificate_name, certificate)))
+}
+
+/// Creates a Service for the ports a module exposes, either through
+/// `ExposedPorts` or `HostConfig.PortBindings` in its create options, selecting
+/// the module's pod. Returns `None` for modules which expose no ports.
+pub fn spec_to_service(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+) -> Result<Option<(String, api_core::Service)>> {
+    let create_options = spec.config().create_options();
+    let mut exposed_ports: Vec<&String> = create_options
+        .exposed_ports()
+        .into_iter()
+        .flat_map(|ports| ports.keys())
+        .chain(
+            create_options
+                .host_config()
+                .and_then(HostConfig::port_bindings)
+                .into_iter()
+                .flat_map(|bindings| bindings.keys()),
         )
-        .unwrap()
+        .collect();
+    exposed_ports.sort();
+    exposed_ports.dedup();
+
+    let ports = exposed_ports
+        .into_iter()
+        .map(|exposed_port| {
+            let mut parts = exposed_port.splitn(2, '/');
+            let port = parts
+                .next()
+                .and_then(|port| port.parse::<i32>().ok())
+                .ok_or_else(|| {
+                    ErrorKind::InvalidRunTimeParameter(
+                        "ExposedPorts".to_string(),
+                        exposed_port.clone(),
+                    )
+                })?;
+            let protocol = parts.next().unwrap_or("tcp").to_lowercase();
+
+            Ok(api_core::ServicePort {
+                name: Some(format!("{}-{}", protocol, port)),
+                port,
+                protocol: Some(protocol.to_uppercase()),
+                target_port: Some(IntOrString::Int(port)),
+                ..api_core::ServicePort::default()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if ports.is_empty() {
+        return Ok(None);
+    }
+
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let service_name = module_label_value.clone();
+
+    let service_ip_type = settings
+        .module(&module_label_value)
+        .map_or_else(KubeServiceIPType::default, ModuleSettings::service_ip_type);
+    let (type_, cluster_ip) = match service_ip_type {
+        KubeServiceIPType::ClusterIP => ("ClusterIP", None),
+        KubeServiceIPType::NodePort => ("NodePort", None),
+        KubeServiceIPType::LoadBalancer => ("LoadBalancer", None),
+        KubeServiceIPType::Headless => ("ClusterIP", Some("None".to_string())),
+    };
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value);
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    // annotations
+    let mut annotations = BTreeMap::new();
+    annotations.insert(EDGE_ORIGINAL_MODULEID.to_string(), spec.name().to_string());
+
+    let service = api_core::Service {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(service_name.clone()),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels.clone()),
+            annotations: Some(annotations),
+            ..api_meta::ObjectMeta::default()
+        }),
+        spec: Some(api_core::ServiceSpec {
+            ports: Some(ports),
+            selector: Some(labels),
+            cluster_ip,
+            type_: Some(type_.to_string()),
+            ..api_core::ServiceSpec::default()
+        }),
+        ..api_core::Service::default()
+    };
+
+    Ok(Some((service_name, service)))
+}
+
+/// A headless Service selecting the module's pods, named after the module with
+/// a `-headless` suffix. It has no cluster IP, so its DNS name resolves to the
+/// pod IPs themselves, and no ports, since clients connect to the pods
+/// directly.
+pub fn module_to_headless_service(
+    settings: &Settings,
+    module_id: &str,
+) -> Result<(String, api_core::Service)> {
+    let module_label_value = sanitize_dns_value(module_id)?;
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let service_name = format!("{}{}", module_label_value, HEADLESS_SERVICE_SUFFIX);
+
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value);
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let mut annotations = BTreeMap::new();
+    annotations.insert(EDGE_ORIGINAL_MODULEID.to_string(), module_id.to_string());
+
+    let service = api_core::Service {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(service_name.clone()),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels.clone()),
+            annotations: Some(annotations),
+            ..api_meta::ObjectMeta::default()
+        }),
+        spec: Some(api_core::ServiceSpec {
+            selector: Some(labels),
+            cluster_ip: Some("None".to_string()),
+            type_: Some("ClusterIP".to_string()),
+            ..api_core::ServiceSpec::default()
+        }),
+        ..api_core::Service::default()
+    };
+
+    Ok((service_name, service))
+}
+
+/// Creates a Multi-Cluster Services API Service Export for the module's
+/// Service when the module's settings ask for it, which makes the Service
+/// resolvable from other clusters in the cluster set under `clusterset.local`.
+/// The export has to share the name of the Service it exports.
+pub fn spec_to_service_export(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+) -> Result<Option<(String, JsonValue)>> {
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    if !settings
+        .module(&module_label_value)
+        .map_or(false, ModuleSettings::export_service)
+    {
+        return Ok(None);
+    }
+
+    let service_name = match spec_to_service(settings, spec)? {
+        Some((service_name, _)) => service_name,
+        None => return Ok(None),
+    };
+
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value);
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let service_export = json!({
+        "apiVersion": SERVICE_EXPORT.api_version(),
+        "kind": "ServiceExport",
+        "metadata": {
+            "name": service_name,
+            "namespace": settings.namespace(),
+            "labels": labels,
+            "annotations": {
+                EDGE_ORIGINAL_MODULEID: spec.name(),
+            },
+        },
+    });
+
+    Ok(Some((service_name, service_export)))
+}
+
+/// Creates a Prometheus operator Pod Monitor for modules which declare a
+/// metrics port through the `net.azure-devices.edge.metrics-port` label in
+/// their create options. The port is scraped on the module's pods directly, as
+/// module Services only expose the ports the module binds. Returns `None` for
+/// modules without one.
+pub fn spec_to_pod_monitor(
+    settings: &Settings,
+    spec: &ModuleSpec<DockerConfig>,
+) -> Result<Option<(String, JsonValue)>> {
+    let spec_labels = spec.config().create_options().labels();
+    let metrics_port = match spec_labels.and_then(|labels| labels.get(EDGE_METRICS_PORT_LABEL)) {
+        Some(port) => port.parse::<u16>().map_err(|_| {
+            ErrorKind::InvalidRunTimeParameter(EDGE_METRICS_PORT_LABEL.to_string(), port.clone())
+        })?,
+        None => return Ok(None),
+    };
+    let metrics_path = spec_labels
+        .and_then(|labels| labels.get(EDGE_METRICS_PATH_LABEL))
+        .map_or(DEFAULT_METRICS_PATH, String::as_str);
+
+    let module_label_value = sanitize_dns_value(spec.name())?;
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let pod_monitor_name = module_label_value.clone();
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_MODULE_LABEL.to_string(), module_label_value);
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let pod_monitor = json!({
+        "apiVersion": POD_MONITOR.api_version(),
+        "kind": "PodMonitor",
+        "metadata": {
+            "name": pod_monitor_name,
+            "namespace": settings.namespace(),
+            "labels": labels,
+            "annotations": {
+                EDGE_ORIGINAL_MODULEID: spec.name(),
+            },
+        },
+        "spec": {
+            "selector": {
+                "matchLabels": labels,
+            },
+            "podMetricsEndpoints": [
+                {
+                    "targetPort": metrics_port,
+                    "path": metrics_path,
+                }
+            ],
+        },
+    });
+
+    Ok(Some((pod_monitor_name, pod_monitor)))
+}
+
+fn policy_rule(api_group: &str, resources: &[&str], verbs: &[&str]) -> api_rbac::PolicyRule {
+    api_rbac::PolicyRule {
+        api_groups: Some(vec![api_group.to_string()]),
+        resources: Some(resources.iter().map(ToString::to_string).collect()),
+        verbs: verbs.iter().map(ToString::to_string).collect(),
+        ..api_rbac::PolicyRule::default()
+    }
+}
+
+/// Creates the Role with the minimum permissions the IoT Edge service account
+/// needs to manage modules in its namespace.
+pub fn settings_to_role(settings: &Settings) -> Result<(String, api_rbac::Role)> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let role_name = sanitize_dns_value(settings.service_account_name())?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let all_verbs = [
+        "get", "list", "watch", "create", "update", "patch", "delete",
+    ];
+
+    let role = api_rbac::Role {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(role_name.clone()),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels),
+            ..api_meta::ObjectMeta::default()
+        }),
+        rules: vec![
+            policy_rule(
+                "",
+                &["pods", "services", "configmaps", "persistentvolumeclaims"],
+                &all_verbs,
+            ),
+            // image pull and Key Vault secrets are looked up by name and replaced
+            policy_rule(
+                "",
+                &["secrets"],
+                &["get", "list", "create", "update", "delete"],
+            ),
+            policy_rule(
+                "",
+                &["serviceaccounts"],
+                &["get", "list", "create", "update", "patch", "delete"],
+            ),
+            policy_rule("", &["events"], &["create"]),
+            policy_rule("apps", &["deployments"], &all_verbs),
+            // the locks held while provisioning persistent volume claims
+            policy_rule(
+                "coordination.k8s.io",
+                &["leases"],
+                &["get", "create", "update"],
+            ),
+            policy_rule(
+                "rbac.authorization.k8s.io",
+                &["rolebindings"],
+                &["get", "create", "update"],
+            ),
+            // optional add-ons, only used when their CRDs are installed
+            policy_rule(
+                POD_MONITOR.group(),
+                &[POD_MONITOR.plural()],
+                &["get", "create", "update", "delete"],
+            ),
+            policy_rule(
+                CERTIFICATE.group(),
+                &[CERTIFICATE.plural()],
+                &["get", "create", "update"],
+            ),
+            policy_rule(
+                SERVICE_EXPORT.group(),
+                &[SERVICE_EXPORT.plural()],
+                &["get", "create", "update"],
+            ),
+            policy_rule(
+                VERTICAL_POD_AUTOSCALER.group(),
+                &[VERTICAL_POD_AUTOSCALER.plural()],
+                &["get"],
+            ),
+        ],
+    };
+
+    Ok((role_name, role))
+}
+
+/// Creates the Cluster Role for the cluster scoped objects the IoT Edge service
+/// account works with, which a namespaced Role cannot grant. Cluster Roles are
+/// shared by every namespace, so the name includes the namespace.
+pub fn settings_to_cluster_role(settings: &Settings) -> Result<(String, api_rbac::ClusterRole)> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let cluster_role_name = cluster_role_name(settings)?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let cluster_role = api_rbac::ClusterRole {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(cluster_role_name.clone()),
+            labels: Some(labels),
+            ..api_meta::ObjectMeta::default()
+        }),
+        rules: vec![
+            // statically provisioned CSI volumes
+            policy_rule("", &["persistentvolumes"], &["get", "create"]),
+            // node labels and cluster type detection
+            policy_rule("", &["nodes"], &["get", "list"]),
+            policy_rule(
+                VALIDATING_ADMISSION_POLICY.group(),
+                &[
+                    VALIDATING_ADMISSION_POLICY.plural(),
+                    VALIDATING_ADMISSION_POLICY_BINDING.plural(),
+                ],
+                &["get", "create", "update"],
+            ),
+            // module authentication
+            policy_rule("authentication.k8s.io", &["tokenreviews"], &["create"]),
+            policy_rule(
+                "authorization.k8s.io",
+                &["subjectaccessreviews"],
+                &["create"],
+            ),
+        ],
+        ..api_rbac::ClusterRole::default()
+    };
+
+    Ok((cluster_role_name, cluster_role))
+}
+
+/// Creates the Cluster Role Binding which grants the IoT Edge service account
+/// the Cluster Role created by `settings_to_cluster_role`.
+pub fn settings_to_cluster_role_binding(
+    settings: &Settings,
+) -> Result<(String, api_rbac::ClusterRoleBinding)> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let cluster_role_binding_name = cluster_role_name(settings)?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let cluster_role_binding = api_rbac::ClusterRoleBinding {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(cluster_role_binding_name.clone()),
+            labels: Some(labels),
+            ..api_meta::ObjectMeta::default()
+        }),
+        role_ref: api_rbac::RoleRef {
+            api_group: "rbac.authorization.k8s.io".into(),
+            kind: "ClusterRole".into(),
+            name: cluster_role_binding_name.clone(),
+        },
+        subjects: vec![api_rbac::Subject {
+            api_group: None,
+            kind: "ServiceAccount".into(),
+            name: settings.service_account_name().into(),
+            namespace: Some(settings.namespace().into()),
+        }],
+    };
+
+    Ok((cluster_role_binding_name, cluster_role_binding))
+}
+
+fn cluster_role_name(settings: &Settings) -> Result<String> {
+    Ok(format!(
+        "{}-{}",
+        sanitize_dns_value(settings.namespace())?,
+        sanitize_dns_value(settings.service_account_name())?
+    ))
+}
+
+/// Creates a CEL based admission policy, and the binding which scopes it to the
+/// IoT Edge namespace, rejecting module pods which were not generated by
+/// iotedged. A validating policy is used because mutating policies cannot
+/// reject a request. Both objects are cluster scoped so their names include
+/// the namespace.
+pub fn settings_to_admission_policy(
+    settings: &Settings,
+) -> Result<((String, JsonValue), (String, JsonValue))> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let policy_name = format!(
+        "{}-iotedge-module-pods",
+        sanitize_dns_value(settings.namespace())?
+    );
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let policy = json!({
+        "apiVersion": VALIDATING_ADMISSION_POLICY.api_version(),
+        "kind": "ValidatingAdmissionPolicy",
+        "metadata": {
+            "name": policy_name,
+            "labels": labels,
+        },
+        "spec": {
+            "failurePolicy": "Fail",
+            "matchConstraints": {
+                "resourceRules": [{
+                    "apiGroups": [""],
+                    "apiVersions": ["v1"],
+                    "operations": ["CREATE"],
+                    "resources": ["pods"],
+                }],
+            },
+            // only pods which belong to a module are checked
+            "matchConditions": [{
+                "name": "module-pods",
+                "expression": format!(
+                    "has(object.metadata.labels) && '{}' in object.metadata.labels",
+                    EDGE_MODULE_LABEL
+                ),
+            }],
+            "validations": [
+                {
+                    "expression": format!("'{}' in object.metadata.labels", EDGE_DEVICE_LABEL),
+                    "message": format!("module pods must have the {} label", EDGE_DEVICE_LABEL),
+                },
+                {
+                    "expression": format!(
+                        "object.spec.containers.exists(c, c.name == '{}')",
+                        PROXY_CONTAINER_NAME
+                    ),
+                    "message": format!(
+                        "module pods must have the {} container",
+                        PROXY_CONTAINER_NAME
+                    ),
+                },
+            ],
+        },
+    });
+
+    let binding = json!({
+        "apiVersion": VALIDATING_ADMISSION_POLICY_BINDING.api_version(),
+        "kind": "ValidatingAdmissionPolicyBinding",
+        "metadata": {
+            "name": policy_name,
+            "labels": labels,
+        },
+        "spec": {
+            "policyName": policy_name,
+            "validationActions": ["Deny"],
+            "matchResources": {
+                "namespaceSelector": {
+                    "matchLabels": {
+                        "kubernetes.io/metadata.name": settings.namespace(),
+                    },
+                },
+            },
+        },
+    });
+
+    Ok(((policy_name.clone(), policy), (policy_name, binding)))
+}
+
+/// Creates the Role Binding which grants the IoT Edge service account the Role
+/// created by `settings_to_role`.
+pub fn settings_to_role_binding(settings: &Settings) -> Result<(String, api_rbac::RoleBinding)> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let role_binding_name = sanitize_dns_value(settings.service_account_name())?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let role_binding = api_rbac::RoleBinding {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(role_binding_name.clone()),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels),
+            ..api_meta::ObjectMeta::default()
+        }),
+        role_ref: api_rbac::RoleRef {
+            api_group: "rbac.authorization.k8s.io".into(),
+            kind: "Role".into(),
+            name: role_binding_name.clone(),
+        },
+        subjects: vec![api_rbac::Subject {
+            api_group: None,
+            kind: "ServiceAccount".into(),
+            name: settings.service_account_name().into(),
+            namespace: Some(settings.namespace().into()),
+        }],
+    };
+
+    Ok((role_binding_name, role_binding))
+}
+
+/// Creates an Event about the IoT Edge runtime itself. Such events have no
+/// pod to refer to, so they are recorded against the IoT Edge service account.
+pub fn settings_to_event(
+    settings: &Settings,
+    reason: &str,
+    type_: &str,
+    message: String,
+    now: DateTime<Utc>,
+) -> Result<api_core::Event> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    let service_account_name = sanitize_dns_value(settings.service_account_name())?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let event = api_core::Event {
+        metadata: api_meta::ObjectMeta {
+            generate_name: Some(format!("{}-", service_account_name)),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels),
+            ..api_meta::ObjectMeta::default()
+        },
+        involved_object: api_core::ObjectReference {
+            api_version: Some("v1".to_string()),
+            kind: Some("ServiceAccount".to_string()),
+            name: Some(service_account_name),
+            namespace: Some(settings.namespace().to_string()),
+            ..api_core::ObjectReference::default()
+        },
+        reason: Some(reason.to_string()),
+        message: Some(message),
+        type_: Some(type_.to_string()),
+        source: Some(api_core::EventSource {
+            component: Some(EVENT_SOURCE_COMPONENT.to_string()),
+            host: None,
+        }),
+        count: Some(1),
+        first_timestamp: Some(api_meta::Time(now)),
+        last_timestamp: Some(api_meta::Time(now)),
+        ..api_core::Event::default()
+    };
+
+    Ok(event)
+}
+
+/// Creates Config Map with Edge Trust Bundle.
+pub fn trust_bundle_to_config_map(
+    settings: &Settings,
+    cert: &impl Certificate,
+) -> Result<(String, api_core::ConfigMap)> {
+    let device_label_value =
+        sanitize_dns_value(settings.device_id().ok_or(ErrorKind::MissingDeviceId)?)?;
+    let hubname_label = sanitize_dns_value(
+        settings
+            .iot_hub_hostname()
+            .ok_or(ErrorKind::MissingHubName)?,
+    )?;
+
+    // labels
+    let mut labels = BTreeMap::new();
+    labels.insert(EDGE_DEVICE_LABEL.to_string(), device_label_value);
+    labels.insert(EDGE_HUBNAME_LABEL.to_string(), hubname_label);
+
+    let cert = cert.pem().context(ErrorKind::IdentityCertificate)?;
+    let cert = str::from_utf8(cert.as_ref()).context(ErrorKind::IdentityCertificate)?;
+
+    let mut data = BTreeMap::new();
+    data.insert(PROXY_TRUST_BUNDLE_FILENAME.to_string(), cert.to_string());
+    let config_map_name = settings.proxy_trust_bundle_config_map_name().to_string();
+
+    let config_map = api_core::ConfigMap {
+        metadata: Some(api_meta::ObjectMeta {
+            name: Some(config_map_name.clone()),
+            namespace: Some(settings.namespace().to_string()),
+            labels: Some(labels),
+            ..api_meta::ObjectMeta::default()
+        }),
+        data: Some(data),
+        ..api_core::ConfigMap::default()
+    };
+    check_config_map_size(&config_map)?;
+    Ok((config_map_name, config_map))
+}
+
+/// Fails with `ErrorKind::ConfigMapTooLarge` when the keys and values of a
+/// config map's data add up to more than the API server accepts.
+pub fn check_config_map_size(config_map: &api_core::ConfigMap) -> Result<()> {
+    let data_size: usize = config_map
+        .data
+        .iter()
+        .flatten()
+        .map(|(key, value)| key.len() + value.len())
+        .sum();
+    let binary_data_size: usize = config_map
+        .binary_data
+        .iter()
+        .flatten()
+        .map(|(key, value)| key.len() + value.0.len())
+        .sum();
+
+    let size = data_size + binary_data_size;
+    if size > CONFIG_MAP_MAX_DATA_SIZE {
+        return Err(ErrorKind::ConfigMapTooLarge {
+            size,
+            limit: CONFIG_MAP_MAX_DATA_SIZE,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::str;
+
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+    use serde_json::json;
+
+    use docker::models::AuthConfig;
+    use docker::models::ContainerCreateBody;
+    use docker::models::HostConfig;
+    use docker::models::Mount;
+    use edgelet_core::{ImagePullPolicy, ModuleSpec};
+    use edgelet_docker::DockerConfig;
+    use edgelet_test_utils::cert::TestCert;
+
+    use crate::constants::*;
+    use crate::convert::to_k8s::{is_valid_quantity, Auth, AuthEntry};
+    use crate::convert::{
+        apply_vpa_recommendation, auth_to_image_pull_secret, module_to_headless_service,
+        settings_to_admission_policy, settings_to_cluster_role, settings_to_cluster_role_binding,
+        settings_to_role, settings_to_role_binding, spec_to_certificate,
+        spec_to_csi_volume_claim_patches, spec_to_csi_volumes, spec_to_deployment,
+        spec_to_pod_monitor, spec_to_role_binding, spec_to_service, spec_to_service_account,
+        spec_to_service_export, trust_bundle_to_config_map,
+    };
+    use crate::tests::make_settings;
+    use crate::ErrorKind;
+
+    fn create_module_spec() -> ModuleSpec<DockerConfig> {
+        let create_body = ContainerCreateBody::new()
+            .with_host_config(
+                HostConfig::new()
+                    .with_binds(vec![String::from("/a:/b:ro"), String::from("/c:/d")])
+                    .with_privileged(true)
+                    .with_mounts(vec![
+                        Mount::new()
+                            .with__type(String::from("bind"))
+                            .with_read_only(true)
+                            .with_source(String::from("/e"))
+                            .with_target(String::from("/f")),
+                        Mount::new()
+                            .with__type(String::from("bind"))
+                            .with_source(String::from("/g"))
+                            .with_target(String::from("/h")),
+                        Mount::new()
+                            .with__type(String::from("volume"))
+                            .with_read_only(true)
+                            .with_source(String::from("i"))
+                            .with_target(String::from("/j")),
+                        Mount::new()
+                            .with__type(String::from("volume"))
+                            .with_source(String::from("k"))
+                            .with_target(String::from("/l")),
+                    ]),
+            )
+            .with_labels({
+                let mut labels = HashMap::<String, String>::new();
+                labels.insert(String::from("label1"), String::from("value1"));
+                labels.insert(String::from("label2"), String::from("value2"));
+                labels
+            });
+        let auth_config = AuthConfig::new()
+            .with_password(String::from("a password"))
+            .with_username(String::from("USERNAME"))
+            .with_serveraddress(String::from("REGISTRY"));
+        ModuleSpec::new(
+            "$edgeAgent".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("my-image:v1.0".to_string(), create_body, Some(auth_config)).unwrap(),
+            {
+                let mut env = HashMap::new();
+                env.insert(String::from("a"), String::from("b"));
+                env.insert(String::from("C"), String::from("D"));
+                env
+            },
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn validate_deployment_metadata(
+        module: &str,
+        device: &str,
+        iothub: &str,
+        meta: Option<&api_meta::ObjectMeta>,
+    ) {
+        assert!(meta.is_some());
+        if let Some(meta) = meta {
+            assert_eq!(meta.name, Some(module.to_string()));
+            assert!(meta.labels.is_some());
+            if let Some(labels) = meta.labels.as_ref() {
+                assert_eq!(labels.get(EDGE_MODULE_LABEL).unwrap(), "edgeagent");
+                assert_eq!(labels.get(EDGE_DEVICE_LABEL).unwrap(), device);
+                assert_eq!(labels.get(EDGE_HUBNAME_LABEL).unwrap(), iothub);
+            }
+        }
+    }
+
+    #[allow(clippy::cognitive_complexity)]
+    #[test]
+    fn deployment_success() {
+        let module_config = create_module_spec();
+
+        let (name, deployment) = spec_to_deployment(&make_settings(None), &module_config).unwrap();
+        assert_eq!(name, "edgeagent");
+        validate_deployment_metadata(
+            "edgeagent",
+            "device1",
+            "iothub",
+            deployment.metadata.as_ref(),
+        );
+
+        assert!(deployment.spec.is_some());
+        if let Some(spec) = deployment.spec.as_ref() {
+            assert_eq!(spec.replicas, Some(1));
+            assert!(spec.selector.match_labels.is_some());
+            if let Some(match_labels) = spec.selector.match_labels.as_ref() {
+                assert_eq!(match_labels.get(EDGE_MODULE_LABEL).unwrap(), "edgeagent");
+                assert_eq!(match_labels.get(EDGE_DEVICE_LABEL).unwrap(), "device1");
+                assert_eq!(match_labels.get(EDGE_HUBNAME_LABEL).unwrap(), "iothub");
+            }
+            assert!(spec.template.spec.is_some());
+            if let Some(podspec) = spec.template.spec.as_ref() {
+                assert_eq!(podspec.containers.len(), 2);
+                if let Some(module) = podspec.containers.iter().find(|c| c.name == "edgeagent") {
+                    // 2 from module spec, 1 for use_pvc
+                    assert_eq!(module.env.as_ref().map(Vec::len).unwrap(), 3);
+                    assert_eq!(module.volume_mounts.as_ref().map(Vec::len).unwrap(), 6);
+                    assert_eq!(module.image.as_ref().unwrap(), "my-image:v1.0");
+                    assert_eq!(module.image_pull_policy.as_ref().unwrap(), "IfNotPresent");
+                }
+                if let Some(proxy) = podspec
+                    .containers
+                    .iter()
+                    .find(|c| c.name == PROXY_CONTAINER_NAME)
+                {
+                    // 2 from module spec, 1 for use_pvc
+                    assert_eq!(proxy.env.as_ref().map(Vec::len).unwrap(), 3);
+                    assert_eq!(proxy.volume_mounts.as_ref().map(Vec::len).unwrap(), 2);
+                    assert_eq!(proxy.image.as_ref().unwrap(), "proxy:latest");
+                    assert_eq!(proxy.image_pull_policy.as_ref().unwrap(), "IfNotPresent");
+                }
+                assert_eq!(podspec.service_account_name.as_ref().unwrap(), "edgeagent");
+                assert!(podspec.image_pull_secrets.is_some());
+                // 4 bind mounts, 2 volume mounts, 1 proxy configmap, 1 trust bundle configmap
+                assert_eq!(podspec.volumes.as_ref().map(Vec::len).unwrap(), 8);
+            }
+        }
+    }
+
+    #[test]
+    fn auth_to_image_pull_secret_success() {
+        let mut auths = BTreeMap::new();
+        auths.insert(
+            "REGISTRY".to_string(),
+            AuthEntry::new("USER".to_string(), "a password".to_string()),
+        );
+        let json_data = serde_json::to_string(&Auth::new(auths)).unwrap();
+        let auth_config = AuthConfig::new()
+            .with_password(String::from("a password"))
+            .with_username(String::from("USER"))
+            .with_serveraddress(String::from("REGISTRY"));
+        let (name, secret) = auth_to_image_pull_secret("namespace", &auth_config).unwrap();
+        assert_eq!(name, "user-registry");
+
+        assert!(secret.metadata.is_some());
+        if let Some(meta) = secret.metadata.as_ref() {
+            assert_eq!(meta.name, Some(name));
+            assert_eq!(meta.namespace, Some("namespace".to_string()));
+        }
+        assert_eq!(
+            str::from_utf8(secret.data.unwrap()[".dockerconfigjson"].0.as_slice()).unwrap(),
+            json_data
+        );
+    }
+
+    #[test]
+    fn auth_to_image_pull_secret_failure() {
+        let auths = vec![
+            AuthConfig::new()
+                .with_username(String::from("USER"))
+                .with_serveraddress(String::from("REGISTRY")),
+            AuthConfig::new()
+                .with_password(String::from("a password"))
+                .with_serveraddress(String::from("REGISTRY")),
+            AuthConfig::new()
+                .with_password(String::from("a password"))
+                .with_username(String::from("USER")),
+        ];
+        for auth in auths {
+            let result = auth_to_image_pull_secret("namespace", &auth);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn module_to_service_account() {
+        let module = create_module_spec();
+
+        let (name, service_account) =
+            spec_to_service_account(&make_settings(None), &module).unwrap();
+        assert_eq!(name, "edgeagent");
+
+        assert!(service_account.metadata.is_some());
+        if let Some(metadata) = service_account.metadata {
+            assert_eq!(metadata.name, Some("edgeagent".to_string()));
+            assert_eq!(metadata.namespace, Some("default".to_string()));
+
+            assert!(metadata.annotations.is_some());
+            if let Some(annotations) = metadata.annotations {
+                assert_eq!(annotations.len(), 1);
+                assert_eq!(annotations[EDGE_ORIGINAL_MODULEID], "$edgeAgent");
+            }
+
+            assert!(metadata.labels.is_some());
+            if let Some(labels) = metadata.labels {
+                assert_eq!(labels.len(), 3);
+                assert_eq!(labels[EDGE_DEVICE_LABEL], "device1");
+                assert_eq!(labels[EDGE_HUBNAME_LABEL], "iothub");
+                assert_eq!(labels[EDGE_MODULE_LABEL], "edgeagent");
+            }
+        }
+    }
+
+    #[test]
+    fn module_to_role_binding() {
+        let module = create_module_spec();
+
+        let (name, role_binding) = spec_to_role_binding(&make_settings(None), &module).unwrap();
+        assert_eq!(name, "edgeagent");
+        assert!(role_binding.metadata.is_some());
+        if let Some(metadata) = role_binding.metadata {
+            assert_eq!(metadata.name, Some("edgeagent".to_string()));
+            assert_eq!(metadata.namespace, Some("default".to_string()));
+
+            assert!(metadata.annotations.is_some());
+            if let Some(annotations) = metadata.annotations {
+                assert_eq!(annotations.len(), 1);
+                assert_eq!(annotations[EDGE_ORIGINAL_MODULEID], "$edgeAgent");
+            }
+
+            assert!(metadata.labels.is_some());
+            if let Some(labels) = metadata.labels {
+                assert_eq!(labels.len(), 3);
+                assert_eq!(labels[EDGE_DEVICE_LABEL], "device1");
+                assert_eq!(labels[EDGE_HUBNAME_LABEL], "iothub");
+                assert_eq!(labels[EDGE_MODULE_LABEL], "edgeagent");
+            }
+        }
+
+        assert_eq!(role_binding.role_ref.api_group, "rbac.authorization.k8s.io");
+        assert_eq!(role_binding.role_ref.kind, "ClusterRole");
+        assert_eq!(role_binding.role_ref.name, "cluster-admin");
+
+        assert_eq!(role_binding.subjects.len(), 1);
+        let subject = &role_binding.subjects[0];
+        assert_eq!(subject.api_group, None);
+        assert_eq!(subject.kind, "ServiceAccount");
+        assert_eq!(subject.name, "edgeagent");
+        assert_eq!(subject.namespace, Some("default".to_string()));
+    }
+
+    #[test]
+    fn deployment_with_log_limits() {
+        let settings = make_settings(Some(json!({
+            "log_max_size": "10m",
+            "log_max_files": 3
+        })));
+        let module = create_module_spec();
+
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(annotations[EDGE_LOG_MAX_SIZE_ANNOTATION], "10m");
+        assert_eq!(annotations[EDGE_LOG_MAX_FILES_ANNOTATION], "3");
+    }
+
+    #[test]
+    fn deployment_with_seccomp_profile() {
+        let settings = make_settings(Some(json!({
+            "secure_defaults": true,
+            "modules": {
+                "edgehub": {
+                    "seccomp_profile": { "type": "Localhost", "path": "profiles/edgehub.json" }
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(annotations[SECCOMP_POD_ANNOTATION], "runtime/default");
+
+        let module = create_module_spec().with_name("edgeHub".to_string());
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(
+            annotations[SECCOMP_POD_ANNOTATION],
+            "localhost/profiles/edgehub.json"
+        );
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert!(!annotations.contains_key(SECCOMP_POD_ANNOTATION));
+    }
+
+    #[test]
+    fn deployment_with_apparmor_profile() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": { "apparmor_profile": "localhost/edge-agent" }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(
+            annotations["container.apparmor.security.beta.kubernetes.io/edgeagent"],
+            "localhost/edge-agent"
+        );
+
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": { "apparmor_profile": "unconfined" }
+            }
+        })));
+        let err = spec_to_deployment(&settings, &create_module_spec()).unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidConfiguration(_) => (),
+            kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn deployment_with_dropped_capabilities() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "capabilities_add": ["NET_BIND_SERVICE"],
+                    "capabilities_drop": ["ALL"]
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        let security_context = pod_spec.containers[0].security_context.as_ref().unwrap();
+        let capabilities = security_context.capabilities.as_ref().unwrap();
+        assert_eq!(capabilities.drop, Some(vec!["ALL".to_string()]));
+        assert_eq!(capabilities.add, Some(vec!["NET_BIND_SERVICE".to_string()]));
+        assert_eq!(security_context.privileged, Some(true));
+        assert!(pod_spec.containers[1].security_context.is_none());
+    }
+
+    #[test]
+    fn deployment_with_grpc_probe() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "grpc_probe": { "port": 50051, "service": "agent" }
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        let command = pod_spec.containers[0]
+            .liveness_probe
+            .as_ref()
+            .and_then(|probe| probe.exec.as_ref())
+            .and_then(|exec| exec.command.as_ref())
+            .unwrap();
+        assert_eq!(
+            command,
+            &vec![
+                GRPC_HEALTH_PROBE_PATH.to_string(),
+                "-addr=localhost:50051".to_string(),
+                "-service=agent".to_string(),
+            ]
+        );
+        assert!(pod_spec.containers[1].liveness_probe.is_none());
+    }
+
+    #[test]
+    fn deployment_with_http_liveness_probe() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "grpc_probe": { "port": 50051 },
+                    "probes": {
+                        "liveness": {
+                            "type": "HttpGet",
+                            "path": "/health",
+                            "port": 8080,
+                            "initial_delay_seconds": 10,
+                            "failure_threshold": 3
+                        },
+                        "readiness": { "type": "TcpSocket", "port": 5671 }
+                    }
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec =
+            serde_json::to_value(deployment.spec.unwrap().template.spec.unwrap()).unwrap();
+        assert_eq!(
+            pod_spec["containers"][0]["livenessProbe"],
+            json!({
+                "httpGet": { "path": "/health", "port": 8080 },
+                "initialDelaySeconds": 10,
+                "failureThreshold": 3
+            })
+        );
+        assert_eq!(
+            pod_spec["containers"][0]["readinessProbe"],
+            json!({ "tcpSocket": { "port": 5671 } })
+        );
+        assert!(pod_spec["containers"][1].get("livenessProbe").is_none());
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let container = &deployment.spec.unwrap().template.spec.unwrap().containers[0];
+        assert!(container.liveness_probe.is_none());
+        assert!(container.readiness_probe.is_none());
+    }
+
+    #[test]
+    fn deployment_with_shared_process_namespace() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": { "share_process_namespace": true }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.share_process_namespace, Some(true));
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.share_process_namespace, None);
+    }
+
+    #[test]
+    fn deployment_with_trust_bundle_init_container() {
+        let settings = make_settings(Some(json!({ "inject_trust_bundle_to_os_store": true })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+
+        let init_containers = pod_spec.init_containers.unwrap();
+        assert_eq!(init_containers.len(), 1);
+        assert_eq!(init_containers[0].name, TRUST_BUNDLE_INIT_CONTAINER_NAME);
+        assert_eq!(
+            init_containers[0].command,
+            Some(vec![
+                "cp".to_string(),
+                "/etc/trust-bundle/trust_bundle.pem".to_string(),
+                "/usr/local/share/ca-certificates/iotedge/trust_bundle.crt".to_string(),
+            ])
+        );
+
+        let module = &pod_spec.containers[0];
+        assert!(module
+            .volume_mounts
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|mount| mount.name == OS_TRUST_STORE_VOLUME_NAME
+                && mount.mount_path == OS_TRUST_STORE_PATH));
+        assert!(pod_spec
+            .volumes
+            .unwrap()
+            .iter()
+            .any(|volume| volume.name == OS_TRUST_STORE_VOLUME_NAME && volume.empty_dir.is_some()));
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.init_containers.is_none());
+    }
+
+    #[test]
+    fn deployment_without_service_account_token() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": { "automount_service_account_token": false }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.automount_service_account_token, Some(false));
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.automount_service_account_token, None);
+    }
+
+    #[test]
+    fn deployment_with_node_os() {
+        let settings = make_settings(Some(json!({
+            "secure_defaults": true,
+            "os": "linux",
+            "modules": {
+                "edgeagent": {
+                    "os": "windows",
+                    "capabilities_drop": ["ALL"]
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let template = deployment.spec.unwrap().template;
+        let pod_spec = template.spec.unwrap();
+        assert_eq!(pod_spec.node_selector.unwrap()[NODE_OS_LABEL], "windows");
+        assert!(pod_spec.containers[0].security_context.is_none());
+        let annotations = template.metadata.unwrap().annotations.unwrap();
+        assert!(!annotations.contains_key(SECCOMP_POD_ANNOTATION));
+
+        let module = create_module_spec().with_name("edgeHub".to_string());
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let template = deployment.spec.unwrap().template;
+        let pod_spec = template.spec.unwrap();
+        assert_eq!(pod_spec.node_selector.unwrap()[NODE_OS_LABEL], "linux");
+        let annotations = template.metadata.unwrap().annotations.unwrap();
+        assert_eq!(annotations[SECCOMP_POD_ANNOTATION], "runtime/default");
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.node_selector.is_none());
+    }
+
+    #[test]
+    fn deployment_with_node_selector_and_tolerations() {
+        let settings = make_settings(Some(json!({
+            "os": "linux",
+            "node_selector": { "agentpool": "gpu" },
+            "tolerations": [{
+                "key": "sku",
+                "operator": "Equal",
+                "value": "gpu",
+                "effect": "NoSchedule"
+            }, {
+                "operator": "Exists",
+                "effect": "NoExecute",
+                "toleration_seconds": 300
+            }]
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec =
+            serde_json::to_value(deployment.spec.unwrap().template.spec.unwrap()).unwrap();
+        assert_eq!(
+            pod_spec["nodeSelector"],
+            json!({ "agentpool": "gpu", "beta.kubernetes.io/os": "linux" })
+        );
+        assert_eq!(
+            pod_spec["tolerations"],
+            json!([{
+                "key": "sku",
+                "operator": "Equal",
+                "value": "gpu",
+                "effect": "NoSchedule"
+            }, {
+                "operator": "Exists",
+                "effect": "NoExecute",
+                "tolerationSeconds": 300
+            }])
+        );
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.tolerations.is_none());
+    }
+
+    #[test]
+    fn edge_hub_deployment_with_drain_grace_period() {
+        let module = create_module_spec().with_name("$edgeHub".to_string());
+
+        let (_, deployment) = spec_to_deployment(&make_settings(None), &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.termination_grace_period_seconds, Some(60));
+
+        let settings = make_settings(Some(json!({
+            "edge_hub_drain_grace_period_seconds": 300
+        })));
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.termination_grace_period_seconds, Some(300));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.termination_grace_period_seconds, None);
+    }
+
+    #[test]
+    fn deployment_with_resource_requirements() {
+        let settings = make_settings(None);
+
+        let (_, deployment) =
+            spec_to_deployment(&settings, &create_module_spec_with_ports()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        let resources = pod_spec.containers[0].resources.as_ref().unwrap();
+        assert_eq!(
+            resources.requests.as_ref().unwrap()["cpu"],
+            Quantity("250m".to_string())
+        );
+        assert_eq!(
+            resources.limits.as_ref().unwrap()["memory"],
+            Quantity("2Gi".to_string())
+        );
+        assert!(pod_spec.containers[1].resources.is_none());
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.containers[0].resources.is_none());
+
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "simulatedsensor": {
+                    "resources": { "limits": { "memory": "2 gigs" } }
+                }
+            }
+        })));
+        let err = spec_to_deployment(&settings, &create_module_spec_with_ports()).unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidConfiguration(message) => assert!(message.contains("2 gigs")),
+            kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn valid_quantities() {
+        for quantity in &[
+            "1", "250m", "0.5", ".5", "2Gi", "128Mi", "1e3", "+1k", "1.5E-2",
+        ] {
+            assert!(is_valid_quantity(quantity), "{}", quantity);
+        }
+        for quantity in &["", "Gi", "2 gigs", "2GB", "1.2.3", "1e", "--1", "0x10"] {
+            assert!(!is_valid_quantity(quantity), "{}", quantity);
+        }
+    }
+
+    #[test]
+    fn deployment_with_hostname_and_subdomain() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "hostname": "agent",
+                    "subdomain": "edge-modules"
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.hostname, Some("agent".to_string()));
+        assert_eq!(pod_spec.subdomain, Some("edge-modules".to_string()));
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.hostname.is_none());
+        assert!(pod_spec.subdomain.is_none());
+    }
+
+    #[test]
+    fn deployment_not_safe_to_evict() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "safe_to_evict": false
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(annotations[SAFE_TO_EVICT_ANNOTATION], "false");
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert!(!annotations.contains_key(SAFE_TO_EVICT_ANNOTATION));
+    }
+
+    #[test]
+    fn deployment_with_readiness_gates() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "readiness_gates": ["example.com/feature-1", "example.com/feature-2"]
+                }
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert_eq!(
+            annotations[EDGE_READINESS_GATES_ANNOTATION],
+            "example.com/feature-1,example.com/feature-2"
+        );
+    }
+
+    #[test]
+    fn pod_spec_is_deterministic() {
+        let settings = make_settings(None);
+        let module_spec = || {
+            let env = (0..20)
+                .map(|i| (format!("VAR_{}", i), format!("value{}", i)))
+                .collect::<HashMap<_, _>>();
+            let module = create_module_spec();
+            ModuleSpec::new(
+                module.name().to_string(),
+                module.type_().to_string(),
+                module.config().clone(),
+                env,
+                ImagePullPolicy::default(),
+            )
+            .unwrap()
+        };
+
+        // each spec gets a freshly seeded HashMap, so unsorted iteration shows up
+        let pod_spec_json = || {
+            let (_, deployment) = spec_to_deployment(&settings, &module_spec()).unwrap();
+            serde_json::to_string(&deployment.spec.unwrap().template.spec).unwrap()
+        };
+        let expected = pod_spec_json();
+        for _ in 0..99 {
+            assert_eq!(expected, pod_spec_json());
+        }
+    }
+
+    #[test]
+    fn deployment_with_vpa_recommendation() {
+        let module = create_module_spec();
+        let (name, mut deployment) = spec_to_deployment(&make_settings(None), &module).unwrap();
+
+        let vpa = json!({
+            "status": {
+                "recommendation": {
+                    "containerRecommendations": [
+                        {
+                            "containerName": name,
+                            "target": { "cpu": "25m", "memory": "262144k" }
+                        }
+                    ]
+                }
+            }
+        });
+        apply_vpa_recommendation(&mut deployment, &name, &vpa);
+
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        let requests = pod_spec.containers[0]
+            .resources
+            .as_ref()
+            .and_then(|resources| resources.requests.as_ref())
+            .unwrap();
+        assert_eq!(requests["cpu"].0, "25m");
+        assert_eq!(requests["memory"].0, "262144k");
+        assert!(pod_spec.containers[1].resources.is_none());
+    }
+
+    #[test]
+    fn module_with_tls_certificate() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "tls_certificate": {
+                        "issuer_ref": { "name": "ca-issuer", "kind": "ClusterIssuer" },
+                        "dns_names": ["edgeagent.local"]
+                    }
+                }
+            }
+        })));
+        let module = create_module_spec();
+
+        let (name, certificate) = spec_to_certificate(&settings, &module).unwrap().unwrap();
+        assert_eq!(name, "edgeagent");
+        assert_eq!(certificate["spec"]["secretName"], "edgeagent-tls");
+        assert_eq!(certificate["spec"]["dnsNames"][0], "edgeagent.local");
+        assert_eq!(
+            certificate["spec"]["dnsNames"][1],
+            "edgeagent.default.svc.cluster.local"
+        );
+        assert_eq!(certificate["spec"]["issuerRef"]["name"], "ca-issuer");
+        assert_eq!(certificate["spec"]["issuerRef"]["kind"], "ClusterIssuer");
+
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.volumes.unwrap().iter().any(|volume| {
+            volume.secret.as_ref().and_then(|s| s.secret_name.as_ref())
+                == Some(&"edgeagent-tls".to_string())
+        }));
+        let env = pod_spec.containers[0].env.as_ref().unwrap();
+        assert!(env.iter().any(|var| var.name == TLS_CERT_PATH
+            && var.value == Some("/etc/iotedge/tls/tls.crt".to_string())));
+        assert!(env.iter().any(|var| var.name == TLS_KEY_PATH
+            && var.value == Some("/etc/iotedge/tls/tls.key".to_string())));
+    }
+
+    #[test]
+    fn tls_certificate_uses_cluster_domain() {
+        let settings = make_settings(Some(json!({
+            "cluster_domain": "cluster.local.internal",
+            "modules": {
+                "edgeagent": {
+                    "tls_certificate": {
+                        "issuer_ref": { "name": "ca-issuer" },
+                        "dns_names": []
+                    }
+                }
+            }
+        })));
+        let module = create_module_spec();
+
+        let (_, certificate) = spec_to_certificate(&settings, &module).unwrap().unwrap();
+        assert_eq!(
+            certificate["spec"]["dnsNames"],
+            json!(["edgeagent.default.svc.cluster.local.internal"])
+        );
+    }
+
+    #[test]
+    fn module_with_proxy_env() {
+        let mut proxy_env = BTreeMap::new();
+        proxy_env.insert("HTTPS_PROXY".to_string(), "http://proxy:3128".to_string());
+        proxy_env.insert("NO_PROXY".to_string(), "localhost".to_string());
+        let settings = make_settings(None).with_proxy_env(proxy_env);
+
+        let mut env = HashMap::new();
+        env.insert("https_proxy".to_string(), "http://old:3128".to_string());
+        let module = create_module_spec().with_env(env);
+
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+
+        let module_env = pod_spec.containers[0].env.as_ref().unwrap();
+        assert!(!module_env.iter().any(|var| var.name == "https_proxy"));
+        assert!(module_env
+            .iter()
+            .any(|var| var.name == "HTTPS_PROXY"
+                && var.value == Some("http://proxy:3128".to_string())));
+        assert!(module_env
+            .iter()
+            .any(|var| var.name == "NO_PROXY" && var.value == Some("localhost".to_string())));
+
+        let proxy_env = pod_spec.containers[1].env.as_ref().unwrap();
+        assert!(!proxy_env.iter().any(|var| var.name == "HTTPS_PROXY"));
     }
 
-    fn validate_deployment_metadata(
-        module: &str,
-        device: &str,
-        iothub: &str,
-        meta: Option<&api_meta::ObjectMeta>,
-    ) {
-        assert!(meta.is_some());
-        if let Some(meta) = meta {
-            assert_eq!(meta.name, Some(module.to_string()));
-            assert!(meta.labels.is_some());
-            if let Some(labels) = meta.labels.as_ref() {
-                assert_eq!(labels.get(EDGE_MODULE_LABEL).unwrap(), "edgeagent");
-                assert_eq!(labels.get(EDGE_DEVICE_LABEL).unwrap(), device);
-                assert_eq!(labels.get(EDGE_HUBNAME_LABEL).unwrap(), iothub);
+    #[test]
+    fn module_env_with_annotation_prefix_becomes_pod_annotation() {
+        let mut env = HashMap::new();
+        env.insert(
+            "k8s.annotation.sidecar.istio.io/inject".to_string(),
+            "true".to_string(),
+        );
+        env.insert("k8s.annotation.billing".to_string(), "team-a".to_string());
+        env.insert(
+            format!("k8s.annotation.{}", EDGE_ORIGINAL_MODULEID),
+            "other".to_string(),
+        );
+        env.insert("a".to_string(), "b".to_string());
+        let module = create_module_spec().with_env(env);
+
+        let (_, deployment) = spec_to_deployment(&make_settings(None), &module).unwrap();
+        let template = deployment.spec.unwrap().template;
+
+        let annotations = template.metadata.unwrap().annotations.unwrap();
+        assert_eq!(annotations["sidecar.istio.io/inject"], "true");
+        assert_eq!(annotations["billing"], "team-a");
+        assert_eq!(annotations[EDGE_ORIGINAL_MODULEID], "$edgeAgent");
+
+        let module_env = template.spec.unwrap().containers[0].env.clone().unwrap();
+        assert!(module_env.iter().any(|var| var.name == "a"));
+        assert!(!module_env
+            .iter()
+            .any(|var| var.name.starts_with(ANNOTATION_ENV_PREFIX)));
+    }
+
+    #[test]
+    fn module_env_with_invalid_annotation_key_fails() {
+        for key in &[
+            "k8s.annotation.",
+            "k8s.annotation.inject\nmalicious: true",
+            "k8s.annotation.Sidecar.Istio.io/inject",
+            "k8s.annotation.a/b/c",
+            "k8s.annotation.-inject",
+            "k8s.annotation.istio.io/",
+        ] {
+            let mut env = HashMap::new();
+            env.insert(key.to_string(), "true".to_string());
+            let module = create_module_spec().with_env(env);
+
+            match spec_to_deployment(&make_settings(None), &module)
+                .unwrap_err()
+                .kind()
+            {
+                ErrorKind::InvalidModuleConfig(_) => (),
+                kind => panic!(
+                    "Expected InvalidModuleConfig for {} but got {:?}",
+                    key, kind
+                ),
             }
         }
     }
 
-    #[allow(clippy::cognitive_complexity)]
     #[test]
-    fn deployment_success() {
-        let module_config = create_module_spec();
-
-        let (name, deployment) = spec_to_deployment(&make_settings(None), &module_config).unwrap();
-        assert_eq!(name, "edgeagent");
-        validate_deployment_metadata(
-            "edgeagent",
-            "device1",
-            "iothub",
-            deployment.metadata.as_ref(),
+    fn deployment_with_image_pull_secrets() {
+        let settings = make_settings(Some(json!({
+            "image_pull_secrets": ["acr-secret", "username-registry"]
+        })));
+        let mut env = HashMap::new();
+        env.insert(
+            IMAGE_PULL_SECRET_ENV.to_string(),
+            "module-secret, acr-secret".to_string(),
+        );
+        let module = create_module_spec().with_env(env);
+
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        let names: Vec<_> = pod_spec
+            .image_pull_secrets
+            .unwrap()
+            .into_iter()
+            .filter_map(|secret| secret.name)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["username-registry", "acr-secret", "module-secret"]
         );
+        assert!(!pod_spec.containers[0]
+            .env
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|var| var.name == IMAGE_PULL_SECRET_ENV));
+
+        let module = create_module_spec_with_ports();
+        let (_, deployment) = spec_to_deployment(&make_settings(None), &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.image_pull_secrets.is_none());
+    }
 
-        assert!(deployment.spec.is_some());
-        if let Some(spec) = deployment.spec.as_ref() {
-            assert_eq!(spec.replicas, Some(1));
-            assert!(spec.selector.match_labels.is_some());
-            if let Some(match_labels) = spec.selector.match_labels.as_ref() {
-                assert_eq!(match_labels.get(EDGE_MODULE_LABEL).unwrap(), "edgeagent");
-                assert_eq!(match_labels.get(EDGE_DEVICE_LABEL).unwrap(), "device1");
-                assert_eq!(match_labels.get(EDGE_HUBNAME_LABEL).unwrap(), "iothub");
-            }
-            assert!(spec.template.spec.is_some());
-            if let Some(podspec) = spec.template.spec.as_ref() {
-                assert_eq!(podspec.containers.len(), 2);
-                if let Some(module) = podspec.containers.iter().find(|c| c.name == "edgeagent") {
-                    // 2 from module spec, 1 for use_pvc
-                    assert_eq!(module.env.as_ref().map(Vec::len).unwrap(), 3);
-                    assert_eq!(module.volume_mounts.as_ref().map(Vec::len).unwrap(), 6);
-                    assert_eq!(module.image.as_ref().unwrap(), "my-image:v1.0");
-                    assert_eq!(module.image_pull_policy.as_ref().unwrap(), "IfNotPresent");
+    #[test]
+    fn deployment_with_env_from_config_maps_and_secrets() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "env_from_config_map": ["shared-config", "agent-config"],
+                    "env_from_secret": ["agent-secrets"]
                 }
-                if let Some(proxy) = podspec
-                    .containers
-                    .iter()
-                    .find(|c| c.name == PROXY_CONTAINER_NAME)
-                {
-                    // 2 from module spec, 1 for use_pvc
-                    assert_eq!(proxy.env.as_ref().map(Vec::len).unwrap(), 3);
-                    assert_eq!(proxy.volume_mounts.as_ref().map(Vec::len).unwrap(), 2);
-                    assert_eq!(proxy.image.as_ref().unwrap(), "proxy:latest");
-                    assert_eq!(proxy.image_pull_policy.as_ref().unwrap(), "IfNotPresent");
+            }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec =
+            serde_json::to_value(deployment.spec.unwrap().template.spec.unwrap()).unwrap();
+        assert_eq!(
+            pod_spec["containers"][0]["envFrom"],
+            json!([
+                { "configMapRef": { "name": "shared-config" } },
+                { "configMapRef": { "name": "agent-config" } },
+                { "secretRef": { "name": "agent-secrets" } }
+            ])
+        );
+        assert!(pod_spec["containers"][0]["env"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|var| var["name"] == "a"));
+        assert!(pod_spec["containers"][1].get("envFrom").is_none());
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.containers[0].env_from.is_none());
+    }
+
+    #[test]
+    fn deployment_with_host_aliases() {
+        let settings = make_settings(Some(json!({
+            "host_aliases": [
+                { "ip": "10.0.0.5", "hostnames": ["historian.plant.local", "historian"] }
+            ]
+        })));
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec =
+            serde_json::to_value(deployment.spec.unwrap().template.spec.unwrap()).unwrap();
+        assert_eq!(
+            pod_spec["hostAliases"],
+            json!([{ "ip": "10.0.0.5", "hostnames": ["historian.plant.local", "historian"] }])
+        );
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.host_aliases.is_none());
+    }
+
+    #[test]
+    fn deployment_with_empty_dir_volumes() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "empty_dir_volumes": [
+                        { "name": "scratch", "mount_path": "/scratch" },
+                        { "name": "cache", "mount_path": "/cache", "medium": "Memory" }
+                    ]
                 }
-                assert_eq!(podspec.service_account_name.as_ref().unwrap(), "edgeagent");
-                assert!(podspec.image_pull_secrets.is_some());
-                // 4 bind mounts, 2 volume mounts, 1 proxy configmap, 1 trust bundle configmap
-                assert_eq!(podspec.volumes.as_ref().map(Vec::len).unwrap(), 8);
             }
-        }
+        })));
+
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let pod_spec =
+            serde_json::to_value(deployment.spec.unwrap().template.spec.unwrap()).unwrap();
+        let volumes = pod_spec["volumes"].as_array().unwrap();
+        assert!(volumes.contains(&json!({ "name": "scratch", "emptyDir": {} })));
+        assert!(volumes.contains(&json!({ "name": "cache", "emptyDir": { "medium": "Memory" } })));
+
+        let mounts = pod_spec["containers"][0]["volumeMounts"]
+            .as_array()
+            .unwrap();
+        assert!(mounts.contains(&json!({ "name": "scratch", "mountPath": "/scratch" })));
+        assert!(mounts.contains(&json!({ "name": "cache", "mountPath": "/cache" })));
+        let proxy_mounts = pod_spec["containers"][1]["volumeMounts"]
+            .as_array()
+            .unwrap();
+        assert!(!proxy_mounts.iter().any(|mount| mount["name"] == "scratch"));
     }
 
     #[test]
-    fn auth_to_image_pull_secret_success() {
-        let mut auths = BTreeMap::new();
-        auths.insert(
-            "REGISTRY".to_string(),
-            AuthEntry::new("USER".to_string(), "a password".to_string()),
+    fn deployment_with_active_deadline() {
+        let settings = make_settings(Some(json!({
+            "modules": { "edgeagent": { "active_deadline_seconds": 3600 } }
+        })));
+        let (_, deployment) = spec_to_deployment(&settings, &create_module_spec()).unwrap();
+        let template = deployment.spec.unwrap().template;
+        assert_eq!(
+            template.metadata.unwrap().annotations.unwrap()[EDGE_ACTIVE_DEADLINE_ANNOTATION],
+            "3600"
         );
-        let json_data = serde_json::to_string(&Auth::new(auths)).unwrap();
-        let auth_config = AuthConfig::new()
-            .with_password(String::from("a password"))
-            .with_username(String::from("USER"))
-            .with_serveraddress(String::from("REGISTRY"));
-        let (name, secret) = auth_to_image_pull_secret("namespace", &auth_config).unwrap();
-        assert_eq!(name, "user-registry");
+        // the API server rejects Deployments with a pod template deadline
+        assert_eq!(template.spec.unwrap().active_deadline_seconds, None);
+
+        let (_, deployment) =
+            spec_to_deployment(&make_settings(None), &create_module_spec()).unwrap();
+        let annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations
+            .unwrap();
+        assert!(!annotations.contains_key(EDGE_ACTIVE_DEADLINE_ANNOTATION));
+
+        let settings = make_settings(Some(json!({
+            "modules": { "edgeagent": { "active_deadline_seconds": 0 } }
+        })));
+        assert!(spec_to_deployment(&settings, &create_module_spec()).is_err());
+    }
 
-        assert!(secret.metadata.is_some());
-        if let Some(meta) = secret.metadata.as_ref() {
-            assert_eq!(meta.name, Some(name));
-            assert_eq!(meta.namespace, Some("namespace".to_string()));
-        }
+    fn create_module_spec_with_ports() -> ModuleSpec<DockerConfig> {
+        let create_body = ContainerCreateBody::new().with_exposed_ports({
+            let mut ports = HashMap::new();
+            ports.insert(String::from("8080/tcp"), json!({}));
+            ports.insert(String::from("5671/udp"), json!({}));
+            ports
+        });
+        ModuleSpec::new(
+            "SimulatedSensor".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("sensor:1.0".to_string(), create_body, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn module_with_exposed_ports_gets_service() {
+        let settings = make_settings(None);
+        let module = create_module_spec_with_ports();
+
+        let (name, service) = spec_to_service(&settings, &module).unwrap().unwrap();
+        assert_eq!(name, "simulatedsensor");
+        let spec = service.spec.unwrap();
+        let ports = spec.ports.unwrap();
+        assert_eq!(ports.len(), 2);
+        assert_eq!(ports[0].port, 5671);
+        assert_eq!(ports[0].protocol, Some("UDP".to_string()));
+        assert_eq!(ports[1].port, 8080);
+        assert_eq!(ports[1].protocol, Some("TCP".to_string()));
         assert_eq!(
-            str::from_utf8(secret.data.unwrap()[".dockerconfigjson"].0.as_slice()).unwrap(),
-            json_data
+            spec.selector.unwrap().get(EDGE_MODULE_LABEL).unwrap(),
+            "simulatedsensor"
         );
+
+        assert!(spec_to_service(&settings, &create_module_spec())
+            .unwrap()
+            .is_none());
     }
 
     #[test]
-    fn auth_to_image_pull_secret_failure() {
-        let auths = vec![
-            AuthConfig::new()
-                .with_username(String::from("USER"))
-                .with_serveraddress(String::from("REGISTRY")),
-            AuthConfig::new()
-                .with_password(String::from("a password"))
-                .with_serveraddress(String::from("REGISTRY")),
-            AuthConfig::new()
-                .with_password(String::from("a password"))
-                .with_username(String::from("USER")),
-        ];
-        for auth in auths {
-            let result = auth_to_image_pull_secret("namespace", &auth);
-            assert!(result.is_err());
-        }
+    fn module_service_with_ip_type() {
+        let module = create_module_spec_with_ports();
+
+        let (_, service) = spec_to_service(&make_settings(None), &module)
+            .unwrap()
+            .unwrap();
+        let spec = service.spec.unwrap();
+        assert_eq!(spec.type_, Some("ClusterIP".to_string()));
+        assert_eq!(spec.cluster_ip, None);
+
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "simulatedsensor": { "service_ip_type": "NodePort" }
+            }
+        })));
+        let (_, service) = spec_to_service(&settings, &module).unwrap().unwrap();
+        assert_eq!(service.spec.unwrap().type_, Some("NodePort".to_string()));
+
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "simulatedsensor": { "service_ip_type": "Headless" }
+            }
+        })));
+        let (_, service) = spec_to_service(&settings, &module).unwrap().unwrap();
+        let spec = service.spec.unwrap();
+        assert_eq!(spec.type_, Some("ClusterIP".to_string()));
+        assert_eq!(spec.cluster_ip, Some("None".to_string()));
     }
 
     #[test]
-    fn module_to_service_account() {
-        let module = create_module_spec();
+    fn service_export_only_when_enabled() {
+        let module = create_module_spec_with_ports();
+
+        let settings = make_settings(None);
+        assert!(spec_to_service_export(&settings, &module)
+            .unwrap()
+            .is_none());
+
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "simulatedsensor": {
+                    "export_service": true
+                }
+            }
+        })));
+        let (name, service_export) = spec_to_service_export(&settings, &module).unwrap().unwrap();
+        assert_eq!(name, "simulatedsensor");
+        assert_eq!(service_export["kind"], "ServiceExport");
+        assert_eq!(
+            service_export["apiVersion"],
+            "multicluster.x-k8s.io/v1alpha1"
+        );
+        assert_eq!(service_export["metadata"]["name"], "simulatedsensor");
+    }
 
-        let (name, service_account) =
-            spec_to_service_account(&make_settings(None), &module).unwrap();
-        assert_eq!(name, "edgeagent");
+    #[test]
+    fn headless_service_selects_module_pods() {
+        let settings = make_settings(None);
 
-        assert!(service_account.metadata.is_some());
-        if let Some(metadata) = service_account.metadata {
-            assert_eq!(metadata.name, Some("edgeagent".to_string()));
-            assert_eq!(metadata.namespace, Some("default".to_string()));
+        let (name, service) = module_to_headless_service(&settings, "SimulatedSensor").unwrap();
+        assert_eq!(name, "simulatedsensor-headless");
 
-            assert!(metadata.annotations.is_some());
-            if let Some(annotations) = metadata.annotations {
-                assert_eq!(annotations.len(), 1);
-                assert_eq!(annotations[EDGE_ORIGINAL_MODULEID], "$edgeAgent");
-            }
+        let meta = service.metadata.unwrap();
+        assert_eq!(meta.name, Some("simulatedsensor-headless".to_string()));
+        assert_eq!(
+            meta.annotations.unwrap()[EDGE_ORIGINAL_MODULEID],
+            "SimulatedSensor"
+        );
 
-            assert!(metadata.labels.is_some());
-            if let Some(labels) = metadata.labels {
-                assert_eq!(labels.len(), 3);
-                assert_eq!(labels[EDGE_DEVICE_LABEL], "device1");
-                assert_eq!(labels[EDGE_HUBNAME_LABEL], "iothub");
-                assert_eq!(labels[EDGE_MODULE_LABEL], "edgeagent");
+        let spec = service.spec.unwrap();
+        assert_eq!(spec.cluster_ip, Some("None".to_string()));
+        assert_eq!(spec.type_, Some("ClusterIP".to_string()));
+        assert!(spec.ports.is_none());
+        assert_eq!(spec.selector.unwrap()[EDGE_MODULE_LABEL], "simulatedsensor");
+    }
+
+    #[test]
+    fn module_with_csi_volumes() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "csi_volumes": [{
+                        "name": "blob",
+                        "driver": "blob.csi.azure.com",
+                        "read_only": true,
+                        "volume_attributes": { "containername": "telemetry" },
+                        "mount_path": "/data"
+                    }]
+                }
             }
-        }
+        })));
+        let module = create_module_spec();
+
+        let volumes = spec_to_csi_volumes(&settings, &module).unwrap();
+        assert_eq!(volumes.len(), 1);
+        let (persistent_volume, claim) = &volumes[0];
+        let csi = persistent_volume
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.csi.as_ref())
+            .unwrap();
+        assert_eq!(csi.driver, "blob.csi.azure.com");
+        assert_eq!(csi.read_only, Some(true));
+        assert_eq!(
+            csi.volume_attributes.as_ref().unwrap()["containername"],
+            "telemetry"
+        );
+        let claim_spec = claim.spec.as_ref().unwrap();
+        assert_eq!(
+            claim_spec.volume_name,
+            persistent_volume.metadata.as_ref().unwrap().name
+        );
+        assert_eq!(
+            claim_spec.access_modes,
+            Some(vec!["ReadOnlyMany".to_string()])
+        );
+
+        let (_, deployment) = spec_to_deployment(&settings, &module).unwrap();
+        let pod_spec = deployment.spec.unwrap().template.spec.unwrap();
+        assert!(pod_spec.volumes.unwrap().iter().any(|volume| {
+            volume
+                .persistent_volume_claim
+                .as_ref()
+                .map(|claim| claim.claim_name.as_str())
+                == Some("edgeagent-blob")
+        }));
+        assert!(pod_spec.containers[0]
+            .volume_mounts
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|mount| mount.name == "blob" && mount.mount_path == "/data"));
     }
 
     #[test]
-    fn module_to_role_binding() {
+    fn module_without_csi_volumes() {
+        let settings = make_settings(None);
         let module = create_module_spec();
 
-        let (name, role_binding) = spec_to_role_binding(&make_settings(None), &module).unwrap();
-        assert_eq!(name, "edgeagent");
-        assert!(role_binding.metadata.is_some());
-        if let Some(metadata) = role_binding.metadata {
-            assert_eq!(metadata.name, Some("edgeagent".to_string()));
-            assert_eq!(metadata.namespace, Some("default".to_string()));
+        assert!(spec_to_csi_volumes(&settings, &module).unwrap().is_empty());
+        assert!(spec_to_csi_volume_claim_patches(&settings, &module)
+            .unwrap()
+            .is_empty());
+    }
 
-            assert!(metadata.annotations.is_some());
-            if let Some(annotations) = metadata.annotations {
-                assert_eq!(annotations.len(), 1);
-                assert_eq!(annotations[EDGE_ORIGINAL_MODULEID], "$edgeAgent");
+    #[test]
+    fn csi_volume_with_volume_attributes_class() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "csi_volumes": [{
+                        "name": "disk",
+                        "driver": "disk.csi.azure.com",
+                        "mount_path": "/data",
+                        "volume_attributes_class_name": "premium-iops"
+                    }, {
+                        "name": "blob",
+                        "driver": "blob.csi.azure.com",
+                        "mount_path": "/blob"
+                    }]
+                }
             }
+        })));
+        let module = create_module_spec();
 
-            assert!(metadata.labels.is_some());
-            if let Some(labels) = metadata.labels {
-                assert_eq!(labels.len(), 3);
-                assert_eq!(labels[EDGE_DEVICE_LABEL], "device1");
-                assert_eq!(labels[EDGE_HUBNAME_LABEL], "iothub");
-                assert_eq!(labels[EDGE_MODULE_LABEL], "edgeagent");
-            }
-        }
+        let patches = spec_to_csi_volume_claim_patches(&settings, &module).unwrap();
+        assert_eq!(
+            vec![(
+                "edgeagent-disk".to_string(),
+                json!({ "spec": { "volumeAttributesClassName": "premium-iops" } })
+            )],
+            patches
+        );
+    }
 
-        assert_eq!(role_binding.role_ref.api_group, "rbac.authorization.k8s.io");
-        assert_eq!(role_binding.role_ref.kind, "ClusterRole");
-        assert_eq!(role_binding.role_ref.name, "cluster-admin");
+    #[test]
+    fn module_to_pod_monitor() {
+        let module = create_module_spec();
+        assert!(spec_to_pod_monitor(&make_settings(None), &module)
+            .unwrap()
+            .is_none());
+
+        let mut labels = HashMap::new();
+        labels.insert(EDGE_METRICS_PORT_LABEL.to_string(), "9600".to_string());
+        let create_body = ContainerCreateBody::new().with_labels(labels);
+        let module = ModuleSpec::new(
+            "$edgeHub".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("my-image:v1.0".to_string(), create_body, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap();
+
+        let (name, pod_monitor) = spec_to_pod_monitor(&make_settings(None), &module)
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, "edgehub");
+        assert_eq!(pod_monitor["kind"], "PodMonitor");
+        assert_eq!(
+            pod_monitor["spec"]["selector"]["matchLabels"][EDGE_MODULE_LABEL],
+            "edgehub"
+        );
+        assert_eq!(
+            pod_monitor["spec"]["podMetricsEndpoints"][0]["targetPort"],
+            9600
+        );
+        assert_eq!(
+            pod_monitor["spec"]["podMetricsEndpoints"][0]["path"],
+            "/metrics"
+        );
+    }
+
+    #[test]
+    fn settings_to_admission_policy_binds_namespace() {
+        let settings = make_settings(None);
+
+        let ((policy_name, policy), (binding_name, binding)) =
+            settings_to_admission_policy(&settings).unwrap();
+        assert_eq!(policy_name, "default-iotedge-module-pods");
+        assert_eq!(binding_name, policy_name);
+        assert_eq!(policy["kind"], "ValidatingAdmissionPolicy");
+        assert_eq!(policy["spec"]["validations"].as_array().unwrap().len(), 2);
+        assert_eq!(binding["spec"]["policyName"], "default-iotedge-module-pods");
+        assert_eq!(
+            binding["spec"]["matchResources"]["namespaceSelector"]["matchLabels"]
+                ["kubernetes.io/metadata.name"],
+            "default"
+        );
+    }
+
+    #[test]
+    fn settings_to_rbac() {
+        let settings = make_settings(None);
+
+        let (name, role) = settings_to_role(&settings).unwrap();
+        assert_eq!(name, "iotedge");
+        assert_eq!(role.rules.len(), 11);
+        assert_eq!(
+            role.rules[0].resources,
+            Some(vec![
+                "pods".to_string(),
+                "services".to_string(),
+                "configmaps".to_string(),
+                "persistentvolumeclaims".to_string(),
+            ])
+        );
+        assert_eq!(role.rules[1].resources, Some(vec!["secrets".to_string()]));
+        assert_eq!(
+            role.rules[1].verbs,
+            vec![
+                "get".to_string(),
+                "list".to_string(),
+                "create".to_string(),
+                "update".to_string(),
+                "delete".to_string()
+            ]
+        );
+        assert_eq!(
+            role.rules[2].resources,
+            Some(vec!["serviceaccounts".to_string()])
+        );
+        assert!(!role.rules[2].verbs.contains(&"watch".to_string()));
+        assert_eq!(role.rules[3].resources, Some(vec!["events".to_string()]));
+        assert_eq!(role.rules[3].verbs, vec!["create".to_string()]);
+        for (api_group, resource) in &[
+            ("monitoring.coreos.com", "podmonitors"),
+            ("cert-manager.io", "certificates"),
+            ("multicluster.x-k8s.io", "serviceexports"),
+            ("autoscaling.k8s.io", "verticalpodautoscalers"),
+        ] {
+            assert!(role.rules.iter().any(|rule| {
+                rule.api_groups == Some(vec![api_group.to_string()])
+                    && rule.resources == Some(vec![resource.to_string()])
+            }));
+        }
 
+        let (name, role_binding) = settings_to_role_binding(&settings).unwrap();
+        assert_eq!(name, "iotedge");
+        assert_eq!(role_binding.role_ref.kind, "Role");
+        assert_eq!(role_binding.role_ref.name, "iotedge");
         assert_eq!(role_binding.subjects.len(), 1);
-        let subject = &role_binding.subjects[0];
-        assert_eq!(subject.api_group, None);
-        assert_eq!(subject.kind, "ServiceAccount");
-        assert_eq!(subject.name, "edgeagent");
-        assert_eq!(subject.namespace, Some("default".to_string()));
+        assert_eq!(role_binding.subjects[0].kind, "ServiceAccount");
+        assert_eq!(role_binding.subjects[0].name, "iotedge");
+        assert_eq!(
+            role_binding.subjects[0].namespace,
+            Some("default".to_string())
+        );
+
+        let (name, cluster_role) = settings_to_cluster_role(&settings).unwrap();
+        assert_eq!(name, "default-iotedge");
+        assert_eq!(cluster_role.metadata.unwrap().namespace, None);
+        for resource in &[
+            "persistentvolumes",
+            "validatingadmissionpolicies",
+            "validatingadmissionpolicybindings",
+        ] {
+            assert!(cluster_role.rules.iter().any(|rule| rule
+                .resources
+                .as_ref()
+                .unwrap()
+                .contains(&resource.to_string())));
+        }
+
+        let (name, cluster_role_binding) = settings_to_cluster_role_binding(&settings).unwrap();
+        assert_eq!(name, "default-iotedge");
+        assert_eq!(cluster_role_binding.role_ref.kind, "ClusterRole");
+        assert_eq!(cluster_role_binding.role_ref.name, "default-iotedge");
+        assert_eq!(cluster_role_binding.subjects[0].name, "iotedge");
+        assert_eq!(
+            cluster_role_binding.subjects[0].namespace,
+            Some("default".to_string())
+        );
     }
 
     #[test]
@@ -844,6 +3655,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn trust_bundle_to_config_map_fails_when_too_large() {
+        let config_map = trust_bundle_to_config_map(
+            &make_settings(None),
+            &TestCert::default().with_cert(vec![b'a'; CONFIG_MAP_MAX_DATA_SIZE]),
+        );
+
+        assert_eq!(
+            config_map.unwrap_err().kind(),
+            &ErrorKind::ConfigMapTooLarge {
+                size: PROXY_TRUST_BUNDLE_FILENAME.len() + CONFIG_MAP_MAX_DATA_SIZE,
+                limit: CONFIG_MAP_MAX_DATA_SIZE,
+            }
+        )
+    }
+
     #[test]
     fn trust_bundle_to_config_map_with_cert() {
         let (name, config_map) = trust_bundle_to_config_map(
@@ -90,6 +90,30 @@ pub enum ErrorKind {
 
     #[fail(display = "An error occurred obtaining the client identity certificate")]
     IdentityCertificate,
+
+    #[fail(display = "Could not sync secrets from Azure Key Vault")]
+    KeyVault,
+
+    #[fail(display = "Signature verification failed for image {:?}", _0)]
+    ImageVerificationFailed(String),
+
+    #[fail(display = "Invalid configuration: {}", _0)]
+    InvalidConfiguration(String),
+
+    #[fail(display = "Invalid module configuration: {}", _0)]
+    InvalidModuleConfig(String),
+
+    #[fail(
+        display = "Module {:?} has persistent volume claims and must be removed with force",
+        _0
+    )]
+    ConfirmationRequired(String),
+
+    #[fail(
+        display = "Config map data of {} bytes exceeds the limit of {} bytes",
+        size, limit
+    )]
+    ConfigMapTooLarge { size: usize, limit: usize },
 }
 
 impl Fail for Error {
@@ -0,0 +1,179 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use log::info;
+
+use edgelet_core::ProvisioningResult as CoreProvisioningResult;
+use kube_client::{Error as KubeClientError, TokenSource};
+use provisioning::{ProvisioningResult, ReprovisioningStatus};
+
+use crate::constants::{PROVISIONED_REASON, PROVISIONING_FAILED_REASON, REPROVISIONED_REASON};
+use crate::convert::settings_to_event;
+use crate::{Error, KubeModuleRuntime};
+
+const NORMAL_EVENT: &str = "Normal";
+const WARNING_EVENT: &str = "Warning";
+
+/// Posts Kubernetes events about the IoT Edge runtime's lifecycle, so that
+/// state changes which don't belong to any module show up alongside the
+/// module events with `kubectl get events`. Events are recorded against the
+/// IoT Edge service account.
+pub struct EventRecorder<T, S> {
+    runtime: KubeModuleRuntime<T, S>,
+}
+
+impl<T, S> EventRecorder<T, S>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    pub fn new(runtime: KubeModuleRuntime<T, S>) -> Self {
+        EventRecorder { runtime }
+    }
+
+    /// Records a `Provisioned` event when the device was assigned to a hub
+    /// for the first time, or `Reprovisioned` when its device data changed
+    /// since the previous run. Nothing is recorded when provisioning returned
+    /// the same device data as before.
+    pub fn record_provisioning(
+        &self,
+        provisioning_result: &ProvisioningResult,
+    ) -> impl Future<Item = (), Error = Error> {
+        let reason = match provisioning_result.reconfigure() {
+            ReprovisioningStatus::DeviceDataNotUpdated => None,
+            ReprovisioningStatus::InitialAssignment => Some(PROVISIONED_REASON),
+            ReprovisioningStatus::DeviceDataUpdated
+            | ReprovisioningStatus::DeviceDataMigrated
+            | ReprovisioningStatus::DeviceDataReset => Some(REPROVISIONED_REASON),
+        };
+
+        let message = format!(
+            "Device {} provisioned to hub {} ({:?})",
+            provisioning_result.device_id(),
+            provisioning_result.hub_name(),
+            provisioning_result.reconfigure(),
+        );
+
+        match reason {
+            Some(reason) => Either::A(self.record(reason, NORMAL_EVENT, message)),
+            None => Either::B(future::ok(())),
+        }
+    }
+
+    /// Records a `ProvisioningFailed` warning with the reason provisioning
+    /// could not complete.
+    pub fn record_provisioning_failure(
+        &self,
+        message: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.record(PROVISIONING_FAILED_REASON, WARNING_EVENT, message)
+    }
+
+    fn record(
+        &self,
+        reason: &str,
+        type_: &str,
+        message: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        info!("Recording {} event: {}", reason, message);
+
+        let client_copy = self.runtime.client().clone();
+        let namespace_copy = self.runtime.settings().namespace().to_owned();
+
+        settings_to_event(self.runtime.settings(), reason, type_, message, Utc::now())
+            .map_err(Error::from)
+            .map(move |event| {
+                client_copy
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .create_event(namespace_copy.as_str(), &event)
+                    .map_err(Error::from)
+                    .map(|_| ())
+            })
+            .into_future()
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::Value as JsonValue;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+    use provisioning::{ProvisioningResult, ReprovisioningStatus};
+
+    use super::EventRecorder;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_records_reprovisioning_against_service_account() {
+        let recorded = Arc::new(AtomicBool::new(false));
+        let recorded_copy = recorded.clone();
+
+        let dispatch_table = routes!(
+            POST "/api/v1/namespaces/default/events" => move |req: Request<Body>| {
+                let recorded = recorded_copy.clone();
+                let fut = req.into_body().concat2().and_then(move |body| {
+                    let event: JsonValue = serde_json::from_slice(&body).unwrap();
+                    assert_eq!(event["reason"], "Reprovisioned");
+                    assert_eq!(event["type"], "Normal");
+                    assert_eq!(event["involvedObject"]["kind"], "ServiceAccount");
+                    assert_eq!(event["involvedObject"]["name"], "iotedge");
+                    recorded.store(true, Ordering::SeqCst);
+                    response(StatusCode::CREATED, move || event.to_string())
+                });
+                Box::new(fut) as ResponseFuture
+            },
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let recorder = EventRecorder::new(create_runtime(make_settings(None), service_fn(handler)));
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(recorder.record_provisioning(&provisioning_result(
+                ReprovisioningStatus::DeviceDataMigrated,
+            )))
+            .unwrap();
+
+        assert!(recorded.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn it_skips_event_when_device_data_not_updated() {
+        // No routes, so posting an event would fail the test.
+        let handler = make_req_dispatcher(BTreeMap::new(), Box::new(not_found_handler));
+        let recorder = EventRecorder::new(create_runtime(make_settings(None), service_fn(handler)));
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(recorder.record_provisioning(&provisioning_result(
+                ReprovisioningStatus::DeviceDataNotUpdated,
+            )))
+            .unwrap();
+    }
+
+    fn provisioning_result(reconfigure: ReprovisioningStatus) -> ProvisioningResult {
+        ProvisioningResult::new("device1", "iotHub", None, reconfigure, None)
+    }
+}
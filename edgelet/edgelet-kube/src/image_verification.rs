@@ -0,0 +1,340 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::client::connect::Connect;
+use hyper::{Body, Client as HyperClient, Method, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+use log::debug;
+use openssl::x509::X509;
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::Result;
+use crate::settings::ImageVerificationPolicy;
+use crate::{Error, ErrorKind};
+
+/// Checks that `image` has a signature recorded in the policy's Sigstore
+/// transparency log which was made with the trusted public key, or by a
+/// certificate issued to the trusted identity. Log entries are looked up by
+/// the image's manifest digest, so the image must be pinned with `@sha256:`.
+pub fn verify_image_signature(
+    image: &str,
+    policy: &ImageVerificationPolicy,
+) -> impl Future<Item = (), Error = Error> {
+    let image = image.to_string();
+    let policy = policy.clone();
+
+    HttpsConnector::new(1)
+        .map(|connector| HyperClient::builder().build::<_, Body>(connector))
+        .with_context(|_| ErrorKind::ImageVerificationFailed(image.clone()))
+        .map_err(Error::from)
+        .map(|client| verify_with_client(&client, &image, &policy))
+        .into_future()
+        .flatten()
+}
+
+fn verify_with_client<C>(
+    client: &HyperClient<C, Body>,
+    image: &str,
+    policy: &ImageVerificationPolicy,
+) -> impl Future<Item = (), Error = Error>
+where
+    C: Connect + Sync + 'static,
+{
+    let digest = match image_digest(image) {
+        Some(digest) => digest,
+        None => {
+            return Either::A(future::err(Error::from(
+                ErrorKind::ImageVerificationFailed(image.to_string()),
+            )))
+        }
+    };
+
+    let client_copy = client.clone();
+    let policy_copy = policy.clone();
+    let image_for_entries = image.to_string();
+    let image_for_result = image.to_string();
+
+    let fut = rekor_request(
+        image,
+        policy,
+        Method::POST,
+        "api/v1/index/retrieve",
+        Some(json!({ "hash": digest })),
+    )
+    .map(|req| get_json(client, image, req))
+    .into_future()
+    .flatten()
+    .and_then(move |uuids| {
+        let entries: Vec<_> = uuids
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(JsonValue::as_str)
+            .map(|uuid| {
+                rekor_request(
+                    &image_for_entries,
+                    &policy_copy,
+                    Method::GET,
+                    &format!("api/v1/log/entries/{}", uuid),
+                    None,
+                )
+                .map(|req| get_json(&client_copy, &image_for_entries, req))
+                .into_future()
+                .flatten()
+            })
+            .collect();
+
+        future::join_all(entries).map(move |entries| {
+            entries
+                .iter()
+                .any(|entry| entry_matches_policy(entry, &policy_copy))
+        })
+    })
+    .and_then(move |verified| {
+        if verified {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::ImageVerificationFailed(
+                image_for_result,
+            )))
+        }
+    });
+
+    Either::B(fut)
+}
+
+fn image_digest(image: &str) -> Option<&str> {
+    let mut parts = image.rsplitn(2, '@');
+    match (parts.next(), parts.next()) {
+        (Some(digest), Some(_)) if digest.starts_with("sha256:") => Some(digest),
+        _ => None,
+    }
+}
+
+fn rekor_request(
+    image: &str,
+    policy: &ImageVerificationPolicy,
+    method: Method,
+    path: &str,
+    body: Option<JsonValue>,
+) -> Result<Request<Body>> {
+    let uri = policy
+        .rekor_url()
+        .join(path)
+        .with_context(|_| ErrorKind::ImageVerificationFailed(image.to_string()))?;
+
+    let mut builder = Request::builder();
+    builder.method(method).uri(uri.as_str());
+    let req = match body {
+        Some(body) => builder
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => builder.body(Body::empty()),
+    }
+    .with_context(|_| ErrorKind::ImageVerificationFailed(image.to_string()))?;
+
+    Ok(req)
+}
+
+fn get_json<C>(
+    client: &HyperClient<C, Body>,
+    image: &str,
+    req: Request<Body>,
+) -> impl Future<Item = JsonValue, Error = Error>
+where
+    C: Connect + Sync + 'static,
+{
+    let image_for_request = image.to_string();
+    let image_for_response = image.to_string();
+
+    client
+        .request(req)
+        .and_then(|resp| {
+            let status = resp.status();
+            resp.into_body().concat2().map(move |body| (status, body))
+        })
+        .map_err(|err| {
+            Error::from(err.context(ErrorKind::ImageVerificationFailed(image_for_request)))
+        })
+        .and_then(move |(status, body)| {
+            if status == StatusCode::OK {
+                serde_json::from_slice(&body)
+                    .context(ErrorKind::ImageVerificationFailed(image_for_response))
+                    .map_err(Error::from)
+            } else {
+                Err(Error::from(ErrorKind::ImageVerificationFailed(
+                    image_for_response,
+                )))
+            }
+        })
+}
+
+/// A log entry response is keyed by the entry UUID and carries the entry
+/// itself base64 encoded in `body`. The signing key or certificate is in
+/// `spec.signature.publicKey.content`, also base64 encoded.
+fn entry_matches_policy(entry: &JsonValue, policy: &ImageVerificationPolicy) -> bool {
+    let signer = entry
+        .as_object()
+        .into_iter()
+        .flat_map(|entries| entries.values())
+        .filter_map(|entry| entry["body"].as_str())
+        .filter_map(|body| base64::decode(body).ok())
+        .filter_map(|body| serde_json::from_slice::<JsonValue>(&body).ok())
+        .filter_map(|body| {
+            body["spec"]["signature"]["publicKey"]["content"]
+                .as_str()
+                .and_then(|content| base64::decode(content).ok())
+        })
+        .collect::<Vec<_>>();
+
+    signer.iter().any(|pem| {
+        let key_matches = policy
+            .public_key()
+            .map_or(false, |public_key| same_pem(public_key.as_bytes(), pem));
+        let identity_matches = policy
+            .certificate_identity()
+            .map_or(false, |identity| certificate_has_identity(pem, identity));
+
+        debug!(
+            "Rekor entry signer matches public key: {}, certificate identity: {}",
+            key_matches, identity_matches
+        );
+        key_matches || identity_matches
+    })
+}
+
+fn same_pem(expected: &[u8], actual: &[u8]) -> bool {
+    let strip = |pem: &[u8]| -> Vec<u8> {
+        pem.iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .cloned()
+            .collect()
+    };
+    strip(expected) == strip(actual)
+}
+
+fn certificate_has_identity(pem: &[u8], identity: &str) -> bool {
+    X509::from_pem(pem)
+        .ok()
+        .and_then(|cert| cert.subject_alt_names())
+        .map_or(false, |names| {
+            names
+                .iter()
+                .any(|name| name.email() == Some(identity) || name.uri() == Some(identity))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use hyper::{Body, Method, Request, Response, StatusCode};
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+    use url::Url;
+
+    use edgelet_test_utils::{get_unused_tcp_port, run_tcp_server};
+
+    use super::verify_image_signature;
+    use crate::settings::ImageVerificationPolicy;
+    use crate::ErrorKind;
+
+    const IMAGE: &str = "example.azurecr.io/module@sha256:abc123";
+    const SIGNER_KEY: &str = "-----BEGIN PUBLIC KEY-----\nc2lnbmVy\n-----END PUBLIC KEY-----\n";
+
+    #[test]
+    fn it_accepts_image_signed_with_trusted_key() {
+        let port = get_unused_tcp_port();
+        let policy = policy(port).with_public_key(SIGNER_KEY.to_string());
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(run_tcp_server("127.0.0.1", port, rekor_handler).map_err(|_| ()));
+        runtime
+            .block_on(verify_image_signature(IMAGE, &policy))
+            .unwrap();
+    }
+
+    #[test]
+    fn it_rejects_image_signed_with_other_key() {
+        let port = get_unused_tcp_port();
+        let policy = policy(port).with_public_key(
+            "-----BEGIN PUBLIC KEY-----\nb3RoZXI=\n-----END PUBLIC KEY-----\n".to_string(),
+        );
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(run_tcp_server("127.0.0.1", port, rekor_handler).map_err(|_| ()));
+        let err = runtime
+            .block_on(verify_image_signature(IMAGE, &policy))
+            .unwrap_err();
+
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ImageVerificationFailed(IMAGE.to_string())
+        );
+    }
+
+    #[test]
+    fn it_rejects_image_not_pinned_by_digest() {
+        let policy = policy(get_unused_tcp_port()).with_public_key(SIGNER_KEY.to_string());
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime
+            .block_on(verify_image_signature(
+                "example.azurecr.io/module:1.0",
+                &policy,
+            ))
+            .unwrap_err();
+
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ImageVerificationFailed("example.azurecr.io/module:1.0".to_string())
+        );
+    }
+
+    fn policy(port: u16) -> ImageVerificationPolicy {
+        ImageVerificationPolicy::new(Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap())
+    }
+
+    fn rekor_handler(
+        req: Request<Body>,
+    ) -> impl Future<Item = Response<Body>, Error = hyper::Error> {
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+
+        req.into_body().concat2().map(move |body| {
+            let response = match (method, path.as_str()) {
+                (Method::POST, "/api/v1/index/retrieve") => {
+                    let query: JsonValue = serde_json::from_slice(&body).unwrap();
+                    if query["hash"] == "sha256:abc123" {
+                        json!(["entry1"])
+                    } else {
+                        json!([])
+                    }
+                }
+                (Method::GET, "/api/v1/log/entries/entry1") => {
+                    let entry = json!({
+                        "kind": "hashedrekord",
+                        "spec": {
+                            "signature": {
+                                "publicKey": {
+                                    "content": base64::encode(SIGNER_KEY),
+                                }
+                            }
+                        }
+                    });
+                    json!({ "entry1": { "body": base64::encode(&entry.to_string()) } })
+                }
+                _ => {
+                    return Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap()
+                }
+            };
+
+            Response::new(response.to_string().into())
+        })
+    }
+}
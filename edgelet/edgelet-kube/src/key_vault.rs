@@ -0,0 +1,570 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::{Duration, Instant};
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::{Body, Client as HyperClient, Method, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+use log::{info, warn};
+use serde_derive::Deserialize;
+use tokio::timer::Interval;
+use url::form_urlencoded::Serializer as UrlSerializer;
+use url::Url;
+
+use kube_client::{Error as KubeClientError, ProjectedTokenSource, TokenSource};
+
+use crate::constants::{
+    AZURE_AD_TOKEN_EXCHANGE_AUDIENCE, KEY_VAULT_API_VERSION, KEY_VAULT_RESOURCE,
+    WORKLOAD_IDENTITY_TOKEN_EXPIRATION_SECS,
+};
+use crate::convert::key_vault_secret_to_secret;
+use crate::error::Result;
+use crate::settings::{KeyVaultSettings, WorkloadIdentity};
+use crate::{Error, ErrorKind, KubeModuleRuntime};
+
+const INSTANCE_METADATA_API_VERSION: &str = "2018-02-01";
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct KeyVaultSecret {
+    value: String,
+}
+
+/// Copies the secrets listed in the `key_vault` settings from Azure Key Vault
+/// into Kubernetes Secrets in the runtime's namespace, and keeps them in sync
+/// by re-reading the vault on the configured interval.
+pub struct AzureKeyVaultSecretSync<T, S, C> {
+    runtime: KubeModuleRuntime<T, S>,
+    client: HyperClient<C, Body>,
+    token_source: Option<ProjectedTokenSource>,
+}
+
+impl<T, S> AzureKeyVaultSecretSync<T, S, HttpsConnector<HttpConnector>> {
+    pub fn new(runtime: KubeModuleRuntime<T, S>) -> Result<Self> {
+        let connector = HttpsConnector::new(4).context(ErrorKind::KeyVault)?;
+        Ok(Self::with_client(
+            runtime,
+            HyperClient::builder().build(connector),
+        ))
+    }
+}
+
+impl<T, S, C> AzureKeyVaultSecretSync<T, S, C> {
+    pub fn with_client(runtime: KubeModuleRuntime<T, S>, client: HyperClient<C, Body>) -> Self {
+        // Shared by every sync, so a token is only requested again when it's
+        // about to expire.
+        let token_source = runtime
+            .settings()
+            .key_vault()
+            .and_then(KeyVaultSettings::workload_identity)
+            .map(|_| {
+                ProjectedTokenSource::new(
+                    runtime.settings().namespace().to_string(),
+                    runtime.settings().service_account_name().to_string(),
+                    vec![AZURE_AD_TOKEN_EXCHANGE_AUDIENCE.to_string()],
+                    Duration::from_secs(WORKLOAD_IDENTITY_TOKEN_EXPIRATION_SECS),
+                )
+            });
+
+        AzureKeyVaultSecretSync {
+            runtime,
+            client,
+            token_source,
+        }
+    }
+}
+
+impl<T, S, C> AzureKeyVaultSecretSync<T, S, C>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    C: Connect + Sync + 'static,
+{
+    /// Reads every configured secret from the vault once and creates or
+    /// replaces the matching Kubernetes Secret.
+    pub fn sync(&self) -> impl Future<Item = (), Error = Error> {
+        let key_vault = match self.runtime.settings().key_vault() {
+            Some(key_vault) => key_vault.clone(),
+            None => return Either::A(future::ok(())),
+        };
+
+        let client = self.client.clone();
+        let runtime = self.runtime.clone();
+
+        let fut = self.access_token(&key_vault).and_then(move |token| {
+            let secrets: Vec<_> = key_vault
+                .secrets()
+                .iter()
+                .map(|name| {
+                    let runtime = runtime.clone();
+                    let name_copy = name.clone();
+                    get_secret(&client, key_vault.vault_uri(), &token, name)
+                        .and_then(move |value| upsert_secret(&runtime, &name_copy, &value))
+                })
+                .collect();
+
+            future::join_all(secrets).map(|_| ())
+        });
+
+        Either::B(fut)
+    }
+
+    /// With a workload identity, the runtime's service account token is
+    /// refreshed if it's close to expiring and exchanged for an access token.
+    /// Otherwise the token is requested from the node's identity endpoint.
+    fn access_token(
+        &self,
+        key_vault: &KeyVaultSettings,
+    ) -> impl Future<Item = String, Error = Error> {
+        match (key_vault.workload_identity(), self.token_source.as_ref()) {
+            (Some(identity), Some(source)) => {
+                let client = self.client.clone();
+                let identity = identity.clone();
+                let source = source.clone();
+
+                let fut = self
+                    .runtime
+                    .client()
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .refresh_projected_token(&source)
+                    .and_then(move |_| source.get())
+                    .map_err(Error::from)
+                    .and_then(|assertion| assertion.ok_or_else(|| Error::from(ErrorKind::KeyVault)))
+                    .and_then(move |assertion| exchange_token(&client, &identity, &assertion));
+                Either::A(fut)
+            }
+            _ => Either::B(get_access_token(&self.client, key_vault)),
+        }
+    }
+
+    /// Syncs the vault secrets now and then again every `resync_interval`.
+    /// Failures are logged and retried on the next tick rather than ending
+    /// the loop.
+    pub fn run(self) -> impl Future<Item = (), Error = Error> {
+        let interval = self
+            .runtime
+            .settings()
+            .key_vault()
+            .map(KeyVaultSettings::resync_interval);
+
+        match interval {
+            Some(interval) => {
+                let fut = Interval::new(Instant::now(), interval)
+                    .map_err(|err| Error::from(err.context(ErrorKind::KeyVault)))
+                    .for_each(move |_| {
+                        self.sync().then(|result| {
+                            match result {
+                                Ok(()) => info!("Synced secrets from Azure Key Vault"),
+                                Err(err) => warn!("Azure Key Vault secret sync failed: {}", err),
+                            }
+                            Ok::<_, Error>(())
+                        })
+                    });
+                Either::A(fut)
+            }
+            None => Either::B(future::ok(())),
+        }
+    }
+}
+
+fn get_access_token<C>(
+    client: &HyperClient<C, Body>,
+    key_vault: &KeyVaultSettings,
+) -> impl Future<Item = String, Error = Error>
+where
+    C: Connect + Sync + 'static,
+{
+    let mut uri = key_vault.identity_endpoint().clone();
+    uri.query_pairs_mut()
+        .append_pair("api-version", INSTANCE_METADATA_API_VERSION)
+        .append_pair("resource", KEY_VAULT_RESOURCE);
+
+    Request::builder()
+        .method(Method::GET)
+        .uri(uri.as_str())
+        .header("Metadata", "true")
+        .body(Body::empty())
+        .context(ErrorKind::KeyVault)
+        .map_err(Error::from)
+        .map(|req| get_json::<_, AccessToken>(client, req).map(|token| token.access_token))
+        .into_future()
+        .flatten()
+}
+
+fn exchange_token<C>(
+    client: &HyperClient<C, Body>,
+    identity: &WorkloadIdentity,
+    assertion: &str,
+) -> impl Future<Item = String, Error = Error>
+where
+    C: Connect + Sync + 'static,
+{
+    let body = UrlSerializer::new(String::new())
+        .append_pair("grant_type", "client_credentials")
+        .append_pair("client_id", identity.client_id())
+        .append_pair("scope", &format!("{}/.default", KEY_VAULT_RESOURCE))
+        .append_pair(
+            "client_assertion_type",
+            "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+        )
+        .append_pair("client_assertion", assertion)
+        .finish();
+
+    identity
+        .authority_host()
+        .join(&format!("{}/oauth2/v2.0/token", identity.tenant_id()))
+        .context(ErrorKind::KeyVault)
+        .map_err(Error::from)
+        .and_then(|uri| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(uri.as_str())
+                .header(
+                    hyper::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .body(Body::from(body))
+                .context(ErrorKind::KeyVault)
+                .map_err(Error::from)
+        })
+        .map(|req| get_json::<_, AccessToken>(client, req).map(|token| token.access_token))
+        .into_future()
+        .flatten()
+}
+
+fn get_secret<C>(
+    client: &HyperClient<C, Body>,
+    vault_uri: &Url,
+    token: &str,
+    name: &str,
+) -> impl Future<Item = String, Error = Error>
+where
+    C: Connect + Sync + 'static,
+{
+    vault_uri
+        .join(&format!("secrets/{}", name))
+        .context(ErrorKind::KeyVault)
+        .map_err(Error::from)
+        .and_then(|mut uri| {
+            uri.query_pairs_mut()
+                .append_pair("api-version", KEY_VAULT_API_VERSION);
+
+            Request::builder()
+                .method(Method::GET)
+                .uri(uri.as_str())
+                .header(hyper::header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .context(ErrorKind::KeyVault)
+                .map_err(Error::from)
+        })
+        .map(|req| get_json::<_, KeyVaultSecret>(client, req).map(|secret| secret.value))
+        .into_future()
+        .flatten()
+}
+
+fn get_json<C, R>(
+    client: &HyperClient<C, Body>,
+    req: Request<Body>,
+) -> impl Future<Item = R, Error = Error>
+where
+    C: Connect + Sync + 'static,
+    R: serde::de::DeserializeOwned,
+{
+    client
+        .request(req)
+        .and_then(|resp| {
+            let status = resp.status();
+            resp.into_body().concat2().map(move |body| (status, body))
+        })
+        .map_err(|err| Error::from(err.context(ErrorKind::KeyVault)))
+        .and_then(|(status, body)| {
+            if status == StatusCode::OK {
+                serde_json::from_slice(&body)
+                    .context(ErrorKind::KeyVault)
+                    .map_err(Error::from)
+            } else {
+                Err(Error::from(ErrorKind::KeyVault))
+            }
+        })
+}
+
+fn upsert_secret<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    name: &str,
+    value: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    key_vault_secret_to_secret(runtime.settings(), name, value)
+        .map_err(Error::from)
+        .map(|(name, new_secret)| {
+            let client_copy = runtime.client().clone();
+            let namespace_copy = runtime.settings().namespace().to_owned();
+
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_secrets(runtime.settings().namespace(), Some(&name))
+                .map_err(Error::from)
+                .and_then(move |secrets| {
+                    if let Some(current) = secrets.items.into_iter().find(|secret| {
+                        secret.metadata.as_ref().map_or(false, |meta| {
+                            meta.name.as_ref().map_or(false, |n| *n == name)
+                        })
+                    }) {
+                        if current == new_secret {
+                            Either::A(Either::A(future::ok(())))
+                        } else {
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_secret(namespace_copy.as_str(), &name, &new_secret)
+                                .map_err(Error::from)
+                                .map(|_| ());
+
+                            Either::A(Either::B(fut))
+                        }
+                    } else {
+                        let fut = client_copy
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .create_secret(namespace_copy.as_str(), &new_secret)
+                            .map_err(Error::from)
+                            .map(|_| ());
+
+                        Either::B(fut)
+                    }
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chrono::{Duration as ChronoDuration, Utc};
+    use futures::future;
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Client as HyperClient, Method, Request, Response, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+    use edgelet_test_utils::{get_unused_tcp_port, routes, run_tcp_server};
+
+    use crate::key_vault::AzureKeyVaultSecretSync;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+    use crate::{ErrorKind, Settings};
+
+    #[test]
+    fn it_creates_secrets_read_from_key_vault() {
+        let port = get_unused_tcp_port();
+        let vault = run_tcp_server("127.0.0.1", port, key_vault_handler)
+            .map_err(|err| eprintln!("{}", err));
+
+        let settings = key_vault_settings(port, "db-password");
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/default/secrets" => empty_secret_list(),
+            POST "/api/v1/namespaces/default/secrets" => create_secret(),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let sync = AzureKeyVaultSecretSync::with_client(
+            create_runtime(settings, service_fn(handler)),
+            HyperClient::new(),
+        );
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(vault);
+        runtime.block_on(sync.sync()).unwrap();
+    }
+
+    #[test]
+    fn it_fails_when_secret_is_missing_from_key_vault() {
+        let port = get_unused_tcp_port();
+        let vault = run_tcp_server("127.0.0.1", port, key_vault_handler)
+            .map_err(|err| eprintln!("{}", err));
+
+        let settings = key_vault_settings(port, "unknown");
+        let service = service_fn(|_: Request<Body>| -> Result<Response<Body>, hyper::Error> {
+            panic!("no Kubernetes API call expected")
+        });
+        let sync = AzureKeyVaultSecretSync::with_client(
+            create_runtime(settings, service),
+            HyperClient::new(),
+        );
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(vault);
+        let err = runtime.block_on(sync.sync()).unwrap_err();
+
+        assert_eq!(err.kind(), &ErrorKind::KeyVault);
+    }
+
+    #[test]
+    fn it_exchanges_service_account_token_with_workload_identity() {
+        let port = get_unused_tcp_port();
+        let vault = run_tcp_server("127.0.0.1", port, workload_identity_handler)
+            .map_err(|err| eprintln!("{}", err));
+
+        let settings = make_settings(Some(json!({
+            "key_vault": {
+                "vault_uri": format!("http://127.0.0.1:{}/", port),
+                "secrets": ["db-password"],
+                "workload_identity": {
+                    "tenant_id": "tenant",
+                    "client_id": "client",
+                    "authority_host": format!("http://127.0.0.1:{}/", port),
+                },
+            }
+        })));
+
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let token_requests_copy = token_requests.clone();
+        let dispatch_table = routes!(
+            POST "/api/v1/namespaces/default/serviceaccounts/iotedge/token" => move |_: Request<Body>| {
+                token_requests_copy.fetch_add(1, Ordering::SeqCst);
+                response(StatusCode::CREATED, || {
+                    json!({
+                        "spec": { "audiences": ["api://AzureADTokenExchange"] },
+                        "status": {
+                            "token": "sa-token",
+                            "expirationTimestamp": (Utc::now() + ChronoDuration::hours(1)).to_rfc3339(),
+                        }
+                    })
+                    .to_string()
+                })
+            },
+            GET "/api/v1/namespaces/default/secrets" => empty_secret_list(),
+            POST "/api/v1/namespaces/default/secrets" => create_secret(),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let sync = AzureKeyVaultSecretSync::with_client(
+            create_runtime(settings, service_fn(handler)),
+            HyperClient::new(),
+        );
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.spawn(vault);
+        runtime.block_on(sync.sync()).unwrap();
+        runtime.block_on(sync.sync()).unwrap();
+
+        // The token is reused until it's close to expiring.
+        assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+    }
+
+    fn key_vault_settings(port: u16, secret: &str) -> Settings {
+        make_settings(Some(json!({
+            "key_vault": {
+                "vault_uri": format!("http://127.0.0.1:{}/", port),
+                "secrets": [secret],
+                "identity_endpoint": format!("http://127.0.0.1:{}/metadata/identity/oauth2/token", port),
+            }
+        })))
+    }
+
+    fn key_vault_handler(
+        req: Request<Body>,
+    ) -> impl Future<Item = Response<Body>, Error = hyper::Error> {
+        let response = match req.uri().path() {
+            "/metadata/identity/oauth2/token"
+                if req.headers().get("Metadata").map_or(false, |v| v == "true") =>
+            {
+                Response::new(json!({ "access_token": "token" }).to_string().into())
+            }
+            "/secrets/db-password"
+                if req
+                    .headers()
+                    .get(hyper::header::AUTHORIZATION)
+                    .map_or(false, |v| v == "Bearer token") =>
+            {
+                Response::new(json!({ "value": "hunter2" }).to_string().into())
+            }
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        };
+
+        future::ok(response)
+    }
+
+    fn workload_identity_handler(
+        req: Request<Body>,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send> {
+        match req.uri().path() {
+            "/tenant/oauth2/v2.0/token" => Box::new(req.into_body().concat2().map(|body| {
+                let body = String::from_utf8_lossy(&body);
+                if body.contains("client_assertion=sa-token") && body.contains("client_id=client") {
+                    Response::new(json!({ "access_token": "token" }).to_string().into())
+                } else {
+                    Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(Body::empty())
+                        .unwrap()
+                }
+            })),
+            _ => Box::new(key_vault_handler(req)),
+        }
+    }
+
+    fn empty_secret_list() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "SecretList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_secret() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::CREATED, || {
+                json!({
+                    "kind": "Secret",
+                    "apiVersion": "v1",
+                    "metadata": {
+                        "name": "db-password",
+                        "namespace": "default",
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+}
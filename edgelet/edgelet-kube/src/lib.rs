@@ -9,24 +9,50 @@
     clippy::use_self
 )]
 
+mod cluster;
 mod constants;
 mod convert;
 mod error;
+mod events;
+mod image_verification;
+mod key_vault;
+mod lock;
 mod module;
 mod runtime;
 mod settings;
 
+pub use cluster::{detect_cluster_type, ClusterType};
 pub use error::{Error, ErrorKind};
-pub use module::KubeModule;
+pub use events::EventRecorder;
+pub use image_verification::verify_image_signature;
+pub use key_vault::AzureKeyVaultSecretSync;
+pub use lock::DistributedLock;
+pub use module::{
+    bootstrap_rbac, DeploymentCondition, DeploymentStatus, KubeModule, ModuleEvent, ModuleWatch,
+};
 pub use runtime::KubeModuleRuntime;
-pub use settings::Settings;
+pub use settings::{
+    CsiVolumeSpec, EmptyDirVolume, GrpcProbe, HostAlias, ImagePullPolicy, ImageVerificationPolicy,
+    IssuerRef, KeyVaultSettings, KubeOS, KubeServiceIPType, ModuleSettings, Probe, ProbeAction,
+    ProbeConfig, ResourceRequirements, SeccompProfile, Settings, TlsCertificate, Toleration,
+    WorkloadIdentity,
+};
 
 #[cfg(test)]
 mod tests {
+    use crate::runtime::KubeModuleRuntime;
     use crate::settings::Settings;
     use config::{Config, File, FileFormat};
+    use edgelet_test_utils::web::ResponseFuture;
+    use futures::future;
+    use hyper::service::Service;
+    use hyper::{Body, Request, Response, StatusCode};
     use json_patch::merge;
+    use kube_client::{Client as KubeClient, Config as KubeConfig, Error, TokenSource};
+    use native_tls::TlsConnector;
     use serde_json::{self, json, Value as JsonValue};
+    use typed_headers::{mime, ContentLength, ContentType, HeaderMapExt};
+    use url::Url;
 
     pub const PROXY_TRUST_BUNDLE_CONFIG_MAP_NAME: &str = "device1-iotedged-proxy-trust-bundle";
 
@@ -68,6 +94,14 @@ mod tests {
             "image_pull_policy": "IfNotPresent",
             "service_account_name": "iotedge",
             "device_hub_selector": "",
+            "modules": {
+                "simulatedsensor": {
+                    "resources": {
+                        "requests": { "cpu": "250m", "memory": "1Gi" },
+                        "limits": { "memory": "2Gi" }
+                    }
+                }
+            }
         });
 
         if let Some(merge_json) = merge_json {
@@ -78,6 +112,65 @@ mod tests {
             .merge(File::from_str(&config_json.to_string(), FileFormat::Json))
             .unwrap();
 
-        config.try_into().unwrap()
+        let settings: Settings = config.try_into().unwrap();
+        settings.validate().unwrap();
+        settings
+    }
+
+    pub fn response(
+        status_code: StatusCode,
+        response: impl Fn() -> String + Clone + Send + 'static,
+    ) -> ResponseFuture {
+        let response = response();
+        let response_len = response.len();
+
+        let mut response = Response::new(response.into());
+        *response.status_mut() = status_code;
+        response
+            .headers_mut()
+            .typed_insert(&ContentLength(response_len as u64));
+        response
+            .headers_mut()
+            .typed_insert(&ContentType(mime::APPLICATION_JSON));
+
+        Box::new(future::ok(response)) as ResponseFuture
+    }
+
+    pub fn not_found_handler(_: Request<Body>) -> ResponseFuture {
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::default())
+            .unwrap();
+
+        Box::new(future::ok(response))
+    }
+
+    pub fn create_runtime<S: Service>(
+        settings: Settings,
+        service: S,
+    ) -> KubeModuleRuntime<TestTokenSource, S> {
+        let client = KubeClient::with_client(get_config(), service);
+
+        KubeModuleRuntime::new(client, settings)
+    }
+
+    pub fn get_config() -> KubeConfig<TestTokenSource> {
+        KubeConfig::new(
+            Url::parse("https://localhost:443").unwrap(),
+            "/api".to_string(),
+            TestTokenSource,
+            TlsConnector::new().unwrap(),
+        )
+    }
+
+    #[derive(Clone)]
+    pub struct TestTokenSource;
+
+    impl TokenSource for TestTokenSource {
+        type Error = Error;
+
+        fn get(&self) -> kube_client::error::Result<Option<String>> {
+            Ok(None)
+        }
     }
 }
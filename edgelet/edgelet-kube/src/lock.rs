@@ -0,0 +1,453 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use failure::Fail;
+use futures::future::{Either, Loop};
+use futures::{future, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use log::debug;
+use tokio::timer::Delay;
+
+use kube_client::coordination::{LEASE_API_VERSION, LEASE_KIND};
+use kube_client::{
+    Error as KubeClientError, ErrorKind as KubeClientErrorKind, Lease, LeaseSpec, TokenSource,
+};
+
+use crate::constants::PVC_LOCK_RETRY_INTERVAL_MILLIS;
+use crate::convert::sanitize_dns_value;
+use crate::error::{Error, ErrorKind, Result};
+use crate::settings::Settings;
+use crate::KubeModuleRuntime;
+
+const MICRO_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6fZ";
+
+/// A lock backed by a Kubernetes `Lease` in the runtime's namespace. It is
+/// used to make sure only one `iotedged` instance provisions a module's
+/// persistent volume claim at a time. A lease which has not been renewed
+/// within `lock_timeout` is considered abandoned and may be taken over.
+#[derive(Clone, Debug)]
+pub struct DistributedLock {
+    name: String,
+    holder_identity: String,
+    lock_timeout: Duration,
+}
+
+impl DistributedLock {
+    pub fn new(name: String, holder_identity: String, lock_timeout: Duration) -> Self {
+        DistributedLock {
+            name,
+            holder_identity,
+            lock_timeout,
+        }
+    }
+
+    pub fn for_pvc(
+        module_name: &str,
+        holder_identity: String,
+        lock_timeout: Duration,
+    ) -> Result<Self> {
+        let name = format!("{}-pvc-lock", sanitize_dns_value(module_name)?);
+        Ok(DistributedLock::new(name, holder_identity, lock_timeout))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn holder_identity(&self) -> &str {
+        &self.holder_identity
+    }
+
+    pub fn lock_timeout(&self) -> Duration {
+        self.lock_timeout
+    }
+
+    /// Resolves to `true` if the lock is now held by this holder and `false`
+    /// if it is held by someone else or another instance won the race for it.
+    pub fn acquire<T, S>(
+        &self,
+        runtime: &KubeModuleRuntime<T, S>,
+    ) -> impl Future<Item = bool, Error = Error>
+    where
+        T: TokenSource,
+        S: Service + 'static,
+        S::ReqBody: From<Vec<u8>>,
+        S::ResBody: Stream,
+        Body: From<S::ResBody>,
+        S::Error: Into<KubeClientError>,
+    {
+        let client_copy = runtime.client();
+        let namespace = runtime.settings().namespace().to_owned();
+        let lock = self.clone();
+
+        runtime
+            .client()
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .get_lease(&namespace, &self.name)
+            .map_err(Error::from)
+            .and_then(move |current| {
+                let now = Utc::now();
+                match current {
+                    None => {
+                        debug!("Creating lease {} for {}", lock.name, lock.holder_identity);
+                        let lease = lock.lease_for(Lease::default(), now);
+                        let fut = client_copy
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .create_lease(&namespace, &lease)
+                            .then(won_race);
+
+                        Either::A(fut)
+                    }
+                    Some(current) => {
+                        if lock.is_available(&current, now) {
+                            debug!("Taking lease {} for {}", lock.name, lock.holder_identity);
+                            let lease = lock.lease_for(current, now);
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_lease(&namespace, &lock.name, &lease)
+                                .then(won_race);
+
+                            Either::B(Either::A(fut))
+                        } else {
+                            Either::B(Either::B(future::ok(false)))
+                        }
+                    }
+                }
+            })
+    }
+
+    /// Resolves once the lock is held by this holder, trying again every
+    /// `PVC_LOCK_RETRY_INTERVAL_MILLIS` while someone else holds it.
+    pub fn acquire_wait<T, S>(
+        &self,
+        runtime: &KubeModuleRuntime<T, S>,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        T: TokenSource,
+        S: Service + 'static,
+        S::ReqBody: From<Vec<u8>>,
+        S::ResBody: Stream,
+        Body: From<S::ResBody>,
+        S::Error: Into<KubeClientError>,
+    {
+        let runtime = runtime.clone();
+        let lock = self.clone();
+
+        future::loop_fn((), move |()| {
+            let name = lock.name.clone();
+            lock.acquire(&runtime).and_then(move |acquired| {
+                if acquired {
+                    return Either::A(future::ok(Loop::Break(())));
+                }
+
+                debug!("Waiting for lease {}", name);
+                let retry = Delay::new(
+                    Instant::now() + Duration::from_millis(PVC_LOCK_RETRY_INTERVAL_MILLIS),
+                )
+                .map_err(|err| Error::from(err.context(ErrorKind::Kubernetes)))
+                .map(|_| Loop::Continue(()));
+                Either::B(retry)
+            })
+        })
+    }
+
+    /// Gives up the lock if it is held by this holder.
+    pub fn release<T, S>(
+        &self,
+        runtime: &KubeModuleRuntime<T, S>,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        T: TokenSource,
+        S: Service + 'static,
+        S::ReqBody: From<Vec<u8>>,
+        S::ResBody: Stream,
+        Body: From<S::ResBody>,
+        S::Error: Into<KubeClientError>,
+    {
+        let client_copy = runtime.client();
+        let namespace = runtime.settings().namespace().to_owned();
+        let lock = self.clone();
+
+        runtime
+            .client()
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .get_lease(&namespace, &self.name)
+            .map_err(Error::from)
+            .and_then(move |current| match current {
+                Some(mut lease) if lock.is_held_by_me(&lease) => {
+                    if let Some(spec) = lease.spec.as_mut() {
+                        spec.holder_identity = None;
+                    }
+                    let fut = client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .replace_lease(&namespace, &lock.name, &lease)
+                        .map_err(Error::from)
+                        .map(|_| ());
+
+                    Either::A(fut)
+                }
+                _ => Either::B(future::ok(())),
+            })
+    }
+
+    fn is_held_by_me(&self, lease: &Lease) -> bool {
+        lease
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.holder_identity.as_ref())
+            .map_or(false, |holder| *holder == self.holder_identity)
+    }
+
+    fn is_available(&self, lease: &Lease, now: DateTime<Utc>) -> bool {
+        lease.spec.as_ref().map_or(true, |spec| {
+            match (&spec.holder_identity, &spec.renew_time) {
+                (None, _) => true,
+                (Some(holder), _) if *holder == self.holder_identity => true,
+                (Some(_), Some(renew_time)) => DateTime::parse_from_rfc3339(renew_time)
+                    .map(|renew_time| {
+                        let duration = ChronoDuration::seconds(i64::from(
+                            spec.lease_duration_seconds.unwrap_or_default(),
+                        ));
+                        renew_time.with_timezone(&Utc) + duration < now
+                    })
+                    .unwrap_or(true),
+                (Some(_), None) => true,
+            }
+        })
+    }
+
+    // Builds the lease this holder writes, keeping the existing metadata so the
+    // resource version guards against concurrent updates.
+    fn lease_for(&self, current: Lease, now: DateTime<Utc>) -> Lease {
+        let now = now.format(MICRO_TIME_FORMAT).to_string();
+        let held_by_me = self.is_held_by_me(&current);
+        let spec = current.spec.unwrap_or_default();
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let lease_duration_seconds = self.lock_timeout.as_secs() as i32;
+
+        Lease {
+            api_version: Some(LEASE_API_VERSION.to_string()),
+            kind: Some(LEASE_KIND.to_string()),
+            metadata: Some(current.metadata.unwrap_or_else(|| api_meta::ObjectMeta {
+                name: Some(self.name.clone()),
+                ..api_meta::ObjectMeta::default()
+            })),
+            spec: Some(LeaseSpec {
+                acquire_time: if held_by_me {
+                    spec.acquire_time
+                } else {
+                    Some(now.clone())
+                },
+                holder_identity: Some(self.holder_identity.clone()),
+                lease_duration_seconds: Some(lease_duration_seconds),
+                lease_transitions: if held_by_me {
+                    spec.lease_transitions
+                } else {
+                    Some(spec.lease_transitions.map_or(0, |t| t + 1))
+                },
+                renew_time: Some(now),
+            }),
+        }
+    }
+}
+
+/// Identifies this `iotedged` instance as a lock holder. That is its pod's
+/// name, which Kubernetes sets as the hostname, outside of a pod the device ID.
+pub fn holder_identity(settings: &Settings) -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| settings.device_id().map(ToString::to_string))
+        .unwrap_or_default()
+}
+
+fn won_race(result: ::std::result::Result<Lease, KubeClientError>) -> Result<bool> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(err) => match err.kind() {
+            KubeClientErrorKind::Conflict => Ok(false),
+            _ => Err(Error::from(err)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::future;
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, Response, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+    use typed_headers::{mime, ContentLength, ContentType, HeaderMapExt};
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::lock::DistributedLock;
+    use crate::tests::{create_runtime, make_settings, not_found_handler};
+
+    const LEASE_PATH: &str = "/apis/coordination.k8s.io/v1/namespaces/default/leases";
+
+    #[test]
+    fn it_acquires_lock_when_lease_does_not_exist() {
+        let dispatch_table = routes!(
+            POST LEASE_PATH => lease_response(StatusCode::CREATED, "device1", "2019-01-01T00:00:00.000000Z"),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let lock = make_lock();
+        let task = lock.acquire(&runtime);
+
+        let mut runtime = Runtime::new().unwrap();
+        assert!(runtime.block_on(task).unwrap());
+    }
+
+    #[test]
+    fn it_does_not_acquire_lock_held_by_another_instance() {
+        let renew_time = chrono::Utc::now()
+            .format(super::MICRO_TIME_FORMAT)
+            .to_string();
+        let dispatch_table = routes!(
+            GET format!("{}/module1-pvc-lock", LEASE_PATH) => lease_response(StatusCode::OK, "device2", &renew_time),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let lock = make_lock();
+        let task = lock.acquire(&runtime);
+
+        let mut runtime = Runtime::new().unwrap();
+        assert!(!runtime.block_on(task).unwrap());
+    }
+
+    #[test]
+    fn it_takes_over_expired_lock() {
+        let dispatch_table = routes!(
+            GET format!("{}/module1-pvc-lock", LEASE_PATH) => lease_response(StatusCode::OK, "device2", "2019-01-01T00:00:00.000000Z"),
+            PUT format!("{}/module1-pvc-lock", LEASE_PATH) => lease_response(StatusCode::OK, "device1", "2019-01-01T00:00:00.000000Z"),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let lock = make_lock();
+        let task = lock.acquire(&runtime);
+
+        let mut runtime = Runtime::new().unwrap();
+        assert!(runtime.block_on(task).unwrap());
+    }
+
+    #[test]
+    fn it_does_not_acquire_lock_when_create_conflicts() {
+        let dispatch_table = routes!(
+            POST LEASE_PATH => lease_response(StatusCode::CONFLICT, "device2", "2019-01-01T00:00:00.000000Z"),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let lock = make_lock();
+        let task = lock.acquire(&runtime);
+
+        let mut runtime = Runtime::new().unwrap();
+        assert!(!runtime.block_on(task).unwrap());
+    }
+
+    #[test]
+    fn it_waits_for_lock_held_by_another_instance() {
+        let renew_time = chrono::Utc::now()
+            .format(super::MICRO_TIME_FORMAT)
+            .to_string();
+        let held = lease_response(StatusCode::OK, "device2", &renew_time);
+        let gets = Arc::new(AtomicUsize::new(0));
+        let gets_copy = gets.clone();
+
+        // The lease is held on the first attempt, and released by the next one.
+        let dispatch_table = routes!(
+            GET format!("{}/module1-pvc-lock", LEASE_PATH) => move |req: Request<Body>| {
+                if gets_copy.fetch_add(1, Ordering::SeqCst) == 0 {
+                    held(req)
+                } else {
+                    not_found_handler(req)
+                }
+            },
+            POST LEASE_PATH => lease_response(StatusCode::CREATED, "device1", &renew_time),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let lock = make_lock();
+        let task = lock.acquire_wait(&runtime);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+        assert_eq!(gets.load(Ordering::SeqCst), 2);
+    }
+
+    fn make_lock() -> DistributedLock {
+        DistributedLock::for_pvc("module1", "device1".to_string(), Duration::from_secs(60)).unwrap()
+    }
+
+    fn lease_response(
+        status_code: StatusCode,
+        holder: &str,
+        renew_time: &str,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let body = json!({
+            "apiVersion": "coordination.k8s.io/v1",
+            "kind": "Lease",
+            "metadata": {
+                "name": "module1-pvc-lock",
+                "namespace": "default",
+                "resourceVersion": "1",
+            },
+            "spec": {
+                "holderIdentity": holder,
+                "leaseDurationSeconds": 60,
+                "renewTime": renew_time,
+            }
+        })
+        .to_string();
+
+        move |_| {
+            let response_len = body.len();
+
+            let mut response = Response::new(body.clone().into());
+            *response.status_mut() = status_code;
+            response
+                .headers_mut()
+                .typed_insert(&ContentLength(response_len as u64));
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+
+            Box::new(future::ok(response)) as ResponseFuture
+        }
+    }
+}
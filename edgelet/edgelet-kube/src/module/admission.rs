@@ -0,0 +1,97 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::{Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use crate::constants::{VALIDATING_ADMISSION_POLICY, VALIDATING_ADMISSION_POLICY_BINDING};
+use crate::convert::settings_to_admission_policy;
+use crate::module::create::create_or_update_custom_object;
+use crate::{Error, KubeModuleRuntime};
+
+/// Ensures the admission policy which guards module pods in the runtime's
+/// namespace, and its binding, exist. The IoT Edge service account needs a
+/// Cluster Role to manage these since they are cluster scoped.
+pub fn apply_admission_policies<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    let runtime_copy = runtime.clone();
+
+    settings_to_admission_policy(runtime.settings())
+        .map_err(Error::from)
+        .map(|((policy_name, policy), (binding_name, binding))| {
+            create_or_update_custom_object(
+                runtime,
+                VALIDATING_ADMISSION_POLICY,
+                policy_name,
+                policy,
+            )
+            .and_then(move |_| {
+                create_or_update_custom_object(
+                    &runtime_copy,
+                    VALIDATING_ADMISSION_POLICY_BINDING,
+                    binding_name,
+                    binding,
+                )
+            })
+        })
+        .into_future()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::module::apply_admission_policies;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_creates_policy_and_binding() {
+        let dispatch_table = routes!(
+            POST "/apis/admissionregistration.k8s.io/v1/validatingadmissionpolicies" => created(),
+            POST "/apis/admissionregistration.k8s.io/v1/validatingadmissionpolicybindings" => created(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = apply_admission_policies(&runtime);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    fn created() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::CREATED, || {
+                json!({
+                    "metadata": {
+                        "name": "default-iotedge-module-pods",
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+}
@@ -7,6 +7,7 @@ use futures::future::{Either, IntoFuture};
 use futures::{future, Future, Stream};
 use hyper::service::Service;
 use hyper::{Body, Request};
+use k8s_openapi::api::authorization::v1 as api_authz;
 use log::Level;
 use typed_headers::{Authorization, HeaderMapExt};
 
@@ -53,15 +54,31 @@ where
                                 .status
                                 .as_ref()
                                 .filter(|status| status.authenticated.filter(|x| *x).is_some())
-                                .and_then(|status| {
-                                    status.user.as_ref().and_then(|user| user.username.clone())
+                                .and_then(|status| status.user.clone())
+                                .and_then(|user| {
+                                    let groups = user.groups.unwrap_or_default();
+                                    user.username.map(|name| (name, groups))
                                 })
-                                .map_or(Either::A(future::ok(AuthId::None)), |name| {
-                                    Either::B(get_module_original_name(
+                                .map_or(Either::A(future::ok(AuthId::None)), |(name, groups)| {
+                                    let fut = check_module_permissions(
                                         &client_copy,
                                         &namespace,
                                         &name,
-                                    ))
+                                        groups,
+                                    )
+                                    .and_then(move |allowed| {
+                                        if allowed {
+                                            Either::A(get_module_original_name(
+                                                &client_copy,
+                                                &namespace,
+                                                &name,
+                                            ))
+                                        } else {
+                                            Either::B(future::ok(AuthId::None))
+                                        }
+                                    });
+
+                                    Either::B(fut)
                                 })
                         });
 
@@ -75,6 +92,56 @@ where
         .flatten()
 }
 
+/// An authenticated token only proves who the caller is. The caller must also
+/// be able to `get` its own service account in the runtime's namespace, as
+/// every module's service account can, so that tokens of unrelated service
+/// accounts are rejected. The review carries the caller's groups, as RBAC
+/// may grant the access through them rather than the user.
+fn check_module_permissions<T, S>(
+    client: &Arc<Mutex<RefCell<KubeClient<T, S>>>>,
+    namespace: &str,
+    username: &str,
+    groups: Vec<String>,
+) -> impl Future<Item = bool, Error = Error>
+where
+    T: TokenSource + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    // Service account usernames are system:serviceaccount:<namespace>:<name>
+    let service_account = username.split(':').last().map(ToOwned::to_owned);
+    let review = api_authz::SubjectAccessReview {
+        spec: api_authz::SubjectAccessReviewSpec {
+            resource_attributes: Some(api_authz::ResourceAttributes {
+                namespace: Some(namespace.to_string()),
+                resource: Some("serviceaccounts".to_string()),
+                name: service_account,
+                verb: Some("get".to_string()),
+                ..api_authz::ResourceAttributes::default()
+            }),
+            user: Some(username.to_string()),
+            groups: Some(groups),
+            ..api_authz::SubjectAccessReviewSpec::default()
+        },
+        ..api_authz::SubjectAccessReview::default()
+    };
+
+    client
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .subject_access_review(&review)
+        .map_err(|err| {
+            log_failure(Level::Warn, &err);
+            Error::from(err)
+        })
+        .map(|review| review.status.map_or(false, |status| status.allowed))
+}
+
 fn get_module_original_name<T, S>(
     client: &Arc<Mutex<RefCell<KubeClient<T, S>>>>,
     namespace: &str,
@@ -1,19 +1,38 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::time::Duration;
+
 use futures::future::Either;
 use futures::prelude::*;
 use futures::{future, Future, Stream};
 use hyper::service::Service;
 use hyper::Body;
+use log::{info, warn};
 
 use edgelet_core::ModuleSpec;
 use edgelet_docker::DockerConfig;
-use kube_client::{Error as KubeClientError, TokenSource};
-
-use crate::constants::EDGE_EDGE_AGENT_NAME;
-use crate::convert::{spec_to_deployment, spec_to_role_binding, spec_to_service_account};
+use k8s_openapi::api::apps::v1 as api_apps;
+use k8s_openapi::api::core::v1 as api_core;
+use kube_client::{
+    CustomResource, Error as KubeClientError, ErrorKind as KubeClientErrorKind, TokenSource,
+};
+use serde_json::Value as JsonValue;
+
+use crate::constants::{
+    CERTIFICATE, EDGE_EDGE_AGENT_NAME, POD_MONITOR, PVC_LOCK_TIMEOUT_SECS, SERVICE_EXPORT,
+    VERTICAL_POD_AUTOSCALER,
+};
+use crate::convert::{
+    apply_node_labels, apply_vpa_recommendation, module_to_headless_service, sanitize_dns_value,
+    spec_to_certificate, spec_to_csi_volume_claim_patches, spec_to_csi_volumes, spec_to_deployment,
+    spec_to_pod_monitor, spec_to_role_binding, spec_to_service, spec_to_service_account,
+    spec_to_service_export,
+};
 use crate::error::Error;
-use crate::KubeModuleRuntime;
+use crate::lock::{holder_identity, DistributedLock};
+use crate::module::sync_module_service_account_annotations;
+use crate::settings::ModuleSettings;
+use crate::{verify_image_signature, KubeModuleRuntime};
 
 pub fn create_module<T, S>(
     runtime: &KubeModuleRuntime<T, S>,
@@ -28,17 +47,92 @@ where
     S::Error: Into<KubeClientError>,
     S::Future: Send,
 {
+    let runtime_for_service_account = runtime.clone();
+    let module_for_service_account = module.clone();
+
     let runtime_for_sa = runtime.clone();
     let module_for_sa = module.clone();
 
+    let runtime_for_certificate = runtime.clone();
+    let module_for_certificate = module.clone();
+
+    let runtime_for_csi_volumes = runtime.clone();
+    let module_for_csi_volumes = module.clone();
+
     let runtime_for_deployment = runtime.clone();
     let module_for_deployment = module.clone();
 
-    create_or_update_service_account(&runtime, &module)
+    let runtime_for_service = runtime.clone();
+    let module_for_service = module.clone();
+
+    let runtime_for_headless_service = runtime.clone();
+    let module_for_headless_service = module.clone();
+
+    let runtime_for_service_export = runtime.clone();
+    let module_for_service_export = module.clone();
+
+    let runtime_for_monitor = runtime.clone();
+    let module_for_monitor = module.clone();
+
+    verify_module_image(&runtime, &module)
+        .and_then(move |_| {
+            create_or_update_service_account(
+                &runtime_for_service_account,
+                &module_for_service_account,
+            )
+            .and_then(move |_| {
+                sync_service_account_annotations(
+                    &runtime_for_service_account,
+                    &module_for_service_account,
+                )
+            })
+        })
         .and_then(move |_| create_or_update_role_binding(&runtime_for_sa, &module_for_sa))
+        .and_then(move |_| {
+            create_or_update_certificate(&runtime_for_certificate, &module_for_certificate)
+        })
+        .and_then(move |_| create_csi_volumes(&runtime_for_csi_volumes, &module_for_csi_volumes))
         .and_then(move |_| {
             create_or_update_deployment(&runtime_for_deployment, &module_for_deployment)
         })
+        .and_then(move |_| create_or_update_service(&runtime_for_service, &module_for_service))
+        .and_then(move |_| {
+            let grpc_target = sanitize_dns_value(module_for_headless_service.name())
+                .ok()
+                .and_then(|name| runtime_for_headless_service.settings().module(&name))
+                .map_or(false, ModuleSettings::grpc_target);
+            if grpc_target {
+                Either::A(create_headless_service(
+                    &runtime_for_headless_service,
+                    module_for_headless_service.name(),
+                ))
+            } else {
+                Either::B(future::ok(()))
+            }
+        })
+        .and_then(move |_| {
+            create_or_update_service_export(&runtime_for_service_export, &module_for_service_export)
+        })
+        .and_then(move |_| {
+            if runtime_for_monitor.settings().metrics() {
+                Either::A(create_or_update_pod_monitor(
+                    &runtime_for_monitor,
+                    &module_for_monitor,
+                ))
+            } else {
+                Either::B(future::ok(()))
+            }
+        })
+}
+
+fn verify_module_image<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error> {
+    match runtime.settings().image_verification() {
+        Some(policy) => Either::A(verify_image_signature(module.config().image(), policy)),
+        None => Either::B(future::ok(())),
+    }
 }
 
 fn create_or_update_service_account<T, S>(
@@ -113,6 +207,46 @@ where
         .flatten()
 }
 
+// Only a Deployment's selector is immutable. Its pod template can change
+// freely: the Deployment rolls out new pods from the updated template rather
+// than updating the running ones, so the rules about which fields a pod keeps
+// for life never come into play.
+fn requires_recreate(current: &api_apps::Deployment, desired: &api_apps::Deployment) -> bool {
+    match (current.spec.as_ref(), desired.spec.as_ref()) {
+        (Some(current), Some(desired)) => current.selector != desired.selector,
+        _ => false,
+    }
+}
+
+fn sync_service_account_annotations<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    let desired_annotations = sanitize_dns_value(module.name())
+        .ok()
+        .and_then(|name| runtime.settings().module(&name))
+        .map(ModuleSettings::service_account_annotations)
+        .filter(|annotations| !annotations.is_empty());
+
+    match desired_annotations {
+        Some(annotations) => Either::A(sync_module_service_account_annotations(
+            runtime,
+            module.name(),
+            annotations,
+        )),
+        None => Either::B(future::ok(())),
+    }
+}
+
 fn create_or_update_role_binding<T, S>(
     runtime: &KubeModuleRuntime<T, S>,
     module: &ModuleSpec<DockerConfig>,
@@ -166,9 +300,17 @@ where
     S::Error: Into<KubeClientError>,
     S::Future: Send,
 {
+    let runtime_copy = runtime.clone();
+    let runtime_for_node = runtime.clone();
+
     spec_to_deployment(runtime.settings(), module)
         .map_err(Error::from)
-        .map(|(name, new_deployment)| {
+        .map(|(name, deployment)| with_vpa_recommendation(runtime, name, deployment))
+        .into_future()
+        .flatten()
+        .and_then(move |(name, deployment)| with_node_labels(&runtime_for_node, name, deployment))
+        .and_then(move |(name, new_deployment)| {
+            let runtime = &runtime_copy;
             let client_copy = runtime.client().clone();
             let namespace_copy = runtime.settings().namespace().to_owned();
 
@@ -191,6 +333,29 @@ where
                     }) {
                         if current == new_deployment {
                             Either::A(Either::A(future::ok(())))
+                        } else if requires_recreate(&current, &new_deployment) {
+                            info!(
+                                "Recreating deployment {} for changes it can't be updated with",
+                                name
+                            );
+                            let client = client_copy.clone();
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .delete_deployment(namespace_copy.as_str(), &name)
+                                .map_err(Error::from)
+                                .and_then(move |_| {
+                                    client
+                                        .lock()
+                                        .expect("Unexpected lock error")
+                                        .borrow_mut()
+                                        .create_deployment(namespace_copy.as_str(), &new_deployment)
+                                        .map_err(Error::from)
+                                        .map(|_| ())
+                                });
+
+                            Either::A(Either::B(Either::A(fut)))
                         } else {
                             let fut = client_copy
                                 .lock()
@@ -200,7 +365,7 @@ where
                                 .map_err(Error::from)
                                 .map(|_| ());
 
-                            Either::A(Either::B(fut))
+                            Either::A(Either::B(Either::B(fut)))
                         }
                     } else {
                         let fut = client_copy
@@ -211,27 +376,560 @@ where
                             .map_err(Error::from)
                             .map(|_| ());
 
-                        Either::B(fut)
-                    }
+                        Either::B(fut)
+                    }
+                })
+        })
+}
+
+fn create_or_update_service<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    spec_to_service(runtime.settings(), module)
+        .map_err(Error::from)
+        .map(|service| match service {
+            Some((name, new_service)) => {
+                let client_copy = runtime.client().clone();
+                let namespace_copy = runtime.settings().namespace().to_owned();
+
+                let fut = runtime
+                    .client()
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .list_services(
+                        runtime.settings().namespace(),
+                        Some(&name),
+                        Some(&runtime.settings().device_hub_selector()),
+                    )
+                    .map_err(Error::from)
+                    .and_then(move |services| {
+                        if let Some(current) = services.items.into_iter().find(|service| {
+                            service.metadata.as_ref().map_or(false, |meta| {
+                                meta.name.as_ref().map_or(false, |n| *n == name)
+                            })
+                        }) {
+                            let new_service = with_current_cluster_ip(new_service, &current);
+                            if current == new_service {
+                                Either::A(Either::A(future::ok(())))
+                            } else {
+                                let fut = client_copy
+                                    .lock()
+                                    .expect("Unexpected lock error")
+                                    .borrow_mut()
+                                    .replace_service(namespace_copy.as_str(), &name, &new_service)
+                                    .map_err(Error::from)
+                                    .map(|_| ());
+
+                                Either::A(Either::B(fut))
+                            }
+                        } else {
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .create_service(namespace_copy.as_str(), &new_service)
+                                .map_err(Error::from)
+                                .map(|_| ());
+
+                            Either::B(fut)
+                        }
+                    });
+
+                Either::A(fut)
+            }
+            None => Either::B(future::ok(())),
+        })
+        .into_future()
+        .flatten()
+}
+
+/// Creates or updates the headless Service of a module which `$edgeHub`
+/// streams to over gRPC.
+pub fn create_headless_service<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module_id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = runtime.client();
+    let namespace_copy = runtime.settings().namespace().to_owned();
+
+    module_to_headless_service(runtime.settings(), module_id)
+        .map_err(Error::from)
+        .map(|(name, new_service)| {
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_services(
+                    runtime.settings().namespace(),
+                    Some(&name),
+                    Some(&runtime.settings().device_hub_selector()),
+                )
+                .map_err(Error::from)
+                .and_then(move |services| {
+                    if let Some(current) = services.items.into_iter().find(|service| {
+                        service.metadata.as_ref().map_or(false, |meta| {
+                            meta.name.as_ref().map_or(false, |n| *n == name)
+                        })
+                    }) {
+                        let new_service = with_current_cluster_ip(new_service, &current);
+                        if current == new_service {
+                            Either::A(Either::A(future::ok(())))
+                        } else {
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_service(namespace_copy.as_str(), &name, &new_service)
+                                .map_err(Error::from)
+                                .map(|_| ());
+
+                            Either::A(Either::B(fut))
+                        }
+                    } else {
+                        let fut = client_copy
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .create_service(namespace_copy.as_str(), &new_service)
+                            .map_err(Error::from)
+                            .map(|_| ());
+
+                        Either::B(fut)
+                    }
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+// A Service's cluster IP can't be changed once allocated, so replacing it has
+// to carry over the address and resource version the API server assigned.
+fn with_current_cluster_ip(
+    mut new_service: api_core::Service,
+    current: &api_core::Service,
+) -> api_core::Service {
+    if let Some(meta) = new_service.metadata.as_mut() {
+        meta.resource_version = current
+            .metadata
+            .as_ref()
+            .and_then(|meta| meta.resource_version.clone());
+    }
+    if let Some(spec) = new_service.spec.as_mut() {
+        spec.cluster_ip = current
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.cluster_ip.clone());
+    }
+    new_service
+}
+
+fn create_or_update_service_export<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    spec_to_service_export(runtime.settings(), module)
+        .map_err(Error::from)
+        .map(|service_export| match service_export {
+            Some((name, new_service_export)) => Either::A(create_or_update_custom_object(
+                runtime,
+                SERVICE_EXPORT,
+                name,
+                new_service_export,
+            )),
+            None => Either::B(future::ok(())),
+        })
+        .into_future()
+        .flatten()
+}
+
+// When enabled, the Vertical Pod Autoscaler's recommendation for the module
+// replaces the resource requests of the module container.
+fn with_vpa_recommendation<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    name: String,
+    mut deployment: api_apps::Deployment,
+) -> impl Future<Item = (String, api_apps::Deployment), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    if !runtime.settings().read_vpa_recommendations() {
+        return Either::A(future::ok((name, deployment)));
+    }
+
+    let fut = runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .get_custom_object(
+            VERTICAL_POD_AUTOSCALER,
+            runtime.settings().namespace(),
+            &name,
+        )
+        .map_err(Error::from)
+        .map(move |vpa| {
+            if let Some(vpa) = vpa {
+                apply_vpa_recommendation(&mut deployment, &name, &vpa);
+            }
+            (name, deployment)
+        });
+
+    Either::B(fut)
+}
+
+// The labels are read from the node when the deployment is created or
+// updated, so a pod only picks up later changes to them when its module is.
+fn with_node_labels<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    name: String,
+    mut deployment: api_apps::Deployment,
+) -> impl Future<Item = (String, api_apps::Deployment), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    let label_keys = runtime.settings().propagate_node_labels().to_vec();
+    if label_keys.is_empty() {
+        return Either::A(future::ok((name, deployment)));
+    }
+
+    let node_name = match runtime.settings().node_name() {
+        Some(node_name) => node_name,
+        None => {
+            warn!(
+                "Node labels are not propagated to module {} because the node name is not set",
+                name
+            );
+            return Either::A(future::ok((name, deployment)));
+        }
+    };
+
+    let fut = runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .get_node(node_name)
+        .map_err(Error::from)
+        .map(move |node| {
+            apply_node_labels(&mut deployment, &node, &label_keys);
+            (name, deployment)
+        });
+
+    Either::B(fut)
+}
+
+fn create_or_update_pod_monitor<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    spec_to_pod_monitor(runtime.settings(), module)
+        .map_err(Error::from)
+        .map(|pod_monitor| match pod_monitor {
+            Some((name, new_pod_monitor)) => Either::A(create_or_update_custom_object(
+                runtime,
+                POD_MONITOR,
+                name,
+                new_pod_monitor,
+            )),
+            None => Either::B(future::ok(())),
+        })
+        .into_future()
+        .flatten()
+}
+
+fn create_or_update_certificate<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    spec_to_certificate(runtime.settings(), module)
+        .map_err(Error::from)
+        .map(|certificate| match certificate {
+            Some((name, new_certificate)) => Either::A(create_or_update_custom_object(
+                runtime,
+                CERTIFICATE,
+                name,
+                new_certificate,
+            )),
+            None => Either::B(future::ok(())),
+        })
+        .into_future()
+        .flatten()
+}
+
+// Persistent Volumes and their claims are immutable once bound, so existing
+// ones are left as they are. Another `iotedged` instance may be provisioning
+// the same claims, so they are only created while holding the module's lock.
+fn create_csi_volumes<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    let runtime = runtime.clone();
+    let module = module.clone();
+
+    spec_to_csi_volumes(runtime.settings(), &module)
+        .map_err(Error::from)
+        .and_then(|volumes| {
+            let lock = DistributedLock::for_pvc(
+                module.name(),
+                holder_identity(runtime.settings()),
+                Duration::from_secs(PVC_LOCK_TIMEOUT_SECS),
+            )?;
+            Ok((volumes, lock))
+        })
+        .map(move |(volumes, lock)| {
+            if volumes.is_empty() {
+                return Either::A(future::ok(()));
+            }
+
+            let runtime_for_release = runtime.clone();
+            let lock_for_release = lock.clone();
+
+            let fut = lock
+                .acquire_wait(&runtime)
+                .and_then(move |_| {
+                    let futures = volumes
+                        .into_iter()
+                        .map(|(persistent_volume, persistent_volume_claim)| {
+                            let client_copy = runtime.client().clone();
+                            let namespace_copy = runtime.settings().namespace().to_owned();
+
+                            runtime
+                                .client()
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .create_persistent_volume(&persistent_volume)
+                                .then(ignore_conflict)
+                                .and_then(move |_| {
+                                    client_copy
+                                        .lock()
+                                        .expect("Unexpected lock error")
+                                        .borrow_mut()
+                                        .create_persistent_volume_claim(
+                                            namespace_copy.as_str(),
+                                            &persistent_volume_claim,
+                                        )
+                                        .then(ignore_conflict)
+                                })
+                                .map_err(Error::from)
+                        })
+                        .collect::<Vec<_>>();
+
+                    future::join_all(futures)
+                        .and_then(move |_| patch_csi_volume_claims(&runtime, &module))
+                })
+                .then(move |result| {
+                    lock_for_release
+                        .release(&runtime_for_release)
+                        .then(move |released| {
+                            if let Err(err) = released {
+                                warn!(
+                                    "Could not release lease {}: {}",
+                                    lock_for_release.name(),
+                                    err
+                                );
+                            }
+                            result
+                        })
+                });
+            Either::B(fut)
+        })
+        .into_future()
+        .flatten()
+}
+
+fn patch_csi_volume_claims<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module: &ModuleSpec<DockerConfig>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    spec_to_csi_volume_claim_patches(runtime.settings(), module)
+        .map_err(Error::from)
+        .map(|patches| {
+            let futures = patches
+                .into_iter()
+                .map(|(claim_name, patch)| {
+                    runtime
+                        .client()
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .patch_persistent_volume_claim(
+                            runtime.settings().namespace(),
+                            &claim_name,
+                            &patch,
+                        )
+                        .map_err(Error::from)
                 })
+                .collect::<Vec<_>>();
+
+            future::join_all(futures).map(|_| ())
         })
         .into_future()
         .flatten()
 }
 
+fn ignore_conflict<T>(result: Result<T, KubeClientError>) -> Result<(), KubeClientError> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => match err.kind() {
+            KubeClientErrorKind::Conflict => Ok(()),
+            _ => Err(err),
+        },
+    }
+}
+
+pub(super) fn create_or_update_custom_object<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    resource: CustomResource,
+    name: String,
+    mut new_object: JsonValue,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Send + Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+    S::Future: Send,
+{
+    let client_copy = runtime.client().clone();
+    let namespace_copy = runtime.settings().namespace().to_owned();
+
+    runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .get_custom_object(resource, runtime.settings().namespace(), &name)
+        .map_err(Error::from)
+        .and_then(move |current| {
+            if let Some(current) = current {
+                if current["metadata"]["labels"] == new_object["metadata"]["labels"]
+                    && current["spec"] == new_object["spec"]
+                {
+                    Either::A(Either::A(future::ok(())))
+                } else {
+                    // custom resources can only be replaced at a known version
+                    new_object["metadata"]["resourceVersion"] =
+                        current["metadata"]["resourceVersion"].clone();
+
+                    let fut = client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .replace_custom_object(
+                            resource,
+                            namespace_copy.as_str(),
+                            &name,
+                            &new_object,
+                        )
+                        .map_err(Error::from)
+                        .map(|_| ());
+
+                    Either::A(Either::B(fut))
+                }
+            } else {
+                let fut = client_copy
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .create_custom_object(resource, namespace_copy.as_str(), &new_object)
+                    .map_err(Error::from)
+                    .map(|_| ());
+
+                Either::B(fut)
+            }
+        })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
-    use futures::future;
-    use hyper::service::{service_fn, Service};
-    use hyper::{Body, Method, Request, Response, StatusCode};
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
     use maplit::btreemap;
-    use native_tls::TlsConnector;
     use serde_json::json;
     use tokio::runtime::Runtime;
-    use typed_headers::{mime, ContentLength, ContentType, HeaderMapExt};
-    use url::Url;
 
     use docker::models::{AuthConfig, ContainerCreateBody, HostConfig, Mount};
     use edgelet_core::{ImagePullPolicy, ModuleSpec};
@@ -240,15 +938,16 @@ mod tests {
     use edgelet_test_utils::web::{
         make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
     };
-    use kube_client::{Client as KubeClient, Config as KubeConfig, TokenSource};
+    use k8s_openapi::api::apps::v1 as api_apps;
 
+    use crate::convert::spec_to_deployment;
     use crate::module::create::{
-        create_or_update_deployment, create_or_update_role_binding,
-        create_or_update_service_account,
+        create_headless_service, create_or_update_certificate, create_or_update_deployment,
+        create_or_update_pod_monitor, create_or_update_role_binding,
+        create_or_update_service_account, requires_recreate,
     };
     use crate::module::create_module;
-    use crate::tests::make_settings;
-    use crate::{Error, KubeModuleRuntime, Settings};
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
 
     #[test]
     fn it_creates_new_deployment_if_does_not_exist() {
@@ -290,6 +989,53 @@ mod tests {
         runtime.block_on(task).unwrap();
     }
 
+    #[test]
+    fn it_creates_deployment_with_vpa_recommendation() {
+        let settings = make_settings(Some(json!({ "read_vpa_recommendations": true })));
+
+        let dispatch_table = routes!(
+            GET format!("/apis/autoscaling.k8s.io/v1/namespaces/{}/verticalpodautoscalers/edgeagent", settings.namespace()) => vpa_handler(),
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => empty_deployment_list_handler(),
+            POST format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => create_deployment_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec("edgeagent");
+
+        let task = create_or_update_deployment(&runtime, &module);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_creates_deployment_with_node_labels() {
+        let settings = make_settings(Some(json!({
+            "node_name": "edge-node-1",
+            "propagate_node_labels": ["location", "hardware"]
+        })));
+
+        let dispatch_table = routes!(
+            GET "/api/v1/nodes/edge-node-1" => node_handler(),
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => empty_deployment_list_handler(),
+            POST format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => create_deployment_with_annotations_handler(btreemap! {
+                "location".to_string() => "factory-floor-1".to_string(),
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec("edgeagent");
+
+        let task = create_or_update_deployment(&runtime, &module);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
     #[test]
     fn it_replaces_role_binding_for_edgeagent() {
         let settings = make_settings(None);
@@ -389,6 +1135,214 @@ mod tests {
         runtime.block_on(task).unwrap();
     }
 
+    #[test]
+    fn it_creates_headless_service_if_does_not_exist() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/services", settings.namespace()) => empty_service_list_handler(),
+            POST format!("/api/v1/namespaces/{}/services", settings.namespace()) => create_headless_service_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = create_headless_service(&runtime, "edgehub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_creates_new_pod_monitor_if_does_not_exist() {
+        let settings = make_settings(Some(json!({ "metrics": true })));
+
+        let dispatch_table = routes!(
+            POST format!("/apis/monitoring.coreos.com/v1/namespaces/{}/podmonitors", settings.namespace()) => create_pod_monitor_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_metrics_module_spec("edgehub", "9600");
+
+        let task = create_or_update_pod_monitor(&runtime, &module);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_does_not_create_pod_monitor_without_metrics_port() {
+        let settings = make_settings(Some(json!({ "metrics": true })));
+
+        let dispatch_table = btreemap!();
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec("edgeagent");
+
+        let task = create_or_update_pod_monitor(&runtime, &module);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_creates_new_certificate_if_configured() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgehub": {
+                    "tls_certificate": {
+                        "issuer_ref": { "name": "ca-issuer" },
+                        "dns_names": ["edgehub.local"]
+                    }
+                }
+            }
+        })));
+
+        let dispatch_table = routes!(
+            POST format!("/apis/cert-manager.io/v1/namespaces/{}/certificates", settings.namespace()) => create_certificate_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+        let module = create_module_spec("edgehub");
+
+        let task = create_or_update_certificate(&runtime, &module);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    fn create_certificate_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::CREATED, || {
+                json!({
+                    "kind": "Certificate",
+                    "apiVersion": "cert-manager.io/v1",
+                    "metadata": {
+                        "name": "edgehub",
+                        "namespace": "my-namespace",
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn empty_service_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ServiceList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_headless_service_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::CREATED, || {
+                json!({
+                    "kind": "Service",
+                    "apiVersion": "v1",
+                    "metadata": {
+                        "name": "edgehub-headless",
+                        "namespace": "my-namespace",
+                    },
+                    "spec": { "clusterIP": "None" }
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_pod_monitor_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::CREATED, || {
+                json!({
+                    "kind": "PodMonitor",
+                    "apiVersion": "monitoring.coreos.com/v1",
+                    "metadata": {
+                        "name": "edgehub",
+                        "namespace": "my-namespace",
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn create_metrics_module_spec(name: &str, port: &str) -> ModuleSpec<DockerConfig> {
+        let mut labels = HashMap::new();
+        labels.insert(
+            "net.azure-devices.edge.metrics-port".to_string(),
+            port.to_string(),
+        );
+        let create_body = ContainerCreateBody::new().with_labels(labels);
+
+        ModuleSpec::new(
+            name.to_string(),
+            "docker".to_string(),
+            DockerConfig::new("my-image:v1.0".to_string(), create_body, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn vpa_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "VerticalPodAutoscaler",
+                    "apiVersion": "autoscaling.k8s.io/v1",
+                    "metadata": {
+                        "name": "edgeagent",
+                        "namespace": "my-namespace",
+                    },
+                    "status": {
+                        "recommendation": {
+                            "containerRecommendations": [
+                                {
+                                    "containerName": "edgeagent",
+                                    "target": { "cpu": "25m", "memory": "262144k" }
+                                }
+                            ]
+                        }
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn node_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "Node",
+                    "apiVersion": "v1",
+                    "metadata": {
+                        "name": "edge-node-1",
+                        "labels": {
+                            "location": "factory-floor-1",
+                            "kubernetes.io/os": "linux"
+                        }
+                    }
+                })
+                .to_string()
+            })
+        }
+    }
+
     fn empty_deployment_list_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
         move |_| {
             response(StatusCode::OK, || {
@@ -438,6 +1392,40 @@ mod tests {
         }
     }
 
+    fn create_deployment_with_annotations_handler(
+        expected: BTreeMap<String, String>,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req: Request<Body>| {
+            let expected = expected.clone();
+            let fut = req.into_body().concat2().and_then(move |body| {
+                let deployment: api_apps::Deployment = serde_json::from_slice(&body).unwrap();
+                let annotations = deployment
+                    .spec
+                    .and_then(|spec| spec.template.metadata)
+                    .and_then(|meta| meta.annotations)
+                    .unwrap();
+                for (key, value) in &expected {
+                    assert_eq!(Some(value), annotations.get(key));
+                }
+                assert!(!annotations.contains_key("kubernetes.io/os"));
+                assert!(!annotations.contains_key("hardware"));
+
+                response(StatusCode::CREATED, move || {
+                    json!({
+                        "kind": "Deployment",
+                        "apiVersion": "apps/v1",
+                        "metadata": {
+                            "name": "edgeagent",
+                            "namespace": "my-namespace",
+                        },
+                    })
+                    .to_string()
+                })
+            });
+            Box::new(fut) as ResponseFuture
+        }
+    }
+
     fn replace_deployment_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
         move |_| {
             response(StatusCode::OK, || {
@@ -547,32 +1535,28 @@ mod tests {
         }
     }
 
-    fn response(
-        status_code: StatusCode,
-        response: impl Fn() -> String + Clone + Send + 'static,
-    ) -> ResponseFuture {
-        let response = response();
-        let response_len = response.len();
-
-        let mut response = Response::new(response.into());
-        *response.status_mut() = status_code;
-        response
-            .headers_mut()
-            .typed_insert(&ContentLength(response_len as u64));
-        response
-            .headers_mut()
-            .typed_insert(&ContentType(mime::APPLICATION_JSON));
-
-        Box::new(future::ok(response)) as ResponseFuture
-    }
-
-    fn not_found_handler(_: Request<Body>) -> ResponseFuture {
-        let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::default())
-            .unwrap();
+    #[test]
+    fn only_selector_changes_require_recreate() {
+        let settings = make_settings(None);
+        let (_, current) = spec_to_deployment(&settings, &create_module_spec("edgeagent")).unwrap();
+
+        let mut desired = current.clone();
+        if let Some(pod_spec) = desired
+            .spec
+            .as_mut()
+            .and_then(|spec| spec.template.spec.as_mut())
+        {
+            pod_spec.node_name = Some("node-2".to_string());
+            pod_spec.host_network = Some(true);
+            pod_spec.containers.pop();
+        }
+        assert!(!requires_recreate(&current, &desired));
 
-        Box::new(future::ok(response))
+        let mut desired = current.clone();
+        if let Some(spec) = desired.spec.as_mut() {
+            spec.selector.match_labels = Some(BTreeMap::new());
+        }
+        assert!(requires_recreate(&current, &desired));
     }
 
     fn create_module_spec(name: &str) -> ModuleSpec<DockerConfig> {
@@ -626,32 +1610,4 @@ mod tests {
         )
         .unwrap()
     }
-
-    fn create_runtime<S: Service>(
-        settings: Settings,
-        service: S,
-    ) -> KubeModuleRuntime<TestTokenSource, S> {
-        let client = KubeClient::with_client(get_config(), service);
-        KubeModuleRuntime::new(client, settings)
-    }
-
-    fn get_config() -> KubeConfig<TestTokenSource> {
-        KubeConfig::new(
-            Url::parse("https://localhost:443").unwrap(),
-            "/api".to_string(),
-            TestTokenSource,
-            TlsConnector::new().unwrap(),
-        )
-    }
-
-    #[derive(Clone)]
-    struct TestTokenSource;
-
-    impl TokenSource for TestTokenSource {
-        type Error = Error;
-
-        fn get(&self) -> kube_client::error::Result<Option<String>> {
-            Ok(None)
-        }
-    }
 }
@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use futures::{future, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use log::{info, warn};
+use tokio::timer::Interval;
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use crate::constants::ACTIVE_DEADLINE_ENFORCE_INTERVAL_SECS;
+use crate::convert::pod_deadline_exceeded;
+use crate::{Error, ErrorKind, KubeModuleRuntime};
+
+/// Deletes the module pods which have run past their active deadline, after
+/// which the module's Deployment starts a new pod in their place. Kubernetes
+/// can't enforce the deadline itself, as Deployments reject pod templates
+/// with `activeDeadlineSeconds`. Until it's gone, a deleted pod reports its
+/// module as failed.
+pub fn enforce_active_deadlines<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = runtime.client().clone();
+    let namespace_copy = runtime.settings().namespace().to_owned();
+
+    runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_pods(
+            runtime.settings().namespace(),
+            Some(&runtime.settings().device_hub_selector()),
+        )
+        .map_err(Error::from)
+        .and_then(move |pods| {
+            let deletes: Vec<_> = pods
+                .items
+                .into_iter()
+                .filter(pod_deadline_exceeded)
+                .filter_map(|pod| {
+                    let meta = pod.metadata?;
+                    if meta.deletion_timestamp.is_some() {
+                        return None;
+                    }
+                    let name = meta.name?;
+                    info!("Deleting pod {} which ran past its active deadline", name);
+
+                    let fut = client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .delete_pod(namespace_copy.as_str(), &name)
+                        .map_err(Error::from);
+                    Some(fut)
+                })
+                .collect();
+
+            future::join_all(deletes).map(|_| ())
+        })
+}
+
+/// Runs `enforce_active_deadlines` on a fixed interval. A failed pass is
+/// logged and retried on the next tick.
+pub fn run_deadline_enforcement<T, S>(
+    runtime: KubeModuleRuntime<T, S>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    Interval::new(
+        Instant::now(),
+        Duration::from_secs(ACTIVE_DEADLINE_ENFORCE_INTERVAL_SECS),
+    )
+    .map_err(|err| Error::from(err.context(ErrorKind::Kubernetes)))
+    .for_each(move |_| {
+        enforce_active_deadlines(&runtime).then(|result| {
+            if let Err(err) = result {
+                warn!("Could not enforce module active deadlines: {}", err);
+            }
+            Ok::<_, Error>(())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chrono::{Duration as ChronoDuration, Utc};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::constants::EDGE_ACTIVE_DEADLINE_ANNOTATION;
+    use crate::module::enforce_active_deadlines;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_deletes_pods_past_their_deadline() {
+        let deletes = Arc::new(AtomicUsize::new(0));
+        let deletes_copy = deletes.clone();
+
+        // Only the overdue pod is routed, so deleting another fails the test.
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/default/pods" => pod_list(),
+            DELETE "/api/v1/namespaces/default/pods/overdue" => move |_: Request<Body>| {
+                deletes_copy.fetch_add(1, Ordering::SeqCst);
+                response(StatusCode::OK, || json!({ "kind": "Status", "status": "Success" }).to_string())
+            },
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let mut executor = Runtime::new().unwrap();
+        executor
+            .block_on(enforce_active_deadlines(&runtime))
+            .unwrap();
+
+        assert_eq!(deletes.load(Ordering::SeqCst), 1);
+    }
+
+    fn pod_list() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let pod = |name: &str, started_minutes_ago: i64, deleting: bool| {
+            let mut metadata = json!({
+                "name": name,
+                "namespace": "default",
+                "annotations": { EDGE_ACTIVE_DEADLINE_ANNOTATION: "3600" },
+            });
+            if deleting {
+                metadata["deletionTimestamp"] = json!(Utc::now().to_rfc3339());
+            }
+            json!({
+                "metadata": metadata,
+                "status": {
+                    "startTime": (Utc::now() - ChronoDuration::minutes(started_minutes_ago)).to_rfc3339(),
+                },
+            })
+        };
+        let body = json!({
+            "kind": "PodList",
+            "apiVersion": "v1",
+            "items": [
+                pod("overdue", 90, false),
+                pod("running", 30, false),
+                pod("deleting", 90, true),
+            ]
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+}
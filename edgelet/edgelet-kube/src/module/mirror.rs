@@ -0,0 +1,347 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use futures::future::{self, Either, Loop};
+use futures::{Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use log::{info, warn};
+use tokio::timer::Delay;
+
+use kube_client::{Error as KubeClientError, TokenSource, WatchEvent};
+
+use crate::constants::CONFIG_MAP_MIRROR_RETRY_SECS;
+use crate::convert::check_config_map_size;
+use crate::{Error, ErrorKind, KubeModuleRuntime};
+
+/// Copies the data of a config map to a config map of the same name in
+/// another namespace, so modules in per-device namespaces can mount config
+/// kept in a shared namespace. The copy is only written when its data differs
+/// from the source.
+pub fn mirror_config_map<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    source_namespace: &str,
+    source_name: &str,
+    target_namespace: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = runtime.client().clone();
+    let source_namespace_copy = source_namespace.to_owned();
+    let name = source_name.to_owned();
+    let target_namespace = target_namespace.to_owned();
+
+    runtime
+        .client()
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_config_maps(source_namespace, Some(source_name), None)
+        .map_err(Error::from)
+        .and_then(move |config_maps| {
+            find_config_map(config_maps, &name)
+                .map(|source| mirrored(source, &target_namespace))
+                .ok_or_else(|| {
+                    Error::from(ErrorKind::NotFound(format!(
+                        "Config map {} not found in namespace {}",
+                        name, source_namespace_copy
+                    )))
+                })
+                .and_then(|mirror| check_config_map_size(&mirror).map(|_| mirror))
+                .map(|mirror| (name, target_namespace, mirror))
+        })
+        .and_then(move |(name, target_namespace, mirror)| {
+            let client = client_copy.clone();
+
+            client_copy
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_config_maps(&target_namespace, Some(&name), None)
+                .map_err(Error::from)
+                .and_then(
+                    move |config_maps| match find_config_map(config_maps, &name) {
+                        Some(ref current)
+                            if current.data == mirror.data
+                                && current.binary_data == mirror.binary_data =>
+                        {
+                            Either::A(future::ok(()))
+                        }
+                        Some(_) => {
+                            info!(
+                                "Updating config map {} in namespace {}",
+                                name, target_namespace
+                            );
+                            let fut = client
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_config_map(&target_namespace, &name, &mirror)
+                                .map_err(Error::from)
+                                .map(|_| ());
+                            Either::B(Either::A(fut))
+                        }
+                        None => {
+                            info!(
+                                "Creating config map {} in namespace {}",
+                                name, target_namespace
+                            );
+                            let fut = client
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .create_config_map(&target_namespace, &mirror)
+                                .map_err(Error::from)
+                                .map(|_| ());
+                            Either::B(Either::B(fut))
+                        }
+                    },
+                )
+        })
+}
+
+/// Mirrors the config map once and again every time the watch on the source
+/// reports a change. The watch is re-established when the API server closes
+/// it, and after a delay when it fails.
+pub fn run_config_map_mirroring<T, S>(
+    runtime: KubeModuleRuntime<T, S>,
+    source_namespace: String,
+    source_name: String,
+    target_namespace: String,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    future::loop_fn((), move |()| {
+        let runtime = runtime.clone();
+        let source_namespace = source_namespace.clone();
+        let source_name = source_name.clone();
+        let target_namespace = target_namespace.clone();
+
+        let mirror =
+            mirror_config_map(&runtime, &source_namespace, &source_name, &target_namespace)
+                .then(log_mirror_result);
+
+        let watch = move |_| {
+            let events = runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .watch_config_maps(&source_namespace, Some(&source_name));
+
+            events
+                .map_err(Error::from)
+                .for_each(move |event| match event {
+                    WatchEvent::Added(_) | WatchEvent::Modified(_) => Either::A(
+                        mirror_config_map(
+                            &runtime,
+                            &source_namespace,
+                            &source_name,
+                            &target_namespace,
+                        )
+                        .then(log_mirror_result),
+                    ),
+                    WatchEvent::Deleted(_) => {
+                        warn!(
+                            "Config map {} was deleted from namespace {}, keeping its copy",
+                            source_name, source_namespace
+                        );
+                        Either::B(future::ok(()))
+                    }
+                    WatchEvent::Error(status) => Either::B(future::err(Error::from(
+                        status.context(ErrorKind::Kubernetes),
+                    ))),
+                })
+        };
+
+        mirror.and_then(watch).then(|result| match result {
+            Ok(()) => Either::A(future::ok(Loop::Continue(()))),
+            Err(err) => {
+                warn!("Config map watch failed: {}", err);
+                let retry =
+                    Delay::new(Instant::now() + Duration::from_secs(CONFIG_MAP_MIRROR_RETRY_SECS))
+                        .map_err(|err| Error::from(err.context(ErrorKind::Kubernetes)))
+                        .map(|_| Loop::Continue(()));
+                Either::B(retry)
+            }
+        })
+    })
+}
+
+// A failed mirror is retried on the next change to the source, so it doesn't
+// end the watch.
+fn log_mirror_result(result: Result<(), Error>) -> Result<(), Error> {
+    if let Err(err) = result {
+        warn!("Could not mirror config map: {}", err);
+    }
+    Ok(())
+}
+
+fn find_config_map(
+    config_maps: api_core::ConfigMapList,
+    name: &str,
+) -> Option<api_core::ConfigMap> {
+    config_maps.items.into_iter().find(|config_map| {
+        config_map.metadata.as_ref().map_or(false, |meta| {
+            meta.name.as_ref().map_or(false, |n| n == name)
+        })
+    })
+}
+
+fn mirrored(source: api_core::ConfigMap, target_namespace: &str) -> api_core::ConfigMap {
+    let (name, labels) = source
+        .metadata
+        .map_or((None, None), |meta| (meta.name, meta.labels));
+
+    api_core::ConfigMap {
+        metadata: Some(api_meta::ObjectMeta {
+            name,
+            namespace: Some(target_namespace.to_string()),
+            labels,
+            ..api_meta::ObjectMeta::default()
+        }),
+        data: source.data,
+        binary_data: source.binary_data,
+        ..api_core::ConfigMap::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::module::mirror_config_map;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+    use crate::ErrorKind;
+
+    #[test]
+    fn it_copies_config_map_to_target_namespace() {
+        let created = Arc::new(AtomicBool::new(false));
+        let created_copy = created.clone();
+
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/shared/configmaps" => config_map_list("shared", "proxy: config"),
+            GET "/api/v1/namespaces/default/configmaps" => empty_config_map_list(),
+            POST "/api/v1/namespaces/default/configmaps" => move |req: Request<Body>| {
+                let created = created_copy.clone();
+                let fut = req.into_body().concat2().and_then(move |body| {
+                    let config_map: JsonValue = serde_json::from_slice(&body).unwrap();
+                    assert_eq!(config_map["metadata"]["name"], "proxy-config");
+                    assert_eq!(config_map["metadata"]["namespace"], "default");
+                    assert_eq!(config_map["data"]["config.yaml"], "proxy: config");
+                    created.store(true, Ordering::SeqCst);
+                    response(StatusCode::CREATED, move || config_map.to_string())
+                });
+                Box::new(fut) as ResponseFuture
+            },
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = mirror_config_map(&runtime, "shared", "proxy-config", "default");
+        Runtime::new().unwrap().block_on(task).unwrap();
+
+        assert!(created.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn it_skips_unchanged_config_map() {
+        // No PUT or POST routes, so writing the copy would fail the test.
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/shared/configmaps" => config_map_list("shared", "proxy: config"),
+            GET "/api/v1/namespaces/default/configmaps" => config_map_list("default", "proxy: config"),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = mirror_config_map(&runtime, "shared", "proxy-config", "default");
+        Runtime::new().unwrap().block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_fails_when_source_config_map_missing() {
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/shared/configmaps" => empty_config_map_list(),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = mirror_config_map(&runtime, "shared", "proxy-config", "default");
+        let err = Runtime::new().unwrap().block_on(task).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::NotFound(_) => (),
+            kind => panic!("Expected a not found error but got {:?}", kind),
+        }
+    }
+
+    fn config_map_list(
+        namespace: &str,
+        config: &str,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let body = json!({
+            "kind": "ConfigMapList",
+            "apiVersion": "v1",
+            "items": [
+                {
+                    "metadata": {
+                        "name": "proxy-config",
+                        "namespace": namespace,
+                        "resourceVersion": "1",
+                    },
+                    "data": {
+                        "config.yaml": config
+                    }
+                }
+            ]
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+
+    fn empty_config_map_list() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ConfigMapList",
+                    "apiVersion": "v1",
+                    "items": []
+                })
+                .to_string()
+            })
+        }
+    }
+}
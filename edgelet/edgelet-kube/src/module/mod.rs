@@ -1,12 +1,32 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+mod admission;
 mod authentication;
 mod create;
+mod deadline;
+mod mirror;
+mod rbac;
+mod readiness;
+mod remove;
+mod rename;
+mod service_account;
 mod trust_bundle;
+mod watch;
 
+pub use admission::apply_admission_policies;
 pub use authentication::authenticate;
-pub use create::create_module;
+pub use create::{create_headless_service, create_module};
+pub use deadline::{enforce_active_deadlines, run_deadline_enforcement};
+pub use mirror::{mirror_config_map, run_config_map_mirroring};
+pub use rbac::bootstrap_rbac;
+pub use readiness::set_module_ready;
+pub use remove::remove_module;
+pub use rename::rename_module;
+pub use service_account::sync_module_service_account_annotations;
 pub use trust_bundle::init_trust_bundle;
+pub use watch::{watch_modules, ModuleEvent, ModuleWatch};
+
+use std::fmt;
 
 use edgelet_core::{Module, ModuleRuntimeState, ModuleStatus};
 use edgelet_docker::DockerConfig;
@@ -20,13 +40,113 @@ const MODULE_TYPE: &str = "docker";
 pub struct KubeModule {
     name: String,
     config: DockerConfig,
+    deployment_status: Option<DeploymentStatus>,
+    pod_ip: Option<String>,
+    deadline_exceeded: bool,
 }
 
 impl KubeModule {
     pub fn new(name: String, config: DockerConfig) -> Result<Self> {
         ensure_not_empty_with_context(&name, || ErrorKind::InvalidModuleName(name.clone()))?;
 
-        Ok(KubeModule { name, config })
+        Ok(KubeModule {
+            name,
+            config,
+            deployment_status: None,
+            pod_ip: None,
+            deadline_exceeded: false,
+        })
+    }
+
+    pub fn with_deployment_status(mut self, deployment_status: Option<DeploymentStatus>) -> Self {
+        self.deployment_status = deployment_status;
+        self
+    }
+
+    /// The rollout state of the module's Deployment, when it was looked up.
+    pub fn deployment_status(&self) -> Option<&DeploymentStatus> {
+        self.deployment_status.as_ref()
+    }
+
+    pub fn with_pod_ip(mut self, pod_ip: Option<String>) -> Self {
+        self.pod_ip = pod_ip;
+        self
+    }
+
+    /// The IP address of the module's pod, once it has been assigned one.
+    pub fn pod_ip(&self) -> Option<&str> {
+        self.pod_ip.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_deadline_exceeded(mut self, deadline_exceeded: bool) -> Self {
+        self.deadline_exceeded = deadline_exceeded;
+        self
+    }
+
+    /// Whether the module's pod ran past its active deadline. The runtime
+    /// deletes such pods, and the module's Deployment starts another pod in
+    /// its place.
+    pub fn deadline_exceeded(&self) -> bool {
+        self.deadline_exceeded
+    }
+}
+
+/// Rollout progress of a module's Deployment, taken from its `status`.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Serialize)]
+pub struct DeploymentStatus {
+    pub ready_replicas: i32,
+    pub updated_replicas: i32,
+    pub unavailable_replicas: i32,
+    pub conditions: Vec<DeploymentCondition>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct DeploymentCondition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub status: String,
+    pub reason: Option<String>,
+    pub message: Option<String>,
+    /// RFC 3339 timestamp of the last update to the condition.
+    pub last_update_time: Option<String>,
+}
+
+impl DeploymentStatus {
+    // A Deployment whose Progressing condition is False has exceeded its
+    // progress deadline, so the rollout won't finish on its own.
+    fn module_status(&self) -> ModuleStatus {
+        let stalled = self
+            .conditions
+            .iter()
+            .any(|condition| condition.type_ == "Progressing" && condition.status == "False");
+
+        if stalled {
+            ModuleStatus::Failed
+        } else if self.ready_replicas > 0 {
+            ModuleStatus::Running
+        } else {
+            ModuleStatus::Unknown
+        }
+    }
+}
+
+impl fmt::Display for DeploymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ready, {} updated, {} unavailable replicas",
+            self.ready_replicas, self.updated_replicas, self.unavailable_replicas
+        )?;
+
+        let progressing = self
+            .conditions
+            .iter()
+            .find(|condition| condition.type_ == "Progressing");
+        if let Some(message) = progressing.and_then(|condition| condition.message.as_ref()) {
+            write!(f, ": {}", message)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -51,8 +171,17 @@ impl Module for KubeModule {
     fn runtime_state(&self) -> Self::RuntimeStateFuture {
         // Working on assumption that if Kube module exists (present in cluster), status is successful
         // TODO: get Pod "last known good state" when we implement a more robust recovery in iotedged
-        Box::new(future::ok(
-            ModuleRuntimeState::default().with_status(ModuleStatus::Running),
-        ))
+        let state = match self.deployment_status {
+            _ if self.deadline_exceeded => ModuleRuntimeState::default()
+                .with_status(ModuleStatus::Failed)
+                .with_status_description(Some("Pod ran past its active deadline".to_string())),
+            Some(ref deployment_status) => ModuleRuntimeState::default()
+                .with_status(deployment_status.module_status())
+                .with_status_description(Some(deployment_status.to_string())),
+            None => ModuleRuntimeState::default().with_status(ModuleStatus::Running),
+        }
+        .with_pod_ip(self.pod_ip.clone());
+
+        Box::new(future::ok(state))
     }
 }
@@ -0,0 +1,256 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::future::Either;
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use log::info;
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use crate::convert::{
+    settings_to_cluster_role, settings_to_cluster_role_binding, settings_to_role,
+    settings_to_role_binding,
+};
+use crate::{Error, KubeModuleRuntime};
+
+/// Ensures the Role and Role Binding the IoT Edge service account needs exist
+/// in the runtime's namespace, along with the Cluster Role and Cluster Role
+/// Binding for the cluster scoped objects it uses. When `dry_run` is set
+/// nothing is sent to the API server and the objects which would have been
+/// applied are logged instead.
+pub fn bootstrap_rbac<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    dry_run: bool,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let settings = runtime.settings();
+
+    settings_to_role(settings)
+        .and_then(|role| {
+            settings_to_role_binding(settings).map(|role_binding| (role, role_binding))
+        })
+        .and_then(|(role, role_binding)| {
+            settings_to_cluster_role(settings)
+                .map(|cluster_role| (role, role_binding, cluster_role))
+        })
+        .and_then(|(role, role_binding, cluster_role)| {
+            settings_to_cluster_role_binding(settings).map(|cluster_role_binding| {
+                (role, role_binding, cluster_role, cluster_role_binding)
+            })
+        })
+        .map_err(Error::from)
+        .map(
+            |(
+                (role_name, role),
+                (role_binding_name, role_binding),
+                (cluster_role_name, cluster_role),
+                (cluster_role_binding_name, cluster_role_binding),
+            )| {
+                if dry_run {
+                    info!(
+                        "Dry run: would apply role {} {:?} and role binding {} {:?}",
+                        role_name, role, role_binding_name, role_binding
+                    );
+                    info!(
+                        "Dry run: would apply cluster role {} {:?} and cluster role binding {} {:?}",
+                        cluster_role_name,
+                        cluster_role,
+                        cluster_role_binding_name,
+                        cluster_role_binding
+                    );
+                    return Either::A(future::ok(()));
+                }
+
+                let client = runtime.client();
+                let namespace = runtime.settings().namespace().to_owned();
+
+                let fut = runtime
+                    .client()
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .replace_role(&namespace, &role_name, &role)
+                    .map_err(Error::from)
+                    .and_then({
+                        let client = client.clone();
+                        move |_| {
+                            client
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_role_binding(&namespace, &role_binding_name, &role_binding)
+                                .map_err(Error::from)
+                        }
+                    })
+                    .and_then({
+                        let client = client.clone();
+                        move |_| {
+                            client
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_cluster_role(&cluster_role_name, &cluster_role)
+                                .map_err(Error::from)
+                        }
+                    })
+                    .and_then(move |_| {
+                        client
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .replace_cluster_role_binding(
+                                &cluster_role_binding_name,
+                                &cluster_role_binding,
+                            )
+                            .map_err(Error::from)
+                    })
+                    .map(|_| ());
+
+                Either::B(fut)
+            },
+        )
+        .into_future()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use hyper::service::service_fn;
+    use hyper::{Body, Error as HyperError, Method, Request, Response, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::module::bootstrap_rbac;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_does_not_call_api_server_on_dry_run() {
+        let called = Arc::new(AtomicBool::new(false));
+        let called_copy = called.clone();
+        let service = service_fn(
+            move |_: Request<Body>| -> Result<Response<Body>, HyperError> {
+                called_copy.store(true, Ordering::SeqCst);
+                Ok(Response::new(Body::empty()))
+            },
+        );
+
+        let runtime = create_runtime(make_settings(None), service);
+        let task = bootstrap_rbac(&runtime, true);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+
+        assert!(!called.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn it_applies_roles_and_role_bindings() {
+        let dispatch_table = routes!(
+            PUT "/apis/rbac.authorization.k8s.io/v1/namespaces/default/roles/iotedge" => role(),
+            PUT "/apis/rbac.authorization.k8s.io/v1/namespaces/default/rolebindings/iotedge" => role_binding(),
+            PUT "/apis/rbac.authorization.k8s.io/v1/clusterroles/default-iotedge" => cluster_role(),
+            PUT "/apis/rbac.authorization.k8s.io/v1/clusterrolebindings/default-iotedge" => cluster_role_binding(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = bootstrap_rbac(&runtime, false);
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    fn role() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "Role",
+                    "apiVersion": "rbac.authorization.k8s.io/v1",
+                    "metadata": {
+                        "name": "iotedge",
+                        "namespace": "default",
+                    },
+                    "rules": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn role_binding() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "RoleBinding",
+                    "apiVersion": "rbac.authorization.k8s.io/v1",
+                    "metadata": {
+                        "name": "iotedge",
+                        "namespace": "default",
+                    },
+                    "roleRef": {
+                        "apiGroup": "rbac.authorization.k8s.io",
+                        "kind": "Role",
+                        "name": "iotedge"
+                    },
+                    "subjects": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn cluster_role() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ClusterRole",
+                    "apiVersion": "rbac.authorization.k8s.io/v1",
+                    "metadata": {
+                        "name": "default-iotedge",
+                    },
+                    "rules": []
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn cluster_role_binding() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "ClusterRoleBinding",
+                    "apiVersion": "rbac.authorization.k8s.io/v1",
+                    "metadata": {
+                        "name": "default-iotedge",
+                    },
+                    "roleRef": {
+                        "apiGroup": "rbac.authorization.k8s.io",
+                        "kind": "ClusterRole",
+                        "name": "default-iotedge"
+                    },
+                    "subjects": []
+                })
+                .to_string()
+            })
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use log::info;
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::convert::sanitize_dns_value;
+use crate::{Error, KubeModuleRuntime};
+
+/// Sets a condition on the status of a module's pods, which is how modules
+/// with their own readiness protocol report the readiness gates listed in
+/// their settings. The transition time only changes when the condition does.
+pub fn set_module_ready<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module_id: &str,
+    condition_type: &str,
+    ready: bool,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = runtime.client().clone();
+    let namespace_copy = runtime.settings().namespace().to_owned();
+    let condition_type = condition_type.to_owned();
+    let status = if ready { "True" } else { "False" };
+
+    sanitize_dns_value(module_id)
+        .map(|name| {
+            let selector = match runtime.settings().device_hub_selector() {
+                "" => format!("{}={}", EDGE_MODULE_LABEL, name),
+                device_hub_selector => {
+                    format!("{}={},{}", EDGE_MODULE_LABEL, name, device_hub_selector)
+                }
+            };
+
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_pods(runtime.settings().namespace(), Some(&selector))
+                .map_err(Error::from)
+                .and_then(move |pods| {
+                    let updates: Vec<_> = pods
+                        .items
+                        .into_iter()
+                        .filter_map(|pod| {
+                            let pod_name = pod.metadata.as_ref()?.name.clone()?;
+                            info!(
+                                "Setting condition {} of pod {} to {}",
+                                condition_type, pod_name, status
+                            );
+
+                            let fut = client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .replace_pod_status(
+                                    namespace_copy.as_str(),
+                                    &pod_name,
+                                    &with_condition(pod, &condition_type, status),
+                                )
+                                .map_err(Error::from)
+                                .map(|_| ());
+                            Some(fut)
+                        })
+                        .collect();
+
+                    future::join_all(updates).map(|_| ())
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+fn with_condition(mut pod: api_core::Pod, condition_type: &str, status: &str) -> api_core::Pod {
+    let conditions = pod
+        .status
+        .get_or_insert_with(api_core::PodStatus::default)
+        .conditions
+        .get_or_insert_with(Vec::new);
+
+    match conditions.iter().position(|c| c.type_ == condition_type) {
+        Some(index) if conditions[index].status == status => (),
+        Some(index) => {
+            conditions[index].status = status.to_string();
+            conditions[index].last_transition_time = Some(api_meta::Time(Utc::now()));
+        }
+        None => conditions.push(api_core::PodCondition {
+            type_: condition_type.to_string(),
+            status: status.to_string(),
+            last_transition_time: Some(api_meta::Time(Utc::now())),
+            ..api_core::PodCondition::default()
+        }),
+    }
+
+    pod
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_sets_pod_condition() {
+        let replaced = Arc::new(AtomicBool::new(false));
+        let replaced_copy = replaced.clone();
+
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/default/pods" => pod_list(),
+            PUT "/api/v1/namespaces/default/pods/edgehub/status" => move |req: Request<Body>| {
+                let replaced = replaced_copy.clone();
+                let fut = req.into_body().concat2().and_then(move |body| {
+                    let pod: JsonValue = serde_json::from_slice(&body).unwrap();
+                    let conditions = pod["status"]["conditions"].as_array().unwrap();
+                    assert_eq!(conditions.len(), 2);
+                    assert_eq!(conditions[1]["type"], "example.com/feature-1");
+                    assert_eq!(conditions[1]["status"], "True");
+                    replaced.store(true, Ordering::SeqCst);
+                    response(StatusCode::OK, move || pod.to_string())
+                });
+                Box::new(fut) as ResponseFuture
+            },
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = runtime.set_module_ready("$edgeHub", "example.com/feature-1", true);
+        Runtime::new().unwrap().block_on(task).unwrap();
+
+        assert!(replaced.load(Ordering::SeqCst));
+    }
+
+    fn pod_list() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let body = json!({
+            "kind": "PodList",
+            "apiVersion": "v1",
+            "items": [
+                {
+                    "metadata": {
+                        "name": "edgehub",
+                        "namespace": "default",
+                    },
+                    "status": {
+                        "conditions": [
+                            { "type": "Ready", "status": "False" }
+                        ]
+                    }
+                }
+            ]
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::future::Either;
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use log::info;
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use super::rename::{delete_persistent_volume_claims, delete_resources, ModuleResources};
+use crate::convert::sanitize_dns_value;
+use crate::error::{Error, ErrorKind};
+use crate::KubeModuleRuntime;
+
+/// Deletes a module's Deployment, Service and persistent volume claims.
+///
+/// Deleting the claims destroys the module's persistent state, so when the
+/// `require_deletion_confirmation` setting is on a module with claims is only
+/// removed if `force` is set. Otherwise nothing is deleted and
+/// `ErrorKind::ConfirmationRequired` is returned.
+pub fn remove_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+    force: bool,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client = runtime.client();
+    let namespace = runtime.settings().namespace().to_owned();
+    let confirm = runtime.settings().require_deletion_confirmation() && !force;
+
+    sanitize_dns_value(id)
+        .map(|name| {
+            let selector = runtime.module_selector(&name);
+
+            let claims = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_persistent_volume_claims(&namespace, Some(&selector));
+            let deployments = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_deployments(&namespace, None, Some(&selector));
+            let services = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_services(&namespace, None, Some(&selector));
+
+            claims
+                .join3(deployments, services)
+                .map_err(Error::from)
+                .and_then(move |(claims, deployments, services)| {
+                    if confirm && !claims.items.is_empty() {
+                        return Either::A(future::err(Error::from(
+                            ErrorKind::ConfirmationRequired(name),
+                        )));
+                    }
+
+                    let resources = ModuleResources {
+                        deployments: deployments.items,
+                        services: services.items,
+                        ..ModuleResources::default()
+                    };
+                    info!("Removing module {}", name);
+
+                    Either::B(delete_resources(&client, &namespace, &resources).and_then(
+                        move |_| delete_persistent_volume_claims(&client, &namespace, &selector),
+                    ))
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::module::remove_module;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+    use crate::ErrorKind;
+
+    #[test]
+    fn module_with_claims_requires_force() {
+        let deleted = Arc::new(AtomicUsize::new(0));
+        let handler = make_req_dispatcher(routes(deleted.clone()), Box::new(not_found_handler));
+        let settings = make_settings(Some(json!({ "require_deletion_confirmation": true })));
+        let runtime = create_runtime(settings, service_fn(handler));
+
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(remove_module(&runtime, "tempSensor", false))
+            .unwrap_err();
+        assert_eq!(
+            &ErrorKind::ConfirmationRequired("tempsensor".to_string()),
+            err.kind()
+        );
+        assert_eq!(deleted.load(Ordering::SeqCst), 0);
+
+        Runtime::new()
+            .unwrap()
+            .block_on(remove_module(&runtime, "tempSensor", true))
+            .unwrap();
+        assert_eq!(deleted.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn module_is_removed_without_confirmation_by_default() {
+        let deleted = Arc::new(AtomicUsize::new(0));
+        let handler = make_req_dispatcher(routes(deleted.clone()), Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        Runtime::new()
+            .unwrap()
+            .block_on(remove_module(&runtime, "tempSensor", false))
+            .unwrap();
+        assert_eq!(deleted.load(Ordering::SeqCst), 3);
+    }
+
+    fn routes(deleted: Arc<AtomicUsize>) -> BTreeMap<(HttpMethod, RequestPath), RequestHandler> {
+        routes!(
+            GET "/apis/apps/v1/namespaces/default/deployments" => list_handler("DeploymentList", deployment()),
+            GET "/api/v1/namespaces/default/services" => list_handler("ServiceList", service()),
+            GET "/api/v1/namespaces/default/persistentvolumeclaims" => list_handler("PersistentVolumeClaimList", claim()),
+            DELETE "/apis/apps/v1/namespaces/default/deployments/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/services/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/persistentvolumeclaims/tempsensor-data" => delete_handler(deleted.clone()),
+        )
+    }
+
+    fn deployment() -> JsonValue {
+        json!({
+            "kind": "Deployment",
+            "apiVersion": "apps/v1",
+            "metadata": {
+                "name": "tempsensor",
+                "namespace": "default",
+                "labels": { "net.azure-devices.edge.module": "tempsensor" }
+            }
+        })
+    }
+
+    fn service() -> JsonValue {
+        json!({
+            "kind": "Service",
+            "apiVersion": "v1",
+            "metadata": {
+                "name": "tempsensor",
+                "namespace": "default",
+                "labels": { "net.azure-devices.edge.module": "tempsensor" }
+            }
+        })
+    }
+
+    fn claim() -> JsonValue {
+        json!({
+            "kind": "PersistentVolumeClaim",
+            "apiVersion": "v1",
+            "metadata": { "name": "tempsensor-data", "namespace": "default" }
+        })
+    }
+
+    fn list_handler(
+        kind: &'static str,
+        item: JsonValue,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let body = json!({
+            "kind": kind,
+            "apiVersion": "v1",
+            "metadata": {},
+            "items": [item]
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+
+    fn delete_handler(
+        deleted: Arc<AtomicUsize>,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            deleted.fetch_add(1, Ordering::SeqCst);
+            response(StatusCode::OK, || {
+                json!({ "kind": "Status", "apiVersion": "v1", "status": "Success" }).to_string()
+            })
+        }
+    }
+}
@@ -0,0 +1,922 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use futures::future::Either;
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::apps::v1 as api_apps;
+use k8s_openapi::api::core::v1 as api_core;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use log::{info, warn};
+use serde_json::json;
+
+use kube_client::{
+    Client as KubeClient, Error as KubeClientError, ErrorKind as KubeClientErrorKind, TokenSource,
+};
+
+use crate::constants::{EDGE_MODULE_LABEL, EDGE_ORIGINAL_MODULEID};
+use crate::convert::sanitize_dns_value;
+use crate::error::Error;
+use crate::KubeModuleRuntime;
+
+type SharedClient<T, S> = Arc<Mutex<RefCell<KubeClient<T, S>>>>;
+
+/// The objects of a module which are renamed along with it.
+#[derive(Clone, Debug, Default)]
+pub(super) struct ModuleResources {
+    pub(super) deployments: Vec<api_apps::Deployment>,
+    pub(super) services: Vec<api_core::Service>,
+    pub(super) service_accounts: Vec<api_core::ServiceAccount>,
+}
+
+/// Moves a module's Deployment, Service and ServiceAccount over to a new
+/// module ID. The old objects are deleted before the renamed copies are
+/// created so the two never run side by side. If either phase fails, whatever
+/// was created under the new name is deleted and the old objects are
+/// recreated, and the original error is returned.
+///
+/// Persistent volume claims can't be renamed, so the renamed Deployment keeps
+/// mounting the old module's claims, which are relabelled to belong to the new
+/// module once the rename has gone through. Only claims which the module no
+/// longer mounts are deleted, and not even those when the
+/// `require_deletion_confirmation` setting is on.
+pub fn rename_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    old_id: &str,
+    new_id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client = runtime.client();
+    let namespace = runtime.settings().namespace().to_owned();
+    let keep_orphans = runtime.settings().require_deletion_confirmation();
+    let new_id = new_id.to_owned();
+
+    sanitize_dns_value(old_id)
+        .and_then(|old_name| sanitize_dns_value(&new_id).map(|new_name| (old_name, new_name)))
+        .map(|(old_name, new_name)| {
+            let selector = runtime.module_selector(&old_name);
+
+            let deployments = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_deployments(&namespace, None, Some(&selector));
+            let services = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_services(&namespace, None, Some(&selector));
+            let service_accounts = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_service_accounts(&namespace, None, Some(&selector));
+
+            deployments
+                .join3(services, service_accounts)
+                .map_err(Error::from)
+                .and_then(move |(deployments, services, service_accounts)| {
+                    let old = ModuleResources {
+                        deployments: deployments.items.into_iter().map(for_create).collect(),
+                        services: services.items.into_iter().map(for_create).collect(),
+                        service_accounts: service_accounts
+                            .items
+                            .into_iter()
+                            .map(for_create)
+                            .collect(),
+                    };
+                    let new = ModuleResources {
+                        deployments: old
+                            .deployments
+                            .iter()
+                            .map(|d| rename_deployment(d, &old_name, &new_name, &new_id))
+                            .collect(),
+                        services: old
+                            .services
+                            .iter()
+                            .map(|s| rename_service(s, &new_name, &new_id))
+                            .collect(),
+                        service_accounts: old
+                            .service_accounts
+                            .iter()
+                            .map(|sa| rename_service_account(sa, &new_name, &new_id))
+                            .collect(),
+                    };
+                    info!("Renaming module {} to {}", old_name, new_name);
+
+                    let client_copy = client.clone();
+                    let namespace_copy = namespace.clone();
+                    let rollback_client = client.clone();
+                    let rollback_namespace = namespace.clone();
+                    let (old_copy, new_copy) = (old.clone(), new.clone());
+                    let mounted = mounted_claims(&new.deployments);
+
+                    delete_resources(&client, &namespace, &old)
+                        .and_then(move |_| create_resources(&client_copy, &namespace_copy, &new))
+                        .or_else(move |err| {
+                            warn!("Rolling back rename of module {}", old_name);
+                            rollback(&rollback_client, &rollback_namespace, &old_copy, &new_copy)
+                                .then(move |_| Err::<(), _>(err))
+                        })
+                        .and_then(move |_| {
+                            hand_over_persistent_volume_claims(
+                                &client,
+                                &namespace,
+                                &selector,
+                                &mounted,
+                                (&new_name, &new_id),
+                                keep_orphans,
+                            )
+                        })
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+pub(super) fn delete_resources<T, S>(
+    client: &SharedClient<T, S>,
+    namespace: &str,
+    resources: &ModuleResources,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let deployments: Vec<_> = resources
+        .deployments
+        .iter()
+        .filter_map(|deployment| object_name(&deployment.metadata))
+        .map(|name| {
+            client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .delete_deployment(namespace, &name)
+        })
+        .collect();
+    let services: Vec<_> = resources
+        .services
+        .iter()
+        .filter_map(|service| object_name(&service.metadata))
+        .map(|name| {
+            client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .delete_service(namespace, &name)
+        })
+        .collect();
+    let service_accounts: Vec<_> = resources
+        .service_accounts
+        .iter()
+        .filter_map(|service_account| object_name(&service_account.metadata))
+        .map(|name| {
+            client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .delete_service_account(namespace, &name)
+        })
+        .collect();
+
+    future::join_all(deployments)
+        .join3(
+            future::join_all(services),
+            future::join_all(service_accounts),
+        )
+        .map_err(Error::from)
+        .map(|_| ())
+}
+
+fn create_resources<T, S>(
+    client: &SharedClient<T, S>,
+    namespace: &str,
+    resources: &ModuleResources,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    // The pods can't start until their service account exists, so it goes first.
+    let service_accounts: Vec<_> = resources
+        .service_accounts
+        .iter()
+        .map(|service_account| {
+            client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .create_service_account(namespace, service_account)
+        })
+        .collect();
+
+    let client = client.clone();
+    let namespace = namespace.to_owned();
+    let resources = resources.clone();
+    future::join_all(service_accounts)
+        .and_then(move |_| {
+            let deployments: Vec<_> = resources
+                .deployments
+                .iter()
+                .map(|deployment| {
+                    client
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .create_deployment(&namespace, deployment)
+                })
+                .collect();
+            let services: Vec<_> = resources
+                .services
+                .iter()
+                .map(|service| {
+                    client
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .create_service(&namespace, service)
+                })
+                .collect();
+
+            future::join_all(deployments).join(future::join_all(services))
+        })
+        .map_err(Error::from)
+        .map(|_| ())
+}
+
+// Best effort: failures are logged, since the error being rolled back is the
+// one reported. Objects which were never deleted already exist, so conflicts
+// when recreating the old ones are expected.
+fn rollback<T, S>(
+    client: &SharedClient<T, S>,
+    namespace: &str,
+    old: &ModuleResources,
+    new: &ModuleResources,
+) -> impl Future<Item = (), Error = ()>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let mut deletes = Vec::new();
+    for name in new
+        .deployments
+        .iter()
+        .filter_map(|d| object_name(&d.metadata))
+    {
+        let fut = client
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .delete_deployment(namespace, &name);
+        deletes.push(Either::A(fut));
+    }
+    for name in new.services.iter().filter_map(|s| object_name(&s.metadata)) {
+        let fut = client
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .delete_service(namespace, &name);
+        deletes.push(Either::B(Either::A(fut)));
+    }
+    for name in new
+        .service_accounts
+        .iter()
+        .filter_map(|sa| object_name(&sa.metadata))
+    {
+        let fut = client
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .delete_service_account(namespace, &name);
+        deletes.push(Either::B(Either::B(fut)));
+    }
+    let deletes = future::join_all(deletes.into_iter().map(|fut| fut.then(log_rollback_error)));
+
+    let client = client.clone();
+    let namespace = namespace.to_owned();
+    let old = old.clone();
+    deletes.and_then(move |_| {
+        let mut creates = Vec::new();
+        for deployment in &old.deployments {
+            let fut = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .create_deployment(&namespace, deployment)
+                .map(|_| ());
+            creates.push(Either::A(fut));
+        }
+        for service in &old.services {
+            let fut = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .create_service(&namespace, service)
+                .map(|_| ());
+            creates.push(Either::B(Either::A(fut)));
+        }
+        for service_account in &old.service_accounts {
+            let fut = client
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .create_service_account(&namespace, service_account)
+                .map(|_| ());
+            creates.push(Either::B(Either::B(fut)));
+        }
+
+        future::join_all(creates.into_iter().map(|fut| {
+            fut.then(|result| match result {
+                Err(ref err) if is_conflict(err) => Ok(()),
+                result => log_rollback_error(result),
+            })
+        }))
+        .map(|_| ())
+    })
+}
+
+fn is_conflict(err: &KubeClientError) -> bool {
+    match err.kind() {
+        KubeClientErrorKind::Conflict => true,
+        _ => false,
+    }
+}
+
+fn log_rollback_error(result: Result<(), KubeClientError>) -> Result<(), ()> {
+    if let Err(err) = result {
+        warn!("Could not roll back module rename: {}", err);
+    }
+    Ok(())
+}
+
+pub(super) fn delete_persistent_volume_claims<T, S>(
+    client: &SharedClient<T, S>,
+    namespace: &str,
+    selector: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = client.clone();
+    let namespace_copy = namespace.to_owned();
+
+    client
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_persistent_volume_claims(namespace, Some(selector))
+        .and_then(move |claims| {
+            let deletes: Vec<_> = claims
+                .items
+                .iter()
+                .filter_map(|claim| object_name(&claim.metadata))
+                .map(|name| {
+                    client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .delete_persistent_volume_claim(&namespace_copy, &name)
+                })
+                .collect();
+
+            future::join_all(deletes).map(|_| ())
+        })
+        .map_err(Error::from)
+}
+
+// Relabels the old module's claims which the renamed Deployments still mount,
+// so they're removed along with the new module, and deletes the rest.
+fn hand_over_persistent_volume_claims<T, S>(
+    client: &SharedClient<T, S>,
+    namespace: &str,
+    selector: &str,
+    mounted: &[String],
+    (new_name, new_id): (&str, &str),
+    keep_orphans: bool,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = client.clone();
+    let namespace_copy = namespace.to_owned();
+    let mounted = mounted.to_vec();
+    let patch = json!({
+        "metadata": {
+            "labels": { EDGE_MODULE_LABEL: new_name },
+            "annotations": { EDGE_ORIGINAL_MODULEID: new_id },
+        }
+    });
+
+    client
+        .lock()
+        .expect("Unexpected lock error")
+        .borrow_mut()
+        .list_persistent_volume_claims(namespace, Some(selector))
+        .and_then(move |claims| {
+            let updates: Vec<_> = claims
+                .items
+                .iter()
+                .filter_map(|claim| object_name(&claim.metadata))
+                .filter_map(|name| {
+                    let client = client_copy.lock().expect("Unexpected lock error");
+                    if mounted.contains(&name) {
+                        let fut = client.borrow_mut().patch_persistent_volume_claim(
+                            &namespace_copy,
+                            &name,
+                            &patch,
+                        );
+                        Some(Either::A(fut.map(|_| ())))
+                    } else if keep_orphans {
+                        warn!(
+                            "Keeping persistent volume claim {}, which is no longer mounted",
+                            name
+                        );
+                        None
+                    } else {
+                        let fut = client
+                            .borrow_mut()
+                            .delete_persistent_volume_claim(&namespace_copy, &name);
+                        Some(Either::B(fut))
+                    }
+                })
+                .collect();
+
+            future::join_all(updates).map(|_| ())
+        })
+        .map_err(Error::from)
+}
+
+fn mounted_claims(deployments: &[api_apps::Deployment]) -> Vec<String> {
+    deployments
+        .iter()
+        .filter_map(|deployment| deployment.spec.as_ref())
+        .filter_map(|spec| spec.template.spec.as_ref())
+        .filter_map(|pod_spec| pod_spec.volumes.as_ref())
+        .flatten()
+        .filter_map(|volume| volume.persistent_volume_claim.as_ref())
+        .map(|claim| claim.claim_name.clone())
+        .collect()
+}
+
+pub(super) fn object_name(metadata: &Option<api_meta::ObjectMeta>) -> Option<String> {
+    metadata.as_ref().and_then(|metadata| metadata.name.clone())
+}
+
+// Strips what the API server assigns, so the object can be created again.
+fn for_create<R: ServerAssigned>(mut resource: R) -> R {
+    resource.clear_server_assigned();
+    resource
+}
+
+trait ServerAssigned {
+    fn clear_server_assigned(&mut self);
+}
+
+impl ServerAssigned for api_apps::Deployment {
+    fn clear_server_assigned(&mut self) {
+        self.metadata = self.metadata.take().map(clear_metadata);
+        self.status = None;
+    }
+}
+
+impl ServerAssigned for api_core::Service {
+    fn clear_server_assigned(&mut self) {
+        self.metadata = self.metadata.take().map(clear_metadata);
+        self.status = None;
+        if let Some(spec) = self.spec.as_mut() {
+            // headless services keep "None"
+            if spec.cluster_ip.as_ref().map(String::as_str) != Some("None") {
+                spec.cluster_ip = None;
+            }
+        }
+    }
+}
+
+impl ServerAssigned for api_core::ServiceAccount {
+    fn clear_server_assigned(&mut self) {
+        self.metadata = self.metadata.take().map(clear_metadata);
+        // token secrets are generated for the new account
+        self.secrets = None;
+    }
+}
+
+fn clear_metadata(metadata: api_meta::ObjectMeta) -> api_meta::ObjectMeta {
+    api_meta::ObjectMeta {
+        name: metadata.name,
+        labels: metadata.labels,
+        annotations: metadata.annotations,
+        owner_references: metadata.owner_references,
+        ..api_meta::ObjectMeta::default()
+    }
+}
+
+fn rename_metadata(metadata: &mut Option<api_meta::ObjectMeta>, new_name: &str, new_id: &str) {
+    if let Some(metadata) = metadata.as_mut() {
+        if metadata.name.is_some() {
+            metadata.name = Some(new_name.to_string());
+        }
+        rename_labels(&mut metadata.labels, new_name);
+        if let Some(annotations) = metadata.annotations.as_mut() {
+            if annotations.contains_key(EDGE_ORIGINAL_MODULEID) {
+                annotations.insert(EDGE_ORIGINAL_MODULEID.to_string(), new_id.to_string());
+            }
+        }
+    }
+}
+
+fn rename_labels(labels: &mut Option<BTreeMap<String, String>>, new_name: &str) {
+    if let Some(labels) = labels.as_mut() {
+        if labels.contains_key(EDGE_MODULE_LABEL) {
+            labels.insert(EDGE_MODULE_LABEL.to_string(), new_name.to_string());
+        }
+    }
+}
+
+fn rename_deployment(
+    deployment: &api_apps::Deployment,
+    old_name: &str,
+    new_name: &str,
+    new_id: &str,
+) -> api_apps::Deployment {
+    let mut deployment = deployment.clone();
+    rename_metadata(&mut deployment.metadata, new_name, new_id);
+
+    if let Some(spec) = deployment.spec.as_mut() {
+        rename_labels(&mut spec.selector.match_labels, new_name);
+
+        let mut template_metadata = spec.template.metadata.take();
+        rename_metadata(&mut template_metadata, new_name, new_id);
+        // the pod template has no name of its own
+        if let Some(metadata) = template_metadata.as_mut() {
+            metadata.name = None;
+        }
+        spec.template.metadata = template_metadata;
+
+        if let Some(pod_spec) = spec.template.spec.as_mut() {
+            for container in &mut pod_spec.containers {
+                if container.name == old_name {
+                    container.name = new_name.to_string();
+                }
+            }
+            if pod_spec.service_account_name.as_ref().map(String::as_str) == Some(old_name) {
+                pod_spec.service_account_name = Some(new_name.to_string());
+            }
+            if pod_spec.service_account.as_ref().map(String::as_str) == Some(old_name) {
+                pod_spec.service_account = Some(new_name.to_string());
+            }
+        }
+    }
+
+    deployment
+}
+
+fn rename_service(service: &api_core::Service, new_name: &str, new_id: &str) -> api_core::Service {
+    let mut service = service.clone();
+    rename_metadata(&mut service.metadata, new_name, new_id);
+
+    if let Some(spec) = service.spec.as_mut() {
+        rename_labels(&mut spec.selector, new_name);
+    }
+
+    service
+}
+
+fn rename_service_account(
+    service_account: &api_core::ServiceAccount,
+    new_name: &str,
+    new_id: &str,
+) -> api_core::ServiceAccount {
+    let mut service_account = service_account.clone();
+    rename_metadata(&mut service_account.metadata, new_name, new_id);
+    service_account
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::module::rename_module;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+    use crate::ErrorKind;
+
+    #[test]
+    fn it_renames_module_resources() {
+        let created = Arc::new(Mutex::new(Vec::new()));
+        let deleted = Arc::new(AtomicUsize::new(0));
+        let patches = Arc::new(Mutex::new(Vec::new()));
+
+        // The mounted claim has no DELETE route, so deleting it fails the test.
+        let dispatch_table = routes!(
+            GET "/apis/apps/v1/namespaces/default/deployments" => list_handler("DeploymentList", vec![deployment()]),
+            GET "/api/v1/namespaces/default/services" => list_handler("ServiceList", vec![service()]),
+            GET "/api/v1/namespaces/default/serviceaccounts" => list_handler("ServiceAccountList", vec![service_account()]),
+            GET "/api/v1/namespaces/default/persistentvolumeclaims" => list_handler("PersistentVolumeClaimList", vec![claim("tempsensor-data"), claim("tempsensor-cache")]),
+            DELETE "/apis/apps/v1/namespaces/default/deployments/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/services/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/serviceaccounts/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/persistentvolumeclaims/tempsensor-cache" => delete_handler(deleted.clone()),
+            PATCH "/api/v1/namespaces/default/persistentvolumeclaims/tempsensor-data" => create_handler(patches.clone(), StatusCode::OK),
+            POST "/apis/apps/v1/namespaces/default/deployments" => create_handler(created.clone(), StatusCode::CREATED),
+            POST "/api/v1/namespaces/default/services" => create_handler(created.clone(), StatusCode::CREATED),
+            POST "/api/v1/namespaces/default/serviceaccounts" => create_handler(created.clone(), StatusCode::CREATED),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = rename_module(&runtime, "tempSensor", "temperature");
+        Runtime::new().unwrap().block_on(task).unwrap();
+
+        assert_eq!(deleted.load(Ordering::SeqCst), 4);
+
+        let patches = patches.lock().unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(
+            patches[0]["metadata"]["labels"]["net.azure-devices.edge.module"],
+            "temperature"
+        );
+
+        let created = created.lock().unwrap();
+        assert_eq!(created.len(), 3);
+        let deployment = created.iter().find(|o| o["kind"] == "Deployment").unwrap();
+        assert_eq!(deployment["metadata"]["name"], "temperature");
+        assert!(deployment["metadata"]["resourceVersion"].is_null());
+        assert_eq!(
+            deployment["metadata"]["annotations"]["net.azure-devices.edge.original-moduleid"],
+            "temperature"
+        );
+        assert_eq!(
+            deployment["spec"]["selector"]["matchLabels"]["net.azure-devices.edge.module"],
+            "temperature"
+        );
+        assert_eq!(
+            deployment["spec"]["template"]["spec"]["containers"][0]["name"],
+            "temperature"
+        );
+        assert_eq!(
+            deployment["spec"]["template"]["spec"]["serviceAccountName"],
+            "temperature"
+        );
+        assert_eq!(
+            deployment["spec"]["template"]["spec"]["volumes"][0]["persistentVolumeClaim"]
+                ["claimName"],
+            "tempsensor-data"
+        );
+        let service = created.iter().find(|o| o["kind"] == "Service").unwrap();
+        assert_eq!(service["metadata"]["name"], "temperature");
+        assert_eq!(
+            service["spec"]["selector"]["net.azure-devices.edge.module"],
+            "temperature"
+        );
+        assert!(service["spec"]["clusterIP"].is_null());
+        let service_account = created
+            .iter()
+            .find(|o| o["kind"] == "ServiceAccount")
+            .unwrap();
+        assert_eq!(service_account["metadata"]["name"], "temperature");
+        assert!(service_account["secrets"].is_null());
+    }
+
+    #[test]
+    fn it_keeps_unmounted_claims_when_deletion_needs_confirmation() {
+        let created = Arc::new(Mutex::new(Vec::new()));
+        let deleted = Arc::new(AtomicUsize::new(0));
+        let patches = Arc::new(Mutex::new(Vec::new()));
+
+        // No DELETE route for either claim, so deleting one fails the test.
+        let dispatch_table = routes!(
+            GET "/apis/apps/v1/namespaces/default/deployments" => list_handler("DeploymentList", vec![deployment()]),
+            GET "/api/v1/namespaces/default/services" => list_handler("ServiceList", vec![service()]),
+            GET "/api/v1/namespaces/default/serviceaccounts" => list_handler("ServiceAccountList", vec![service_account()]),
+            GET "/api/v1/namespaces/default/persistentvolumeclaims" => list_handler("PersistentVolumeClaimList", vec![claim("tempsensor-data"), claim("tempsensor-cache")]),
+            DELETE "/apis/apps/v1/namespaces/default/deployments/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/services/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/serviceaccounts/tempsensor" => delete_handler(deleted.clone()),
+            PATCH "/api/v1/namespaces/default/persistentvolumeclaims/tempsensor-data" => create_handler(patches.clone(), StatusCode::OK),
+            POST "/apis/apps/v1/namespaces/default/deployments" => create_handler(created.clone(), StatusCode::CREATED),
+            POST "/api/v1/namespaces/default/services" => create_handler(created.clone(), StatusCode::CREATED),
+            POST "/api/v1/namespaces/default/serviceaccounts" => create_handler(created.clone(), StatusCode::CREATED),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let settings = make_settings(Some(json!({ "require_deletion_confirmation": true })));
+        let runtime = create_runtime(settings, service_fn(handler));
+
+        let task = rename_module(&runtime, "tempSensor", "temperature");
+        Runtime::new().unwrap().block_on(task).unwrap();
+
+        assert_eq!(deleted.load(Ordering::SeqCst), 3);
+        assert_eq!(patches.lock().unwrap().len(), 1);
+        assert_eq!(created.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn it_rolls_back_failed_rename() {
+        let created = Arc::new(Mutex::new(Vec::new()));
+        let deleted = Arc::new(AtomicUsize::new(0));
+
+        let dispatch_table = routes!(
+            GET "/apis/apps/v1/namespaces/default/deployments" => list_handler("DeploymentList", vec![deployment()]),
+            GET "/api/v1/namespaces/default/services" => list_handler("ServiceList", vec![service()]),
+            GET "/api/v1/namespaces/default/serviceaccounts" => list_handler("ServiceAccountList", vec![service_account()]),
+            DELETE "/apis/apps/v1/namespaces/default/deployments/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/apis/apps/v1/namespaces/default/deployments/temperature" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/services/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/services/temperature" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/serviceaccounts/tempsensor" => delete_handler(deleted.clone()),
+            DELETE "/api/v1/namespaces/default/serviceaccounts/temperature" => delete_handler(deleted.clone()),
+            POST "/apis/apps/v1/namespaces/default/deployments" => create_handler(created.clone(), StatusCode::CREATED),
+            POST "/api/v1/namespaces/default/services" => create_handler(created.clone(), StatusCode::UNPROCESSABLE_ENTITY),
+            POST "/api/v1/namespaces/default/serviceaccounts" => create_handler(created.clone(), StatusCode::CREATED),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let task = rename_module(&runtime, "tempSensor", "temperature");
+        let err = Runtime::new().unwrap().block_on(task).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::KubeClient);
+
+        // the three old objects, then the three new ones
+        assert_eq!(deleted.load(Ordering::SeqCst), 6);
+
+        // the renamed copies, then the old objects again
+        let created = created.lock().unwrap();
+        let names: Vec<_> = created
+            .iter()
+            .map(|o| o["metadata"]["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names.iter().filter(|n| *n == "tempsensor").count(), 3);
+        assert_eq!(names.iter().filter(|n| *n == "temperature").count(), 3);
+    }
+
+    fn deployment() -> JsonValue {
+        json!({
+            "kind": "Deployment",
+            "apiVersion": "apps/v1",
+            "metadata": {
+                "name": "tempsensor",
+                "namespace": "default",
+                "resourceVersion": "42",
+                "labels": { "net.azure-devices.edge.module": "tempsensor" },
+                "annotations": { "net.azure-devices.edge.original-moduleid": "tempSensor" }
+            },
+            "spec": {
+                "selector": { "matchLabels": { "net.azure-devices.edge.module": "tempsensor" } },
+                "template": {
+                    "metadata": {
+                        "labels": { "net.azure-devices.edge.module": "tempsensor" },
+                        "annotations": { "net.azure-devices.edge.original-moduleid": "tempSensor" }
+                    },
+                    "spec": {
+                        "serviceAccountName": "tempsensor",
+                        "containers": [
+                            { "name": "tempsensor", "image": "tempsensor:1.0" },
+                            { "name": "proxy", "image": "proxy:1.0" }
+                        ],
+                        "volumes": [{
+                            "name": "data",
+                            "persistentVolumeClaim": { "claimName": "tempsensor-data" }
+                        }]
+                    }
+                }
+            }
+        })
+    }
+
+    fn service() -> JsonValue {
+        json!({
+            "kind": "Service",
+            "apiVersion": "v1",
+            "metadata": {
+                "name": "tempsensor",
+                "namespace": "default",
+                "resourceVersion": "43",
+                "labels": { "net.azure-devices.edge.module": "tempsensor" }
+            },
+            "spec": {
+                "clusterIP": "10.0.0.12",
+                "selector": { "net.azure-devices.edge.module": "tempsensor" },
+                "ports": [{ "port": 8080 }]
+            }
+        })
+    }
+
+    fn service_account() -> JsonValue {
+        json!({
+            "kind": "ServiceAccount",
+            "apiVersion": "v1",
+            "metadata": {
+                "name": "tempsensor",
+                "namespace": "default",
+                "resourceVersion": "44",
+                "labels": { "net.azure-devices.edge.module": "tempsensor" }
+            },
+            "secrets": [{ "name": "tempsensor-token-abcde" }]
+        })
+    }
+
+    fn claim(name: &str) -> JsonValue {
+        json!({
+            "kind": "PersistentVolumeClaim",
+            "apiVersion": "v1",
+            "metadata": { "name": name, "namespace": "default" }
+        })
+    }
+
+    fn list_handler(
+        kind: &'static str,
+        items: Vec<JsonValue>,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let body = json!({
+            "kind": kind,
+            "apiVersion": "v1",
+            "metadata": {},
+            "items": items
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+
+    fn delete_handler(
+        deleted: Arc<AtomicUsize>,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            deleted.fetch_add(1, Ordering::SeqCst);
+            response(StatusCode::OK, || {
+                json!({ "kind": "Status", "apiVersion": "v1", "status": "Success" }).to_string()
+            })
+        }
+    }
+
+    fn create_handler(
+        created: Arc<Mutex<Vec<JsonValue>>>,
+        status_code: StatusCode,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req: Request<Body>| {
+            let created = created.clone();
+            let fut = req.into_body().concat2().and_then(move |body| {
+                let object: JsonValue = serde_json::from_slice(&body).unwrap();
+                created.lock().unwrap().push(object.clone());
+                if status_code.is_success() {
+                    response(status_code, move || object.to_string())
+                } else {
+                    response(status_code, || {
+                        json!({ "kind": "Status", "apiVersion": "v1", "status": "Failure", "code": 422 })
+                            .to_string()
+                    })
+                }
+            });
+            Box::new(fut) as ResponseFuture
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use futures::future::Either;
+use futures::{future, Future, IntoFuture, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use log::info;
+use serde_json::{json, Value as JsonValue};
+
+use kube_client::{Error as KubeClientError, TokenSource};
+
+use crate::convert::sanitize_dns_value;
+use crate::{Error, KubeModuleRuntime};
+
+/// Brings the annotations on a module's service account in line with
+/// `desired_annotations`. Annotations which are missing or have another value
+/// are patched; annotations which aren't in `desired_annotations` are left
+/// alone, since Kubernetes and other controllers add their own. Nothing is
+/// sent when the service account already has every desired annotation.
+pub fn sync_module_service_account_annotations<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    module_id: &str,
+    desired_annotations: &BTreeMap<String, String>,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    let client_copy = runtime.client().clone();
+    let namespace_copy = runtime.settings().namespace().to_owned();
+    let desired_annotations = desired_annotations.clone();
+
+    sanitize_dns_value(module_id)
+        .map(|name| {
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .get_service_account(runtime.settings().namespace(), &name)
+                .map_err(Error::from)
+                .and_then(move |service_account| {
+                    let current = service_account
+                        .metadata
+                        .and_then(|meta| meta.annotations)
+                        .unwrap_or_default();
+                    let changed: BTreeMap<_, _> = desired_annotations
+                        .into_iter()
+                        .filter(|(key, value)| current.get(key) != Some(value))
+                        .collect();
+
+                    if changed.is_empty() {
+                        Either::A(future::ok(()))
+                    } else {
+                        info!(
+                            "Updating annotations {:?} on service account {}",
+                            changed.keys().collect::<Vec<_>>(),
+                            name
+                        );
+                        let patch: JsonValue = json!({ "metadata": { "annotations": changed } });
+                        let fut = client_copy
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .patch_service_account(namespace_copy.as_str(), &name, &patch)
+                            .map_err(Error::from)
+                            .map(|_| ());
+                        Either::B(fut)
+                    }
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use futures::{Future, Stream};
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use crate::module::sync_module_service_account_annotations;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    const CLIENT_ID_ANNOTATION: &str = "azure.workload.identity/client-id";
+
+    #[test]
+    fn it_patches_changed_annotations() {
+        let patched = Arc::new(AtomicBool::new(false));
+        let patched_copy = patched.clone();
+
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/default/serviceaccounts/edgehub" => service_account("old-id"),
+            PATCH "/api/v1/namespaces/default/serviceaccounts/edgehub" => move |req: Request<Body>| {
+                let patched = patched_copy.clone();
+                let fut = req.into_body().concat2().and_then(move |body| {
+                    let patch: JsonValue = serde_json::from_slice(&body).unwrap();
+                    assert_eq!(
+                        patch,
+                        json!({
+                            "metadata": {
+                                "annotations": {
+                                    CLIENT_ID_ANNOTATION: "new-id",
+                                    "azure.workload.identity/tenant-id": "tenant"
+                                }
+                            }
+                        })
+                    );
+                    patched.store(true, Ordering::SeqCst);
+                    response(StatusCode::OK, move || patch.to_string())
+                });
+                Box::new(fut) as ResponseFuture
+            },
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let desired = btreemap! {
+            CLIENT_ID_ANNOTATION.to_string() => "new-id".to_string(),
+            "azure.workload.identity/tenant-id".to_string() => "tenant".to_string(),
+        };
+        let task = sync_module_service_account_annotations(&runtime, "$edgeHub", &desired);
+        Runtime::new().unwrap().block_on(task).unwrap();
+
+        assert!(patched.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn it_skips_patch_when_annotations_match() {
+        // No PATCH route, so patching would fail the test.
+        let dispatch_table = routes!(
+            GET "/api/v1/namespaces/default/serviceaccounts/edgehub" => service_account("client-id"),
+        );
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(make_settings(None), service_fn(handler));
+
+        let desired = btreemap! {
+            CLIENT_ID_ANNOTATION.to_string() => "client-id".to_string(),
+        };
+        let task = sync_module_service_account_annotations(&runtime, "$edgeHub", &desired);
+        Runtime::new().unwrap().block_on(task).unwrap();
+    }
+
+    fn service_account(client_id: &str) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let body = json!({
+            "kind": "ServiceAccount",
+            "apiVersion": "v1",
+            "metadata": {
+                "name": "edgehub",
+                "namespace": "default",
+                "annotations": {
+                    CLIENT_ID_ANNOTATION: client_id,
+                    "other": "value"
+                }
+            }
+        })
+        .to_string();
+
+        move |_| {
+            let body = body.clone();
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+}
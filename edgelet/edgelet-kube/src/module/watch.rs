@@ -0,0 +1,387 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+
+use failure::Fail;
+use futures::{Async, Future, Poll, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::core::v1 as api_core;
+use log::{debug, warn};
+
+use edgelet_core::Module;
+use kube_client::{Error as KubeClientError, TokenSource, WatchEvent};
+
+use crate::convert::pod_to_module;
+use crate::error::{Error, ErrorKind};
+use crate::module::KubeModule;
+use crate::KubeModuleRuntime;
+
+// status code of a watch whose resource version is too old to resume from
+const GONE: u16 = 410;
+
+/// A change to the modules of the device, as seen on their pods. A module
+/// is added with its first pod and deleted with its last one, and every other
+/// change to its pods modifies it.
+pub enum ModuleEvent {
+    Added(KubeModule),
+    Modified(KubeModule),
+    /// The ID of the deleted module.
+    Deleted(String),
+}
+
+/// Streams the changes to the device's modules. The pods are listed first,
+/// which adds every module, then watched from the list's resource version.
+/// The watch is resumed when the API server closes it, and the pods are
+/// listed again when it can no longer be resumed, which reports the changes
+/// missed in between. Dropping the stream ends the watch.
+pub fn watch_modules<T, S>(runtime: &KubeModuleRuntime<T, S>) -> ModuleWatch<T, S>
+where
+    T: TokenSource + 'static,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    ModuleWatch {
+        runtime: runtime.clone(),
+        state: WatchState::List,
+        pods: HashMap::new(),
+        resource_version: None,
+        pending: VecDeque::new(),
+    }
+}
+
+pub struct ModuleWatch<T, S> {
+    runtime: KubeModuleRuntime<T, S>,
+    state: WatchState,
+    // the ID of the module each known pod belongs to, by pod name
+    pods: HashMap<String, String>,
+    resource_version: Option<String>,
+    pending: VecDeque<ModuleEvent>,
+}
+
+enum WatchState {
+    List,
+    Listing(Box<dyn Future<Item = api_core::PodList, Error = Error>>),
+    Watch,
+    Watching(Box<dyn Stream<Item = WatchEvent<api_core::Pod>, Error = Error>>),
+}
+
+impl<T, S> ModuleWatch<T, S>
+where
+    T: TokenSource + 'static,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    fn list(&self) -> WatchState {
+        let pods = self
+            .runtime
+            .client()
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .list_pods(
+                self.runtime.settings().namespace(),
+                Some(&self.runtime.settings().device_hub_selector()),
+            )
+            .map_err(Error::from);
+        WatchState::Listing(Box::new(pods))
+    }
+
+    fn watch(&self) -> WatchState {
+        let events = self
+            .runtime
+            .client()
+            .lock()
+            .expect("Unexpected lock error")
+            .borrow_mut()
+            .watch_pods(
+                self.runtime.settings().namespace(),
+                Some(&self.runtime.settings().device_hub_selector()),
+                self.resource_version.as_ref().map(String::as_str),
+            )
+            .map_err(Error::from);
+        WatchState::Watching(Box::new(events))
+    }
+
+    // Modules whose pods are all gone since the last list are deleted.
+    fn relisted(&mut self, pods: api_core::PodList) {
+        self.resource_version = pods.metadata.and_then(|meta| meta.resource_version);
+
+        let known: HashSet<String> = self.pods.values().cloned().collect();
+        self.pods.clear();
+        for pod in &pods.items {
+            self.pod_changed(pod, &known);
+        }
+
+        let mut deleted: Vec<_> = known
+            .into_iter()
+            .filter(|module_id| !self.pods.values().any(|id| id == module_id))
+            .collect();
+        deleted.sort();
+        self.pending
+            .extend(deleted.into_iter().map(ModuleEvent::Deleted));
+    }
+
+    fn apply(&mut self, event: WatchEvent<api_core::Pod>) -> Result<(), Error> {
+        match event {
+            WatchEvent::Added(pod) | WatchEvent::Modified(pod) => {
+                self.update_resource_version(&pod);
+                let known = self.pods.values().cloned().collect();
+                self.pod_changed(&pod, &known);
+            }
+            WatchEvent::Deleted(pod) => {
+                self.update_resource_version(&pod);
+                let module_id = pod_name(&pod).and_then(|name| self.pods.remove(name));
+                if let Some(module_id) = module_id {
+                    if !self.pods.values().any(|id| *id == module_id) {
+                        self.pending.push_back(ModuleEvent::Deleted(module_id));
+                    }
+                }
+            }
+            WatchEvent::Error(status) => {
+                return Err(Error::from(status.context(ErrorKind::Kubernetes)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pod_changed(&mut self, pod: &api_core::Pod, known: &HashSet<String>) {
+        let module = match pod_to_module(pod) {
+            Some(Ok(module)) => module,
+            Some(Err(err)) => {
+                warn!("Ignoring a module pod which could not be read: {}", err);
+                return;
+            }
+            None => return,
+        };
+
+        if let Some(name) = pod_name(pod) {
+            self.pods
+                .insert(name.to_string(), module.name().to_string());
+        }
+        let event = if known.contains(module.name()) {
+            ModuleEvent::Modified(module)
+        } else {
+            ModuleEvent::Added(module)
+        };
+        self.pending.push_back(event);
+    }
+
+    fn update_resource_version(&mut self, pod: &api_core::Pod) {
+        if let Some(resource_version) = pod
+            .metadata
+            .as_ref()
+            .and_then(|meta| meta.resource_version.clone())
+        {
+            self.resource_version = Some(resource_version);
+        }
+    }
+}
+
+impl<T, S> Stream for ModuleWatch<T, S>
+where
+    T: TokenSource + 'static,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    type Item = ModuleEvent;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Async::Ready(Some(event)));
+            }
+
+            self.state = match mem::replace(&mut self.state, WatchState::List) {
+                WatchState::List => self.list(),
+                WatchState::Listing(mut pods) => match pods.poll() {
+                    Ok(Async::Ready(pods)) => {
+                        self.relisted(pods);
+                        WatchState::Watch
+                    }
+                    Ok(Async::NotReady) => {
+                        self.state = WatchState::Listing(pods);
+                        return Ok(Async::NotReady);
+                    }
+                    // the next poll lists the pods again
+                    Err(err) => return Err(err),
+                },
+                WatchState::Watch => self.watch(),
+                WatchState::Watching(mut events) => match events.poll() {
+                    Ok(Async::Ready(Some(event))) => match self.apply(event) {
+                        Ok(()) => WatchState::Watching(events),
+                        Err(ref err) if is_gone(err) => {
+                            debug!("Module watch expired, listing module pods again");
+                            WatchState::List
+                        }
+                        Err(err) => {
+                            self.state = WatchState::Watch;
+                            return Err(err);
+                        }
+                    },
+                    // the API server closed the watch after its timeout
+                    Ok(Async::Ready(None)) => WatchState::Watch,
+                    Ok(Async::NotReady) => {
+                        self.state = WatchState::Watching(events);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(ref err) if is_gone(err) => {
+                        debug!("Module watch expired, listing module pods again");
+                        WatchState::List
+                    }
+                    Err(err) => {
+                        self.state = WatchState::Watch;
+                        return Err(err);
+                    }
+                },
+            };
+        }
+    }
+}
+
+fn pod_name(pod: &api_core::Pod) -> Option<&str> {
+    pod.metadata
+        .as_ref()
+        .and_then(|meta| meta.name.as_ref())
+        .map(String::as_str)
+}
+
+fn is_gone(err: &Error) -> bool {
+    Fail::iter_chain(err).any(|cause| {
+        if let Some(status) = cause.downcast_ref::<kube_client::KubeStatus>() {
+            status.code == Some(GONE)
+        } else if let Some(err) = cause.downcast_ref::<KubeClientError>() {
+            err.kube_status()
+                .and_then(|status| status.code)
+                .map_or(false, |code| code == GONE)
+        } else {
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures::Stream;
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::{json, Value as JsonValue};
+    use tokio::runtime::Runtime;
+
+    use edgelet_core::Module;
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use super::ModuleEvent;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn modules_are_watched_and_relisted_when_the_watch_expires() {
+        let settings = make_settings(None);
+        let lists = Arc::new(AtomicUsize::new(0));
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => pods_handler(lists.clone()),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let runtime = create_runtime(settings, service_fn(handler));
+
+        let events = runtime
+            .watch_modules()
+            .take(5)
+            .map(|event| match event {
+                ModuleEvent::Added(module) => format!("added {}", module.name()),
+                ModuleEvent::Modified(module) => format!("modified {}", module.name()),
+                ModuleEvent::Deleted(module_id) => format!("deleted {}", module_id),
+            })
+            .collect();
+        let events = Runtime::new().unwrap().block_on(events).unwrap();
+
+        assert_eq!(
+            vec![
+                "added $edgeHub",
+                "modified $edgeHub",
+                "added tempSensor",
+                "modified tempSensor",
+                "deleted $edgeHub",
+            ],
+            events
+        );
+        assert_eq!(2, lists.load(Ordering::SeqCst));
+    }
+
+    // The first list has edgeHub's pod. The watch then reports a change to it
+    // and a new pod for tempSensor before expiring, and by the next list
+    // edgeHub's pod is gone.
+    fn pods_handler(lists: Arc<AtomicUsize>) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            let query = req.uri().query().unwrap_or_default().to_string();
+            let body = if query.contains("watch=true") {
+                assert!(query.contains("resourceVersion=1"));
+                [
+                    json!({ "type": "MODIFIED", "object": pod("edgehub-1", "edgehub", "$edgeHub", "2") }),
+                    json!({ "type": "ADDED", "object": pod("tempsensor-1", "tempsensor", "tempSensor", "3") }),
+                    json!({
+                        "type": "ERROR",
+                        "object": { "kind": "Status", "status": "Failure", "reason": "Expired", "code": 410 }
+                    }),
+                ]
+                .iter()
+                .map(|event| format!("{}\n", event))
+                .collect::<String>()
+            } else if lists.fetch_add(1, Ordering::SeqCst) == 0 {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "metadata": { "resourceVersion": "1" },
+                    "items": [pod("edgehub-1", "edgehub", "$edgeHub", "1")]
+                })
+                .to_string()
+            } else {
+                json!({
+                    "kind": "PodList",
+                    "apiVersion": "v1",
+                    "metadata": { "resourceVersion": "5" },
+                    "items": [pod("tempsensor-1", "tempsensor", "tempSensor", "4")]
+                })
+                .to_string()
+            };
+
+            response(StatusCode::OK, move || body.clone())
+        }
+    }
+
+    fn pod(name: &str, module: &str, module_id: &str, resource_version: &str) -> JsonValue {
+        json!({
+            "kind": "Pod",
+            "apiVersion": "v1",
+            "metadata": {
+                "name": name,
+                "resourceVersion": resource_version,
+                "labels": { "net.azure-devices.edge.module": module },
+                "annotations": { "net.azure-devices.edge.original-moduleid": module_id }
+            },
+            "spec": {
+                "containers": [{ "name": module, "image": format!("{}:1.0", module) }]
+            }
+        })
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -12,9 +13,10 @@ use hyper::client::HttpConnector;
 use hyper::service::Service;
 use hyper::{Body, Chunk as HyperChunk, Request};
 use hyper_tls::HttpsConnector;
+use log::{info, warn};
 
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, LogOptions, MakeModuleRuntime, ModuleRegistry,
+    AuthId, Authenticator, GetTrustBundle, LogOptions, MakeModuleRuntime, Module, ModuleRegistry,
     ModuleRuntime, ModuleRuntimeState, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
     RuntimeOperation, SystemInfo,
 };
@@ -24,9 +26,19 @@ use kube_client::{
 };
 use provisioning::ProvisioningResult;
 
-use crate::convert::{auth_to_image_pull_secret, pod_to_module};
+use crate::cluster::detect_cluster_type;
+use crate::constants::{EDGE_MODULE_LABEL, POD_MONITOR};
+use crate::convert::{
+    auth_to_image_pull_secret, deployment_to_status, pod_to_module, sanitize_dns_value,
+};
 use crate::error::{Error, ErrorKind};
-use crate::module::{authenticate, create_module, init_trust_bundle, KubeModule};
+use crate::events::EventRecorder;
+use crate::key_vault::AzureKeyVaultSecretSync;
+use crate::module::{
+    apply_admission_policies, authenticate, bootstrap_rbac, create_headless_service, create_module,
+    init_trust_bundle, remove_module, rename_module, run_config_map_mirroring,
+    run_deadline_enforcement, set_module_ready, watch_modules, KubeModule, ModuleWatch,
+};
 use crate::settings::Settings;
 
 pub struct KubeModuleRuntime<T, S> {
@@ -49,6 +61,65 @@ impl<T, S> KubeModuleRuntime<T, S> {
     pub(crate) fn settings(&self) -> &Settings {
         &self.settings
     }
+
+    // Selects the pods of one module on this device.
+    pub(crate) fn module_selector(&self, name: &str) -> String {
+        match self.settings().device_hub_selector() {
+            "" => format!("{}={}", EDGE_MODULE_LABEL, name),
+            device_hub_selector => {
+                format!("{}={},{}", EDGE_MODULE_LABEL, name, device_hub_selector)
+            }
+        }
+    }
+}
+
+impl<T, S> KubeModuleRuntime<T, S>
+where
+    T: TokenSource,
+    S: Service + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Into<KubeClientError>,
+{
+    /// Reports whether a module passes one of its readiness gates by setting
+    /// the gate's condition on the module's pods.
+    pub fn set_module_ready(
+        &self,
+        module_id: &str,
+        condition_type: &str,
+        ready: bool,
+    ) -> impl Future<Item = (), Error = Error> {
+        set_module_ready(self, module_id, condition_type, ready)
+    }
+
+    /// Moves a module's Deployment and Service over to a new module ID,
+    /// restoring them if the move fails part way.
+    pub fn rename_module(
+        &self,
+        old_id: &str,
+        new_id: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        rename_module(self, old_id, new_id)
+    }
+
+    /// Creates or updates the headless Service through which `$edgeHub`
+    /// streams to a module over gRPC.
+    pub fn create_headless_service(
+        &self,
+        module_id: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        create_headless_service(self, module_id)
+    }
+
+    /// Streams changes to the device's modules from a watch on their pods,
+    /// instead of listing them every time.
+    pub fn watch_modules(&self) -> ModuleWatch<T, S>
+    where
+        T: 'static,
+    {
+        watch_modules(self)
+    }
 }
 
 // NOTE:
@@ -173,7 +244,72 @@ impl MakeModuleRuntime
             .map_err(Error::from)
             .map(|runtime| init_trust_bundle(&runtime, &crypto).map(|_| runtime))
             .into_future()
-            .flatten();
+            .flatten()
+            .and_then(|runtime| {
+                // the policy only guards against pods created outside iotedged,
+                // so the runtime carries on without it on clusters which lack
+                // the admission policy API or don't let us apply it
+                if runtime.settings().apply_admission_policies() {
+                    Either::A(apply_admission_policies(&runtime).then(|result| {
+                        if let Err(err) = result {
+                            warn!("Could not apply module pod admission policy: {}", err);
+                        }
+                        Ok(runtime)
+                    }))
+                } else {
+                    Either::B(future::ok(runtime))
+                }
+            })
+            .and_then(|runtime| {
+                let dry_run = !runtime.settings().bootstrap_rbac();
+                bootstrap_rbac(&runtime, dry_run).map(|_| runtime)
+            })
+            .and_then(|runtime| {
+                detect_cluster_type(&runtime).then(|cluster_type| {
+                    let cluster_type = cluster_type.unwrap_or_default();
+                    info!("Detected {} cluster", cluster_type);
+                    Ok(runtime)
+                })
+            })
+            .and_then(move |runtime| {
+                EventRecorder::new(runtime.clone())
+                    .record_provisioning(&provisioning_result)
+                    .then(|result| {
+                        if let Err(err) = result {
+                            warn!("Could not record provisioning event: {}", err);
+                        }
+                        Ok(runtime)
+                    })
+            })
+            .map(|runtime| {
+                tokio::spawn(
+                    run_deadline_enforcement(runtime.clone()).map_err(|err| {
+                        warn!("Module active deadline enforcement stopped: {}", err)
+                    }),
+                );
+                if runtime.settings().key_vault().is_some() {
+                    match AzureKeyVaultSecretSync::new(runtime.clone()) {
+                        Ok(sync) => {
+                            tokio::spawn(sync.run().map_err(|err| {
+                                warn!("Azure Key Vault secret sync stopped: {}", err)
+                            }));
+                        }
+                        Err(err) => warn!("Could not start Azure Key Vault secret sync: {}", err),
+                    }
+                }
+                if let Some(source_namespace) = runtime.settings().proxy_config_map_namespace() {
+                    tokio::spawn(
+                        run_config_map_mirroring(
+                            runtime.clone(),
+                            source_namespace.to_string(),
+                            runtime.settings().proxy_config_map_name().to_string(),
+                            runtime.settings().namespace().to_string(),
+                        )
+                        .map_err(|err| warn!("Proxy config map mirroring stopped: {}", err)),
+                    );
+                }
+                runtime
+            });
 
         Box::new(fut)
     }
@@ -209,13 +345,67 @@ where
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type SystemInfoFuture = Box<dyn Future<Item = SystemInfo, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StatsFuture = Box<dyn Future<Item = serde_json::Value, Error = Self::Error> + Send>;
+    type ClearLogsFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         Box::new(create_module(self, &module))
     }
 
-    fn get(&self, _id: &str) -> Self::GetFuture {
-        unimplemented!()
+    // Modules are looked up by their pod, and their state comes from the
+    // rollout status of their Deployment.
+    fn get(&self, id: &str) -> Self::GetFuture {
+        let client_copy = self.client.clone();
+        let namespace_copy = self.settings().namespace().to_owned();
+        let id_copy = id.to_owned();
+
+        let fut = sanitize_dns_value(id)
+            .map(|name| {
+                self.client
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .list_pods(
+                        self.settings().namespace(),
+                        Some(&self.module_selector(&name)),
+                    )
+                    .map_err(Error::from)
+                    .and_then(move |pods| {
+                        pods.items
+                            .iter()
+                            .filter_map(pod_to_module)
+                            .collect::<Result<Vec<_>, _>>()
+                            .and_then(|modules| {
+                                without_replaced_pods(modules)
+                                    .into_iter()
+                                    .next()
+                                    .ok_or_else(|| {
+                                        Error::from(ErrorKind::NotFound(format!(
+                                            "Module {} not found",
+                                            id_copy
+                                        )))
+                                    })
+                            })
+                    })
+                    .and_then(move |module| {
+                        client_copy
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .list_deployments(namespace_copy.as_str(), Some(&name), None)
+                            .map_err(Error::from)
+                            .map(|deployments| {
+                                let deployment_status =
+                                    deployments.items.first().and_then(deployment_to_status);
+                                module.with_deployment_status(deployment_status)
+                            })
+                    })
+                    .and_then(|module| module.runtime_state().map(|state| (module, state)))
+            })
+            .into_future()
+            .flatten();
+
+        Box::new(fut)
     }
 
     fn start(&self, _id: &str) -> Self::StartFuture {
@@ -226,12 +416,75 @@ where
         Box::new(future::ok(()))
     }
 
-    fn restart(&self, _id: &str) -> Self::RestartFuture {
-        Box::new(future::ok(()))
+    // The module's Deployment replaces its pods as soon as they are deleted,
+    // which restarts every container in them.
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        let client_copy = self.client.clone();
+        let namespace_copy = self.settings().namespace().to_owned();
+
+        let fut = sanitize_dns_value(id)
+            .map(|name| {
+                self.client
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .borrow_mut()
+                    .list_pods(
+                        self.settings().namespace(),
+                        Some(&self.module_selector(&name)),
+                    )
+                    .map_err(Error::from)
+                    .and_then(move |pods| {
+                        let deletes: Vec<_> = pods
+                            .items
+                            .into_iter()
+                            .filter_map(|pod| pod.metadata.and_then(|meta| meta.name))
+                            .map(|pod_name| {
+                                client_copy
+                                    .lock()
+                                    .expect("Unexpected lock error")
+                                    .borrow_mut()
+                                    .delete_pod(namespace_copy.as_str(), &pod_name)
+                                    .map_err(Error::from)
+                            })
+                            .collect();
+
+                        future::join_all(deletes).map(|_| ())
+                    })
+            })
+            .into_future()
+            .flatten();
+
+        Box::new(fut)
     }
 
-    fn remove(&self, _id: &str) -> Self::RemoveFuture {
-        Box::new(future::ok(()))
+    // Removing a module with persistent volume claims deletes them, so when
+    // deletion confirmation is required that has to be forced.
+    fn remove(&self, id: &str, force: bool) -> Self::RemoveFuture {
+        let client_copy = self.client.clone();
+        let namespace_copy = self.settings().namespace().to_owned();
+        let metrics = self.settings().metrics();
+        let id_copy = id.to_string();
+
+        let fut = remove_module(self, id, force).and_then(move |_| {
+            if !metrics {
+                return Either::A(future::ok(()));
+            }
+
+            let fut = sanitize_dns_value(&id_copy)
+                .map(|name| {
+                    client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .delete_custom_object(POD_MONITOR, &namespace_copy, &name)
+                        .map_err(Error::from)
+                })
+                .into_future()
+                .flatten();
+            Either::B(fut)
+        });
+
+        Box::new(fut)
     }
 
     fn system_info(&self) -> Self::SystemInfoFuture {
@@ -263,6 +516,7 @@ where
                             modules
                         })
                     })
+                    .map(without_replaced_pods)
                     .into_future()
             });
 
@@ -284,6 +538,42 @@ where
     fn remove_all(&self) -> Self::RemoveAllFuture {
         Box::new(future::ok(()))
     }
+
+    // Pod usage is only available from the metrics API, which not every
+    // cluster serves.
+    fn stats(&self, id: &str) -> Self::StatsFuture {
+        Box::new(future::err(Error::from(ErrorKind::RuntimeOperation(
+            RuntimeOperation::GetModuleStats(id.to_string()),
+        ))))
+    }
+
+    // The kubelet rotates container logs itself and offers no way to empty
+    // them.
+    fn clear_logs(&self, id: &str) -> Self::ClearLogsFuture {
+        Box::new(future::err(Error::from(ErrorKind::RuntimeOperation(
+            RuntimeOperation::ClearModuleLogs(id.to_string()),
+        ))))
+    }
+}
+
+// A pod deleted at its active deadline lingers while its containers stop,
+// after the module's Deployment has started another in its place, and it's
+// the new pod that is reported.
+fn without_replaced_pods(modules: Vec<KubeModule>) -> Vec<KubeModule> {
+    let live: HashSet<String> = modules
+        .iter()
+        .filter(|module| !module.deadline_exceeded())
+        .map(|module| module.name().to_string())
+        .collect();
+    let mut stopped = HashSet::new();
+
+    modules
+        .into_iter()
+        .filter(|module| {
+            !module.deadline_exceeded()
+                || (!live.contains(module.name()) && stopped.insert(module.name().to_string()))
+        })
+        .collect()
 }
 
 impl<T, S> Authenticator for KubeModuleRuntime<T, S>
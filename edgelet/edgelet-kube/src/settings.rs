@@ -1,6 +1,10 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::BTreeMap;
+use std::env;
+use std::net::IpAddr;
 use std::path::Path;
+use std::time::Duration;
 
 use config::{Config, Environment};
 use edgelet_core::{
@@ -9,8 +13,13 @@ use edgelet_core::{
 };
 use edgelet_docker::{DockerConfig, DEFAULTS};
 use edgelet_utils::YamlFileSource;
+use url::Url;
 
-use crate::error::Error;
+use crate::constants::{
+    AZURE_AUTHORITY_HOST, AZURE_INSTANCE_METADATA_TOKEN_ENDPOINT, DEFAULT_CLUSTER_DOMAIN,
+    DEFAULT_EDGE_HUB_DRAIN_GRACE_PERIOD_SECS, DEFAULT_REKOR_URL, PROXY_ENV_KEYS,
+};
+use crate::error::{Error, ErrorKind};
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
 pub struct Settings {
@@ -23,14 +32,61 @@ pub struct Settings {
     proxy_image: String,
     proxy_config_path: String,
     proxy_config_map_name: String,
+    proxy_config_map_namespace: Option<String>,
     proxy_trust_bundle_path: String,
     proxy_trust_bundle_config_map_name: String,
-    image_pull_policy: String,
+    image_pull_policy: ImagePullPolicy,
     service_account_name: String,
     device_hub_selector: String,
+    #[serde(default)]
+    metrics: bool,
+    #[serde(default)]
+    modules: BTreeMap<String, ModuleSettings>,
+    #[serde(default)]
+    read_vpa_recommendations: bool,
+    log_max_size: Option<String>,
+    log_max_files: Option<u32>,
+    #[serde(default)]
+    apply_admission_policies: bool,
+    #[serde(default)]
+    bootstrap_rbac: bool,
+    #[serde(skip)]
+    proxy_env: BTreeMap<String, String>,
+    key_vault: Option<KeyVaultSettings>,
+    image_verification: Option<ImageVerificationPolicy>,
+    #[serde(default)]
+    secure_defaults: bool,
+    os: Option<KubeOS>,
+    #[serde(default = "Settings::default_cluster_domain")]
+    cluster_domain: String,
+    #[serde(default)]
+    require_deletion_confirmation: bool,
+    #[serde(default)]
+    inject_trust_bundle_to_os_store: bool,
+    node_name: Option<String>,
+    #[serde(default)]
+    propagate_node_labels: Vec<String>,
+    #[serde(default)]
+    node_selector: BTreeMap<String, String>,
+    #[serde(default)]
+    tolerations: Vec<Toleration>,
+    #[serde(default = "Settings::default_edge_hub_drain_grace_period_seconds")]
+    edge_hub_drain_grace_period_seconds: u64,
+    #[serde(default)]
+    image_pull_secrets: Vec<String>,
+    #[serde(default)]
+    host_aliases: Vec<HostAlias>,
 }
 
 impl Settings {
+    fn default_cluster_domain() -> String {
+        DEFAULT_CLUSTER_DOMAIN.to_string()
+    }
+
+    fn default_edge_hub_drain_grace_period_seconds() -> u64 {
+        DEFAULT_EDGE_HUB_DRAIN_GRACE_PERIOD_SECS
+    }
+
     pub fn new(filename: Option<&Path>) -> Result<Self, Error> {
         let filename = filename.map(|filename| {
             filename.to_str().unwrap_or_else(|| {
@@ -48,7 +104,86 @@ impl Settings {
 
         config.merge(Environment::with_prefix("iotedge"))?;
 
-        Ok(config.try_into()?)
+        let settings: Self = config.try_into()?;
+        settings.validate()?;
+        Ok(settings.with_proxy_env(proxy_env_from_process()))
+    }
+
+    /// Checks the values Kubernetes or the proxy would only reject once the
+    /// runtime has started creating resources with them.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !is_valid_dns_label(&self.namespace) {
+            return Err(ErrorKind::InvalidConfiguration(format!(
+                "namespace {:?} is not a valid DNS label",
+                self.namespace
+            ))
+            .into());
+        }
+        if !is_valid_image_reference(&self.proxy_image) {
+            return Err(ErrorKind::InvalidConfiguration(format!(
+                "proxy_image {:?} is not a valid image reference",
+                self.proxy_image
+            ))
+            .into());
+        }
+        if self.device_id.as_ref().map_or(false, String::is_empty) {
+            return Err(
+                ErrorKind::InvalidConfiguration("device_id must not be empty".to_string()).into(),
+            );
+        }
+
+        for alias in &self.host_aliases {
+            if alias.ip.parse::<IpAddr>().is_err() {
+                return Err(ErrorKind::InvalidConfiguration(format!(
+                    "host_aliases ip {:?} is not a valid IP address",
+                    alias.ip
+                ))
+                .into());
+            }
+        }
+
+        for (module, module_settings) in &self.modules {
+            for volume in module_settings.empty_dir_volumes() {
+                if !is_absolute_container_path(volume.mount_path()) {
+                    return Err(ErrorKind::InvalidConfiguration(format!(
+                        "mount_path {:?} of empty_dir volume {} of module {} is not an absolute path",
+                        volume.mount_path(),
+                        volume.name(),
+                        module
+                    ))
+                    .into());
+                }
+            }
+        }
+
+        check_uri("connect.management_uri", self.connect().management_uri())?;
+        check_uri("connect.workload_uri", self.connect().workload_uri())?;
+        check_uri("listen.management_uri", self.listen().management_uri())?;
+        check_uri("listen.workload_uri", self.listen().workload_uri())?;
+
+        // These are paths in the proxy container, which always runs Linux,
+        // so they're checked as such whatever the host is.
+        for (setting, path) in &[
+            ("proxy_config_path", &self.proxy_config_path),
+            ("proxy_trust_bundle_path", &self.proxy_trust_bundle_path),
+        ] {
+            if !path.starts_with('/') {
+                return Err(ErrorKind::InvalidConfiguration(format!(
+                    "{} {:?} is not an absolute path",
+                    setting, path
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the proxy environment variables which are passed on to module
+    /// containers.
+    pub fn with_proxy_env(mut self, proxy_env: BTreeMap<String, String>) -> Self {
+        self.proxy_env = proxy_env;
+        self
     }
 
     pub fn with_device_id(mut self, device_id: &str) -> Self {
@@ -89,6 +224,12 @@ impl Settings {
         &self.proxy_config_map_name
     }
 
+    /// The namespace the proxy config map is maintained in, when it is shared
+    /// between devices. It is mirrored into this device's namespace.
+    pub fn proxy_config_map_namespace(&self) -> Option<&str> {
+        self.proxy_config_map_namespace.as_ref().map(String::as_str)
+    }
+
     pub fn proxy_trust_bundle_path(&self) -> &str {
         &self.proxy_trust_bundle_path
     }
@@ -97,8 +238,8 @@ impl Settings {
         &self.proxy_trust_bundle_config_map_name
     }
 
-    pub fn image_pull_policy(&self) -> &str {
-        &self.image_pull_policy
+    pub fn image_pull_policy(&self) -> ImagePullPolicy {
+        self.image_pull_policy
     }
 
     pub fn service_account_name(&self) -> &str {
@@ -108,6 +249,987 @@ impl Settings {
     pub fn device_hub_selector(&self) -> &str {
         &self.device_hub_selector
     }
+
+    pub fn metrics(&self) -> bool {
+        self.metrics
+    }
+
+    pub fn read_vpa_recommendations(&self) -> bool {
+        self.read_vpa_recommendations
+    }
+
+    pub fn log_max_size(&self) -> Option<&str> {
+        self.log_max_size.as_ref().map(String::as_str)
+    }
+
+    pub fn log_max_files(&self) -> Option<u32> {
+        self.log_max_files
+    }
+
+    pub fn apply_admission_policies(&self) -> bool {
+        self.apply_admission_policies
+    }
+
+    /// Whether the runtime applies the Role and Role Binding its service
+    /// account needs on startup. Otherwise they are only logged.
+    pub fn bootstrap_rbac(&self) -> bool {
+        self.bootstrap_rbac
+    }
+
+    pub fn proxy_env(&self) -> &BTreeMap<String, String> {
+        &self.proxy_env
+    }
+
+    pub fn key_vault(&self) -> Option<&KeyVaultSettings> {
+        self.key_vault.as_ref()
+    }
+
+    pub fn image_verification(&self) -> Option<&ImageVerificationPolicy> {
+        self.image_verification.as_ref()
+    }
+
+    /// The DNS domain of the cluster, `cluster.local` unless the cluster was
+    /// set up with another.
+    pub fn cluster_domain(&self) -> &str {
+        &self.cluster_domain
+    }
+
+    /// Whether removing a module which has persistent volume claims, and so
+    /// deleting its persistent state, has to be forced.
+    pub fn require_deletion_confirmation(&self) -> bool {
+        self.require_deletion_confirmation
+    }
+
+    /// Whether module pods copy the trust bundle into the OS trust store
+    /// directory with an init container, for modules which only trust
+    /// certificates from there.
+    pub fn inject_trust_bundle_to_os_store(&self) -> bool {
+        self.inject_trust_bundle_to_os_store
+    }
+
+    /// The name of the node iotedged runs on, usually passed in the
+    /// `IOTEDGE_NODE_NAME` variable from the pod's `spec.nodeName`.
+    pub fn node_name(&self) -> Option<&str> {
+        self.node_name.as_ref().map(String::as_str)
+    }
+
+    /// Keys of the labels of iotedged's node which are copied to module pods
+    /// as annotations when they are created.
+    pub fn propagate_node_labels(&self) -> &[String] {
+        &self.propagate_node_labels
+    }
+
+    /// Node labels every module's pod requires of the node it runs on, e.g.
+    /// to keep modules to a node pool.
+    pub fn node_selector(&self) -> &BTreeMap<String, String> {
+        &self.node_selector
+    }
+
+    /// Taints every module's pod tolerates, so modules can be scheduled on
+    /// dedicated nodes.
+    pub fn tolerations(&self) -> &[Toleration] {
+        &self.tolerations
+    }
+
+    /// How long the edgeHub pod is given to flush its pending messages when
+    /// it is deleted, before it is killed.
+    pub fn edge_hub_drain_grace_period_seconds(&self) -> u64 {
+        self.edge_hub_drain_grace_period_seconds
+    }
+
+    /// Names of existing `docker-registry` Secrets every module's pod pulls
+    /// its images with.
+    pub fn image_pull_secrets(&self) -> &[String] {
+        &self.image_pull_secrets
+    }
+
+    /// Entries added to the hosts file of every module's pod, for hostnames
+    /// modules use which no DNS server resolves.
+    pub fn host_aliases(&self) -> &[HostAlias] {
+        &self.host_aliases
+    }
+
+    /// The fully-qualified DNS name of a Service in the device's namespace.
+    pub fn service_dns_name(&self, service_name: &str) -> String {
+        format!(
+            "{}.{}.svc.{}",
+            service_name, self.namespace, self.cluster_domain
+        )
+    }
+
+    /// Looks up the settings for a module by its Kubernetes name, i.e. the
+    /// sanitized, lower-case form of the module name.
+    pub fn module(&self, name: &str) -> Option<&ModuleSettings> {
+        self.modules.get(name)
+    }
+
+    /// When set, modules without their own security configuration run with
+    /// restrictive defaults, such as the container runtime's seccomp profile.
+    pub fn secure_defaults(&self) -> bool {
+        self.secure_defaults
+    }
+
+    /// The operating system of the nodes modules are scheduled on, unless a
+    /// module's own settings choose another.
+    pub fn os(&self) -> Option<KubeOS> {
+        self.os
+    }
+
+    /// The operating system the named module runs on, from the module's
+    /// settings if given and otherwise from the device-wide setting.
+    pub fn module_os(&self, name: &str) -> Option<KubeOS> {
+        self.module(name).and_then(ModuleSettings::os).or(self.os)
+    }
+}
+
+// Reads the proxy variables iotedged was started with, accepting either case.
+fn proxy_env_from_process() -> BTreeMap<String, String> {
+    PROXY_ENV_KEYS
+        .iter()
+        .filter_map(|key| {
+            env::var(key)
+                .or_else(|_| env::var(key.to_lowercase()))
+                .ok()
+                .map(|value| ((*key).to_string(), value))
+        })
+        .collect()
+}
+
+// Namespaces must be RFC 1123 labels: at most 63 lower case alphanumeric
+// characters or '-', starting and ending with an alphanumeric character.
+fn is_valid_dns_label(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+// Follows the grammar of Docker image references,
+// [registry[:port]/]path[:tag][@digest], where the registry is only told
+// apart from the first path component by containing a '.' or ':', or being
+// localhost.
+fn is_valid_image_reference(image: &str) -> bool {
+    let mut parts = image.splitn(2, '@');
+    let name = parts.next().unwrap_or_default();
+    if !parts.next().map_or(true, is_valid_digest) {
+        return false;
+    }
+
+    let (name, tag) = match name.rfind(':') {
+        Some(index) if !name[index..].contains('/') => (&name[..index], Some(&name[index + 1..])),
+        _ => (name, None),
+    };
+    if !tag.map_or(true, is_valid_tag) {
+        return false;
+    }
+
+    let mut components: Vec<&str> = name.split('/').collect();
+    if components.len() > 1 {
+        let first = components[0];
+        if first.contains('.') || first.contains(':') || first == "localhost" {
+            if !is_valid_registry(first) {
+                return false;
+            }
+            components.remove(0);
+        }
+    }
+    components.into_iter().all(is_valid_path_component)
+}
+
+fn is_valid_registry(registry: &str) -> bool {
+    let mut parts = registry.splitn(2, ':');
+    let host = parts.next().unwrap_or_default();
+    let valid_port = parts.next().map_or(true, |port| {
+        !port.is_empty() && port.chars().all(|c| c.is_ascii_digit())
+    });
+
+    valid_port
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+}
+
+// Lower case alphanumeric runs, separated by a '.', one or two '_', or any
+// number of '-'.
+fn is_valid_path_component(component: &str) -> bool {
+    let mut separator = String::new();
+    for c in component.chars() {
+        if c.is_ascii_lowercase() || c.is_ascii_digit() {
+            let valid_separator = separator.is_empty()
+                || separator == "."
+                || separator == "_"
+                || separator == "__"
+                || separator.chars().all(|c| c == '-');
+            if !valid_separator {
+                return false;
+            }
+            separator.clear();
+        } else if c == '.' || c == '_' || c == '-' {
+            separator.push(c);
+        } else {
+            return false;
+        }
+    }
+
+    component
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && separator.is_empty()
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 128
+        && !tag.starts_with('.')
+        && !tag.starts_with('-')
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+fn is_valid_digest(digest: &str) -> bool {
+    let mut parts = digest.splitn(2, ':');
+    let algorithm = parts.next().unwrap_or_default();
+    let encoded = parts.next().unwrap_or_default();
+
+    !algorithm.is_empty()
+        && algorithm
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '.' || c == '_' || c == '-')
+        && encoded.len() >= 32
+        && encoded.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// iotedged listens on and connects to HTTP(S) addresses, Unix sockets, and
+// when socket activated, file descriptors.
+// A path in a module's container, which is a Linux path or, for Windows
+// modules, one starting with a drive letter.
+fn is_absolute_container_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || (bytes.len() >= 3
+            && bytes[0].is_ascii_alphabetic()
+            && bytes[1] == b':'
+            && (bytes[2] == b'\\' || bytes[2] == b'/'))
+}
+
+fn check_uri(setting: &str, uri: &Url) -> Result<(), Error> {
+    let valid = match uri.scheme() {
+        "http" | "https" | "fd" => uri.host_str().map_or(false, |host| !host.is_empty()),
+        "unix" => uri.path() != "" && uri.path() != "/",
+        _ => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ErrorKind::InvalidConfiguration(format!(
+            "{} {:?} is not an http, https, unix or fd URI",
+            setting,
+            uri.as_str()
+        ))
+        .into())
+    }
+}
+
+/// Azure Key Vault secrets to copy into Kubernetes Secrets in the runtime's
+/// namespace. The vault is accessed with the node's managed identity, unless
+/// `workload_identity` is set.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct KeyVaultSettings {
+    #[serde(with = "url_serde")]
+    vault_uri: Url,
+    #[serde(default)]
+    secrets: Vec<String>,
+    #[serde(default = "KeyVaultSettings::default_resync_interval_secs")]
+    resync_interval_secs: u64,
+    #[serde(
+        default = "KeyVaultSettings::default_identity_endpoint",
+        with = "url_serde"
+    )]
+    identity_endpoint: Url,
+    workload_identity: Option<WorkloadIdentity>,
+}
+
+impl KeyVaultSettings {
+    fn default_resync_interval_secs() -> u64 {
+        300
+    }
+
+    fn default_identity_endpoint() -> Url {
+        Url::parse(AZURE_INSTANCE_METADATA_TOKEN_ENDPOINT)
+            .expect("Instance metadata endpoint is a valid URL")
+    }
+
+    pub fn vault_uri(&self) -> &Url {
+        &self.vault_uri
+    }
+
+    pub fn secrets(&self) -> &[String] {
+        &self.secrets
+    }
+
+    pub fn resync_interval(&self) -> Duration {
+        Duration::from_secs(self.resync_interval_secs)
+    }
+
+    pub fn identity_endpoint(&self) -> &Url {
+        &self.identity_endpoint
+    }
+
+    pub fn workload_identity(&self) -> Option<&WorkloadIdentity> {
+        self.workload_identity.as_ref()
+    }
+}
+
+/// An Azure AD app federated with the runtime's service account. Tokens of the
+/// service account bound to the Azure AD token exchange audience are exchanged
+/// for access tokens of the app.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct WorkloadIdentity {
+    tenant_id: String,
+    client_id: String,
+    #[serde(
+        default = "WorkloadIdentity::default_authority_host",
+        with = "url_serde"
+    )]
+    authority_host: Url,
+}
+
+impl WorkloadIdentity {
+    fn default_authority_host() -> Url {
+        Url::parse(AZURE_AUTHORITY_HOST).expect("Azure authority host is a valid URL")
+    }
+
+    pub fn tenant_id(&self) -> &str {
+        &self.tenant_id
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn authority_host(&self) -> &Url {
+        &self.authority_host
+    }
+}
+
+/// Module images must have a signature recorded in this Sigstore transparency
+/// log, made with `public_key` or by a certificate issued to
+/// `certificate_identity`, before their pods are created.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ImageVerificationPolicy {
+    #[serde(
+        default = "ImageVerificationPolicy::default_rekor_url",
+        with = "url_serde"
+    )]
+    rekor_url: Url,
+    public_key: Option<String>,
+    certificate_identity: Option<String>,
+}
+
+impl ImageVerificationPolicy {
+    fn default_rekor_url() -> Url {
+        Url::parse(DEFAULT_REKOR_URL).expect("Default Rekor URL is a valid URL")
+    }
+
+    pub fn new(rekor_url: Url) -> Self {
+        ImageVerificationPolicy {
+            rekor_url,
+            public_key: None,
+            certificate_identity: None,
+        }
+    }
+
+    pub fn with_public_key(mut self, public_key: String) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    pub fn with_certificate_identity(mut self, certificate_identity: String) -> Self {
+        self.certificate_identity = Some(certificate_identity);
+        self
+    }
+
+    pub fn rekor_url(&self) -> &Url {
+        &self.rekor_url
+    }
+
+    pub fn public_key(&self) -> Option<&str> {
+        self.public_key.as_ref().map(String::as_str)
+    }
+
+    pub fn certificate_identity(&self) -> Option<&str> {
+        self.certificate_identity.as_ref().map(String::as_str)
+    }
+}
+
+/// Kubernetes specific configuration for a single module.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ModuleSettings {
+    tls_certificate: Option<TlsCertificate>,
+    #[serde(default)]
+    csi_volumes: Vec<CsiVolumeSpec>,
+    #[serde(default)]
+    empty_dir_volumes: Vec<EmptyDirVolume>,
+    #[serde(default)]
+    export_service: bool,
+    seccomp_profile: Option<SeccompProfile>,
+    apparmor_profile: Option<String>,
+    #[serde(default)]
+    capabilities_add: Vec<String>,
+    #[serde(default)]
+    capabilities_drop: Vec<String>,
+    grpc_probe: Option<GrpcProbe>,
+    probes: Option<ProbeConfig>,
+    #[serde(default)]
+    share_process_namespace: bool,
+    automount_service_account_token: Option<bool>,
+    os: Option<KubeOS>,
+    #[serde(default)]
+    readiness_gates: Vec<String>,
+    #[serde(default)]
+    service_ip_type: KubeServiceIPType,
+    #[serde(default)]
+    service_account_annotations: BTreeMap<String, String>,
+    hostname: Option<String>,
+    subdomain: Option<String>,
+    safe_to_evict: Option<bool>,
+    resources: Option<ResourceRequirements>,
+    #[serde(default)]
+    grpc_target: bool,
+    #[serde(default)]
+    env_from_config_map: Vec<String>,
+    #[serde(default)]
+    env_from_secret: Vec<String>,
+    active_deadline_seconds: Option<u64>,
+}
+
+impl ModuleSettings {
+    pub fn tls_certificate(&self) -> Option<&TlsCertificate> {
+        self.tls_certificate.as_ref()
+    }
+
+    pub fn csi_volumes(&self) -> &[CsiVolumeSpec] {
+        &self.csi_volumes
+    }
+
+    /// Scratch space mounted into the module's container, which outlives
+    /// restarts of the container but not the pod.
+    pub fn empty_dir_volumes(&self) -> &[EmptyDirVolume] {
+        &self.empty_dir_volumes
+    }
+
+    pub fn export_service(&self) -> bool {
+        self.export_service
+    }
+
+    pub fn seccomp_profile(&self) -> Option<&SeccompProfile> {
+        self.seccomp_profile.as_ref()
+    }
+
+    /// The AppArmor profile to confine the module's container with, either
+    /// `runtime/default` or `localhost/<profile name>`.
+    pub fn apparmor_profile(&self) -> Option<&str> {
+        self.apparmor_profile.as_ref().map(String::as_str)
+    }
+
+    /// Linux capabilities granted to the module's container.
+    pub fn capabilities_add(&self) -> &[String] {
+        &self.capabilities_add
+    }
+
+    /// Linux capabilities removed from the module's container, such as `ALL`.
+    pub fn capabilities_drop(&self) -> &[String] {
+        &self.capabilities_drop
+    }
+
+    pub fn grpc_probe(&self) -> Option<&GrpcProbe> {
+        self.grpc_probe.as_ref()
+    }
+
+    /// Liveness and readiness checks of the module's container. A liveness
+    /// probe given here is used instead of `grpc_probe`.
+    pub fn probes(&self) -> Option<&ProbeConfig> {
+        self.probes.as_ref()
+    }
+
+    /// Whether the containers of the module's pod, including the proxy,
+    /// share a PID namespace so debugging tools can attach across them.
+    pub fn share_process_namespace(&self) -> bool {
+        self.share_process_namespace
+    }
+
+    /// Whether the module's service account token is mounted into its pod.
+    /// When unset the service account's own setting applies.
+    pub fn automount_service_account_token(&self) -> Option<bool> {
+        self.automount_service_account_token
+    }
+
+    pub fn os(&self) -> Option<KubeOS> {
+        self.os
+    }
+
+    /// Pod condition types the module sets itself to signal it is ready.
+    pub fn readiness_gates(&self) -> &[String] {
+        &self.readiness_gates
+    }
+
+    pub fn service_ip_type(&self) -> KubeServiceIPType {
+        self.service_ip_type
+    }
+
+    /// Annotations kept on the module's service account, such as the client
+    /// ID Azure Workload Identity exchanges the account's token for.
+    pub fn service_account_annotations(&self) -> &BTreeMap<String, String> {
+        &self.service_account_annotations
+    }
+
+    /// The hostname of the module's pod, instead of the generated pod name.
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_ref().map(String::as_str)
+    }
+
+    /// When a headless Service with this name exists in the namespace the
+    /// pod is reachable at `<hostname>.<subdomain>.<namespace>.svc.<cluster domain>`.
+    pub fn subdomain(&self) -> Option<&str> {
+        self.subdomain.as_ref().map(String::as_str)
+    }
+
+    /// Whether the cluster autoscaler may evict the module's pod to scale
+    /// down a node. Defaults to `true`.
+    pub fn safe_to_evict(&self) -> bool {
+        self.safe_to_evict.unwrap_or(true)
+    }
+
+    pub fn resources(&self) -> Option<&ResourceRequirements> {
+        self.resources.as_ref()
+    }
+
+    /// Whether `$edgeHub` streams to the module directly over gRPC. Such
+    /// modules get a headless Service as well, which resolves to the IPs of
+    /// the module's pods so connections bypass kube-proxy.
+    pub fn grpc_target(&self) -> bool {
+        self.grpc_target
+    }
+
+    /// Config maps whose keys are all set as env vars in the module's
+    /// container, besides the module's own env.
+    pub fn env_from_config_map(&self) -> &[String] {
+        &self.env_from_config_map
+    }
+
+    /// Secrets whose keys are all set as env vars in the module's container,
+    /// besides the module's own env.
+    pub fn env_from_secret(&self) -> &[String] {
+        &self.env_from_secret
+    }
+
+    /// How long the module's pod may run. The runtime deletes pods which run
+    /// for longer and reports the module as failed until they're gone, and
+    /// the module's Deployment starts a new pod in their place.
+    pub fn active_deadline_seconds(&self) -> Option<u64> {
+        self.active_deadline_seconds
+    }
+}
+
+/// Compute resources of a module's container, keyed by resource name with
+/// values in the Kubernetes quantity format, e.g. `cpu: 250m` or
+/// `memory: 128Mi`.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ResourceRequirements {
+    #[serde(default)]
+    requests: BTreeMap<String, String>,
+    #[serde(default)]
+    limits: BTreeMap<String, String>,
+}
+
+impl ResourceRequirements {
+    /// The resources the scheduler reserves for the container.
+    pub fn requests(&self) -> &BTreeMap<String, String> {
+        &self.requests
+    }
+
+    /// The most the container may use.
+    pub fn limits(&self) -> &BTreeMap<String, String> {
+        &self.limits
+    }
+}
+
+/// How the Service for a module's exposed ports is reachable.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub enum KubeServiceIPType {
+    /// A virtual IP from the cluster's service address range.
+    ClusterIP,
+    /// A cluster IP plus a port on every node.
+    NodePort,
+    /// A cluster IP plus an external load balancer from the cloud provider.
+    LoadBalancer,
+    /// No cluster IP; the module's DNS name resolves to its pod's address,
+    /// for networks where the service address range conflicts with others.
+    Headless,
+}
+
+impl Default for KubeServiceIPType {
+    fn default() -> Self {
+        KubeServiceIPType::ClusterIP
+    }
+}
+
+/// When the kubelet pulls the image of a pod's container.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Serialize)]
+pub enum ImagePullPolicy {
+    Always,
+    IfNotPresent,
+    Never,
+}
+
+impl ImagePullPolicy {
+    /// The value of a container's `imagePullPolicy`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImagePullPolicy::Always => "Always",
+            ImagePullPolicy::IfNotPresent => "IfNotPresent",
+            ImagePullPolicy::Never => "Never",
+        }
+    }
+}
+
+// Matched regardless of case, but otherwise exactly: a value with stray
+// whitespace is rejected instead of being passed on to the API server.
+impl<'de> serde::Deserialize<'de> for ImagePullPolicy {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value: String = serde::Deserialize::deserialize(deserializer)?;
+        match value.to_lowercase().as_str() {
+            "always" => Ok(ImagePullPolicy::Always),
+            "ifnotpresent" => Ok(ImagePullPolicy::IfNotPresent),
+            "never" => Ok(ImagePullPolicy::Never),
+            _ => Err(serde::de::Error::custom(format!(
+                "invalid image_pull_policy {:?}, expected one of Always, IfNotPresent or Never",
+                value
+            ))),
+        }
+    }
+}
+
+/// The operating system of the node pool a module's pod is scheduled on, for
+/// clusters which mix Linux and Windows nodes.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KubeOS {
+    Linux,
+    Windows,
+}
+
+impl KubeOS {
+    /// The value of the node's operating system label.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KubeOS::Linux => "linux",
+            KubeOS::Windows => "windows",
+        }
+    }
+}
+
+/// A liveness check against a module's implementation of the gRPC health
+/// checking protocol. An empty `service` checks the server as a whole.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct GrpcProbe {
+    port: u16,
+    service: Option<String>,
+}
+
+impl GrpcProbe {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_ref().map(String::as_str)
+    }
+}
+
+/// A taint a module's pod tolerates, as in the Kubernetes API. The fields
+/// are snake_case like the rest of the settings.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Toleration {
+    key: Option<String>,
+    operator: Option<String>,
+    value: Option<String>,
+    effect: Option<String>,
+    toleration_seconds: Option<i64>,
+}
+
+impl Toleration {
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(String::as_str)
+    }
+
+    /// `Exists` or `Equal`, which is assumed when unset.
+    pub fn operator(&self) -> Option<&str> {
+        self.operator.as_ref().map(String::as_str)
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_ref().map(String::as_str)
+    }
+
+    /// The taint effect tolerated, or all of them when unset.
+    pub fn effect(&self) -> Option<&str> {
+        self.effect.as_ref().map(String::as_str)
+    }
+
+    /// How long a `NoExecute` taint is tolerated before the pod is evicted.
+    pub fn toleration_seconds(&self) -> Option<i64> {
+        self.toleration_seconds
+    }
+}
+
+/// Hostnames resolved to an IP address by the hosts file of a module's pod.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct HostAlias {
+    ip: String,
+    #[serde(default)]
+    hostnames: Vec<String>,
+}
+
+impl HostAlias {
+    /// An IPv4 or IPv6 address, checked when the settings are loaded.
+    pub fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    pub fn hostnames(&self) -> &[String] {
+        &self.hostnames
+    }
+}
+
+/// The probes the kubelet runs against a module's container. Either may be
+/// left out, in which case the container has no such probe. For example:
+///
+/// ```json
+/// {
+///     "liveness": {
+///         "type": "HttpGet",
+///         "path": "/health",
+///         "port": 8080,
+///         "initial_delay_seconds": 10,
+///         "period_seconds": 30
+///     },
+///     "readiness": { "type": "TcpSocket", "port": 5671 }
+/// }
+/// ```
+///
+/// or, for a module with its own check:
+///
+/// ```json
+/// { "liveness": { "type": "Exec", "command": ["/app/healthcheck"] } }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ProbeConfig {
+    liveness: Option<Probe>,
+    readiness: Option<Probe>,
+}
+
+impl ProbeConfig {
+    /// Restarts the container when it fails.
+    pub fn liveness(&self) -> Option<&Probe> {
+        self.liveness.as_ref()
+    }
+
+    /// Takes the pod out of its Service's endpoints while it fails.
+    pub fn readiness(&self) -> Option<&Probe> {
+        self.readiness.as_ref()
+    }
+}
+
+/// A single probe: what is checked, and how often. Timings that are left
+/// unset take the Kubernetes defaults.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Probe {
+    #[serde(flatten)]
+    action: ProbeAction,
+    initial_delay_seconds: Option<i32>,
+    period_seconds: Option<i32>,
+    timeout_seconds: Option<i32>,
+    success_threshold: Option<i32>,
+    failure_threshold: Option<i32>,
+}
+
+impl Probe {
+    pub fn action(&self) -> &ProbeAction {
+        &self.action
+    }
+
+    pub fn initial_delay_seconds(&self) -> Option<i32> {
+        self.initial_delay_seconds
+    }
+
+    pub fn period_seconds(&self) -> Option<i32> {
+        self.period_seconds
+    }
+
+    pub fn timeout_seconds(&self) -> Option<i32> {
+        self.timeout_seconds
+    }
+
+    pub fn success_threshold(&self) -> Option<i32> {
+        self.success_threshold
+    }
+
+    pub fn failure_threshold(&self) -> Option<i32> {
+        self.failure_threshold
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "type")]
+pub enum ProbeAction {
+    /// Succeeds on a response with a 2xx or 3xx status to a GET of `path`.
+    HttpGet {
+        path: String,
+        port: u16,
+        /// `HTTP` unless set to `HTTPS`.
+        scheme: Option<String>,
+    },
+    /// Succeeds when a TCP connection to `port` can be opened.
+    TcpSocket { port: u16 },
+    /// Succeeds when `command` exits with status 0 in the container.
+    Exec { command: Vec<String> },
+}
+
+/// The seccomp profile which restricts the system calls a module's
+/// containers can make.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "type")]
+pub enum SeccompProfile {
+    /// The container runtime's default profile.
+    RuntimeDefault,
+    /// No system call filtering.
+    Unconfined,
+    /// A profile file on the node, relative to the kubelet's seccomp profile root.
+    Localhost { path: String },
+}
+
+/// A volume provided by a CSI driver, such as the Azure Blob Storage driver,
+/// to mount into a module's container.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct CsiVolumeSpec {
+    name: String,
+    driver: String,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    volume_attributes: BTreeMap<String, String>,
+    mount_path: String,
+    volume_attributes_class_name: Option<String>,
+}
+
+impl CsiVolumeSpec {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn volume_attributes(&self) -> &BTreeMap<String, String> {
+        &self.volume_attributes
+    }
+
+    pub fn mount_path(&self) -> &str {
+        &self.mount_path
+    }
+
+    /// The VolumeAttributesClass of the volume's claim, which sets storage
+    /// parameters such as IOPS that can be changed after the claim is created.
+    pub fn volume_attributes_class_name(&self) -> Option<&str> {
+        self.volume_attributes_class_name
+            .as_ref()
+            .map(String::as_str)
+    }
+}
+
+/// An `emptyDir` volume to mount into a module's container.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct EmptyDirVolume {
+    name: String,
+    mount_path: String,
+    medium: Option<String>,
+}
+
+impl EmptyDirVolume {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// An absolute path, checked when the settings are loaded.
+    pub fn mount_path(&self) -> &str {
+        &self.mount_path
+    }
+
+    /// `Memory` for a RAM-backed tmpfs. The node's disk is used when unset.
+    pub fn medium(&self) -> Option<&str> {
+        self.medium.as_ref().map(String::as_str)
+    }
+}
+
+/// A cert-manager certificate to issue for a module which serves HTTPS.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct TlsCertificate {
+    issuer_ref: IssuerRef,
+    dns_names: Vec<String>,
+}
+
+impl TlsCertificate {
+    pub fn issuer_ref(&self) -> &IssuerRef {
+        &self.issuer_ref
+    }
+
+    pub fn dns_names(&self) -> &[String] {
+        &self.dns_names
+    }
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct IssuerRef {
+    name: String,
+    #[serde(default = "IssuerRef::default_kind")]
+    kind: String,
+    group: Option<String>,
+}
+
+impl IssuerRef {
+    fn default_kind() -> String {
+        "Issuer".to_string()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_ref().map(String::as_str)
+    }
 }
 
 impl RuntimeSettings for Settings {
@@ -149,3 +1271,198 @@ impl RuntimeSettings for Settings {
         self.base.watchdog()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use url::Url;
+
+    use super::{check_uri, is_valid_image_reference, HostAlias, ImagePullPolicy};
+    use crate::tests::make_settings;
+    use crate::ErrorKind;
+
+    #[test]
+    fn image_pull_policy_deserializes_regardless_of_case() {
+        let cases = vec![
+            ("Always", ImagePullPolicy::Always),
+            ("IfNotPresent", ImagePullPolicy::IfNotPresent),
+            ("Never", ImagePullPolicy::Never),
+            ("ifnotpresent", ImagePullPolicy::IfNotPresent),
+            ("ALWAYS", ImagePullPolicy::Always),
+        ];
+
+        for (value, expected) in cases {
+            let policy: ImagePullPolicy = serde_json::from_value(json!(value)).unwrap();
+            assert_eq!(expected, policy);
+            assert_eq!(
+                expected,
+                serde_json::from_value(json!(policy.as_str())).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn image_pull_policy_rejects_unknown_value() {
+        let err = serde_json::from_value::<ImagePullPolicy>(json!("IfNotPresent ")).unwrap_err();
+
+        assert_eq!(
+            "invalid image_pull_policy \"IfNotPresent \", expected one of Always, IfNotPresent or Never",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn validate_rejects_namespace_which_is_not_a_dns_label() {
+        for namespace in &[
+            "",
+            "Default",
+            "-default",
+            "default-",
+            "iot.edge",
+            "a".repeat(64).as_str(),
+        ] {
+            let mut settings = make_settings(None);
+            settings.namespace = namespace.to_string();
+
+            match settings.validate().unwrap_err().kind() {
+                ErrorKind::InvalidConfiguration(message) => assert!(message.contains("namespace")),
+                kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn validate_rejects_invalid_proxy_image() {
+        let mut settings = make_settings(None);
+        settings.proxy_image = "Proxy:latest".to_string();
+
+        match settings.validate().unwrap_err().kind() {
+            ErrorKind::InvalidConfiguration(message) => assert!(message.contains("proxy_image")),
+            kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_empty_device_id() {
+        let settings = make_settings(None).with_device_id("");
+
+        match settings.validate().unwrap_err().kind() {
+            ErrorKind::InvalidConfiguration(message) => assert!(message.contains("device_id")),
+            kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_invalid_host_alias_ip() {
+        let settings = make_settings(Some(json!({
+            "host_aliases": [
+                { "ip": "10.0.0.5", "hostnames": ["historian.plant.local"] },
+                { "ip": "fd00::5", "hostnames": ["mes.plant.local"] }
+            ]
+        })));
+        assert_eq!(2, settings.host_aliases().len());
+
+        let mut settings = settings;
+        settings.host_aliases.push(HostAlias {
+            ip: "historian.plant.local".to_string(),
+            hostnames: vec!["historian".to_string()],
+        });
+        match settings.validate().unwrap_err().kind() {
+            ErrorKind::InvalidConfiguration(message) => assert!(message.contains("host_aliases")),
+            kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_relative_empty_dir_mount_path() {
+        let settings = make_settings(Some(json!({
+            "modules": {
+                "edgeagent": {
+                    "empty_dir_volumes": [
+                        { "name": "scratch", "mount_path": "/scratch" },
+                        { "name": "windows", "mount_path": "C:\\scratch" }
+                    ]
+                }
+            }
+        })));
+        assert!(settings.validate().is_ok());
+
+        let mut settings = settings;
+        settings.modules.insert(
+            "filter".to_string(),
+            serde_json::from_value(json!({
+                "empty_dir_volumes": [{ "name": "scratch", "mount_path": "scratch" }]
+            }))
+            .unwrap(),
+        );
+        match settings.validate().unwrap_err().kind() {
+            ErrorKind::InvalidConfiguration(message) => assert!(message.contains("mount_path")),
+            kind => panic!("Expected InvalidConfiguration but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_relative_proxy_paths() {
+        let mut settings = make_settings(None);
+        settings.proxy_config_path = "etc/traefik".to_string();
+        assert!(settings.validate().is_err());
+
+        let mut settings = make_settings(None);
+        settings.proxy_trust_bundle_path = "trust-bundle".to_string();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn image_references_are_validated() {
+        let valid = [
+            "proxy",
+            "proxy:latest",
+            "azureiotedge/azureiotedge-proxy:1.0",
+            "mcr.microsoft.com/azureiotedge-proxy:1.0.9-linux-amd64",
+            "localhost:5000/proxy",
+            "localhost/proxy",
+            "registry.io/edge/my_proxy__v2.a-b",
+            "proxy@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ];
+        for image in &valid {
+            assert!(is_valid_image_reference(image), "{} should be valid", image);
+        }
+
+        let invalid = [
+            "",
+            "Proxy",
+            "proxy:",
+            "proxy:-latest",
+            "/proxy",
+            "edge//proxy",
+            "proxy_",
+            "my...proxy",
+            "registry.io:port/proxy",
+            "proxy@sha256:1234",
+            "proxy latest",
+        ];
+        for image in &invalid {
+            assert!(
+                !is_valid_image_reference(image),
+                "{} should be invalid",
+                image
+            );
+        }
+    }
+
+    #[test]
+    fn uris_must_use_a_supported_scheme() {
+        for uri in &[
+            "http://localhost:35000",
+            "https://localhost:35000",
+            "unix:///var/run/iotedge/mgmt.sock",
+            "fd://iotedge.mgmt.socket",
+        ] {
+            assert!(check_uri("connect.management_uri", &Url::parse(uri).unwrap()).is_ok());
+        }
+
+        for uri in &["ftp://localhost:35000", "unix:///"] {
+            assert!(check_uri("connect.management_uri", &Url::parse(uri).unwrap()).is_err());
+        }
+    }
+}
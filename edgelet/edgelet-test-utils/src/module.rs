@@ -307,6 +307,8 @@ where
     type StopFuture = FutureResult<(), Self::Error>;
     type SystemInfoFuture = FutureResult<SystemInfo, Self::Error>;
     type RemoveAllFuture = FutureResult<(), Self::Error>;
+    type StatsFuture = FutureResult<serde_json::Value, Self::Error>;
+    type ClearLogsFuture = FutureResult<(), Self::Error>;
 
     fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         match self.module.as_ref().unwrap() {
@@ -343,7 +345,7 @@ where
         }
     }
 
-    fn remove(&self, _id: &str) -> Self::RemoveFuture {
+    fn remove(&self, _id: &str, _force: bool) -> Self::RemoveFuture {
         match self.module.as_ref().unwrap() {
             Ok(_) => future::ok(()),
             Err(ref e) => future::err(e.clone()),
@@ -391,4 +393,18 @@ where
     fn remove_all(&self) -> Self::RemoveAllFuture {
         future::ok(())
     }
+
+    fn stats(&self, _id: &str) -> Self::StatsFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(serde_json::json!({})),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn clear_logs(&self, _id: &str) -> Self::ClearLogsFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
 }
@@ -1,8 +1,12 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use bytes::BytesMut;
-use futures::future;
+use failure::Fail;
 use futures::prelude::*;
+use futures::{future, stream};
 use hyper::body::Payload;
 use hyper::client::connect::Connect;
 use hyper::client::{Client as HyperClient, HttpConnector, ResponseFuture};
@@ -12,14 +16,23 @@ use hyper::{Body, Error as HyperError};
 use hyper_tls::HttpsConnector;
 use k8s_openapi::api::apps::v1 as api_apps;
 use k8s_openapi::api::authentication::v1 as api_auth;
+use k8s_openapi::api::authorization::v1 as api_authz;
 use k8s_openapi::api::core::v1 as api_core;
 use k8s_openapi::api::rbac::v1 as api_rbac;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
 use k8s_openapi::{http, Response as K8sResponse, ResponseBody};
 use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 use crate::config::{Config, TokenSource};
+use crate::coordination::Lease;
+use crate::custom::CustomResource;
+use crate::discovery::{ApiDiscovery, ApiDiscoveryCache, ApiGroupList, ApiVersions};
 use crate::error::{Error, ErrorKind};
+use crate::status::KubeStatus;
+use crate::token::{ProjectedToken, ProjectedTokenSource, TokenRequest, TokenResponse};
+use crate::watch::WatchEvent;
 
 pub struct HttpClient<C, B>(pub HyperClient<C, B>);
 
@@ -42,6 +55,7 @@ where
 pub struct Client<T, S> {
     config: Config<T>,
     client: S,
+    discovery: Arc<Mutex<ApiDiscoveryCache>>,
 }
 
 impl<T: TokenSource> Client<T, HttpClient<HttpsConnector<HttpConnector>, Body>> {
@@ -55,6 +69,7 @@ impl<T: TokenSource> Client<T, HttpClient<HttpsConnector<HttpConnector>, Body>>
         Client {
             config,
             client: HttpClient(HyperClient::builder().build::<_, Body>(connector)),
+            discovery: Arc::default(),
         }
     }
 }
@@ -63,7 +78,11 @@ impl<T: TokenSource> Client<T, HttpClient<HttpsConnector<HttpConnector>, Body>>
 // everywhere in the code, in tests for instance
 impl<T: TokenSource, S> Client<T, S> {
     pub fn with_client(config: Config<T>, client: S) -> Self {
-        Client { config, client }
+        Client {
+            config,
+            client,
+            discovery: Arc::default(),
+        }
     }
 }
 
@@ -174,6 +193,34 @@ where
         .flatten()
     }
 
+    /// Streams changes to the config maps in a namespace, or to the single
+    /// config map with the given name. The API server closes the stream after
+    /// its watch timeout, so callers are expected to watch again.
+    pub fn watch_config_maps(
+        &mut self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> impl Stream<Item = WatchEvent<api_core::ConfigMap>, Error = Error> {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("watch", "true");
+        if let Some(name) = name {
+            query.append_pair("fieldSelector", &format!("metadata.name={}", name));
+        }
+
+        json_request::<()>(
+            http::Method::GET,
+            &format!(
+                "/api/v1/namespaces/{}/configmaps?{}",
+                namespace,
+                query.finish()
+            ),
+            None,
+        )
+        .map(|req| self.request_stream(req))
+        .into_future()
+        .flatten_stream()
+    }
+
     pub fn list_deployments(
         &mut self,
         namespace: &str,
@@ -246,6 +293,28 @@ where
         .flatten()
     }
 
+    pub fn delete_deployment(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        api_apps::Deployment::delete_namespaced_deployment(
+            name,
+            namespace,
+            api_apps::DeleteNamespacedDeploymentOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_apps::DeleteNamespacedDeploymentResponse::OkStatus(_)
+                | api_apps::DeleteNamespacedDeploymentResponse::OkValue(_) => Ok(()),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
     pub fn list_pods(
         &mut self,
         namespace: &str,
@@ -267,6 +336,312 @@ where
             .flatten()
     }
 
+    /// Streams changes to the pods in a namespace, starting after
+    /// `resource_version` when it's given. A resource version which is too
+    /// old to resume from fails with status code 410 Gone, after which the
+    /// pods have to be listed again.
+    pub fn watch_pods(
+        &mut self,
+        namespace: &str,
+        label_selector: Option<&str>,
+        resource_version: Option<&str>,
+    ) -> impl Stream<Item = WatchEvent<api_core::Pod>, Error = Error> {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("watch", "true");
+        if let Some(label_selector) = label_selector {
+            query.append_pair("labelSelector", label_selector);
+        }
+        if let Some(resource_version) = resource_version {
+            query.append_pair("resourceVersion", resource_version);
+        }
+
+        json_request::<()>(
+            http::Method::GET,
+            &format!("/api/v1/namespaces/{}/pods?{}", namespace, query.finish()),
+            None,
+        )
+        .map(|req| self.request_stream(req))
+        .into_future()
+        .flatten_stream()
+    }
+
+    pub fn replace_pod_status(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        pod: &api_core::Pod,
+    ) -> impl Future<Item = api_core::Pod, Error = Error> {
+        api_core::Pod::replace_namespaced_pod_status(
+            name,
+            namespace,
+            pod,
+            api_core::ReplaceNamespacedPodStatusOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::ReplaceNamespacedPodStatusResponse::Created(pod)
+                | api_core::ReplaceNamespacedPodStatusResponse::Ok(pod) => Ok(pod),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn delete_pod(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        api_core::Pod::delete_namespaced_pod(
+            name,
+            namespace,
+            api_core::DeleteNamespacedPodOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::DeleteNamespacedPodResponse::OkStatus(_)
+                | api_core::DeleteNamespacedPodResponse::OkValue(_) => Ok(()),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn list_events(
+        &mut self,
+        namespace: &str,
+        field_selector: Option<&str>,
+    ) -> impl Future<Item = api_core::EventList, Error = Error> {
+        let params = api_core::ListNamespacedEventOptional {
+            field_selector,
+            ..api_core::ListNamespacedEventOptional::default()
+        };
+
+        api_core::Event::list_namespaced_event(namespace, params)
+            .map_err(Error::from)
+            .map(|req| {
+                self.request(req).and_then(|response| match response {
+                    api_core::ListNamespacedEventResponse::Ok(list) => Ok(list),
+                    _ => Err(Error::from(ErrorKind::Response)),
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn create_event(
+        &mut self,
+        namespace: &str,
+        event: &api_core::Event,
+    ) -> impl Future<Item = api_core::Event, Error = Error> {
+        api_core::Event::create_namespaced_event(
+            namespace,
+            event,
+            api_core::CreateNamespacedEventOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::CreateNamespacedEventResponse::Accepted(e)
+                | api_core::CreateNamespacedEventResponse::Created(e)
+                | api_core::CreateNamespacedEventResponse::Ok(e) => Ok(e),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn list_nodes(
+        &mut self,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = api_core::NodeList, Error = Error> {
+        let params = api_core::ListNodeOptional {
+            label_selector,
+            ..api_core::ListNodeOptional::default()
+        };
+
+        api_core::Node::list_node(params)
+            .map_err(Error::from)
+            .map(|req| {
+                self.request(req).and_then(|response| match response {
+                    api_core::ListNodeResponse::Ok(list) => Ok(list),
+                    _ => Err(Error::from(ErrorKind::Response)),
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn get_node(&mut self, name: &str) -> impl Future<Item = api_core::Node, Error = Error> {
+        api_core::Node::read_node(name, api_core::ReadNodeOptional::default())
+            .map_err(Error::from)
+            .map(|req| {
+                self.request(req).and_then(|response| match response {
+                    api_core::ReadNodeResponse::Ok(node) => Ok(node),
+                    _ => Err(Error::from(ErrorKind::Response)),
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn list_namespaces(
+        &mut self,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = api_core::NamespaceList, Error = Error> {
+        let params = api_core::ListNamespaceOptional {
+            label_selector,
+            ..api_core::ListNamespaceOptional::default()
+        };
+
+        api_core::Namespace::list_namespace(params)
+            .map_err(Error::from)
+            .map(|req| {
+                self.request(req).and_then(|response| match response {
+                    api_core::ListNamespaceResponse::Ok(list) => Ok(list),
+                    _ => Err(Error::from(ErrorKind::Response)),
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    /// Returns the API groups and versions served by the cluster. The result is
+    /// cached for `API_DISCOVERY_TTL` and shared by clones of this client.
+    pub fn discover_apis(&mut self) -> impl Future<Item = ApiDiscovery, Error = Error> {
+        let cached = self.discovery.lock().expect("Unexpected lock error").get();
+        if let Some(discovery) = cached {
+            return future::Either::A(future::ok(discovery));
+        }
+
+        let cache = self.discovery.clone();
+        let core = json_request::<()>(http::Method::GET, "/api", None)
+            .map(|req| self.request_json(req))
+            .into_future()
+            .flatten();
+        let groups = json_request::<()>(http::Method::GET, "/apis", None)
+            .map(|req| self.request_json(req))
+            .into_future()
+            .flatten();
+
+        let fut = core
+            .join(groups)
+            .map(move |(core, groups): (ApiVersions, ApiGroupList)| {
+                let discovery = ApiDiscovery::new(
+                    core.versions,
+                    groups.groups.into_iter().map(From::from).collect(),
+                );
+                cache
+                    .lock()
+                    .expect("Unexpected lock error")
+                    .set(discovery.clone());
+                discovery
+            });
+
+        future::Either::B(fut)
+    }
+
+    /// Fails with `ErrorKind::Conflict` if the Persistent Volume already exists.
+    pub fn create_persistent_volume(
+        &mut self,
+        persistent_volume: &api_core::PersistentVolume,
+    ) -> impl Future<Item = api_core::PersistentVolume, Error = Error> {
+        api_core::PersistentVolume::create_persistent_volume(
+            persistent_volume,
+            api_core::CreatePersistentVolumeOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|(req, _)| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    /// Fails with `ErrorKind::Conflict` if the claim already exists.
+    pub fn create_persistent_volume_claim(
+        &mut self,
+        namespace: &str,
+        persistent_volume_claim: &api_core::PersistentVolumeClaim,
+    ) -> impl Future<Item = api_core::PersistentVolumeClaim, Error = Error> {
+        api_core::PersistentVolumeClaim::create_namespaced_persistent_volume_claim(
+            namespace,
+            persistent_volume_claim,
+            api_core::CreateNamespacedPersistentVolumeClaimOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|(req, _)| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    /// Applies a JSON merge patch to a persistent volume claim. Most of a
+    /// claim's spec can't be changed once it is created.
+    pub fn patch_persistent_volume_claim(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        patch: &serde_json::Value,
+    ) -> impl Future<Item = api_core::PersistentVolumeClaim, Error = Error> {
+        merge_patch_request(
+            &format!(
+                "/api/v1/namespaces/{}/persistentvolumeclaims/{}",
+                namespace, name
+            ),
+            patch,
+        )
+        .map(|req| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    pub fn list_persistent_volume_claims(
+        &mut self,
+        namespace: &str,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = api_core::PersistentVolumeClaimList, Error = Error> {
+        let params = api_core::ListNamespacedPersistentVolumeClaimOptional {
+            label_selector,
+            ..api_core::ListNamespacedPersistentVolumeClaimOptional::default()
+        };
+
+        api_core::PersistentVolumeClaim::list_namespaced_persistent_volume_claim(namespace, params)
+            .map_err(Error::from)
+            .map(|req| {
+                self.request(req).and_then(|response| match response {
+                    api_core::ListNamespacedPersistentVolumeClaimResponse::Ok(list) => Ok(list),
+                    _ => Err(Error::from(ErrorKind::Response)),
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn delete_persistent_volume_claim(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        api_core::PersistentVolumeClaim::delete_namespaced_persistent_volume_claim(
+            name,
+            namespace,
+            api_core::DeleteNamespacedPersistentVolumeClaimOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::DeleteNamespacedPersistentVolumeClaimResponse::OkStatus(_)
+                | api_core::DeleteNamespacedPersistentVolumeClaimResponse::OkValue(_) => Ok(()),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
     pub fn list_secrets(
         &mut self,
         namespace: &str,
@@ -368,6 +743,75 @@ where
         .flatten()
     }
 
+    pub fn subject_access_review(
+        &mut self,
+        review: &api_authz::SubjectAccessReview,
+    ) -> impl Future<Item = api_authz::SubjectAccessReview, Error = Error> {
+        api_authz::SubjectAccessReview::create_subject_access_review(
+            review,
+            api_authz::CreateSubjectAccessReviewOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_authz::CreateSubjectAccessReviewResponse::Created(r)
+                | api_authz::CreateSubjectAccessReviewResponse::Ok(r) => Ok(r),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    /// Requests a token for a service account which is only valid for
+    /// `audiences`, and fails if the API server issued it for any others.
+    pub fn create_service_account_token(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        audiences: &[String],
+        expiration: Duration,
+    ) -> impl Future<Item = ProjectedToken, Error = Error> {
+        let path = format!(
+            "/api/v1/namespaces/{}/serviceaccounts/{}/token",
+            namespace, name
+        );
+        let audiences = audiences.to_vec();
+        json_request(
+            http::Method::POST,
+            &path,
+            Some(&TokenRequest::new(&audiences, expiration)),
+        )
+        .map(|req| {
+            self.request_json(req)
+                .and_then(move |response: TokenResponse| response.into_token(&audiences))
+        })
+        .into_future()
+        .flatten()
+    }
+
+    /// Replaces the source's token with a new one when it is missing or about
+    /// to expire.
+    pub fn refresh_projected_token(
+        &mut self,
+        source: &ProjectedTokenSource,
+    ) -> impl Future<Item = (), Error = Error> {
+        if !source.needs_refresh() {
+            return future::Either::A(future::ok(()));
+        }
+
+        let source = source.clone();
+        let fut = self
+            .create_service_account_token(
+                source.namespace(),
+                source.service_account(),
+                source.audiences(),
+                source.expiration(),
+            )
+            .map(move |token| source.set_token(token));
+        future::Either::B(fut)
+    }
+
     pub fn list_service_accounts(
         &mut self,
         namespace: &str,
@@ -418,76 +862,419 @@ where
         .flatten()
     }
 
-    pub fn get_service_account(
+    pub fn get_service_account(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = api_core::ServiceAccount, Error = Error> {
+        api_core::ServiceAccount::read_namespaced_service_account(
+            name,
+            namespace,
+            api_core::ReadNamespacedServiceAccountOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::ReadNamespacedServiceAccountResponse::Ok(service_account) => {
+                    Ok(service_account)
+                }
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_service_account(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        service_account: &api_core::ServiceAccount,
+    ) -> impl Future<Item = api_core::ServiceAccount, Error = Error> {
+        api_core::ServiceAccount::replace_namespaced_service_account(
+            name,
+            namespace,
+            service_account,
+            api_core::ReplaceNamespacedServiceAccountOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::ReplaceNamespacedServiceAccountResponse::Created(service_account)
+                | api_core::ReplaceNamespacedServiceAccountResponse::Ok(service_account) => {
+                    Ok(service_account)
+                }
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    /// Applies a JSON merge patch to a service account, which only touches
+    /// the fields present in the patch.
+    pub fn patch_service_account(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        patch: &serde_json::Value,
+    ) -> impl Future<Item = api_core::ServiceAccount, Error = Error> {
+        merge_patch_request(
+            &format!("/api/v1/namespaces/{}/serviceaccounts/{}", namespace, name),
+            patch,
+        )
+        .map(|req| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    pub fn delete_service_account(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        api_core::ServiceAccount::delete_namespaced_service_account(
+            name,
+            namespace,
+            api_core::DeleteNamespacedServiceAccountOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::DeleteNamespacedServiceAccountResponse::OkStatus(_)
+                | api_core::DeleteNamespacedServiceAccountResponse::OkValue(_) => Ok(()),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn list_services(
+        &mut self,
+        namespace: &str,
+        name: Option<&str>,
+        label_selector: Option<&str>,
+    ) -> impl Future<Item = api_core::ServiceList, Error = Error> {
+        let field_selector = name.map(|name| format!("metadata.name={}", name));
+        let params = api_core::ListNamespacedServiceOptional {
+            field_selector: field_selector.as_ref().map(String::as_ref),
+            label_selector,
+            ..api_core::ListNamespacedServiceOptional::default()
+        };
+
+        api_core::Service::list_namespaced_service(namespace, params)
+            .map_err(Error::from)
+            .map(|req| {
+                self.request(req).and_then(|response| match response {
+                    api_core::ListNamespacedServiceResponse::Ok(list) => Ok(list),
+                    _ => Err(Error::from(ErrorKind::Response)),
+                })
+            })
+            .into_future()
+            .flatten()
+    }
+
+    pub fn create_service(
+        &mut self,
+        namespace: &str,
+        service: &api_core::Service,
+    ) -> impl Future<Item = api_core::Service, Error = Error> {
+        api_core::Service::create_namespaced_service(
+            namespace,
+            service,
+            api_core::CreateNamespacedServiceOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::CreateNamespacedServiceResponse::Accepted(service)
+                | api_core::CreateNamespacedServiceResponse::Created(service)
+                | api_core::CreateNamespacedServiceResponse::Ok(service) => Ok(service),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_service(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        service: &api_core::Service,
+    ) -> impl Future<Item = api_core::Service, Error = Error> {
+        api_core::Service::replace_namespaced_service(
+            name,
+            namespace,
+            service,
+            api_core::ReplaceNamespacedServiceOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::ReplaceNamespacedServiceResponse::Created(service)
+                | api_core::ReplaceNamespacedServiceResponse::Ok(service) => Ok(service),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn delete_service(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = (), Error = Error> {
+        api_core::Service::delete_namespaced_service(
+            name,
+            namespace,
+            api_core::DeleteNamespacedServiceOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_core::DeleteNamespacedServiceResponse::OkStatus(_)
+                | api_core::DeleteNamespacedServiceResponse::OkValue(_) => Ok(()),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_role(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        role: &api_rbac::Role,
+    ) -> impl Future<Item = api_rbac::Role, Error = Error> {
+        api_rbac::Role::replace_namespaced_role(
+            name,
+            namespace,
+            role,
+            api_rbac::ReplaceNamespacedRoleOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_rbac::ReplaceNamespacedRoleResponse::Created(role)
+                | api_rbac::ReplaceNamespacedRoleResponse::Ok(role) => Ok(role),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_role_binding(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        role_binding: &api_rbac::RoleBinding,
+    ) -> impl Future<Item = api_rbac::RoleBinding, Error = Error> {
+        api_rbac::RoleBinding::replace_namespaced_role_binding(
+            name,
+            namespace,
+            role_binding,
+            api_rbac::ReplaceNamespacedRoleBindingOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_rbac::ReplaceNamespacedRoleBindingResponse::Created(role_binding)
+                | api_rbac::ReplaceNamespacedRoleBindingResponse::Ok(role_binding) => {
+                    Ok(role_binding)
+                }
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_cluster_role(
+        &mut self,
+        name: &str,
+        cluster_role: &api_rbac::ClusterRole,
+    ) -> impl Future<Item = api_rbac::ClusterRole, Error = Error> {
+        api_rbac::ClusterRole::replace_cluster_role(
+            name,
+            cluster_role,
+            api_rbac::ReplaceClusterRoleOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_rbac::ReplaceClusterRoleResponse::Created(cluster_role)
+                | api_rbac::ReplaceClusterRoleResponse::Ok(cluster_role) => Ok(cluster_role),
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_cluster_role_binding(
+        &mut self,
+        name: &str,
+        cluster_role_binding: &api_rbac::ClusterRoleBinding,
+    ) -> impl Future<Item = api_rbac::ClusterRoleBinding, Error = Error> {
+        api_rbac::ClusterRoleBinding::replace_cluster_role_binding(
+            name,
+            cluster_role_binding,
+            api_rbac::ReplaceClusterRoleBindingOptional::default(),
+        )
+        .map_err(Error::from)
+        .map(|req| {
+            self.request(req).and_then(|response| match response {
+                api_rbac::ReplaceClusterRoleBindingResponse::Created(cluster_role_binding)
+                | api_rbac::ReplaceClusterRoleBindingResponse::Ok(cluster_role_binding) => {
+                    Ok(cluster_role_binding)
+                }
+                _ => Err(Error::from(ErrorKind::Response)),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn get_lease(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Item = Option<Lease>, Error = Error> {
+        json_request::<Lease>(
+            http::Method::GET,
+            &format!(
+                "/apis/coordination.k8s.io/v1/namespaces/{}/leases/{}",
+                namespace, name
+            ),
+            None,
+        )
+        .map(|req| {
+            self.request_json(req).then(|result| match result {
+                Ok(lease) => Ok(Some(lease)),
+                Err(ref err) if is_not_found(err) => Ok(None),
+                Err(err) => Err(err),
+            })
+        })
+        .into_future()
+        .flatten()
+    }
+
+    pub fn create_lease(
+        &mut self,
+        namespace: &str,
+        lease: &Lease,
+    ) -> impl Future<Item = Lease, Error = Error> {
+        json_request(
+            http::Method::POST,
+            &format!(
+                "/apis/coordination.k8s.io/v1/namespaces/{}/leases",
+                namespace
+            ),
+            Some(lease),
+        )
+        .map(|req| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_lease(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        lease: &Lease,
+    ) -> impl Future<Item = Lease, Error = Error> {
+        json_request(
+            http::Method::PUT,
+            &format!(
+                "/apis/coordination.k8s.io/v1/namespaces/{}/leases/{}",
+                namespace, name
+            ),
+            Some(lease),
+        )
+        .map(|req| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    pub fn get_custom_object(
         &mut self,
+        resource: CustomResource,
         namespace: &str,
         name: &str,
-    ) -> impl Future<Item = api_core::ServiceAccount, Error = Error> {
-        api_core::ServiceAccount::read_namespaced_service_account(
-            name,
-            namespace,
-            api_core::ReadNamespacedServiceAccountOptional::default(),
+    ) -> impl Future<Item = Option<serde_json::Value>, Error = Error> {
+        json_request::<serde_json::Value>(
+            http::Method::GET,
+            &resource.item_path(namespace, name),
+            None,
         )
-        .map_err(Error::from)
         .map(|req| {
-            self.request(req).and_then(|response| match response {
-                api_core::ReadNamespacedServiceAccountResponse::Ok(service_account) => {
-                    Ok(service_account)
-                }
-                _ => Err(Error::from(ErrorKind::Response)),
+            self.request_json(req).then(|result| match result {
+                Ok(object) => Ok(Some(object)),
+                Err(ref err) if is_not_found(err) => Ok(None),
+                Err(err) => Err(err),
             })
         })
         .into_future()
         .flatten()
     }
 
-    pub fn replace_service_account(
+    pub fn create_custom_object(
+        &mut self,
+        resource: CustomResource,
+        namespace: &str,
+        object: &serde_json::Value,
+    ) -> impl Future<Item = serde_json::Value, Error = Error> {
+        json_request(
+            http::Method::POST,
+            &resource.collection_path(namespace),
+            Some(object),
+        )
+        .map(|req| self.request_json(req))
+        .into_future()
+        .flatten()
+    }
+
+    pub fn replace_custom_object(
         &mut self,
+        resource: CustomResource,
         namespace: &str,
         name: &str,
-        service_account: &api_core::ServiceAccount,
-    ) -> impl Future<Item = api_core::ServiceAccount, Error = Error> {
-        api_core::ServiceAccount::replace_namespaced_service_account(
-            name,
-            namespace,
-            service_account,
-            api_core::ReplaceNamespacedServiceAccountOptional::default(),
+        object: &serde_json::Value,
+    ) -> impl Future<Item = serde_json::Value, Error = Error> {
+        json_request(
+            http::Method::PUT,
+            &resource.item_path(namespace, name),
+            Some(object),
         )
-        .map_err(Error::from)
-        .map(|req| {
-            self.request(req).and_then(|response| match response {
-                api_core::ReplaceNamespacedServiceAccountResponse::Created(service_account)
-                | api_core::ReplaceNamespacedServiceAccountResponse::Ok(service_account) => {
-                    Ok(service_account)
-                }
-                _ => Err(Error::from(ErrorKind::Response)),
-            })
-        })
+        .map(|req| self.request_json(req))
         .into_future()
         .flatten()
     }
 
-    pub fn replace_role_binding(
+    /// Deleting an object which does not exist is not treated as an error.
+    pub fn delete_custom_object(
         &mut self,
+        resource: CustomResource,
         namespace: &str,
         name: &str,
-        role_binding: &api_rbac::RoleBinding,
-    ) -> impl Future<Item = api_rbac::RoleBinding, Error = Error> {
-        api_rbac::RoleBinding::replace_namespaced_role_binding(
-            name,
-            namespace,
-            role_binding,
-            api_rbac::ReplaceNamespacedRoleBindingOptional::default(),
+    ) -> impl Future<Item = (), Error = Error> {
+        json_request::<serde_json::Value>(
+            http::Method::DELETE,
+            &resource.item_path(namespace, name),
+            None,
         )
-        .map_err(Error::from)
         .map(|req| {
-            self.request(req).and_then(|response| match response {
-                api_rbac::ReplaceNamespacedRoleBindingResponse::Created(role_binding)
-                | api_rbac::ReplaceNamespacedRoleBindingResponse::Ok(role_binding) => {
-                    Ok(role_binding)
-                }
-                _ => Err(Error::from(ErrorKind::Response)),
-            })
+            self.request_json::<serde_json::Value>(req)
+                .then(|result| match result {
+                    Ok(_) => Ok(()),
+                    Err(ref err) if is_not_found(err) => Ok(()),
+                    Err(err) => Err(err),
+                })
         })
         .into_future()
         .flatten()
@@ -512,16 +1299,90 @@ where
                 .map_err(Error::from)
                 .and_then(move |buf| {
                     debug!("HTTP Response:\n{}", ::std::str::from_utf8(&buf).unwrap());
+                    if !status_code.is_success() && KubeStatus::from_body(&buf).is_some() {
+                        return Err(Error::from_response(ErrorKind::Response, &buf));
+                    }
+
                     R::try_from_parts(status_code, &buf)
                         .map_err(Error::from)
                         .map(|(result, _)| result)
-                        .into_future()
                 })
         };
 
         self.execute(req).and_then(next)
     }
 
+    // Used for resources which k8s-openapi does not generate request builders for.
+    fn request_json<R: DeserializeOwned>(
+        &mut self,
+        req: http::Request<Vec<u8>>,
+    ) -> impl Future<Item = R, Error = Error> {
+        let next = |response: http::Response<Body>| {
+            let status_code = response.status();
+            response
+                .into_body()
+                .fold(BytesMut::new(), |mut buf, chunk| {
+                    buf.extend_from_slice(chunk.as_ref());
+                    future::ok::<_, HyperError>(buf)
+                })
+                .map_err(Error::from)
+                .and_then(move |buf| {
+                    debug!("HTTP Response:\n{}", ::std::str::from_utf8(&buf).unwrap());
+                    match status_code {
+                        s if s.is_success() => serde_json::from_slice(&buf).map_err(Error::from),
+                        http::StatusCode::NOT_FOUND => {
+                            Err(Error::from_response(ErrorKind::NotFound, &buf))
+                        }
+                        http::StatusCode::CONFLICT => {
+                            Err(Error::from_response(ErrorKind::Conflict, &buf))
+                        }
+                        _ => Err(Error::from_response(ErrorKind::Response, &buf)),
+                    }
+                })
+        };
+
+        self.execute(req).and_then(next)
+    }
+
+    // Splits a streamed response into its newline-delimited JSON objects.
+    fn request_stream<R: DeserializeOwned>(
+        &mut self,
+        req: http::Request<Vec<u8>>,
+    ) -> impl Stream<Item = R, Error = Error> {
+        self.execute(req)
+            .map(|response| {
+                let status_code = response.status();
+                let body = response.into_body().map_err(Error::from);
+
+                if status_code.is_success() {
+                    let mut buf = BytesMut::new();
+                    let lines = body
+                        .map(move |chunk| {
+                            buf.extend_from_slice(chunk.as_ref());
+
+                            let mut lines = Vec::new();
+                            while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+                                lines.push(buf.split_to(end + 1));
+                            }
+                            stream::iter_ok(lines)
+                        })
+                        .flatten()
+                        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+                        .and_then(|line| {
+                            debug!("HTTP Response:\n{}", String::from_utf8_lossy(&line));
+                            serde_json::from_slice(&line).map_err(Error::from)
+                        });
+                    future::Either::A(lines)
+                } else {
+                    let err = body.concat2().and_then(|buf| {
+                        Err::<R, _>(Error::from_response(ErrorKind::Response, &buf))
+                    });
+                    future::Either::B(err.into_stream())
+                }
+            })
+            .flatten_stream()
+    }
+
     fn execute(
         &mut self,
         mut req: http::Request<Vec<u8>>,
@@ -560,13 +1421,52 @@ where
     }
 }
 
+fn json_request<B: Serialize>(
+    method: http::Method,
+    path: &str,
+    body: Option<&B>,
+) -> Result<http::Request<Vec<u8>>, Error> {
+    let body = body.map_or_else(|| Ok(vec![]), serde_json::to_vec)?;
+
+    http::Request::builder()
+        .method(method)
+        .uri(path)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .map_err(|err| Error::from(err.context(ErrorKind::Request)))
+}
+
+fn merge_patch_request<B: Serialize>(
+    path: &str,
+    body: &B,
+) -> Result<http::Request<Vec<u8>>, Error> {
+    let mut req = json_request(http::Method::PATCH, path, Some(body))?;
+    req.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        http::header::HeaderValue::from_static("application/merge-patch+json"),
+    );
+    Ok(req)
+}
+
+fn is_not_found(err: &Error) -> bool {
+    match err.kind() {
+        ErrorKind::NotFound => true,
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
     use bytes::BytesMut;
     use futures::{future, Future, Stream};
     use hyper::service::{service_fn, Service};
     use hyper::{Body, Error as HyperError, Request, Response, StatusCode};
     use k8s_openapi::api::apps::v1 as api_apps;
+    use k8s_openapi::api::authorization::v1 as api_authz;
     use k8s_openapi::api::core::v1 as api_core;
     use native_tls::TlsConnector;
     use serde_json;
@@ -575,6 +1475,11 @@ mod tests {
     use url::Url;
 
     use crate::config::{Config, TokenSource};
+    use crate::coordination::Lease;
+    use crate::custom::CustomResource;
+    use crate::error::ErrorKind;
+    use crate::token::ProjectedTokenSource;
+    use crate::watch::WatchEvent;
     use crate::Client;
 
     #[derive(Clone)]
@@ -720,6 +1625,32 @@ mod tests {
             });
     }
 
+    #[test]
+    fn list_pods_error_status() {
+        const NAMESPACE: &str = "custom-namespace";
+        let service = service_fn(
+            |_req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                let mut res = Response::new(Body::from(
+                    r#"{"kind":"Status","apiVersion":"v1","status":"Failure","message":"pods is forbidden","reason":"Forbidden","code":403}"#,
+                ));
+                *res.status_mut() = StatusCode::FORBIDDEN;
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+
+        let fut = client.list_pods(NAMESPACE, None);
+        let err = Runtime::new().unwrap().block_on(fut).unwrap_err();
+        match err.kind() {
+            ErrorKind::Response => (),
+            kind => panic!("Expected a response error but got {:?}", kind),
+        }
+        let status = err.kube_status().unwrap();
+        assert_eq!(Some("Forbidden".to_string()), status.reason);
+        assert_eq!(Some("pods is forbidden".to_string()), status.message);
+    }
+
     #[test]
     fn list_pods_service_error() {
         const NAMESPACE: &str = "custom-namespace";
@@ -991,6 +1922,327 @@ mod tests {
         }
     }
 
+    #[test]
+    fn subject_access_review_success() {
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(req.method(), "POST");
+            assert_eq!(
+                req.uri().path(),
+                "/apis/authorization.k8s.io/v1/subjectaccessreviews"
+            );
+            let mut res = Response::new(Body::from(
+                r#"{"kind":"SubjectAccessReview","spec":{"user":"module-abc"},"status":{"allowed":true}}"#,
+            ));
+            *res.status_mut() = StatusCode::CREATED;
+            Ok(res)
+        });
+
+        let mut client = make_test_client(service);
+
+        let review = api_authz::SubjectAccessReview {
+            spec: api_authz::SubjectAccessReviewSpec {
+                user: Some("module-abc".to_string()),
+                ..api_authz::SubjectAccessReviewSpec::default()
+            },
+            ..api_authz::SubjectAccessReview::default()
+        };
+        let fut = client.subject_access_review(&review);
+
+        let review = Runtime::new().unwrap().block_on(fut).unwrap();
+        assert!(review.status.unwrap().allowed);
+    }
+
+    #[test]
+    fn refresh_projected_token_requests_token_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_copy = calls.clone();
+        let service = service_fn(
+            move |req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                calls_copy.fetch_add(1, Ordering::SeqCst);
+                assert_eq!(req.method(), "POST");
+                assert_eq!(
+                    req.uri().path(),
+                    "/api/v1/namespaces/default/serviceaccounts/iotedge/token"
+                );
+                let body = req.into_body().concat2().wait().unwrap();
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                assert_eq!(request["spec"]["audiences"][0], "iotedge");
+                assert_eq!(request["spec"]["expirationSeconds"], 3600);
+
+                let mut res = Response::new(Body::from(
+                    r#"{"kind":"TokenRequest","spec":{"audiences":["iotedge"]},"status":{"token":"projected","expirationTimestamp":"2100-01-01T00:00:00Z"}}"#,
+                ));
+                *res.status_mut() = StatusCode::CREATED;
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+        let source = ProjectedTokenSource::new(
+            "default".to_string(),
+            "iotedge".to_string(),
+            vec!["iotedge".to_string()],
+            Duration::from_secs(3600),
+        );
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(client.refresh_projected_token(&source))
+            .unwrap();
+        runtime
+            .block_on(client.refresh_projected_token(&source))
+            .unwrap();
+
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+        assert_eq!(Some("projected".to_string()), source.get().unwrap());
+    }
+
+    #[test]
+    fn discover_apis_is_cached() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_copy = calls.clone();
+        let service = service_fn(
+            move |req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                calls_copy.fetch_add(1, Ordering::SeqCst);
+                let body = match req.uri().path() {
+                    "/api" => r#"{"kind":"APIVersions","versions":["v1"]}"#,
+                    "/apis" => {
+                        r#"{"kind":"APIGroupList","apiVersion":"v1","groups":[{"name":"autoscaling","versions":[{"groupVersion":"autoscaling/v2","version":"v2"},{"groupVersion":"autoscaling/v2beta2","version":"v2beta2"}],"preferredVersion":{"groupVersion":"autoscaling/v2","version":"v2"}}]}"#
+                    }
+                    path => panic!("Unexpected path {}", path),
+                };
+                Ok(Response::new(Body::from(body)))
+            },
+        );
+
+        let mut client = make_test_client(service);
+        let mut runtime = Runtime::new().unwrap();
+
+        let discovery = runtime.block_on(client.discover_apis()).unwrap();
+        assert_eq!(&["v1".to_string()], discovery.core_versions());
+        let autoscaling = discovery.group("autoscaling").unwrap();
+        assert_eq!(
+            &["v2".to_string(), "v2beta2".to_string()],
+            autoscaling.versions()
+        );
+        assert_eq!(Some("v2"), autoscaling.preferred_version());
+
+        let cached = runtime.block_on(client.discover_apis()).unwrap();
+        assert_eq!(discovery, cached);
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn create_persistent_volume_claim_conflict() {
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(
+                req.uri().path(),
+                "/api/v1/namespaces/default/persistentvolumeclaims"
+            );
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::CONFLICT;
+            Ok(res)
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .create_persistent_volume_claim("default", &api_core::PersistentVolumeClaim::default());
+        let err = Runtime::new().unwrap().block_on(fut).unwrap_err();
+        match err.kind() {
+            ErrorKind::Conflict => (),
+            kind => panic!("Expected a conflict error but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn get_lease_not_found() {
+        const NAMESPACE: &str = "custom-namespace";
+        const NAME: &str = "lease1";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(
+                req.uri().path(),
+                "/apis/coordination.k8s.io/v1/namespaces/custom-namespace/leases/lease1"
+            );
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_FOUND;
+            Ok(res)
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client.get_lease(NAMESPACE, NAME);
+        let lease = Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+        assert!(lease.is_none());
+    }
+
+    #[test]
+    fn delete_custom_object_not_found() {
+        const RESOURCE: CustomResource =
+            CustomResource::new("monitoring.coreos.com", "v1", "servicemonitors");
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(
+                req.uri().path(),
+                "/apis/monitoring.coreos.com/v1/namespaces/custom-namespace/servicemonitors/sm1"
+            );
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_FOUND;
+            Ok(res)
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client.delete_custom_object(RESOURCE, "custom-namespace", "sm1");
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn delete_cluster_scoped_custom_object() {
+        const RESOURCE: CustomResource = CustomResource::cluster_scoped(
+            "admissionregistration.k8s.io",
+            "v1",
+            "validatingadmissionpolicies",
+        );
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(
+                req.uri().path(),
+                "/apis/admissionregistration.k8s.io/v1/validatingadmissionpolicies/policy1"
+            );
+            Ok(Response::new(Body::from("{}")))
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client.delete_custom_object(RESOURCE, "custom-namespace", "policy1");
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn create_lease_conflict() {
+        const NAMESPACE: &str = "custom-namespace";
+        let service = service_fn(
+            |_req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::CONFLICT;
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+
+        let fut = client.create_lease(NAMESPACE, &Lease::default());
+        let err = Runtime::new().unwrap().block_on(fut).unwrap_err();
+        match err.kind() {
+            ErrorKind::Conflict => (),
+            kind => panic!("Expected a conflict error but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn watch_config_maps_success() {
+        const NAMESPACE: &str = "custom-namespace";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            let q = req.uri().query().unwrap();
+            assert!(q.contains("watch=true"));
+            assert!(q.contains("fieldSelector=metadata.name%3Dproxy-config"));
+            Ok(Response::new(Body::from(
+                r#"{"type":"ADDED","object":{"metadata":{"name":"proxy-config"}}}
+{"type":"MODIFIED","object":{"metadata":{"name":"proxy-config"}}}
+"#,
+            )))
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .watch_config_maps(NAMESPACE, Some("proxy-config"))
+            .collect();
+        let events = Runtime::new().unwrap().block_on(fut).unwrap();
+        match events.as_slice() {
+            [WatchEvent::Added(_), WatchEvent::Modified(config_map)] => assert_eq!(
+                config_map.metadata.as_ref().unwrap().name,
+                Some("proxy-config".to_string())
+            ),
+            events => panic!("Unexpected watch events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn watch_pods_success() {
+        const NAMESPACE: &str = "custom-namespace";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(req.uri().path(), "/api/v1/namespaces/custom-namespace/pods");
+            let q = req.uri().query().unwrap();
+            assert!(q.contains("watch=true"));
+            assert!(q.contains("labelSelector=net.azure-devices.edge.deviceid%3Ddevice1"));
+            assert!(q.contains("resourceVersion=1234"));
+            Ok(Response::new(Body::from(
+                r#"{"type":"DELETED","object":{"metadata":{"name":"edgehub-5d8f"}}}
+{"type":"ERROR","object":{"kind":"Status","status":"Failure","reason":"Expired","code":410}}
+"#,
+            )))
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .watch_pods(
+                NAMESPACE,
+                Some("net.azure-devices.edge.deviceid=device1"),
+                Some("1234"),
+            )
+            .collect();
+        let events = Runtime::new().unwrap().block_on(fut).unwrap();
+        match events.as_slice() {
+            [WatchEvent::Deleted(pod), WatchEvent::Error(status)] => {
+                assert_eq!(
+                    pod.metadata.as_ref().unwrap().name,
+                    Some("edgehub-5d8f".to_string())
+                );
+                assert_eq!(status.code, Some(410));
+            }
+            events => panic!("Unexpected watch events {:?}", events),
+        }
+    }
+
+    #[test]
+    fn patch_service_account_sends_merge_patch() {
+        const NAMESPACE: &str = "custom-namespace";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            assert_eq!(req.method(), "PATCH");
+            assert_eq!(
+                req.uri().path(),
+                "/api/v1/namespaces/custom-namespace/serviceaccounts/edgehub"
+            );
+            assert_eq!(
+                req.headers()["content-type"],
+                "application/merge-patch+json"
+            );
+            Ok(Response::new(Body::from(
+                r#"{"kind":"ServiceAccount","metadata":{"name":"edgehub"}}"#,
+            )))
+        });
+
+        let mut client = make_test_client(service);
+
+        let patch = serde_json::json!({ "metadata": { "annotations": { "a": "b" } } });
+        let fut = client.patch_service_account(NAMESPACE, "edgehub", &patch);
+        let service_account = Runtime::new().unwrap().block_on(fut).unwrap();
+        assert_eq!(
+            service_account.metadata.unwrap().name,
+            Some("edgehub".to_string())
+        );
+    }
+
     fn make_test_client<S: Service>(service: S) -> Client<TestTokenSource, S> {
         Client {
             config: Config::new(
@@ -1000,7 +2252,7 @@ mod tests {
                 TlsConnector::new().unwrap(),
             ),
             client: service,
+            discovery: Arc::default(),
         }
     }
-
 }
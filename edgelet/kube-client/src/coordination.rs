@@ -0,0 +1,40 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Types for the `coordination.k8s.io` API group. These are not generated by
+//! the version of k8s-openapi we build against so they are defined by hand.
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+use serde_derive::{Deserialize, Serialize};
+
+pub const LEASE_API_VERSION: &str = "coordination.k8s.io/v1";
+pub const LEASE_KIND: &str = "Lease";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lease {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<api_meta::ObjectMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec: Option<LeaseSpec>,
+}
+
+/// Timestamps are kept in their `MicroTime` wire format (RFC 3339 with
+/// microsecond precision).
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaseSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acquire_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub holder_identity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lease_duration_seconds: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lease_transitions: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renew_time: Option<String>,
+}
@@ -0,0 +1,71 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+/// Identifies a resource type which k8s-openapi does not generate types for,
+/// e.g. `monitoring.coreos.com/v1` `servicemonitors`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CustomResource {
+    group: &'static str,
+    version: &'static str,
+    plural: &'static str,
+    namespaced: bool,
+}
+
+impl CustomResource {
+    pub const fn new(group: &'static str, version: &'static str, plural: &'static str) -> Self {
+        CustomResource {
+            group,
+            version,
+            plural,
+            namespaced: true,
+        }
+    }
+
+    /// The namespace passed to the client is ignored for cluster scoped resources.
+    pub const fn cluster_scoped(
+        group: &'static str,
+        version: &'static str,
+        plural: &'static str,
+    ) -> Self {
+        CustomResource {
+            group,
+            version,
+            plural,
+            namespaced: false,
+        }
+    }
+
+    pub fn group(&self) -> &str {
+        self.group
+    }
+
+    pub fn version(&self) -> &str {
+        self.version
+    }
+
+    pub fn plural(&self) -> &str {
+        self.plural
+    }
+
+    pub fn namespaced(&self) -> bool {
+        self.namespaced
+    }
+
+    pub fn api_version(&self) -> String {
+        format!("{}/{}", self.group, self.version)
+    }
+
+    pub(crate) fn collection_path(&self, namespace: &str) -> String {
+        if self.namespaced {
+            format!(
+                "/apis/{}/{}/namespaces/{}/{}",
+                self.group, self.version, namespace, self.plural
+            )
+        } else {
+            format!("/apis/{}/{}/{}", self.group, self.version, self.plural)
+        }
+    }
+
+    pub(crate) fn item_path(&self, namespace: &str, name: &str) -> String {
+        format!("{}/{}", self.collection_path(namespace), name)
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The API groups and versions served by the cluster, as reported by the
+//! `/api` and `/apis` discovery endpoints.
+
+use std::time::{Duration, Instant};
+
+use serde_derive::Deserialize;
+
+/// How long discovered APIs are reused before the API server is asked again.
+pub const API_DISCOVERY_TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApiDiscovery {
+    core_versions: Vec<String>,
+    groups: Vec<ApiGroup>,
+}
+
+impl ApiDiscovery {
+    pub fn new(core_versions: Vec<String>, groups: Vec<ApiGroup>) -> Self {
+        ApiDiscovery {
+            core_versions,
+            groups,
+        }
+    }
+
+    /// Versions of the core (legacy) API group served under `/api`.
+    pub fn core_versions(&self) -> &[String] {
+        &self.core_versions
+    }
+
+    pub fn groups(&self) -> &[ApiGroup] {
+        &self.groups
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ApiGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    pub fn supports(&self, group: &str, version: &str) -> bool {
+        self.group(group)
+            .map_or(false, |group| group.versions.iter().any(|v| v == version))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApiGroup {
+    name: String,
+    versions: Vec<String>,
+    preferred_version: Option<String>,
+}
+
+impl ApiGroup {
+    pub fn new(name: String, versions: Vec<String>, preferred_version: Option<String>) -> Self {
+        ApiGroup {
+            name,
+            versions,
+            preferred_version,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn versions(&self) -> &[String] {
+        &self.versions
+    }
+
+    pub fn preferred_version(&self) -> Option<&str> {
+        self.preferred_version.as_ref().map(AsRef::as_ref)
+    }
+}
+
+/// Cached result of the last discovery, shared by clones of a client.
+#[derive(Debug, Default)]
+pub(crate) struct ApiDiscoveryCache {
+    value: Option<(Instant, ApiDiscovery)>,
+}
+
+impl ApiDiscoveryCache {
+    pub(crate) fn get(&self) -> Option<ApiDiscovery> {
+        match self.value {
+            Some((fetched_at, ref discovery)) if fetched_at.elapsed() < API_DISCOVERY_TTL => {
+                Some(discovery.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set(&mut self, discovery: ApiDiscovery) {
+        self.value = Some((Instant::now(), discovery));
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiVersions {
+    #[serde(default)]
+    pub(crate) versions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiGroupList {
+    #[serde(default)]
+    pub(crate) groups: Vec<ApiGroupResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ApiGroupResponse {
+    name: String,
+    #[serde(default)]
+    versions: Vec<GroupVersion>,
+    preferred_version: Option<GroupVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupVersion {
+    version: String,
+}
+
+impl From<ApiGroupResponse> for ApiGroup {
+    fn from(group: ApiGroupResponse) -> Self {
+        ApiGroup::new(
+            group.name,
+            group.versions.into_iter().map(|v| v.version).collect(),
+            group.preferred_version.map(|v| v.version),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discovery::{ApiDiscovery, ApiGroup};
+
+    #[test]
+    fn supports_served_group_versions() {
+        let discovery = ApiDiscovery::new(
+            vec!["v1".to_string()],
+            vec![ApiGroup::new(
+                "autoscaling".to_string(),
+                vec!["v1".to_string(), "v2".to_string()],
+                Some("v2".to_string()),
+            )],
+        );
+
+        assert!(discovery.supports("autoscaling", "v2"));
+        assert!(!discovery.supports("autoscaling", "v2beta2"));
+        assert!(!discovery.supports("apps", "v1"));
+        assert_eq!(
+            Some("v2"),
+            discovery
+                .group("autoscaling")
+                .and_then(ApiGroup::preferred_version)
+        );
+    }
+}
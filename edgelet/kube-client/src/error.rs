@@ -14,9 +14,12 @@ use k8s_openapi::http::uri::InvalidUri;
 use k8s_openapi::{RequestError, ResponseError};
 use native_tls::Error as NativeTlsError;
 use openssl::error::ErrorStack;
+use serde_json::Error as SerdeJsonError;
 use serde_yaml::Error as SerdeYamlError;
 use url::ParseError as UrlParseError;
 
+use crate::status::KubeStatus;
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -58,6 +61,17 @@ pub enum ErrorKind {
     Request,
     #[fail(display = "HTTP response error")]
     Response,
+    #[fail(display = "The requested resource was not found")]
+    NotFound,
+    #[fail(display = "The resource was modified concurrently")]
+    Conflict,
+    #[fail(display = "The service account token has expired")]
+    ExpiredToken,
+    #[fail(
+        display = "The service account token was issued for other audiences {:?}",
+        _0
+    )]
+    TokenAudience(Vec<String>),
     #[cfg(test)]
     #[fail(display = "HTTP test error")]
     HttpTest,
@@ -87,6 +101,20 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// The `Status` returned by the API server, if the request failed with one.
+    pub fn kube_status(&self) -> Option<&KubeStatus> {
+        self.inner
+            .cause()
+            .and_then(|cause| cause.downcast_ref::<KubeStatus>())
+    }
+
+    pub(crate) fn from_response(kind: ErrorKind, body: &[u8]) -> Self {
+        match KubeStatus::from_body(body) {
+            Some(status) => Error::from(status.context(kind)),
+            None => Error::from(kind),
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -151,6 +179,14 @@ impl From<SerdeYamlError> for Error {
     }
 }
 
+impl From<SerdeJsonError> for Error {
+    fn from(error: SerdeJsonError) -> Self {
+        Error {
+            inner: error.context(ErrorKind::Serde),
+        }
+    }
+}
+
 impl From<DecodeError> for Error {
     fn from(error: DecodeError) -> Self {
         Error {
@@ -11,9 +11,21 @@
 
 pub mod client;
 pub mod config;
+pub mod coordination;
+pub mod custom;
+pub mod discovery;
 pub mod error;
 pub mod kube;
+pub mod status;
+pub mod token;
+pub mod watch;
 
 pub use self::client::{Client, HttpClient};
 pub use self::config::{get_config, Config, TokenSource, ValueToken};
+pub use self::coordination::{Lease, LeaseSpec};
+pub use self::custom::CustomResource;
+pub use self::discovery::{ApiDiscovery, ApiGroup, API_DISCOVERY_TTL};
 pub use self::error::{Error, ErrorKind};
+pub use self::status::KubeStatus;
+pub use self::token::{ProjectedToken, ProjectedTokenSource, PROJECTED_TOKEN_REFRESH_MARGIN};
+pub use self::watch::WatchEvent;
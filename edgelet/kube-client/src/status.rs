@@ -0,0 +1,73 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The `Status` object the API server returns in the body of failed requests.
+
+use std::fmt;
+
+use failure::Fail;
+use serde_derive::Deserialize;
+
+const STATUS_KIND: &str = "Status";
+
+#[derive(Clone, Debug, Default, Deserialize, Fail, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct KubeStatus {
+    pub kind: Option<String>,
+    pub status: Option<String>,
+    pub message: Option<String>,
+    pub reason: Option<String>,
+    pub code: Option<u16>,
+}
+
+impl KubeStatus {
+    /// Returns `None` unless the body is a JSON `Status` object.
+    pub fn from_body(body: &[u8]) -> Option<KubeStatus> {
+        serde_json::from_slice::<KubeStatus>(body)
+            .ok()
+            .filter(|status| status.kind.as_ref().map(String::as_str) == Some(STATUS_KIND))
+    }
+}
+
+impl fmt::Display for KubeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.reason, &self.message) {
+            (Some(reason), Some(message)) => write!(f, "{}: {}", reason, message),
+            (Some(reason), None) => write!(f, "{}", reason),
+            (None, Some(message)) => write!(f, "{}", message),
+            (None, None) => write!(f, "Unknown Kubernetes API error"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KubeStatus;
+
+    #[test]
+    fn from_body_parses_status() {
+        let body = br#"{
+            "kind": "Status",
+            "apiVersion": "v1",
+            "metadata": {},
+            "status": "Failure",
+            "message": "pods \"edgeagent\" is forbidden",
+            "reason": "Forbidden",
+            "code": 403
+        }"#;
+
+        let status = KubeStatus::from_body(body).unwrap();
+        assert_eq!(Some("Forbidden".to_string()), status.reason);
+        assert_eq!(Some(403), status.code);
+        assert_eq!(
+            "Forbidden: pods \"edgeagent\" is forbidden",
+            status.to_string()
+        );
+    }
+
+    #[test]
+    fn from_body_ignores_other_objects() {
+        assert_eq!(None, KubeStatus::from_body(br#"{"kind": "Pod"}"#));
+        assert_eq!(None, KubeStatus::from_body(b"upstream connect error"));
+        assert_eq!(None, KubeStatus::from_body(b""));
+    }
+}
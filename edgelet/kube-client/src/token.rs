@@ -0,0 +1,258 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Service account tokens issued by the API server through the `token`
+//! subresource of a service account. Unlike the token mounted into a pod these
+//! are bound to the audiences they were requested for, so they can't be reused
+//! against services which expect another audience.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::TokenSource;
+use crate::error::{Error, ErrorKind, Result};
+
+/// How long before it expires a projected token is replaced.
+pub const PROJECTED_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectedToken {
+    token: String,
+    expiration: DateTime<Utc>,
+}
+
+impl ProjectedToken {
+    pub fn new(token: String, expiration: DateTime<Utc>) -> Self {
+        ProjectedToken { token, expiration }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn expiration(&self) -> DateTime<Utc> {
+        self.expiration
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expiration <= Utc::now()
+    }
+
+    pub fn needs_refresh(&self) -> bool {
+        let margin = chrono::Duration::from_std(PROJECTED_TOKEN_REFRESH_MARGIN)
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        self.expiration - margin <= Utc::now()
+    }
+}
+
+/// A token source handing out a token requested for `audiences`. Clones share
+/// the token, so a clone kept outside the client's config can be passed to
+/// `Client::refresh_projected_token` to replace it before it expires.
+#[derive(Clone, Debug)]
+pub struct ProjectedTokenSource {
+    namespace: String,
+    service_account: String,
+    audiences: Vec<String>,
+    expiration: Duration,
+    token: Arc<Mutex<Option<ProjectedToken>>>,
+}
+
+impl ProjectedTokenSource {
+    pub fn new(
+        namespace: String,
+        service_account: String,
+        audiences: Vec<String>,
+        expiration: Duration,
+    ) -> Self {
+        ProjectedTokenSource {
+            namespace,
+            service_account,
+            audiences,
+            expiration,
+            token: Arc::default(),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn service_account(&self) -> &str {
+        &self.service_account
+    }
+
+    pub fn audiences(&self) -> &[String] {
+        &self.audiences
+    }
+
+    /// The lifetime requested for each token.
+    pub fn expiration(&self) -> Duration {
+        self.expiration
+    }
+
+    pub fn needs_refresh(&self) -> bool {
+        self.token
+            .lock()
+            .expect("Unexpected lock error")
+            .as_ref()
+            .map_or(true, ProjectedToken::needs_refresh)
+    }
+
+    pub fn set_token(&self, token: ProjectedToken) {
+        *self.token.lock().expect("Unexpected lock error") = Some(token);
+    }
+}
+
+impl TokenSource for ProjectedTokenSource {
+    type Error = Error;
+
+    fn get(&self) -> Result<Option<String>> {
+        match *self.token.lock().expect("Unexpected lock error") {
+            Some(ref token) if !token.is_expired() => Ok(Some(token.token.clone())),
+            _ => Err(Error::from(ErrorKind::ExpiredToken)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TokenRequest<'a> {
+    api_version: &'static str,
+    kind: &'static str,
+    spec: TokenRequestSpec<'a>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenRequestSpec<'a> {
+    audiences: &'a [String],
+    expiration_seconds: u64,
+}
+
+impl<'a> TokenRequest<'a> {
+    pub(crate) fn new(audiences: &'a [String], expiration: Duration) -> Self {
+        TokenRequest {
+            api_version: "authentication.k8s.io/v1",
+            kind: "TokenRequest",
+            spec: TokenRequestSpec {
+                audiences,
+                expiration_seconds: expiration.as_secs(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenResponse {
+    #[serde(default)]
+    spec: TokenResponseSpec,
+    status: TokenResponseStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TokenResponseSpec {
+    #[serde(default)]
+    audiences: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenResponseStatus {
+    token: String,
+    expiration_timestamp: DateTime<Utc>,
+}
+
+impl TokenResponse {
+    /// The issued token, provided it is valid for exactly the requested
+    /// audiences.
+    pub(crate) fn into_token(self, audiences: &[String]) -> Result<ProjectedToken> {
+        let mut issued = self.spec.audiences;
+        let mut requested = audiences.to_vec();
+        issued.sort();
+        requested.sort();
+        if issued != requested {
+            return Err(Error::from(ErrorKind::TokenAudience(issued)));
+        }
+
+        Ok(ProjectedToken::new(
+            self.status.token,
+            self.status.expiration_timestamp,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Duration as ChronoDuration, Utc};
+    use serde_json::json;
+
+    use super::{ProjectedToken, ProjectedTokenSource, TokenResponse};
+    use crate::config::TokenSource;
+    use crate::error::ErrorKind;
+
+    fn source() -> ProjectedTokenSource {
+        ProjectedTokenSource::new(
+            "default".to_string(),
+            "iotedge".to_string(),
+            vec!["iotedge".to_string()],
+            Duration::from_secs(3600),
+        )
+    }
+
+    #[test]
+    fn projected_token_is_refreshed_before_expiry() {
+        let source = source();
+        assert!(source.needs_refresh());
+        assert!(source.get().is_err());
+
+        source.set_token(ProjectedToken::new(
+            "token".to_string(),
+            Utc::now() + ChronoDuration::hours(1),
+        ));
+        assert!(!source.needs_refresh());
+        assert_eq!(Some("token".to_string()), source.get().unwrap());
+
+        source.set_token(ProjectedToken::new(
+            "token".to_string(),
+            Utc::now() + ChronoDuration::minutes(1),
+        ));
+        assert!(source.needs_refresh());
+        assert_eq!(Some("token".to_string()), source.get().unwrap());
+
+        source.set_token(ProjectedToken::new(
+            "token".to_string(),
+            Utc::now() - ChronoDuration::minutes(1),
+        ));
+        match source.get().unwrap_err().kind() {
+            ErrorKind::ExpiredToken => (),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn token_for_other_audience_is_rejected() {
+        let response: TokenResponse = serde_json::from_value(json!({
+            "spec": { "audiences": ["https://kubernetes.default.svc"] },
+            "status": { "token": "token", "expirationTimestamp": "2019-07-01T12:00:00Z" }
+        }))
+        .unwrap();
+
+        match response
+            .into_token(&["iotedge".to_string()])
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::TokenAudience(audiences) => {
+                assert_eq!(
+                    &vec!["https://kubernetes.default.svc".to_string()],
+                    audiences
+                )
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
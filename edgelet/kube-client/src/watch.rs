@@ -0,0 +1,17 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Events streamed by the API server in response to a watch request, one JSON
+//! object per line.
+
+use serde_derive::Deserialize;
+
+use crate::status::KubeStatus;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "object", rename_all = "UPPERCASE")]
+pub enum WatchEvent<T> {
+    Added(T),
+    Modified(T),
+    Deleted(T),
+    Error(KubeStatus),
+}
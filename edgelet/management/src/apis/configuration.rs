@@ -10,11 +10,13 @@
 
 use failure::{format_err, Error};
 use hyper::client::connect::Connect;
+use hyper::header::HeaderMap;
 use hyper::{Client, Uri};
 
 pub struct Configuration<C: Connect> {
     pub base_path: String,
     pub user_agent: Option<String>,
+    pub headers: HeaderMap,
     pub client: Client<C>,
     pub uri_composer: Box<dyn Fn(&str, &str) -> Result<Uri, Error> + Send + Sync>,
 }
@@ -24,6 +26,7 @@ impl<C: Connect> Configuration<C> {
         Configuration {
             base_path: "http://localhost".to_owned(),
             user_agent: Some(format!("iotedge/{}", env!("CARGO_PKG_VERSION"))),
+            headers: HeaderMap::new(),
             client,
             uri_composer: Box::new(|base_path, path| {
                 format!("{}{}", base_path, path)
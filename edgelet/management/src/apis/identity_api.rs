@@ -81,6 +81,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let mut req = req
             .body(hyper::Body::from(serialized))
             .expect("could not build hyper::Request");
@@ -139,6 +142,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -189,6 +195,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
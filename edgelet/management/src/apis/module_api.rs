@@ -30,6 +30,11 @@ impl<C: hyper::client::connect::Connect> ModuleApiClient<C> {
 }
 
 pub trait ModuleApi: Send + Sync {
+    fn clear_module_logs(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn create_module(
         &self,
         api_version: &str,
@@ -57,6 +62,11 @@ pub trait ModuleApi: Send + Sync {
         tail: &str,
         since: i32,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
+    fn module_stats(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send>;
     fn restart_module(
         &self,
         api_version: &str,
@@ -86,6 +96,60 @@ where
     <C as hyper::client::connect::Connect>::Transport: 'static,
     <C as hyper::client::connect::Connect>::Future: 'static,
 {
+    fn clear_module_logs(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::DELETE;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!("/modules/{name}/logs?{}", query, name = name);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|_| futures::future::ok(())),
+        )
+    }
+
     fn create_module(
         &self,
         api_version: &str,
@@ -114,6 +178,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let mut req = req
             .body(hyper::Body::from(serialized))
             .expect("could not build hyper::Request");
@@ -173,6 +240,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -225,6 +295,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -280,6 +353,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -341,6 +417,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -363,6 +442,63 @@ where
         )
     }
 
+    fn module_stats(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::GET;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!("/modules/{name}/stats?{}", query, name = name);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|body| {
+                    let parsed: Result<serde_json::Value, _> = serde_json::from_slice(&body);
+                    parsed.map_err(Error::from)
+                }),
+        )
+    }
+
     fn restart_module(
         &self,
         api_version: &str,
@@ -387,6 +523,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -438,6 +577,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -489,6 +631,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let req = req
             .body(hyper::Body::empty())
             .expect("could not build hyper::Request");
@@ -545,6 +690,9 @@ where
         if let Some(ref user_agent) = configuration.user_agent {
             req.header(http::header::USER_AGENT, &**user_agent);
         }
+        for (name, value) in &configuration.headers {
+            req.header(name.as_str(), value.as_bytes());
+        }
         let mut req = req
             .body(hyper::Body::from(serialized))
             .expect("could not build hyper::Request");
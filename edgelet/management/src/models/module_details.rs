@@ -27,6 +27,9 @@ pub struct ModuleDetails {
     config: crate::models::Config,
     #[serde(rename = "status")]
     status: crate::models::Status,
+    /// The IP address of the module's pod, when it runs in one.
+    #[serde(rename = "podIp", skip_serializing_if = "Option::is_none")]
+    pod_ip: Option<String>,
 }
 
 impl ModuleDetails {
@@ -43,6 +46,7 @@ impl ModuleDetails {
             type_,
             config,
             status,
+            pod_ip: None,
         }
     }
 
@@ -110,4 +114,21 @@ impl ModuleDetails {
     pub fn status(&self) -> &crate::models::Status {
         &self.status
     }
+
+    pub fn set_pod_ip(&mut self, pod_ip: String) {
+        self.pod_ip = Some(pod_ip);
+    }
+
+    pub fn with_pod_ip(mut self, pod_ip: String) -> Self {
+        self.pod_ip = Some(pod_ip);
+        self
+    }
+
+    pub fn pod_ip(&self) -> Option<&str> {
+        self.pod_ip.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_pod_ip(&mut self) {
+        self.pod_ip = None;
+    }
 }